@@ -1,5 +1,9 @@
-use crate::primitives::{ChainAddress, ChainName, IdentityContext, JudgementStateBlanked};
-use crate::Database;
+use crate::connector::Judgement;
+use crate::primitives::{
+    ChainAddress, ErasureReport, IdentityContext, JudgementHistoryEntryBlanked,
+    JudgementStateBlanked, JudgementSubmission,
+};
+use crate::{Database, NetworkConfig, Networks};
 use std::str::FromStr;
 
 pub type Result<T> = std::result::Result<T, Response>;
@@ -7,7 +11,12 @@ pub type Result<T> = std::result::Result<T, Response>;
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Command {
     Status(ChainAddress),
+    History(ChainAddress),
     Verify(ChainAddress, Vec<RawFieldName>),
+    Judge(ChainAddress, Judgement),
+    Erase(ChainAddress),
+    Failed,
+    Retry(ChainAddress),
     Help,
 }
 
@@ -24,7 +33,14 @@ impl FromStr for Command {
                 return Err(Response::UnknownCommand);
             }
 
-            Ok(Command::Status(ChainAddress::from(parts[0].to_string())))
+            Ok(Command::Status(parse_address(parts[0])?))
+        } else if s.starts_with("history") {
+            let parts: Vec<&str> = s.split(' ').skip(1).collect();
+            if parts.len() != 1 {
+                return Err(Response::UnknownCommand);
+            }
+
+            Ok(Command::History(parse_address(parts[0])?))
         } else if s.starts_with("verify") {
             let parts: Vec<&str> = s.split(' ').skip(1).collect();
             if parts.len() < 2 {
@@ -32,12 +48,44 @@ impl FromStr for Command {
             }
 
             Ok(Command::Verify(
-                ChainAddress::from(parts[0].to_string()),
+                parse_address(parts[0])?,
                 parts[1..]
                     .iter()
                     .map(|s| RawFieldName::from_str(s))
                     .collect::<Result<Vec<RawFieldName>>>()?,
             ))
+        } else if s.starts_with("judge") {
+            let parts: Vec<&str> = s.split(' ').skip(1).collect();
+            if parts.len() != 2 {
+                return Err(Response::UnknownCommand);
+            }
+
+            Ok(Command::Judge(
+                parse_address(parts[0])?,
+                parse_judgement(parts[1])?,
+            ))
+        } else if s.starts_with("erase") {
+            let parts: Vec<&str> = s.split(' ').skip(1).collect();
+            if parts.len() != 1 {
+                return Err(Response::UnknownCommand);
+            }
+
+            Ok(Command::Erase(parse_address(parts[0])?))
+        } else if s.starts_with("failed") {
+            let count = s.split(' ').count();
+
+            if count > 1 {
+                return Err(Response::UnknownCommand);
+            }
+
+            Ok(Command::Failed)
+        } else if s.starts_with("retry") {
+            let parts: Vec<&str> = s.split(' ').skip(1).collect();
+            if parts.len() != 1 {
+                return Err(Response::UnknownCommand);
+            }
+
+            Ok(Command::Retry(parse_address(parts[0])?))
         } else if s.starts_with("help") {
             let count = s.split(' ').count();
 
@@ -52,14 +100,27 @@ impl FromStr for Command {
     }
 }
 
+/// A judgement which could not be submitted, see `Command::Failed`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct FailedSubmission {
+    pub context: IdentityContext,
+    pub submission: JudgementSubmission,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Response {
-    Status(JudgementStateBlanked),
+    Status(Box<JudgementStateBlanked>),
+    History(Vec<JudgementHistoryEntryBlanked>),
     Verified(ChainAddress, Vec<RawFieldName>),
     UnknownCommand,
     IdentityNotFound,
     InvalidSyntax(Option<String>),
     FullyVerified(ChainAddress),
+    JudgementPending(ChainAddress, Judgement),
+    Erased(Vec<ErasureReport>),
+    FailedSubmissions(Vec<FailedSubmission>),
+    SubmissionRetried(ChainAddress),
+    NoFailedSubmission,
     InternalError,
     Help,
 }
@@ -68,6 +129,13 @@ impl std::fmt::Display for Response {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let msg = match self {
             Response::Status(state) => serde_json::to_string_pretty(state).unwrap(),
+            Response::History(history) => {
+                if history.is_empty() {
+                    "No history was recorded for this identity".to_string()
+                } else {
+                    serde_json::to_string_pretty(history).unwrap()
+                }
+            }
             Response::Verified(_, fields) => {
                 format!("Verified the following fields: {}", {
                     let mut all = String::new();
@@ -100,12 +168,34 @@ impl std::fmt::Display for Response {
             }
             Response::Help => "\
                 status <ADDR>\t\t\tShow the current verification status of the specified address.\n\
+                history <ADDR>\t\t\tShow all recorded states of the specified address, oldest first.\n\
                 verify <ADDR> <FIELD>...\tVerify one or multiple fields of the specified address.\n\
+                judge <ADDR> <JUDGEMENT>\tIssue the specified judgement for the specified address.\n\
+                erase <ADDR>\t\t\tRemove all personal data of the specified address.\n\
+                failed\t\t\t\tShow all judgements which could not be submitted.\n\
+                retry <ADDR>\t\t\tSubmit the failed judgement of the specified address again.\n\
                 "
             .to_string(),
             Response::FullyVerified(_) => {
                 "Identity has been fully verified. The extrinsic will be submitted in a couple of minutes".to_string()
             },
+            Response::JudgementPending(_, judgement) => {
+                format!("Judgement '{}' will be submitted in a couple of minutes", judgement.as_str())
+            },
+            Response::Erased(reports) => serde_json::to_string_pretty(reports).unwrap(),
+            Response::FailedSubmissions(failed) => {
+                if failed.is_empty() {
+                    "No judgement submission failed".to_string()
+                } else {
+                    serde_json::to_string_pretty(failed).unwrap()
+                }
+            }
+            Response::SubmissionRetried(_) => {
+                "The judgement will be submitted again in a couple of seconds".to_string()
+            }
+            Response::NoFailedSubmission => {
+                "No failed judgement submission was found for this identity".to_string()
+            }
         };
 
         write!(f, "{}", msg)
@@ -145,7 +235,7 @@ impl FromStr for RawFieldName {
 
     fn from_str(s: &str) -> Result<Self> {
         // Convenience handler.
-        let s = s.trim().replace('-', "").replace('_', "").to_lowercase();
+        let s = s.trim().replace(&['-', '_'][..], "").to_lowercase();
 
         let f = match s.as_str() {
             "legalname" => RawFieldName::LegalName,
@@ -162,22 +252,66 @@ impl FromStr for RawFieldName {
     }
 }
 
+fn parse_address(s: &str) -> Result<ChainAddress> {
+    ChainAddress::from_str(s).map_err(|_| Response::InvalidSyntax(Some(s.to_string())))
+}
+
+fn parse_judgement(s: &str) -> Result<Judgement> {
+    // Convenience handler.
+    let s = s.trim().replace(['-', '_'], "").to_lowercase();
+
+    let j = match s.as_str() {
+        "reasonable" => Judgement::Reasonable,
+        "knowngood" => Judgement::KnownGood,
+        "outofdate" => Judgement::OutOfDate,
+        "lowquality" => Judgement::LowQuality,
+        "erroneous" => Judgement::Erroneous,
+        _ => return Err(Response::InvalidSyntax(Some(s.to_string()))),
+    };
+
+    Ok(j)
+}
+
 #[allow(clippy::needless_lifetimes)]
-pub async fn process_admin<'a>(db: &'a Database, command: Command) -> Response {
+pub async fn process_admin<'a>(
+    db: &'a Database,
+    networks: &'a Networks,
+    command: Command,
+) -> Response {
     let local = |db: &'a Database, command: Command| async move {
         match command {
             Command::Status(addr) => {
-                let context = create_context(addr);
+                let context = match resolve_context(db, networks, &addr).await? {
+                    Some(context) => context,
+                    None => return Ok(Response::IdentityNotFound),
+                };
+
                 let state = db.fetch_judgement_state(&context).await?;
 
                 // Determine response based on database lookup.
                 match state {
-                    Some(state) => Ok(Response::Status(state.into())),
+                    Some(state) => Ok(Response::Status(Box::new(state.into()))),
                     None => Ok(Response::IdentityNotFound),
                 }
             }
+            Command::History(addr) => {
+                let context = match resolve_context(db, networks, &addr).await? {
+                    Some(context) => context,
+                    None => return Ok(Response::IdentityNotFound),
+                };
+
+                let history = db.fetch_judgement_history(&context).await?;
+
+                Ok(Response::History(
+                    history.into_iter().map(|entry| entry.into()).collect(),
+                ))
+            }
             Command::Verify(addr, fields) => {
-                let context = create_context(addr.clone());
+                let context = match resolve_context(db, networks, &addr).await? {
+                    Some(context) => context,
+                    None => return Ok(Response::IdentityNotFound),
+                };
+                let addr = context.address.clone();
 
                 // Check if _all_ should be verified (respectively the full identity)
                 #[allow(clippy::collapsible_if)]
@@ -198,6 +332,58 @@ pub async fn process_admin<'a>(db: &'a Database, command: Command) -> Response {
 
                 Ok(Response::Verified(addr, fields))
             }
+            Command::Judge(addr, judgement) => {
+                let context = match resolve_context(db, networks, &addr).await? {
+                    Some(context) => context,
+                    None => return Ok(Response::IdentityNotFound),
+                };
+                let addr = context.address.clone();
+
+                if db.set_pending_judgement(&context, judgement).await? {
+                    Ok(Response::JudgementPending(addr, judgement))
+                } else {
+                    Ok(Response::IdentityNotFound)
+                }
+            }
+            Command::Erase(addr) => {
+                let reports = erase_address(db, networks, &addr).await?;
+                if reports.is_empty() {
+                    return Ok(Response::IdentityNotFound);
+                }
+
+                info!("Erased personal data: {:?}", reports);
+
+                Ok(Response::Erased(reports))
+            }
+            Command::Failed => {
+                let failed = db
+                    .fetch_failed_submissions()
+                    .await?
+                    .into_iter()
+                    .filter_map(|state| {
+                        let context = state.context;
+                        state.submission.map(|submission| FailedSubmission {
+                            context,
+                            submission,
+                        })
+                    })
+                    .collect();
+
+                Ok(Response::FailedSubmissions(failed))
+            }
+            Command::Retry(addr) => {
+                let context = match resolve_context(db, networks, &addr).await? {
+                    Some(context) => context,
+                    None => return Ok(Response::IdentityNotFound),
+                };
+                let addr = context.address.clone();
+
+                if db.retry_judgement_submission(&context).await? {
+                    Ok(Response::SubmissionRetried(addr))
+                } else {
+                    Ok(Response::NoFailedSubmission)
+                }
+            }
             Command::Help => Ok(Response::Help),
         }
     };
@@ -213,15 +399,66 @@ pub async fn process_admin<'a>(db: &'a Database, command: Command) -> Response {
 }
 
 /// Convenience function for creating a full identity context when only the
-/// address itself is present. Only supports Kusama and Polkadot for now.
-pub fn create_context(address: ChainAddress) -> IdentityContext {
-    let chain = if address.as_str().starts_with('1') {
-        ChainName::Polkadot
-    } else {
-        ChainName::Kusama
-    };
+/// address itself is present. The network is determined by the SS58 prefix
+/// and the address is normalized to the canonical encoding of that network.
+/// If no network uses the prefix (e.g. the generic Substrate format), the
+/// address is looked up on all networks. Returns `None` if no identity was
+/// found.
+pub async fn resolve_context(
+    db: &Database,
+    networks: &Networks,
+    address: &ChainAddress,
+) -> crate::Result<Option<IdentityContext>> {
+    for context in candidate_contexts(networks, address)? {
+        if db.fetch_judgement_state(&context).await?.is_some() {
+            return Ok(Some(context));
+        }
+    }
 
-    IdentityContext { address, chain }
+    Ok(None)
+}
+
+/// The contexts the address could refer to, see `resolve_context`. Does not
+/// require the identity to exist.
+pub fn candidate_contexts(
+    networks: &Networks,
+    address: &ChainAddress,
+) -> crate::Result<Vec<IdentityContext>> {
+    let (prefix, _) = address.decode()?;
+    let mut candidates: Vec<&NetworkConfig> = networks.with_ss58_prefix(prefix).collect();
+    if candidates.is_empty() {
+        candidates = networks.iter().collect();
+    }
+
+    candidates
+        .into_iter()
+        .map(|network| {
+            Ok(IdentityContext::new(
+                address.normalize(network.ss58_prefix)?,
+                network.name.clone(),
+            ))
+        })
+        .collect()
+}
+
+/// Erases the personal data of the address on every network it could refer
+/// to, including identities which are only left in the history (e.g. after a
+/// cancellation). Returns the reports of the networks where personal data was
+/// found.
+pub async fn erase_address(
+    db: &Database,
+    networks: &Networks,
+    address: &ChainAddress,
+) -> crate::Result<Vec<ErasureReport>> {
+    let mut reports = vec![];
+    for context in candidate_contexts(networks, address)? {
+        let report = db.erase(&context).await?;
+        if !report.is_empty() {
+            reports.push(report);
+        }
+    }
+
+    Ok(reports)
 }
 
 #[cfg(test)]
@@ -229,63 +466,118 @@ mod tests {
     use super::*;
     use crate::primitives::JudgementState;
 
+    const ALICE: &str = "1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZP";
+
+    fn alice() -> ChainAddress {
+        ChainAddress::from(ALICE)
+    }
+
     #[test]
     fn command_status() {
-        let resp = Command::from_str("status Alice").unwrap();
-        assert_eq!(
-            resp,
-            Command::Status(ChainAddress::from("Alice".to_string()))
-        );
+        let resp = Command::from_str(&format!("status {}", ALICE)).unwrap();
+        assert_eq!(resp, Command::Status(alice()));
+
+        let resp = Command::from_str(&format!("status  {}", ALICE)).unwrap();
+        assert_eq!(resp, Command::Status(alice()));
+
+        let resp = Command::from_str("status");
+        assert!(resp.is_err());
 
-        let resp = Command::from_str("status  Alice").unwrap();
+        // Invalid addresses.
+        let resp = Command::from_str("status Alice");
         assert_eq!(
             resp,
-            Command::Status(ChainAddress::from("Alice".to_string()))
+            Err(Response::InvalidSyntax(Some("Alice".to_string())))
         );
 
-        let resp = Command::from_str("status");
-        assert!(resp.is_err())
+        let resp = Command::from_str("status 1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZQ");
+        assert!(resp.is_err());
+    }
+
+    #[test]
+    fn command_history() {
+        let resp = Command::from_str(&format!("history {}", ALICE)).unwrap();
+        assert_eq!(resp, Command::History(alice()));
+
+        let resp = Command::from_str("history");
+        assert!(resp.is_err());
+
+        let resp = Command::from_str(&format!("history {} email", ALICE));
+        assert!(resp.is_err());
     }
 
     #[test]
     fn command_verify() {
-        let resp = Command::from_str("verify Alice email").unwrap();
-        assert_eq!(
-            resp,
-            Command::Verify(
-                ChainAddress::from("Alice".to_string()),
-                vec![RawFieldName::Email]
-            )
-        );
+        let resp = Command::from_str(&format!("verify {} email", ALICE)).unwrap();
+        assert_eq!(resp, Command::Verify(alice(), vec![RawFieldName::Email]));
 
-        let resp = Command::from_str("verify Alice email displayname").unwrap();
+        let resp = Command::from_str(&format!("verify {} email displayname", ALICE)).unwrap();
         assert_eq!(
             resp,
             Command::Verify(
-                ChainAddress::from("Alice".to_string()),
+                alice(),
                 vec![RawFieldName::Email, RawFieldName::DisplayName]
             )
         );
 
-        let resp = Command::from_str("verify Alice email display_name").unwrap();
+        let resp = Command::from_str(&format!("verify {} email display_name", ALICE)).unwrap();
         assert_eq!(
             resp,
             Command::Verify(
-                ChainAddress::from("Alice".to_string()),
+                alice(),
                 vec![RawFieldName::Email, RawFieldName::DisplayName]
             )
         );
 
-        let resp = Command::from_str("verify Alice all").unwrap();
-        assert_eq!(
-            resp,
-            Command::Verify(
-                ChainAddress::from("Alice".to_string()),
-                vec![RawFieldName::All]
-            )
-        );
+        let resp = Command::from_str(&format!("verify {} all", ALICE)).unwrap();
+        assert_eq!(resp, Command::Verify(alice(), vec![RawFieldName::All]));
+
+        let resp = Command::from_str(&format!("verify {}", ALICE));
+        assert!(resp.is_err());
+    }
+
+    #[test]
+    fn command_judge() {
+        let resp = Command::from_str(&format!("judge {} knowngood", ALICE)).unwrap();
+        assert_eq!(resp, Command::Judge(alice(), Judgement::KnownGood));
+
+        let resp = Command::from_str(&format!("judge {} known_good", ALICE)).unwrap();
+        assert_eq!(resp, Command::Judge(alice(), Judgement::KnownGood));
+
+        let resp = Command::from_str(&format!("judge {} erroneous", ALICE)).unwrap();
+        assert_eq!(resp, Command::Judge(alice(), Judgement::Erroneous));
+
+        let resp = Command::from_str(&format!("judge {} unknown", ALICE));
+        assert!(resp.is_err());
+
+        let resp = Command::from_str(&format!("judge {}", ALICE));
+        assert!(resp.is_err());
+    }
+
+    #[test]
+    fn command_erase() {
+        let resp = Command::from_str(&format!("erase {}", ALICE)).unwrap();
+        assert_eq!(resp, Command::Erase(alice()));
+
+        let resp = Command::from_str("erase");
+        assert!(resp.is_err());
+
+        let resp = Command::from_str(&format!("erase {} all", ALICE));
+        assert!(resp.is_err());
+    }
+
+    #[test]
+    fn command_failed_and_retry() {
+        let resp = Command::from_str("failed").unwrap();
+        assert_eq!(resp, Command::Failed);
+
+        let resp = Command::from_str(&format!("failed {}", ALICE));
+        assert!(resp.is_err());
+
+        let resp = Command::from_str(&format!("retry {}", ALICE)).unwrap();
+        assert_eq!(resp, Command::Retry(alice()));
 
-        let resp = Command::from_str("verify Alice");
+        let resp = Command::from_str("retry");
         assert!(resp.is_err());
     }
 
@@ -304,7 +596,7 @@ mod tests {
     #[test]
     #[ignore]
     fn response_status_debug() {
-        let resp = Response::Status(JudgementState::alice().into());
+        let resp = Response::Status(Box::new(JudgementState::alice().into()));
         println!("{}", resp);
     }
 
@@ -1,7 +1,7 @@
 use crate::adapters::admin::{process_admin, Command, Response};
 use crate::adapters::Adapter;
 use crate::primitives::{ExternalMessage, ExternalMessageType, Timestamp};
-use crate::{Database, Result};
+use crate::{Database, Networks, Result};
 use matrix_sdk::events::room::member::MemberEventContent;
 use matrix_sdk::events::room::message::MessageEventContent;
 use matrix_sdk::events::{AnyMessageEventContent, StrippedStateEvent, SyncMessageEvent};
@@ -30,6 +30,7 @@ impl MatrixClient {
         password: &str,
         db_path: &str,
         db: Database,
+        networks: Networks,
         admins: Vec<MatrixHandle>,
     ) -> Result<MatrixClient> {
         info!("Setting up Matrix client");
@@ -56,6 +57,7 @@ impl MatrixClient {
                 client.clone(),
                 Arc::clone(&messages),
                 db,
+                networks,
                 admins,
             )))
             .await;
@@ -84,6 +86,7 @@ struct Listener {
     client: Client,
     messages: Arc<Mutex<Vec<ExternalMessage>>>,
     db: Database,
+    networks: Networks,
     admins: Vec<MatrixHandle>,
 }
 
@@ -92,12 +95,14 @@ impl Listener {
         client: Client,
         messages: Arc<Mutex<Vec<ExternalMessage>>>,
         db: Database,
+        networks: Networks,
         admins: Vec<MatrixHandle>,
     ) -> Self {
         Self {
             client,
             messages,
             db,
+            networks,
             admins,
         }
     }
@@ -157,7 +162,7 @@ impl EventHandler for Listener {
             if self.admins.contains(&MatrixHandle(sender)) {
                 let resp = match Command::from_str(msg_body) {
                     // If a valid admin command was found, execute it.
-                    Ok(cmd) => Some(process_admin(&self.db, cmd).await),
+                    Ok(cmd) => Some(process_admin(&self.db, &self.networks, cmd).await),
                     Err(err @ Response::InvalidSyntax(_)) => Some(err),
                     // Ignore, allow noise (catches `UnknownCommand`).
                     Err(_) => None,
@@ -212,6 +217,9 @@ impl Adapter for MatrixClient {
         Ok(std::mem::take(&mut *lock))
     }
     async fn send_message(&mut self, _to: &str, _content: Self::MessageType) -> Result<()> {
-        unimplemented!()
+        Err(anyhow!(
+            "Sending messages is not supported by the {} adapter",
+            self.name()
+        ))
     }
 }
@@ -1,18 +1,20 @@
-use crate::database::{Database, EventCursor};
+use crate::database::Database;
 use crate::primitives::{
     ExpectedMessage, ExternalMessage, IdentityFieldValue, NotificationMessage,
 };
-use crate::{AdapterConfig, Result};
-use tokio::time::{interval, Duration};
+use crate::{AdapterConfig, LockoutPolicy, Networks, Result};
+use tokio::time::{interval, sleep, Duration};
 use tracing::Instrument;
 
 pub mod admin;
 pub mod email;
 pub mod matrix;
 pub mod twitter;
+pub mod web;
 
-pub async fn run_adapters(config: AdapterConfig, db: Database) -> Result<()> {
-    let listener = AdapterListener::new(db.clone()).await;
+pub async fn run_adapters(config: AdapterConfig, db: Database, networks: Networks) -> Result<()> {
+    let lockout = LockoutPolicy::new(config.lockout.clone(), &networks);
+    let listener = AdapterListener::new(db.clone(), lockout).await;
     // Convenience flat for logging
     let mut started = false;
 
@@ -22,7 +24,12 @@ pub async fn run_adapters(config: AdapterConfig, db: Database) -> Result<()> {
         matrix: matrix_config,
         twitter: twitter_config,
         email: email_config,
+        web: web_config,
         display_name: _,
+        judgement: _,
+        challenge: _,
+        lockout: _,
+        retention: _,
     } = config;
 
     // Matrix client configuration and execution.
@@ -42,13 +49,14 @@ pub async fn run_adapters(config: AdapterConfig, db: Database) -> Result<()> {
                 &config.username,
                 &config.password,
                 &config.db_path,
-                db,
+                db.clone(),
+                networks,
                 config.admins.unwrap_or_default(),
             )
             .await?;
 
             info!("Starting message adapter");
-            listener.start_message_adapter(matrix_client, 1).await;
+            listener.start_message_adapter(matrix_client, 1).await?;
             Result::Ok(())
         }
         .instrument(span)
@@ -76,7 +84,7 @@ pub async fn run_adapters(config: AdapterConfig, db: Database) -> Result<()> {
             info!("Starting message adapter");
             listener
                 .start_message_adapter(twitter_client, config.request_interval)
-                .await;
+                .await?;
 
             Result::Ok(())
         }
@@ -111,7 +119,33 @@ pub async fn run_adapters(config: AdapterConfig, db: Database) -> Result<()> {
             info!("Starting message adapter");
             listener
                 .start_message_adapter(email_client, config.request_interval)
-                .await;
+                .await?;
+
+            Result::Ok(())
+        }
+        .instrument(span)
+        .await?;
+
+        started = true;
+    }
+
+    // Web verifier configuration and execution.
+    if web_config.enabled {
+        let config = web_config;
+
+        let span = info_span!("web_adapter");
+        info!(request_interval = config.request_interval);
+
+        async {
+            info!("Configuring verifier");
+            let web_verifier = web::WebVerifierBuilder::new()
+                .database(db.clone())
+                .build()?;
+
+            info!("Starting message adapter");
+            listener
+                .start_message_adapter(web_verifier, config.request_interval)
+                .await?;
 
             Result::Ok(())
         }
@@ -144,80 +178,88 @@ impl From<ExpectedMessage> for () {
 
 pub struct AdapterListener {
     db: Database,
+    lockout: LockoutPolicy,
 }
 
 impl AdapterListener {
-    pub async fn new(db: Database) -> Self {
-        AdapterListener { db }
+    pub async fn new(db: Database, lockout: LockoutPolicy) -> Self {
+        AdapterListener { db, lockout }
     }
-    pub async fn start_message_adapter<T>(&self, mut adapter: T, timeout: u64)
+    pub async fn start_message_adapter<T>(&self, mut adapter: T, timeout: u64) -> Result<()>
     where
         T: 'static + Adapter + Send,
         <T as Adapter>::MessageType: From<ExpectedMessage>,
     {
         let mut interval = interval(Duration::from_secs(timeout));
 
-        let mut db = self.db.clone();
-        let mut cursor = EventCursor::new();
+        let db = self.db.clone();
+        let lockout = self.lockout.clone();
+        let mut events = db
+            .subscribe_events(&format!("{}_adapter", adapter.name()))
+            .await?;
+
         actix::spawn(async move {
             loop {
-                // Timeout (skipped the first time);
-                interval.tick().await;
-
-                // Fetch message and send it to the listener, if any.
-                match adapter.fetch_messages().await {
-                    Ok(messages) => {
-                        for message in messages {
-                            debug!("Processing message from: {:?}", message.origin);
-                            let _ = db
-                                .verify_message(&message)
-                                .await
-                                .map_err(|err| error!("Error when verifying message: {:?}", err));
+                tokio::select! {
+                    // Timeout (skipped the first time);
+                    _ = interval.tick() => {
+                        // Fetch message and send it to the listener, if any.
+                        match adapter.fetch_messages().await {
+                            Ok(messages) => {
+                                for message in messages {
+                                    debug!("Processing message from: {:?}", message.origin);
+                                    let _ = db
+                                        .verify_message(&message, &lockout)
+                                        .await
+                                        .map_err(|err| error!("Error when verifying message: {:?}", err));
+                                }
+                            }
+                            Err(err) => {
+                                error!(
+                                    "Error fetching messages in {} adapter: {:?}",
+                                    adapter.name(),
+                                    err
+                                );
+                            }
                         }
                     }
-                    Err(err) => {
-                        error!(
-                            "Error fetching messages in {} adapter: {:?}",
-                            adapter.name(),
-                            err
-                        );
-                    }
-                }
-
-                // Check if a second challenge must be sent to the user directly.
-                match db.fetch_events(&mut cursor).await {
-                    Ok(events) => {
-                        for event in &events {
-                            if let NotificationMessage::AwaitingSecondChallenge { context, field } =
-                                event
-                            {
-                                if let IdentityFieldValue::Email(to) = field {
+                    // Check if a second challenge must be sent to the user directly.
+                    event = events.next() => {
+                        match event {
+                            Ok(NotificationMessage::AwaitingSecondChallenge { context, field }) => {
+                                if let IdentityFieldValue::Email(to) = &field {
                                     if adapter.name() == "email" {
                                         debug!("Sending second challenge to {}", to);
                                         if let Ok(challenge) = db
-                                            .fetch_second_challenge(context, field)
+                                            .fetch_second_challenge(&context, &field)
                                             .await
                                             .map_err(|err| error!("Failed to fetch second challenge from database: {:?}", err)) {
                                                 let _ = adapter
                                                     .send_message(to.as_str(), challenge.into())
                                                     .await
                                                     .map_err(|err| error!("Failed to send second challenge to {} ({} adapter): {:?}", to, adapter.name(), err));
-                                                }
+                                            }
                                     }
                                 }
                             }
+                            Ok(_) => {}
+                            Err(err) => {
+                                error!(
+                                    "Error fetching events in {} adapter: {:?}",
+                                    adapter.name(),
+                                    err
+                                );
+
+                                // Prevent a busy loop if the database is unavailable.
+                                sleep(Duration::from_secs(1)).await;
+                            }
                         }
                     }
-                    Err(err) => {
-                        error!(
-                            "Error fetching messages in {} adapter: {:?}",
-                            adapter.name(),
-                            err
-                        );
-                    }
                 }
             }
         });
+
+        Ok(())
     }
 }
 
@@ -256,7 +298,10 @@ pub mod tests {
             Ok(std::mem::take(&mut *lock))
         }
         async fn send_message(&mut self, _to: &str, _content: Self::MessageType) -> Result<()> {
-            unimplemented!()
+            Err(anyhow!(
+                "Sending messages is not supported by the {} adapter",
+                self.name()
+            ))
         }
     }
 }
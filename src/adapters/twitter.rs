@@ -226,7 +226,7 @@ impl TwitterClient {
             fields.append(&mut params.to_vec());
         }
 
-        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        fields.sort_by_key(|(name, _)| *name);
 
         let mut params = String::new();
         for (name, val) in &fields {
@@ -253,7 +253,7 @@ impl TwitterClient {
 
         // Insert the signature;
         fields.push(("oauth_signature", &sig));
-        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        fields.sort_by_key(|(name, _)| *name);
 
         // Merge all fields into the OAuth header.
         let mut oauth_header = String::new();
@@ -426,6 +426,9 @@ impl Adapter for TwitterClient {
         self.request_messages().await
     }
     async fn send_message(&mut self, _to: &str, _content: Self::MessageType) -> Result<()> {
-        unimplemented!()
+        Err(anyhow!(
+            "Sending messages is not supported by the {} adapter",
+            self.name()
+        ))
     }
 }
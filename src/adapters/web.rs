@@ -0,0 +1,446 @@
+use crate::adapters::Adapter;
+use crate::database::Database;
+use crate::primitives::{ExternalMessage, ExternalMessageType, MessagePart, Timestamp};
+use crate::Result;
+use reqwest::{redirect, Client};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use trust_dns_resolver::TokioAsyncResolver;
+use url::{Host, Url};
+
+/// The prefix of DNS TXT records which contain the challenge, e.g.
+/// `polkadot-registrar=<CHALLENGE>`. Other TXT records are ignored.
+pub const TXT_RECORD_PREFIX: &str = "polkadot-registrar=";
+/// The path of the file (relative to the domain root) which contains the
+/// challenge.
+pub const WELL_KNOWN_PATH: &str = "/.well-known/polkadot-registrar.txt";
+/// The maximum size of the `.well-known` file, in bytes. Larger files are
+/// ignored.
+pub const MAX_WELL_KNOWN_SIZE: usize = 4096;
+
+// Anyone can set a web domain on chain, so slow servers must not stall the
+// verification of the other domains.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Redirects are only followed to the same host.
+const MAX_REDIRECTS: usize = 3;
+
+/// Abstraction over DNS lookups, so the verifier can be tested without
+/// relying on public DNS servers.
+#[async_trait]
+pub trait DnsResolver {
+    async fn txt_records(&self, domain: &str) -> Result<Vec<String>>;
+    async fn ip_addrs(&self, domain: &str) -> Result<Vec<IpAddr>>;
+}
+
+/// Resolves DNS records based on the system configuration (e.g.
+/// `/etc/resolv.conf`).
+pub struct SystemResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl SystemResolver {
+    pub fn new() -> Result<Self> {
+        Ok(SystemResolver {
+            resolver: TokioAsyncResolver::tokio_from_system_conf()?,
+        })
+    }
+}
+
+#[async_trait]
+impl DnsResolver for SystemResolver {
+    async fn txt_records(&self, domain: &str) -> Result<Vec<String>> {
+        let lookup = self.resolver.txt_lookup(domain).await?;
+
+        Ok(lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data).to_string())
+                    .collect::<String>()
+            })
+            .collect())
+    }
+    async fn ip_addrs(&self, domain: &str) -> Result<Vec<IpAddr>> {
+        Ok(self.resolver.lookup_ip(domain).await?.iter().collect())
+    }
+}
+
+pub struct WebVerifierBuilder {
+    db: Option<Database>,
+    resolver: Option<Box<dyn DnsResolver + Send + Sync>>,
+    scheme: String,
+    allow_private_addresses: bool,
+}
+
+impl WebVerifierBuilder {
+    pub fn new() -> Self {
+        WebVerifierBuilder {
+            db: None,
+            resolver: None,
+            scheme: "https".to_string(),
+            allow_private_addresses: false,
+        }
+    }
+    pub fn database(mut self, db: Database) -> Self {
+        self.db = Some(db);
+        self
+    }
+    #[cfg(test)]
+    pub fn resolver<T>(mut self, resolver: T) -> Self
+    where
+        T: 'static + DnsResolver + Send + Sync,
+    {
+        self.resolver = Some(Box::new(resolver));
+        self
+    }
+    /// The URL scheme used when fetching the `.well-known` file. Defaults to
+    /// `https`.
+    #[cfg(test)]
+    pub fn scheme(mut self, scheme: String) -> Self {
+        self.scheme = scheme;
+        self
+    }
+    /// Fetches the `.well-known` file from loopback and private addresses,
+    /// which are refused by default.
+    #[cfg(test)]
+    pub fn allow_private_addresses(mut self) -> Self {
+        self.allow_private_addresses = true;
+        self
+    }
+    pub fn build(self) -> Result<WebVerifier> {
+        Ok(WebVerifier {
+            db: self.db.ok_or_else(|| anyhow!("database not specified"))?,
+            resolver: match self.resolver {
+                Some(resolver) => resolver,
+                None => Box::new(SystemResolver::new()?),
+            },
+            scheme: self.scheme,
+            allow_private_addresses: self.allow_private_addresses,
+            cache: HashMap::new(),
+            counter: 0,
+        })
+    }
+}
+
+/// Polls the DNS TXT records and the `.well-known` file of every unverified
+/// web domain and forwards any published challenges to the database for
+/// verification.
+pub struct WebVerifier {
+    db: Database,
+    resolver: Box<dyn DnsResolver + Send + Sync>,
+    scheme: String,
+    allow_private_addresses: bool,
+    // Keep track of the last published values of each domain, so that
+    // unchanged values are not reported (and counted as failed attempts)
+    // repeatedly.
+    cache: HashMap<String, Vec<MessagePart>>,
+    counter: u64,
+}
+
+impl WebVerifier {
+    async fn fetch_txt_records(&self, host: &str) -> Vec<MessagePart> {
+        match self.resolver.txt_records(host).await {
+            Ok(records) => records
+                .into_iter()
+                .filter_map(|record| {
+                    record
+                        .trim()
+                        .strip_prefix(TXT_RECORD_PREFIX)
+                        .map(|value| value.trim().to_string().into())
+                })
+                .collect(),
+            Err(err) => {
+                debug!("No TXT records found for {}: {:?}", host, err);
+                vec![]
+            }
+        }
+    }
+    /// Builds the client to fetch the given URL with. The host must only
+    /// resolve to public addresses and the connection is pinned to the checked
+    /// address, so the domain cannot be rebound to another one in between.
+    async fn client(&self, url: &Url) -> Result<Client> {
+        let host = url
+            .host()
+            .ok_or_else(|| anyhow!("no host specified in {}", url))?
+            .to_owned();
+
+        let host_str = url.host_str().unwrap_or_default().to_string();
+        let redirect_host = host_str.clone();
+        let mut builder = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() > MAX_REDIRECTS
+                    || attempt.url().host_str() != Some(redirect_host.as_str())
+                {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }));
+
+        if !self.allow_private_addresses {
+            let addrs = match host {
+                Host::Ipv4(addr) => vec![IpAddr::V4(addr)],
+                Host::Ipv6(addr) => vec![IpAddr::V6(addr)],
+                Host::Domain(domain) => {
+                    let addrs = self.resolver.ip_addrs(&domain).await?;
+                    if let Some(addr) = addrs.first() {
+                        builder = builder.resolve(&domain, SocketAddr::new(*addr, 0));
+                    }
+
+                    addrs
+                }
+            };
+
+            if addrs.is_empty() {
+                return Err(anyhow!("{} does not resolve to any address", host_str));
+            }
+            if let Some(addr) = addrs.iter().find(|addr| !is_public_address(addr)) {
+                return Err(anyhow!(
+                    "{} resolves to non-public address {}",
+                    host_str,
+                    addr
+                ));
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+    async fn fetch_well_known(&self, domain: &str) -> Vec<MessagePart> {
+        let url = format!("{}://{}{}", self.scheme, domain, WELL_KNOWN_PATH);
+
+        let client = match Url::parse(&url) {
+            Ok(parsed) => match self.client(&parsed).await {
+                Ok(client) => client,
+                Err(err) => {
+                    debug!("Refusing to fetch {}: {:?}", url, err);
+                    return vec![];
+                }
+            },
+            Err(err) => {
+                debug!("Invalid URL {}: {:?}", url, err);
+                return vec![];
+            }
+        };
+
+        let mut resp = match client.get(&url).send().await {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(resp) => {
+                debug!("Unexpected status code from {}: {}", url, resp.status());
+                return vec![];
+            }
+            Err(err) => {
+                debug!("Failed to fetch {}: {:?}", url, err);
+                return vec![];
+            }
+        };
+
+        // Read the body in chunks, so large files are not buffered entirely.
+        let mut body = vec![];
+        loop {
+            match resp.chunk().await {
+                Ok(Some(chunk)) => {
+                    if body.len() + chunk.len() > MAX_WELL_KNOWN_SIZE {
+                        debug!(
+                            "Body from {} exceeds {} bytes, ignoring",
+                            url, MAX_WELL_KNOWN_SIZE
+                        );
+                        return vec![];
+                    }
+
+                    body.extend_from_slice(&chunk);
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("Failed to read body from {}: {:?}", url, err);
+                    return vec![];
+                }
+            }
+        }
+
+        let body = String::from_utf8_lossy(&body);
+        let body = body.trim();
+        if body.is_empty() {
+            vec![]
+        } else {
+            vec![body.to_string().into()]
+        }
+    }
+    async fn request_messages(&mut self) -> Result<Vec<ExternalMessage>> {
+        let domains = self.db.fetch_unverified_web_domains().await?;
+
+        // Drop tracked domains which are no longer pending.
+        self.cache.retain(|domain, _| domains.contains(domain));
+
+        let mut messages = vec![];
+        for domain in domains {
+            let authority = normalize_domain(&domain);
+            if authority.is_empty() {
+                continue;
+            }
+
+            // DNS lookups do not support ports.
+            let host = authority.split(':').next().unwrap_or_default();
+
+            let mut values = self.fetch_txt_records(host).await;
+            values.append(&mut self.fetch_well_known(&authority).await);
+
+            // Skip if nothing was published or nothing changed since the last
+            // check.
+            if values.is_empty() || self.cache.get(&domain) == Some(&values) {
+                continue;
+            }
+
+            debug!("Found published challenge(s) for {}", domain);
+            self.cache.insert(domain.clone(), values.clone());
+
+            self.counter += 1;
+            messages.push(ExternalMessage {
+                origin: ExternalMessageType::Web(domain),
+                id: self.counter.into(),
+                timestamp: Timestamp::now(),
+                values,
+            });
+        }
+
+        Ok(messages)
+    }
+}
+
+#[async_trait]
+impl Adapter for WebVerifier {
+    type MessageType = ();
+
+    fn name(&self) -> &'static str {
+        "web"
+    }
+    async fn fetch_messages(&mut self) -> Result<Vec<ExternalMessage>> {
+        self.request_messages().await
+    }
+    async fn send_message(&mut self, _to: &str, _content: Self::MessageType) -> Result<()> {
+        Err(anyhow!(
+            "Sending messages is not supported by the {} adapter",
+            self.name()
+        ))
+    }
+}
+
+/// Whether the address is reachable on the public internet. Domains must not
+/// point the verifier to the local host or network.
+fn is_public_address(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => is_public_ipv4(addr),
+        IpAddr::V6(addr) => match addr.segments() {
+            // IPv4-mapped addresses.
+            [0, 0, 0, 0, 0, 0xffff, high, low] => is_public_ipv4(&Ipv4Addr::new(
+                (high >> 8) as u8,
+                high as u8,
+                (low >> 8) as u8,
+                low as u8,
+            )),
+            // Unique local (fc00::/7) and link-local (fe80::/10) addresses.
+            [first, ..] if first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80 => false,
+            _ => !(addr.is_loopback() || addr.is_unspecified()),
+        },
+    }
+}
+
+fn is_public_ipv4(addr: &Ipv4Addr) -> bool {
+    let [first, second, ..] = addr.octets();
+
+    !(addr.is_loopback()
+        || addr.is_private()
+        || addr.is_link_local()
+        || addr.is_unspecified()
+        || addr.is_broadcast()
+        // Shared address space (100.64.0.0/10).
+        || (first == 100 && second & 0xc0 == 64))
+}
+
+/// Strips the scheme, path and trailing dot of the on-chain web value, e.g.
+/// `https://www.example.com/about` becomes `www.example.com`.
+fn normalize_domain(value: &str) -> String {
+    let value = value.trim();
+    let value = value
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(value);
+
+    value
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Resolves to a fixed set of TXT records, independent of the domain.
+    pub struct StubResolver {
+        records: Vec<String>,
+    }
+
+    impl StubResolver {
+        pub fn new(records: Vec<String>) -> Self {
+            StubResolver { records }
+        }
+    }
+
+    #[async_trait]
+    impl DnsResolver for StubResolver {
+        async fn txt_records(&self, _domain: &str) -> Result<Vec<String>> {
+            Ok(self.records.clone())
+        }
+        async fn ip_addrs(&self, _domain: &str) -> Result<Vec<IpAddr>> {
+            Ok(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)])
+        }
+    }
+
+    #[test]
+    fn normalize_web_domains() {
+        assert_eq!(normalize_domain("example.com"), "example.com");
+        assert_eq!(normalize_domain(" Example.com "), "example.com");
+        assert_eq!(normalize_domain("https://example.com/"), "example.com");
+        assert_eq!(
+            normalize_domain("http://www.example.com/about"),
+            "www.example.com"
+        );
+        assert_eq!(normalize_domain("example.com.?ref=1"), "example.com");
+        assert_eq!(normalize_domain("localhost:8080/"), "localhost:8080");
+        assert_eq!(normalize_domain(""), "");
+    }
+
+    #[test]
+    fn public_addresses() {
+        let public = ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"];
+        for addr in &public {
+            assert!(is_public_address(&addr.parse().unwrap()), "{}", addr);
+        }
+
+        let private = [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ];
+        for addr in &private {
+            assert!(!is_public_address(&addr.parse().unwrap()), "{}", addr);
+        }
+    }
+}
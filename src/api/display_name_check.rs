@@ -2,7 +2,7 @@ use super::JsonResult;
 use crate::connector::DisplayNameEntry;
 use crate::database::Database;
 use crate::primitives::ChainName;
-use crate::{display_name::DisplayNameVerifier, DisplayNameConfig};
+use crate::{display_name::DisplayNameVerifier, DisplayNameConfig, Networks};
 use actix::prelude::*;
 use actix_web::{web, HttpResponse};
 
@@ -41,7 +41,7 @@ impl Handler<CheckDisplayName> for DisplayNameChecker {
             async move {
                 trace!("Received a similarities check: {:?}", msg);
                 verifier
-                    .check_similarities(msg.check.as_str(), msg.chain, None)
+                    .check_similarities(msg.check.as_str(), &msg.chain, None)
                     .await
                     .map(|violations| {
                         let outcome = if violations.is_empty() {
@@ -76,7 +76,14 @@ pub struct CheckDisplayName {
     pub chain: ChainName,
 }
 
-pub async fn check_display_name(req: web::Json<CheckDisplayName>) -> HttpResponse {
+pub async fn check_display_name(
+    req: web::Json<CheckDisplayName>,
+    networks: web::Data<Networks>,
+) -> HttpResponse {
+    if networks.get(&req.chain).is_none() {
+        return HttpResponse::Ok().json(JsonResult::<Outcome>::Err("Unknown network".to_string()));
+    }
+
     HttpResponse::Ok().json(
         DisplayNameChecker::from_registry()
             .send(req.into_inner())
@@ -1,6 +1,7 @@
 use super::JsonResult;
 use crate::database::Database;
 use crate::primitives::{IdentityContext, JudgementStateBlanked, NotificationMessage};
+use crate::Networks;
 use actix::prelude::*;
 use actix_broker::BrokerSubscribe;
 use actix_web_actors::ws;
@@ -124,7 +125,7 @@ impl Handler<SubscribeAccountState> for LookupServer {
                             .or_insert_with(|| vec![subscriber]);
                     }
                 } else {
-                    let _ = subscriber.do_send(JsonResult::Err(
+                    subscriber.do_send(JsonResult::Err(
                         "There is no judgement request from that account for this registrar"
                             .to_string(),
                     ));
@@ -170,8 +171,15 @@ impl Handler<NotifyAccountState> for LookupServer {
     }
 }
 
-#[derive(Default)]
-pub struct WsAccountStatusSession;
+pub struct WsAccountStatusSession {
+    networks: Networks,
+}
+
+impl WsAccountStatusSession {
+    pub fn new(networks: Networks) -> Self {
+        WsAccountStatusSession { networks }
+    }
+}
 
 impl Actor for WsAccountStatusSession {
     type Context = ws::WebsocketContext<Self>;
@@ -194,25 +202,38 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsAccountStatusSe
                     return;
                 }
 
-                if let Ok(context) = serde_json::from_slice::<IdentityContext>(msg.as_bytes()) {
-                    // Subscribe the the specified identity context.
-                    LookupServer::from_registry()
-                        .send(SubscribeAccountState {
-                            subscriber: ctx.address().recipient(),
-                            id_context: context,
-                        })
-                        .into_actor(self)
-                        .then(|_, _, _| fut::ready(()))
-                        .wait(ctx);
-                } else {
-                    // Invalid message type, inform caller.
-                    match serde_json::to_string(&JsonResult::<()>::Err(
-                        "Invalid message type".to_string(),
-                    )) {
-                        Ok(m) => ctx.text(m),
-                        Err(err) => {
-                            error!("Failed to serialize WS session message response: {:?}", err)
-                        }
+                let err_msg = match serde_json::from_slice::<IdentityContext>(msg.as_bytes()) {
+                    Ok(mut context) => match self.networks.get(&context.chain) {
+                        // Reject malformed addresses and normalize the address to the
+                        // canonical encoding of the network.
+                        Some(network) => match context.address.normalize(network.ss58_prefix) {
+                            Ok(address) => {
+                                context.address = address;
+
+                                // Subscribe the the specified identity context.
+                                LookupServer::from_registry()
+                                    .send(SubscribeAccountState {
+                                        subscriber: ctx.address().recipient(),
+                                        id_context: context,
+                                    })
+                                    .into_actor(self)
+                                    .then(|_, _, _| fut::ready(()))
+                                    .wait(ctx);
+
+                                return;
+                            }
+                            Err(_) => "Invalid address",
+                        },
+                        None => "Unknown network",
+                    },
+                    Err(_) => "Invalid message type",
+                };
+
+                // Invalid message, inform caller.
+                match serde_json::to_string(&JsonResult::<()>::Err(err_msg.to_string())) {
+                    Ok(m) => ctx.text(m),
+                    Err(err) => {
+                        error!("Failed to serialize WS session message response: {:?}", err)
                     }
                 }
             }
@@ -1,20 +1,27 @@
 use self::judgement_state::WsAccountStatusSession;
 use crate::database::Database;
-use crate::{NotifierConfig, Result};
+use crate::{LockoutPolicy, Networks, NotifierConfig, Result};
 use actix::prelude::*;
 use actix::registry::SystemRegistry;
 use actix_cors::Cors;
 use actix_web::{web, App, Error as ActixError, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
 use display_name_check::{check_display_name, DisplayNameChecker};
+use pgp_signature::verify_pgp_signature;
 use second_challenge::{verify_second_challenge, SecondChallengeVerifier};
 
 mod display_name_check;
 mod judgement_state;
+mod pgp_signature;
 mod second_challenge;
 
 // Reexport
-pub use self::judgement_state::{LookupServer, NotifyAccountState, ResponseAccountState};
+#[cfg(test)]
+pub use self::judgement_state::ResponseAccountState;
+pub use self::judgement_state::{LookupServer, NotifyAccountState};
+pub use self::pgp_signature::PgpSignatureVerifier;
+#[cfg(test)]
+pub use self::pgp_signature::VerifyPgpSignature;
 pub use self::second_challenge::VerifyChallenge;
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Message)]
@@ -29,15 +36,60 @@ async fn healthcheck() -> HttpResponse {
     HttpResponse::Ok().body("OK")
 }
 
+/// Reports the connection states of the Watchers of each configured network,
+/// as stored by the adapter listener. Fails if any network has no connected
+/// Watcher or if its state was not stored (recently).
+async fn watcher_healthcheck(
+    db: web::Data<Database>,
+    networks: web::Data<Networks>,
+) -> HttpResponse {
+    let mut stored = match db.fetch_watcher_status().await {
+        Ok(reports) => reports,
+        Err(err) => {
+            error!("Failed to fetch Watcher connection states: {:?}", err);
+            return HttpResponse::ServiceUnavailable().json(JsonResult::<()>::Err(
+                "Backend error, contact admin".to_string(),
+            ));
+        }
+    };
+
+    // Reports of networks which are no longer configured are ignored.
+    let mut healthy = true;
+    let mut reports = vec![];
+    for network in networks.iter() {
+        match stored
+            .iter()
+            .position(|report| report.network == network.name)
+        {
+            Some(index) => {
+                let report = stored.swap_remove(index);
+                healthy &= report.is_healthy();
+                reports.push(report);
+            }
+            None => healthy = false,
+        }
+    }
+
+    if healthy {
+        HttpResponse::Ok().json(JsonResult::Ok(reports))
+    } else {
+        HttpResponse::ServiceUnavailable().json(JsonResult::Ok(reports))
+    }
+}
+
 pub async fn run_rest_api_server(
     config: NotifierConfig,
     db: Database,
+    networks: Networks,
 ) -> Result<Addr<LookupServer>> {
     // Add configured actor to the registry.
+    let lockout = LockoutPolicy::new(config.lockout, &networks);
+
     let actor = LookupServer::new(db.clone()).start();
     SystemRegistry::set(actor.clone());
-    SystemRegistry::set(SecondChallengeVerifier::new(db.clone()).start());
-    SystemRegistry::set(DisplayNameChecker::new(db, config.display_name).start());
+    SystemRegistry::set(SecondChallengeVerifier::new(db.clone(), lockout.clone()).start());
+    SystemRegistry::set(PgpSignatureVerifier::new(db.clone(), config.pgp, lockout).start());
+    SystemRegistry::set(DisplayNameChecker::new(db.clone(), config.display_name).start());
 
     // Run the WS server.
     let server = HttpServer::new(move || {
@@ -45,12 +97,19 @@ pub async fn run_rest_api_server(
 
         App::new()
             .wrap(cors)
+            .app_data(web::Data::new(networks.clone()))
+            .app_data(web::Data::new(db.clone()))
             .route("/healthcheck", web::get().to(healthcheck))
+            .route("/healthcheck/watcher", web::get().to(watcher_healthcheck))
             .service(web::resource("/api/account_status").to(account_status_server_route))
             .route(
                 "/api/verify_second_challenge",
                 web::post().to(verify_second_challenge),
             )
+            .route(
+                "/api/verify_pgp_signature",
+                web::post().to(verify_pgp_signature),
+            )
             .route(
                 "/api/check_display_name",
                 web::post().to(check_display_name),
@@ -68,14 +127,20 @@ pub async fn run_rest_api_server(
 async fn account_status_server_route(
     req: HttpRequest,
     stream: web::Payload,
+    networks: web::Data<Networks>,
 ) -> std::result::Result<HttpResponse, ActixError> {
-    ws::start(WsAccountStatusSession::default(), &req, stream)
+    ws::start(
+        WsAccountStatusSession::new(networks.get_ref().clone()),
+        &req,
+        stream,
+    )
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
     use crate::database::Database;
+    use crate::pgp::tests::StubVerifier;
     use crate::DisplayNameConfig;
     use actix_test::{start, TestServer};
 
@@ -96,17 +161,29 @@ pub mod tests {
         let server = start(move || {
             // Add configured actor to the registry.
             SystemRegistry::set(t_actor.clone());
-            SystemRegistry::set(SecondChallengeVerifier::new(db.clone()).start());
+            SystemRegistry::set(
+                SecondChallengeVerifier::new(db.clone(), LockoutPolicy::default()).start(),
+            );
+            SystemRegistry::set(
+                PgpSignatureVerifier::with_verifier(db.clone(), StubVerifier, 4).start(),
+            );
             SystemRegistry::set(
                 DisplayNameChecker::new(db.clone(), DisplayNameConfig::default()).start(),
             );
 
             App::new()
+                .app_data(web::Data::new(Networks::default()))
+                .app_data(web::Data::new(db.clone()))
+                .route("/healthcheck/watcher", web::get().to(watcher_healthcheck))
                 .service(web::resource("/api/account_status").to(account_status_server_route))
                 .route(
                     "/api/verify_second_challenge",
                     web::post().to(verify_second_challenge),
                 )
+                .route(
+                    "/api/verify_pgp_signature",
+                    web::post().to(verify_pgp_signature),
+                )
                 .route(
                     "/api/check_display_name",
                     web::post().to(check_display_name),
@@ -0,0 +1,131 @@
+use super::JsonResult;
+use crate::database::Database;
+use crate::pgp::{GpgVerifier, SignatureVerifier};
+use crate::{LockoutPolicy, PgpConfig};
+use actix::prelude::*;
+use actix_web::{web, HttpResponse};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+pub struct PgpSignatureVerifier {
+    db: Database,
+    lockout: LockoutPolicy,
+    // `None` if PGP verification is disabled.
+    verifier: Option<Arc<dyn SignatureVerifier + Send + Sync>>,
+    // Limits the number of concurrent verifications (`gpg` processes).
+    permits: Arc<Semaphore>,
+}
+
+impl Default for PgpSignatureVerifier {
+    fn default() -> Self {
+        panic!("PgpSignatureVerifier is not initialized");
+    }
+}
+
+impl PgpSignatureVerifier {
+    pub fn new(db: Database, config: PgpConfig, lockout: LockoutPolicy) -> Self {
+        let verifier: Option<Arc<dyn SignatureVerifier + Send + Sync>> = if config.enabled {
+            Some(Arc::new(GpgVerifier::new(config.keyserver)))
+        } else {
+            None
+        };
+
+        PgpSignatureVerifier {
+            db,
+            lockout,
+            verifier,
+            permits: Arc::new(Semaphore::new(config.max_concurrent_verifications)),
+        }
+    }
+    #[cfg(test)]
+    pub fn with_verifier<T>(db: Database, verifier: T, max_concurrent: usize) -> Self
+    where
+        T: 'static + SignatureVerifier + Send + Sync,
+    {
+        PgpSignatureVerifier {
+            db,
+            lockout: LockoutPolicy::default(),
+            verifier: Some(Arc::new(verifier)),
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+        }
+    }
+}
+
+impl SystemService for PgpSignatureVerifier {}
+impl Supervised for PgpSignatureVerifier {}
+
+impl Actor for PgpSignatureVerifier {
+    type Context = Context<Self>;
+}
+
+impl Handler<VerifyPgpSignature> for PgpSignatureVerifier {
+    type Result = ResponseActFuture<Self, JsonResult<bool>>;
+
+    fn handle(&mut self, msg: VerifyPgpSignature, _ctx: &mut Self::Context) -> Self::Result {
+        let db = self.db.clone();
+        let lockout = self.lockout.clone();
+        let verifier = self.verifier.clone();
+        let permits = Arc::clone(&self.permits);
+
+        Box::pin(
+            async move {
+                debug!("Received signed message: {:?}", msg);
+
+                let verifier = match verifier {
+                    Some(verifier) => verifier,
+                    None => return JsonResult::Err("PGP verification is disabled".to_string()),
+                };
+
+                let permit = match permits.try_acquire() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        return JsonResult::Err(
+                            "Too many signatures are being verified, try again later".to_string(),
+                        )
+                    }
+                };
+
+                let signed = verifier.verify(&msg.signed_message).await;
+                drop(permit);
+
+                let signed = match signed {
+                    Ok(signed) => signed,
+                    Err(err) => {
+                        debug!("Failed to verify PGP signature: {:?}", err);
+                        return JsonResult::Err(
+                            "Invalid signature or unknown public key".to_string(),
+                        );
+                    }
+                };
+
+                db.verify_signed_message(&signed.fingerprint, &signed.text, &lockout)
+                    .await
+                    .map(JsonResult::Ok)
+                    .unwrap_or_else(|_| JsonResult::Err("Backend error, contact admin".to_string()))
+            }
+            .into_actor(self),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Message)]
+#[rtype(result = "JsonResult<bool>")]
+pub struct VerifyPgpSignature {
+    /// An OpenPGP clearsigned message which contains the challenge.
+    pub signed_message: String,
+}
+
+pub async fn verify_pgp_signature(req: web::Json<VerifyPgpSignature>) -> HttpResponse {
+    let result = PgpSignatureVerifier::from_registry()
+        .send(req.into_inner())
+        .await
+        .unwrap_or_else(|err| {
+            error!(
+                "Failed to send message to PGP signature verifier: {:?}",
+                err
+            );
+            JsonResult::Err("Backend error, contact admin".to_string())
+        });
+
+    HttpResponse::Ok().json(result)
+}
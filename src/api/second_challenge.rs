@@ -1,11 +1,13 @@
 use super::JsonResult;
 use crate::database::Database;
 use crate::primitives::IdentityFieldValue;
+use crate::LockoutPolicy;
 use actix::prelude::*;
 use actix_web::{web, HttpResponse};
 
 pub struct SecondChallengeVerifier {
     db: Database,
+    lockout: LockoutPolicy,
 }
 
 impl Default for SecondChallengeVerifier {
@@ -15,8 +17,8 @@ impl Default for SecondChallengeVerifier {
 }
 
 impl SecondChallengeVerifier {
-    pub fn new(db: Database) -> Self {
-        SecondChallengeVerifier { db }
+    pub fn new(db: Database, lockout: LockoutPolicy) -> Self {
+        SecondChallengeVerifier { db, lockout }
     }
 }
 
@@ -32,11 +34,12 @@ impl Handler<VerifyChallenge> for SecondChallengeVerifier {
 
     fn handle(&mut self, msg: VerifyChallenge, _ctx: &mut Self::Context) -> Self::Result {
         let db = self.db.clone();
+        let lockout = self.lockout.clone();
 
         Box::pin(
             async move {
                 debug!("Received second challenge: {:?}", msg);
-                db.verify_second_challenge(msg)
+                db.verify_second_challenge(msg, &lockout)
                     .await
                     .map(JsonResult::Ok)
                     .unwrap_or_else(|_| JsonResult::Err("Backend error, contact admin".to_string()))
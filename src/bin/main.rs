@@ -1,4 +1,4 @@
-use system::{run, Result};
+use system::{erase, export, import, migrate, mock_watcher, run, Result};
 use tracing::Level;
 
 #[actix::main]
@@ -8,8 +8,23 @@ async fn main() -> Result<()> {
         .with_env_filter("system")
         .init();
 
-    tracing::info!("Starting registrar service");
+    let args: Vec<String> = std::env::args().collect();
 
-    run().await?;
-    unreachable!()
+    match args.get(1).map(|cmd| cmd.as_str()) {
+        None => {
+            tracing::info!("Starting registrar service");
+
+            run().await?;
+            unreachable!()
+        }
+        Some("migrate") => migrate().await,
+        Some("export") => export(&args[2..]).await,
+        Some("import") => import(&args[2..]).await,
+        Some("erase") => erase(&args[2..]).await,
+        Some("mock-watcher") => mock_watcher(&args[2..]).await,
+        Some(cmd) => Err(anyhow::anyhow!(
+            "Unknown command '{}', expected no command, 'migrate', 'export', 'import', 'erase' or 'mock-watcher'",
+            cmd
+        )),
+    }
 }
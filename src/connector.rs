@@ -1,8 +1,12 @@
 use crate::display_name::DisplayNameVerifier;
+use crate::pgp::normalize_fingerprint;
 use crate::primitives::{
     ChainAddress, ChainName, IdentityContext, IdentityFieldValue, JudgementState, Timestamp,
 };
-use crate::{Database, DisplayNameConfig, Result, WatcherConfig};
+use crate::watcher_auth::WatcherLink;
+use crate::{
+    ChallengeConfig, Database, DisplayNameConfig, JudgementConfig, LockoutConfig, Networks, Result,
+};
 use actix::io::SinkWrite;
 use actix::io::WriteHandler;
 use actix::prelude::*;
@@ -10,26 +14,95 @@ use actix_codec::Framed;
 use awc::{
     error::WsProtocolError,
     ws::{Codec, Frame, Message},
-    BoxedSocket, Client,
+    BoxedSocket,
 };
 use futures::stream::{SplitSink, StreamExt};
-use std::collections::HashMap;
+use rand::{thread_rng, Rng};
+use serde::de::IgnoredAny;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::{self, UnboundedSender};
 use tokio::sync::RwLock;
-use tokio::time::sleep;
+use tokio::time::{interval, sleep};
 use tracing::Instrument;
 
 // In seconds
 const HEARTBEAT_INTERVAL: u64 = 30;
+// The connection is reset if nothing (including pongs) was received from the
+// Watcher for this long.
+const HEARTBEAT_TIMEOUT: u64 = HEARTBEAT_INTERVAL * 2;
 #[cfg(not(test))]
 const PENDING_JUDGEMENTS_INTERVAL: u64 = 10;
 #[cfg(not(test))]
 const DISPLAY_NAMES_INTERVAL: u64 = 10;
 #[cfg(not(test))]
 const JUDGEMENT_CANDIDATES_INTERVAL: u64 = 10;
-const RECONNECTION_TIMEOUT: u64 = 10;
+#[cfg(not(test))]
+const EXPIRED_CHALLENGES_INTERVAL: u64 = 60;
+// Failed attempts are checked when they happen, the background task only
+// catches up on configuration changes.
+#[cfg(not(test))]
+const LOCKOUT_POLICY_INTERVAL: u64 = 60;
+// How often connections check whether they are the active one of their
+// network.
+#[cfg(not(test))]
+const FAILOVER_INTERVAL: u64 = 5;
+// How often the adapter listener stores the connection states of the
+// Watchers, so the API of any instance can report those.
+#[cfg(not(test))]
+const STATUS_REPORT_INTERVAL: u64 = 10;
+// The delay between reconnection attempts doubles with every failed attempt,
+// starting at the base delay.
+const RECONNECTION_BASE_DELAY: u64 = 1;
+const RECONNECTION_MAX_DELAY: u64 = 300;
+// Failed reconnection attempts are reported as errors from this point on.
+const RECONNECTION_ALERT_ATTEMPTS: u32 = 10;
+// Watchers which do not respond to the handshake in time are assumed to speak
+// the protocol from before the handshake was introduced (version 0).
+const HANDSHAKE_TIMEOUT: u64 = 10;
+
+/// The version of the Watcher protocol implemented by the connector. Version
+/// 0 is the protocol from before the handshake was introduced.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// The oldest version of the Watcher protocol which is still supported.
+pub const MIN_PROTOCOL_VERSION: u32 = 0;
+
+// The capabilities of the Watcher protocol. Messages which require a
+// capability that was not negotiated are not exchanged.
+pub const CAP_JUDGEMENT_REQUESTS: &str = "judgementRequests";
+pub const CAP_JUDGEMENT_RESULTS: &str = "judgementResults";
+pub const CAP_DISPLAY_NAMES: &str = "displayNames";
+pub const CAP_JUDGEMENT_CANCELLATIONS: &str = "judgementCancellations";
+const CAPABILITIES: &[&str] = &[
+    CAP_JUDGEMENT_REQUESTS,
+    CAP_JUDGEMENT_RESULTS,
+    CAP_DISPLAY_NAMES,
+    CAP_JUDGEMENT_CANCELLATIONS,
+];
+// The capabilities of Watchers which predate the handshake.
+const LEGACY_CAPABILITIES: &[&str] = &[
+    CAP_JUDGEMENT_REQUESTS,
+    CAP_JUDGEMENT_RESULTS,
+    CAP_DISPLAY_NAMES,
+];
+
+// Display names and judgement requests which are no longer reported by the
+// Watcher are only removed if at most this many (or this share) of the stored
+// entries are affected. A partial response of the Watcher must not wipe the
+// stored entries.
+const MAX_STALE_ENTRIES: usize = 10;
+const MAX_STALE_ENTRIES_RATIO: f64 = 0.1;
+// Stale entries beyond those limits are removed once the Watcher reported the
+// same entries as stale on this many consecutive syncs.
+const STALE_ENTRIES_CONFIRMATIONS: usize = 3;
+// In seconds. Judgement requests which were received recently might not be
+// included in the pending judgements of the Watcher yet.
+const CANCELLATION_GRACE_PERIOD: u64 = 60;
+// In seconds. Stored connection states which were not refreshed for this long
+// are outdated, e.g. because the adapter listener is down.
+const STATUS_REPORT_MAX_AGE: u64 = STATUS_REPORT_INTERVAL * 6;
 
 #[cfg(test)]
 const PENDING_JUDGEMENTS_INTERVAL: u64 = 1;
@@ -37,45 +110,454 @@ const PENDING_JUDGEMENTS_INTERVAL: u64 = 1;
 const DISPLAY_NAMES_INTERVAL: u64 = 1;
 #[cfg(test)]
 const JUDGEMENT_CANDIDATES_INTERVAL: u64 = 1;
+#[cfg(test)]
+const EXPIRED_CHALLENGES_INTERVAL: u64 = 1;
+#[cfg(test)]
+const LOCKOUT_POLICY_INTERVAL: u64 = 1;
+#[cfg(test)]
+const FAILOVER_INTERVAL: u64 = 1;
+#[cfg(test)]
+const STATUS_REPORT_INTERVAL: u64 = 1;
 
 pub async fn run_connector(
     db: Database,
-    watchers: Vec<WatcherConfig>,
+    networks: Networks,
     dn_config: DisplayNameConfig,
+    judgement_config: JudgementConfig,
+    challenge_config: ChallengeConfig,
+    lockout_config: LockoutConfig,
+    status: WatcherStatus,
 ) -> Result<()> {
-    if watchers.is_empty() {
-        warn!("No watcher is configured. Cannot process any requests or issue judgments");
+    if networks.iter().next().is_none() {
+        warn!("No network is configured. Cannot process any requests or issue judgments");
         return Ok(());
     }
 
-    for config in watchers {
-        let span = info_span!("connector_initialization");
-        span.in_scope(|| {
-            debug!(
-                network = config.network.as_str(),
-                endpoint = config.endpoint.as_str()
-            );
-        });
+    for config in networks.iter() {
+        if config.endpoints.is_empty() {
+            return Err(anyhow!(
+                "No Watcher endpoint configured for network {}",
+                config.name.as_str()
+            ));
+        }
 
-        async {
-            // Start Connector.
-            let dn_verifier = DisplayNameVerifier::new(db.clone(), dn_config.clone());
-            let conn =
-                Connector::start(config.endpoint, config.network, db.clone(), dn_verifier).await?;
+        let link = WatcherLink::new(config.watcher_auth.as_ref(), config.watcher_tls.as_ref())
+            .map_err(|err| {
+                anyhow!(
+                    "Invalid Watcher settings of network {}: {:?}",
+                    config.name.as_str(),
+                    err
+                )
+            })?;
+
+        // Connect to all Watchers of the network, the first connected one (in
+        // order of preference) becomes the active connection.
+        status.register(&config.name, &config.endpoints).await;
+
+        let mut connected = false;
+        for endpoint in &config.endpoints {
+            let span = info_span!("connector_initialization");
+            span.in_scope(|| {
+                debug!(network = config.name.as_str(), endpoint = endpoint.as_str());
 
-            info!("Connection initiated");
-            info!("Sending pending judgements request to Watcher");
-            let _ = conn.send(ClientCommand::RequestPendingJudgements).await?;
+                if link.sends_credentials() && !endpoint.starts_with("wss://") {
+                    warn!("Credentials are sent to the Watcher without encryption");
+                }
+            });
+
+            // Network specific policies take precedence.
+            let setup = ConnectorSetup {
+                endpoint: endpoint.clone(),
+                network: config.name.clone(),
+                ss58_prefix: config.ss58_prefix,
+                db: db.clone(),
+                dn_verifier: DisplayNameVerifier::new(db.clone(), dn_config.clone()),
+                judgement_config: config
+                    .judgement
+                    .clone()
+                    .unwrap_or_else(|| judgement_config.clone()),
+                challenge_config: config
+                    .challenge
+                    .clone()
+                    .unwrap_or_else(|| challenge_config.clone()),
+                lockout_config: config
+                    .lockout
+                    .clone()
+                    .unwrap_or_else(|| lockout_config.clone()),
+                status: status.clone(),
+                link: link.clone(),
+            };
+
+            // Start Connector. Unavailable Watchers are retried in the
+            // background, as long as any Watcher of the network is available.
+            match Connector::start(setup.clone())
+                .instrument(span.clone())
+                .await
+            {
+                Ok(_) => {
+                    span.in_scope(|| info!("Connection initiated"));
+                    connected = true;
+                }
+                Err(err) => {
+                    span.in_scope(|| warn!("Failed to connect to Watcher: {:?}", err));
+                    actix::spawn(reconnect(setup, None).instrument(span));
+                }
+            }
+        }
 
-            Result::Ok(())
+        if !connected {
+            return Err(anyhow!(
+                "Failed to connect to any Watcher of network {}",
+                config.name.as_str()
+            ));
         }
-        .instrument(span)
-        .await?;
     }
 
+    start_status_report_task(db, status);
+
     Ok(())
 }
 
+/// Periodically stores the connection states of the Watchers, those are
+/// reported by the API of the session notifier.
+fn start_status_report_task(db: Database, status: WatcherStatus) {
+    actix::spawn(async move {
+        let mut interval = interval(Duration::from_secs(STATUS_REPORT_INTERVAL));
+
+        loop {
+            interval.tick().await;
+
+            for (network, state) in status.states().await {
+                let report = WatcherStatusReport {
+                    network,
+                    state,
+                    reported_at: Timestamp::now(),
+                };
+
+                if let Err(err) = db.store_watcher_status(&report).await {
+                    error!("Failed to store Watcher connection state: {:?}", err);
+                }
+            }
+        }
+    });
+}
+
+/// Tries to reconnect to the Watcher until it succeeds. Refused Watchers are
+/// only retried after the maximum delay, it probably takes a while until those
+/// get upgraded.
+async fn reconnect(setup: ConnectorSetup, refused: Option<String>) {
+    let status = setup.status.clone();
+    let network = setup.network.clone();
+    let endpoint = setup.endpoint.clone();
+
+    if let Some(reason) = refused {
+        status.set_refused(&network, &endpoint, reason).await;
+        sleep(Duration::from_secs(RECONNECTION_MAX_DELAY)).await;
+    }
+
+    let down_since = Timestamp::now();
+    let mut failed_attempts = 0;
+    loop {
+        status
+            .set_reconnecting(&network, &endpoint, down_since, failed_attempts)
+            .await;
+
+        let delay = reconnection_delay(failed_attempts, thread_rng().gen());
+        sleep(delay).await;
+
+        if let Err(err) = Connector::start(setup.clone()).await {
+            failed_attempts += 1;
+
+            if failed_attempts >= RECONNECTION_ALERT_ATTEMPTS {
+                error!(
+                    "Cannot reconnect to Watcher after {} attempts, down for {} seconds: {:?}",
+                    failed_attempts,
+                    Timestamp::now().raw() - down_since.raw(),
+                    err
+                );
+            } else {
+                warn!("Reconnection failed, retrying: {:?}", err);
+            }
+        } else {
+            info!(
+                "Reconnected to Watcher after {} seconds",
+                Timestamp::now().raw() - down_since.raw()
+            );
+            break;
+        }
+    }
+}
+
+/// The delay before the next reconnection attempt. Grows exponentially with
+/// the number of failed attempts. The jitter (between 0 and 1) spreads the
+/// attempts of several instances, at least half of the delay is kept.
+pub fn reconnection_delay(failed_attempts: u32, jitter: f64) -> Duration {
+    let delay = RECONNECTION_BASE_DELAY
+        .saturating_mul(2u64.saturating_pow(failed_attempts))
+        .min(RECONNECTION_MAX_DELAY);
+
+    Duration::from_secs_f64(delay as f64 * (0.5 + jitter.clamp(0.0, 1.0) / 2.0))
+}
+
+/// The state of the connection to the Watcher of a network.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum ConnectionState {
+    Connected {
+        since: Timestamp,
+    },
+    Reconnecting {
+        down_since: Timestamp,
+        failed_attempts: u32,
+    },
+    // The Watcher speaks an incompatible protocol version.
+    Refused {
+        since: Timestamp,
+        reason: String,
+    },
+}
+
+impl ConnectionState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected { .. })
+    }
+}
+
+/// The connection state of a single Watcher of a network.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EndpointState {
+    pub endpoint: String,
+    #[serde(flatten)]
+    pub state: ConnectionState,
+}
+
+/// The connection states of the Watchers of a network.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NetworkState {
+    // The first connected endpoint, in order of preference. Only the active
+    // connection requests data from and provides judgements to its Watcher,
+    // the others are on standby.
+    pub active: Option<String>,
+    // In order of preference.
+    pub endpoints: Vec<EndpointState>,
+}
+
+impl NetworkState {
+    pub fn is_connected(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+fn active_endpoint(endpoints: &[EndpointState]) -> Option<&str> {
+    endpoints
+        .iter()
+        .find(|entry| entry.state.is_connected())
+        .map(|entry| entry.endpoint.as_str())
+}
+
+/// The connection states of all networks. Shared between the Connectors and
+/// stored periodically for the health check of the API.
+#[derive(Debug, Clone, Default)]
+pub struct WatcherStatus {
+    // The endpoints of each network, in order of preference.
+    states: Arc<RwLock<HashMap<ChainName, Vec<EndpointState>>>>,
+}
+
+impl WatcherStatus {
+    /// Registers the endpoints of the network in order of preference. Those
+    /// are reported as reconnecting until connected. Endpoints which are not
+    /// registered are appended on first use.
+    pub async fn register(&self, network: &ChainName, endpoints: &[String]) {
+        let mut states = self.states.write().await;
+        let entries = states.entry(network.clone()).or_default();
+
+        for endpoint in endpoints {
+            if !entries.iter().any(|entry| &entry.endpoint == endpoint) {
+                entries.push(EndpointState {
+                    endpoint: endpoint.clone(),
+                    state: ConnectionState::Reconnecting {
+                        down_since: Timestamp::now(),
+                        failed_attempts: 0,
+                    },
+                });
+            }
+        }
+    }
+    async fn set(&self, network: &ChainName, endpoint: &str, state: ConnectionState) {
+        let mut states = self.states.write().await;
+        let entries = states.entry(network.clone()).or_default();
+
+        match entries.iter_mut().find(|entry| entry.endpoint == endpoint) {
+            Some(entry) => entry.state = state,
+            None => entries.push(EndpointState {
+                endpoint: endpoint.to_string(),
+                state,
+            }),
+        }
+    }
+    pub async fn set_connected(&self, network: &ChainName, endpoint: &str) {
+        self.set(
+            network,
+            endpoint,
+            ConnectionState::Connected {
+                since: Timestamp::now(),
+            },
+        )
+        .await;
+    }
+    pub async fn set_reconnecting(
+        &self,
+        network: &ChainName,
+        endpoint: &str,
+        down_since: Timestamp,
+        failed_attempts: u32,
+    ) {
+        self.set(
+            network,
+            endpoint,
+            ConnectionState::Reconnecting {
+                down_since,
+                failed_attempts,
+            },
+        )
+        .await;
+    }
+    pub async fn set_refused(&self, network: &ChainName, endpoint: &str, reason: String) {
+        self.set(
+            network,
+            endpoint,
+            ConnectionState::Refused {
+                since: Timestamp::now(),
+                reason,
+            },
+        )
+        .await;
+    }
+    /// Whether the endpoint is the active one of the network.
+    pub async fn is_active(&self, network: &ChainName, endpoint: &str) -> bool {
+        self.states
+            .read()
+            .await
+            .get(network)
+            .and_then(|entries| active_endpoint(entries))
+            == Some(endpoint)
+    }
+    pub async fn states(&self) -> HashMap<ChainName, NetworkState> {
+        self.states
+            .read()
+            .await
+            .iter()
+            .map(|(network, entries)| {
+                (
+                    network.clone(),
+                    NetworkState {
+                        active: active_endpoint(entries).map(|endpoint| endpoint.to_string()),
+                        endpoints: entries.clone(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// The connection states of the Watchers of a network, as stored by the
+/// adapter listener.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WatcherStatusReport {
+    pub network: ChainName,
+    pub state: NetworkState,
+    pub reported_at: Timestamp,
+}
+
+impl WatcherStatusReport {
+    /// Whether the report was not refreshed in time, the state might have
+    /// changed since.
+    pub fn is_outdated(&self) -> bool {
+        Timestamp::now()
+            .raw()
+            .saturating_sub(self.reported_at.raw())
+            > STATUS_REPORT_MAX_AGE
+    }
+    pub fn is_healthy(&self) -> bool {
+        !self.is_outdated() && self.state.is_connected()
+    }
+}
+
+/// Exchanged by both sides when connecting. Each side sends the protocol
+/// versions and capabilities it supports.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Handshake {
+    pub version: u32,
+    pub min_version: u32,
+    // Unknown capabilities are ignored.
+    pub capabilities: Vec<String>,
+}
+
+impl Handshake {
+    /// The handshake of this registrar.
+    pub fn local() -> Self {
+        Handshake {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|cap| cap.to_string()).collect(),
+        }
+    }
+    /// The implicit handshake of Watchers which predate the handshake.
+    pub fn legacy() -> Self {
+        Handshake {
+            version: 0,
+            min_version: 0,
+            capabilities: LEGACY_CAPABILITIES
+                .iter()
+                .map(|cap| cap.to_string())
+                .collect(),
+        }
+    }
+    /// Picks the highest version supported by both sides and the capabilities
+    /// both sides support.
+    pub fn negotiate(&self, remote: &Handshake) -> Result<Protocol> {
+        if remote.version < self.min_version {
+            return Err(anyhow!(
+                "Watcher protocol version {} is not supported, the minimum version is {}",
+                remote.version,
+                self.min_version
+            ));
+        }
+
+        if self.version < remote.min_version {
+            return Err(anyhow!(
+                "Watcher requires protocol version {} or newer, the registrar supports up to version {}",
+                remote.min_version,
+                self.version
+            ));
+        }
+
+        Ok(Protocol {
+            version: self.version.min(remote.version),
+            capabilities: self
+                .capabilities
+                .iter()
+                .filter(|cap| remote.capabilities.contains(cap))
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+/// The protocol negotiated with the Watcher.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Protocol {
+    pub version: u32,
+    pub capabilities: HashSet<String>,
+}
+
+impl Protocol {
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ResponseMessage<T> {
     pub event: EventType,
@@ -100,9 +582,13 @@ pub enum EventType {
     DisplayNamesRequest,
     #[serde(rename = "displayNamesResponse")]
     DisplayNamesResponse,
+    #[serde(rename = "handshake")]
+    Handshake,
+    #[serde(rename = "judgementRequestCancelled")]
+    JudgementRequestCancelled,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct JudgementResponse {
     pub address: ChainAddress,
     pub judgement: Judgement,
@@ -110,25 +596,110 @@ pub struct JudgementResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AckResponse {
-    result: String,
-    address: Option<ChainAddress>,
+    pub result: String,
+    pub address: Option<ChainAddress>,
 }
 
+/// An error reported by the Watcher. Errors which refer to a judgement contain
+/// the address of the identity.
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    #[serde(alias = "message")]
+    pub result: String,
+    pub address: Option<ChainAddress>,
+}
+
+/// The judgements the registrar can issue. `Unknown` and `FeePaid` are
+/// reserved by the chain and can not be provided by a registrar.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Judgement {
     #[serde(rename = "reasonable")]
     Reasonable,
+    #[serde(rename = "knownGood")]
+    KnownGood,
+    #[serde(rename = "outOfDate")]
+    OutOfDate,
+    #[serde(rename = "lowQuality")]
+    LowQuality,
     #[serde(rename = "erroneous")]
     Erroneous,
 }
 
+impl Judgement {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Judgement::Reasonable => "reasonable",
+            Judgement::KnownGood => "knownGood",
+            Judgement::OutOfDate => "outOfDate",
+            Judgement::LowQuality => "lowQuality",
+            Judgement::Erroneous => "erroneous",
+        }
+    }
+}
+
+/// Why a judgement request was removed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CancellationReason {
+    // The user cancelled the request on chain.
+    #[serde(rename = "requestCancelled")]
+    RequestCancelled,
+    // The user cleared the identity on chain.
+    #[serde(rename = "identityCleared")]
+    IdentityCleared,
+    // The request was no longer included in the pending judgements of the
+    // Watcher. Only set by the registrar.
+    #[serde(rename = "noLongerPending")]
+    NoLongerPending,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JudgementCancellation {
+    pub address: ChainAddress,
+    pub reason: CancellationReason,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JudgementRequest {
     pub address: ChainAddress,
     pub accounts: HashMap<AccountType, String>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+/// The request as sent by the Watcher. Then converted into `JudgementRequest`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JudgementRequestRaw {
+    pub address: ChainAddress,
+    pub accounts: HashMap<AccountType, serde_json::Value>,
+}
+
+impl JudgementRequestRaw {
+    /// Converts the request, skipping fields which do not have a string value.
+    /// Returns the names of the skipped fields.
+    pub fn into_request(self) -> (JudgementRequest, Vec<String>) {
+        let mut accounts = HashMap::new();
+        let mut skipped = vec![];
+
+        for (ty, value) in self.accounts {
+            match value {
+                serde_json::Value::String(value) => {
+                    accounts.insert(ty, value);
+                }
+                _ => skipped.push(ty.as_str().to_string()),
+            }
+        }
+
+        skipped.sort();
+
+        (
+            JudgementRequest {
+                address: self.address,
+                accounts,
+            },
+            skipped,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct DisplayNameEntry {
     pub context: IdentityContext,
     pub display_name: String,
@@ -164,19 +735,81 @@ fn try_decode_hex(display_name: &mut String) {
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "crate::Result<()>")]
 pub enum WatcherMessage {
+    Handshake(Handshake),
     Ack(AckResponse),
+    Error(ErrorResponse),
     NewJudgementRequest(JudgementRequest),
     PendingJudgementsRequests(Vec<JudgementRequest>),
     ActiveDisplayNames(Vec<DisplayNameEntryRaw>),
+    JudgementRequestCancelled(JudgementCancellation),
+}
+
+impl WatcherMessage {
+    /// The capability which must be negotiated to accept the message.
+    fn capability(&self) -> Option<&'static str> {
+        match self {
+            WatcherMessage::Handshake(_) | WatcherMessage::Error(_) => None,
+            WatcherMessage::Ack(_) => Some(CAP_JUDGEMENT_RESULTS),
+            WatcherMessage::NewJudgementRequest(_)
+            | WatcherMessage::PendingJudgementsRequests(_) => Some(CAP_JUDGEMENT_REQUESTS),
+            WatcherMessage::ActiveDisplayNames(_) => Some(CAP_DISPLAY_NAMES),
+            WatcherMessage::JudgementRequestCancelled(_) => Some(CAP_JUDGEMENT_CANCELLATIONS),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "crate::Result<()>")]
 pub enum ClientCommand {
-    ProvideJudgement(IdentityContext),
+    Handshake(Handshake),
+    ProvideJudgement(IdentityContext, Judgement),
     RequestPendingJudgements,
     RequestDisplayNames,
     Ping,
+    Error(String),
+    // Reports a judgement request (or some of its fields) which could not be
+    // processed.
+    RequestError(ErrorResponse),
+}
+
+impl ClientCommand {
+    /// The capability which must be negotiated to send the command.
+    fn capability(&self) -> Option<&'static str> {
+        match self {
+            ClientCommand::ProvideJudgement(_, _) => Some(CAP_JUDGEMENT_RESULTS),
+            ClientCommand::RequestPendingJudgements => Some(CAP_JUDGEMENT_REQUESTS),
+            ClientCommand::RequestDisplayNames => Some(CAP_DISPLAY_NAMES),
+            ClientCommand::Handshake(_)
+            | ClientCommand::Ping
+            | ClientCommand::Error(_)
+            | ClientCommand::RequestError(_) => None,
+        }
+    }
+    /// Whether the command is only sent by the active connection of the
+    /// network.
+    fn requires_active(&self) -> bool {
+        matches!(
+            self,
+            ClientCommand::ProvideJudgement(_, _)
+                | ClientCommand::RequestPendingJudgements
+                | ClientCommand::RequestDisplayNames
+        )
+    }
+}
+
+/// Everything required to (re-)connect to a Watcher.
+#[derive(Clone)]
+struct ConnectorSetup {
+    endpoint: String,
+    network: ChainName,
+    ss58_prefix: u16,
+    db: Database,
+    dn_verifier: DisplayNameVerifier,
+    judgement_config: JudgementConfig,
+    challenge_config: ChallengeConfig,
+    lockout_config: LockoutConfig,
+    status: WatcherStatus,
+    link: WatcherLink,
 }
 
 /// Handles incoming and outgoing websocket messages to and from the Watcher.
@@ -185,23 +818,49 @@ struct Connector {
     sink: Option<SinkWrite<Message, SplitSink<Framed<BoxedSocket, Codec>, Message>>>,
     db: Database,
     dn_verifier: DisplayNameVerifier,
+    judgement_config: JudgementConfig,
+    challenge_config: ChallengeConfig,
+    lockout_config: LockoutConfig,
     endpoint: String,
     network: ChainName,
+    // Addresses received from the Watcher are normalized to this prefix.
+    ss58_prefix: u16,
     outgoing: UnboundedSender<ClientCommand>,
     inserted_states: Arc<RwLock<Vec<JudgementState>>>,
+    stale_requests: Arc<RwLock<StaleEntries<IdentityContext>>>,
+    stale_display_names: Arc<RwLock<StaleEntries<DisplayNameEntry>>>,
     // Tracks the last message received from the Watcher. If a certain treshold
     // was exceeded, the Connector attempts to reconnect.
     last_watcher_msg: Timestamp,
+    status: WatcherStatus,
+    // The protocol negotiated with the Watcher. Commands which require a
+    // capability are queued until the handshake is completed.
+    protocol: Option<Protocol>,
+    queued: Vec<ClientCommand>,
+    // Set if the Watcher was refused because of an incompatible protocol.
+    refused: Option<String>,
+    // Whether this is the active connection of the network, see
+    // `NetworkState`.
+    active: bool,
+    link: WatcherLink,
 }
 
 impl Connector {
-    async fn start(
-        endpoint: String,
-        network: ChainName,
-        db: Database,
-        dn_verifier: DisplayNameVerifier,
-    ) -> Result<Addr<Connector>> {
-        let (_, framed) = Client::new()
+    async fn start(setup: ConnectorSetup) -> Result<Addr<Connector>> {
+        let ConnectorSetup {
+            endpoint,
+            network,
+            ss58_prefix,
+            db,
+            dn_verifier,
+            judgement_config,
+            challenge_config,
+            lockout_config,
+            status,
+            link,
+        } = setup;
+
+        let (_, framed) = link
             .ws(&endpoint)
             .max_frame_size(5_000_000)
             .connect()
@@ -217,6 +876,8 @@ impl Connector {
         // Create throw-away channels (`outgoing` in `Connector` is only used in tests.)
         let (outgoing, _recv) = mpsc::unbounded_channel();
 
+        status.set_connected(&network, &endpoint).await;
+
         // Start the Connector actor with the attached websocket stream.
         let (sink, stream) = framed.split();
         let actor = Connector::create(|ctx| {
@@ -225,18 +886,96 @@ impl Connector {
                 sink: Some(SinkWrite::new(sink, ctx)),
                 db,
                 dn_verifier,
+                judgement_config,
+                challenge_config,
+                lockout_config,
                 endpoint,
                 network,
+                ss58_prefix,
                 outgoing,
                 inserted_states: Default::default(),
+                stale_requests: Default::default(),
+                stale_display_names: Default::default(),
                 last_watcher_msg: Timestamp::now(),
+                status,
+                protocol: None,
+                queued: vec![],
+                refused: None,
+                active: false,
+                link,
             }
         });
 
         Ok(actor)
     }
-    // Send a heartbeat to the Watcher every couple of seconds.
-    #[allow(unused)]
+    fn setup(&self) -> ConnectorSetup {
+        ConnectorSetup {
+            endpoint: self.endpoint.clone(),
+            network: self.network.clone(),
+            ss58_prefix: self.ss58_prefix,
+            db: self.db.clone(),
+            dn_verifier: self.dn_verifier.clone(),
+            judgement_config: self.judgement_config.clone(),
+            challenge_config: self.challenge_config.clone(),
+            lockout_config: self.lockout_config.clone(),
+            status: self.status.clone(),
+            link: self.link.clone(),
+        }
+    }
+    // Send the handshake to the Watcher. If the Watcher does not respond in
+    // time, it is assumed to predate the handshake.
+    fn start_handshake(&self, ctx: &mut Context<Self>) {
+        if self.protocol.is_some() {
+            return;
+        }
+
+        info!("Sending handshake to Watcher");
+        ctx.address()
+            .do_send(ClientCommand::Handshake(Handshake::local()));
+
+        ctx.run_later(Duration::new(HANDSHAKE_TIMEOUT, 0), |act, ctx| {
+            if act.protocol.is_none() {
+                warn!("Watcher did not respond to the handshake, assuming protocol version 0");
+                act.process_handshake(&Handshake::legacy(), ctx);
+            }
+        });
+    }
+    // Negotiates the protocol with the handshake of the Watcher. Incompatible
+    // Watchers are informed and disconnected.
+    fn process_handshake(&mut self, remote: &Handshake, ctx: &mut Context<Self>) {
+        match Handshake::local().negotiate(remote) {
+            Ok(protocol) => {
+                info!(
+                    "Negotiated Watcher protocol version {} with capabilities {:?}",
+                    protocol.version, protocol.capabilities
+                );
+
+                self.protocol = Some(protocol);
+                for msg in std::mem::take(&mut self.queued) {
+                    ctx.address().do_send(msg);
+                }
+            }
+            Err(err) => {
+                error!("Refusing Watcher connection: {}", err);
+
+                let reason = err.to_string();
+                if let Err(err) = Handler::<ClientCommand>::handle(
+                    self,
+                    ClientCommand::Error(reason.clone()),
+                    ctx,
+                ) {
+                    error!("Failed to send error to Watcher: {:?}", err);
+                }
+
+                self.refused = Some(reason);
+                // Give the error some time to be sent.
+                ctx.run_later(Duration::new(1, 0), |_act, ctx| ctx.stop());
+            }
+        }
+    }
+    // Send a websocket ping to the Watcher every couple of seconds. The
+    // connection is reset if no pong (or any other message) is received in
+    // time, see `HEARTBEAT_TIMEOUT`.
     fn start_heartbeat_task(&self, ctx: &mut Context<Self>) {
         info!("Starting heartbeat background task");
 
@@ -244,18 +983,59 @@ impl Connector {
             ctx.address().do_send(ClientCommand::Ping)
         });
     }
-    // Process any tangling submissions, meaning any verified requests that were
-    // submitted to the Watcher but the issued extrinsic was not direclty
-    // confirmed back. This usually does not happen, but can.
-    fn start_dangling_judgements_task(&self, ctx: &mut Context<Self>) {
-        info!("Starting dangling judgement pruning task");
+    // Check every couple of seconds whether this is the active connection of
+    // the network. A standby connection takes over once the connections to
+    // all preferred Watchers are down, e.g. because the heartbeat failed.
+    fn start_failover_task(&self, ctx: &mut Context<Self>) {
+        // Only occurs when testing, the connector is always active.
+        if self.sink.is_none() {
+            return;
+        }
+
+        info!("Starting failover background task");
+
+        self.update_active(ctx);
+        ctx.run_interval(Duration::new(FAILOVER_INTERVAL, 0), |act, ctx| {
+            act.update_active(ctx)
+        });
+    }
+    fn update_active(&self, ctx: &mut Context<Self>) {
+        let status = self.status.clone();
+        let network = self.network.clone();
+        let endpoint = self.endpoint.clone();
+
+        ctx.spawn(
+            async move { status.is_active(&network, &endpoint).await }
+                .into_actor(self)
+                .map(|active, act, ctx| {
+                    if active == act.active {
+                        return;
+                    }
+
+                    act.active = active;
+                    if active {
+                        info!("Connection to Watcher {} is now active", act.endpoint);
+                        // Catch up on what happened while on standby.
+                        ctx.address()
+                            .do_send(ClientCommand::RequestPendingJudgements);
+                        ctx.address().do_send(ClientCommand::RequestDisplayNames);
+                    } else {
+                        info!("Connection to Watcher {} is now on standby", act.endpoint);
+                    }
+                }),
+        );
+    }
+    // Complete identity updates which were interrupted, e.g. by a restart,
+    // before their events were inserted into the event log.
+    fn start_interrupted_updates_task(&self, ctx: &mut Context<Self>) {
+        info!("Starting interrupted updates background task");
 
         ctx.run_interval(Duration::new(60, 0), |act, _ctx| {
             let db = act.db.clone();
 
             actix::spawn(async move {
-                if let Err(err) = db.process_dangling_judgement_states().await {
-                    error!("Error when pruning dangling judgements: {:?}", err);
+                if let Err(err) = db.process_interrupted_updates().await {
+                    error!("Error when processing interrupted updates: {:?}", err);
                 }
             });
         });
@@ -280,27 +1060,68 @@ impl Connector {
             ctx.address().do_send(ClientCommand::RequestDisplayNames)
         });
     }
-    // Look for verified identities and submit those to the Watcher.
+    // Look for verified identities and submit those to the Watcher. Unconfirmed
+    // submissions are retried with an increasing delay, until the configured
+    // maximum of attempts is reached.
     fn start_judgement_candidates_task(&self, ctx: &mut Context<Self>) {
         info!("Starting judgement candidate submitter background task");
 
         let db = self.db.clone();
         let addr = ctx.address();
-        let network = self.network;
+        let network = self.network.clone();
+        let max_attempts = self.judgement_config.max_submission_attempts;
 
         ctx.run_interval(
             Duration::new(JUDGEMENT_CANDIDATES_INTERVAL, 0),
-            move |_act, _ctx| {
+            move |act, _ctx| {
+                // Only run once per network, by the active connection.
+                if !act.active {
+                    return;
+                }
+
                 let db = db.clone();
+                let network = network.clone();
                 let addr = addr.clone();
 
                 actix::spawn(async move {
                     // Provide judgments for the specific network.
-                    match db.fetch_judgement_candidates(network).await {
+                    match db.fetch_judgement_candidates(&network).await {
                         Ok(completed) => {
                             for state in completed {
-                                info!("Notifying Watcher about judgement: {:?}", state.context);
-                                addr.do_send(ClientCommand::ProvideJudgement(state.context));
+                                // States verified before judgements were
+                                // recorded do not have one set.
+                                let judgement = state.judgement.unwrap_or(Judgement::Reasonable);
+
+                                match db
+                                    .record_judgement_submission(
+                                        &state.context,
+                                        judgement,
+                                        max_attempts,
+                                    )
+                                    .await
+                                {
+                                    Ok(true) => {}
+                                    Ok(false) => {
+                                        error!(
+                                            "Judgement was not confirmed by the Watcher, giving up: {:?}, {:?}",
+                                            state.context, judgement
+                                        );
+                                        continue;
+                                    }
+                                    Err(err) => {
+                                        error!("Failed to record judgement submission: {:?}", err);
+                                        continue;
+                                    }
+                                }
+
+                                info!(
+                                    "Notifying Watcher about judgement: {:?}, {:?}",
+                                    state.context, judgement
+                                );
+                                addr.do_send(ClientCommand::ProvideJudgement(
+                                    state.context,
+                                    judgement,
+                                ));
                             }
                         }
                         Err(err) => {
@@ -311,6 +1132,102 @@ impl Connector {
             },
         );
     }
+    // Schedule erroneous judgements for identities that exceeded the failed
+    // attempts threshold.
+    fn start_judgement_policy_task(&self, ctx: &mut Context<Self>) {
+        let max_failed_attempts = match self.judgement_config.erroneous_failed_attempts {
+            Some(max) => max,
+            None => return,
+        };
+
+        info!("Starting judgement policy background task");
+
+        let db = self.db.clone();
+        let network = self.network.clone();
+
+        ctx.run_interval(
+            Duration::new(JUDGEMENT_CANDIDATES_INTERVAL, 0),
+            move |act, _ctx| {
+                if !act.active {
+                    return;
+                }
+
+                let db = db.clone();
+                let network = network.clone();
+
+                actix::spawn(async move {
+                    if let Err(err) = db
+                        .apply_failed_attempts_policy(&network, max_failed_attempts)
+                        .await
+                    {
+                        error!("Failed to apply judgement policy: {:?}", err);
+                    }
+                });
+            },
+        );
+    }
+    // Replace expired challenges, so users can continue the verification
+    // process with fresh ones.
+    fn start_expired_challenges_task(&self, ctx: &mut Context<Self>) {
+        let ttl = match self.challenge_config.ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+
+        info!("Starting expired challenges background task");
+
+        let db = self.db.clone();
+        let network = self.network.clone();
+
+        ctx.run_interval(
+            Duration::new(EXPIRED_CHALLENGES_INTERVAL, 0),
+            move |act, _ctx| {
+                if !act.active {
+                    return;
+                }
+
+                let db = db.clone();
+                let network = network.clone();
+
+                actix::spawn(async move {
+                    if let Err(err) = db.regenerate_expired_challenges(&network, ttl).await {
+                        error!("Failed to regenerate expired challenges: {:?}", err);
+                    }
+                });
+            },
+        );
+    }
+    // Lock, reset or flag fields with too many failed verification attempts.
+    fn start_lockout_policy_task(&self, ctx: &mut Context<Self>) {
+        if self.lockout_config.max_failed_attempts.is_none() {
+            return;
+        }
+
+        info!("Starting lockout policy background task");
+
+        let db = self.db.clone();
+        let network = self.network.clone();
+        let config = self.lockout_config.clone();
+
+        ctx.run_interval(
+            Duration::new(LOCKOUT_POLICY_INTERVAL, 0),
+            move |act, _ctx| {
+                if !act.active {
+                    return;
+                }
+
+                let db = db.clone();
+                let network = network.clone();
+                let config = config.clone();
+
+                actix::spawn(async move {
+                    if let Err(err) = db.apply_lockout_policy(&network, &config).await {
+                        error!("Failed to apply lockout policy: {:?}", err);
+                    }
+                });
+            },
+        );
+    }
 }
 
 impl Actor for Connector {
@@ -325,55 +1242,35 @@ impl Actor for Connector {
                 endpoint = self.endpoint.as_str()
             );
 
-            // Note: heartbeat task remains disabled.
-            //self.start_heartbeat_task(ctx);
+            self.start_handshake(ctx);
+            self.start_heartbeat_task(ctx);
+            self.start_failover_task(ctx);
             self.start_pending_judgements_task(ctx);
-            self.start_dangling_judgements_task(ctx);
+            self.start_interrupted_updates_task(ctx);
             self.start_active_display_names_task(ctx);
             self.start_judgement_candidates_task(ctx);
+            self.start_judgement_policy_task(ctx);
+            self.start_expired_challenges_task(ctx);
+            self.start_lockout_policy_task(ctx);
         });
     }
 
     fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        // Only occurs when testing, there is no connection to restore.
+        if self.sink.is_none() {
+            return;
+        }
+
         let span = warn_span!("watcher_connection_drop");
         span.in_scope(|| {
             debug!(
                 network = self.network.as_str(),
                 endpoint = self.endpoint.as_str()
             );
+            warn!("Watcher disconnected, trying to reconnect...");
         });
 
-        let endpoint = self.endpoint.clone();
-        let network = self.network;
-        let db = self.db.clone();
-        let dn_verifier = self.dn_verifier.clone();
-
-        actix::spawn(
-            async move {
-                warn!("Watcher disconnected, trying to reconnect...");
-
-                let mut counter = 0;
-                loop {
-                    if Connector::start(endpoint.clone(), network, db.clone(), dn_verifier.clone())
-                        .await
-                        .is_err()
-                    {
-                        warn!("Reconnection failed, retrying...");
-
-                        counter += 1;
-                        if counter >= 10 {
-                            error!("Cannot reconnect to Watcher after {} attempts", counter);
-                        }
-
-                        sleep(Duration::from_secs(RECONNECTION_TIMEOUT)).await;
-                    } else {
-                        info!("Reconnected to Watcher!");
-                        break;
-                    }
-                }
-            }
-            .instrument(span),
-        );
+        actix::spawn(reconnect(self.setup(), self.refused.take()).instrument(span));
     }
 }
 
@@ -393,6 +1290,29 @@ impl Handler<ClientCommand> for Connector {
             endpoint = self.endpoint.as_str()
         );
 
+        if msg.requires_active() && !self.active {
+            debug!("Connection to Watcher is on standby, skipping message");
+            return Ok(());
+        }
+
+        if let Some(capability) = msg.capability() {
+            match &self.protocol {
+                None => {
+                    debug!("Handshake with Watcher is pending, queuing message");
+                    self.queued.push(msg);
+                    return Ok(());
+                }
+                Some(protocol) if !protocol.supports(capability) => {
+                    debug!(
+                        "Watcher does not support '{}', skipping message",
+                        capability
+                    );
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
         // If the sink (outgoing WS stream) is not configured (i.e. when
         // testing), send the client command to the channel.
         if self.sink.is_none() {
@@ -410,26 +1330,37 @@ impl Handler<ClientCommand> for Connector {
         }
 
         // Do a timestamp check and reconnect if necessary.
-        if Timestamp::now().raw() - self.last_watcher_msg.raw() > (HEARTBEAT_INTERVAL * 2) {
+        if Timestamp::now().raw() - self.last_watcher_msg.raw() > HEARTBEAT_TIMEOUT {
             warn!("Last received message from the Watcher was a while ago, resetting connection");
             ctx.stop();
             return Ok(());
         }
 
         match msg {
-            ClientCommand::ProvideJudgement(id) => {
-                debug!("Providing judgement over websocket stream: {:?}", id);
+            ClientCommand::Handshake(handshake) => {
+                debug!("Sending handshake over websocket stream");
 
                 sink.write(Message::Text(
-                    serde_json::to_string(&ResponseMessage {
-                        event: EventType::JudgementResult,
-                        data: JudgementResponse {
-                            address: id.address,
-                            judgement: Judgement::Reasonable,
-                        },
-                    })
-                    .unwrap()
-                    .into(),
+                    self.link.encode(EventType::Handshake, handshake)?.into(),
+                ))
+                .map_err(|err| anyhow!("failed to send handshake: {:?}", err))?;
+            }
+            ClientCommand::ProvideJudgement(id, judgement) => {
+                debug!(
+                    "Providing judgement over websocket stream: {:?}, {:?}",
+                    id, judgement
+                );
+
+                sink.write(Message::Text(
+                    self.link
+                        .encode(
+                            EventType::JudgementResult,
+                            JudgementResponse {
+                                address: id.address,
+                                judgement,
+                            },
+                        )?
+                        .into(),
                 ))
                 .map_err(|err| anyhow!("failed to provide judgement: {:?}", err))?;
             }
@@ -437,12 +1368,9 @@ impl Handler<ClientCommand> for Connector {
                 debug!("Requesting pending judgements over websocket stream");
 
                 sink.write(Message::Text(
-                    serde_json::to_string(&ResponseMessage {
-                        event: EventType::PendingJudgementsRequest,
-                        data: (),
-                    })
-                    .unwrap()
-                    .into(),
+                    self.link
+                        .encode(EventType::PendingJudgementsRequest, ())?
+                        .into(),
                 ))
                 .map_err(|err| anyhow!("failed to request pending judgements: {:?}", err))?;
             }
@@ -450,21 +1378,32 @@ impl Handler<ClientCommand> for Connector {
                 debug!("Requesting display names over websocket stream");
 
                 sink.write(Message::Text(
-                    serde_json::to_string(&ResponseMessage {
-                        event: EventType::DisplayNamesRequest,
-                        data: (),
-                    })
-                    .unwrap()
-                    .into(),
+                    self.link.encode(EventType::DisplayNamesRequest, ())?.into(),
                 ))
                 .map_err(|err| anyhow!("failed to request display names: {:?}", err))?;
             }
             ClientCommand::Ping => {
                 debug!("Sending ping to Watcher over websocket stream");
 
-                sink.write(Message::Text("ping".to_string().into()))
+                sink.write(Message::Ping(Default::default()))
                     .map_err(|err| anyhow!("failed to send ping over websocket: {:?}", err))?;
             }
+            ClientCommand::Error(message) => {
+                debug!("Sending error to Watcher over websocket stream");
+
+                sink.write(Message::Text(
+                    self.link.encode(EventType::Error, message)?.into(),
+                ))
+                .map_err(|err| anyhow!("failed to send error: {:?}", err))?;
+            }
+            ClientCommand::RequestError(data) => {
+                debug!("Sending judgement request error to Watcher over websocket stream");
+
+                sink.write(Message::Text(
+                    self.link.encode(EventType::Error, data)?.into(),
+                ))
+                .map_err(|err| anyhow!("failed to send judgement request error: {:?}", err))?;
+            }
         }
 
         Ok(())
@@ -475,13 +1414,15 @@ impl Handler<ClientCommand> for Connector {
 impl Handler<WatcherMessage> for Connector {
     type Result = ResponseActFuture<Self, crate::Result<()>>;
 
-    fn handle(&mut self, msg: WatcherMessage, _ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: WatcherMessage, ctx: &mut Context<Self>) -> Self::Result {
         /// Handle a judgement request.
         async fn process_request(
             db: &Database,
             id: IdentityContext,
             mut accounts: HashMap<AccountType, String>,
             dn_verifier: &DisplayNameVerifier,
+            judgement_config: &JudgementConfig,
+            challenge_config: &ChallengeConfig,
             inserted_states: &Arc<RwLock<Vec<JudgementState>>>,
         ) -> Result<()> {
             // Decode display name if appropriate.
@@ -492,7 +1433,8 @@ impl Handler<WatcherMessage> for Connector {
                 try_decode_hex(val);
             }
 
-            let state = JudgementState::new(id, accounts.into_iter().map(|a| a.into()).collect());
+            let state = JudgementState::new(id, accounts.into_iter().map(|a| a.into()).collect())
+                .with_challenge_ttl(challenge_config.ttl);
 
             // Add the judgement state that's about to get inserted into the
             // local queue which is then fetched from the unit tests.
@@ -514,22 +1456,68 @@ impl Handler<WatcherMessage> for Connector {
                 );
 
                 dn_verifier.verify_display_name(&state).await?;
+
+                // Issue an erroneous judgement if the identity impersonates
+                // another one.
+                if let Some(limit) = judgement_config.erroneous_display_name_limit {
+                    if dn_verifier.is_impersonation(&state, limit).await? {
+                        info!(
+                            "Display name impersonation detected, issuing erroneous judgement: {:?}",
+                            state.context
+                        );
+
+                        db.set_pending_judgement(&state.context, Judgement::Erroneous)
+                            .await?;
+                    }
+                }
             }
 
             Ok(())
         }
 
-        // Update timestamp
-        self.last_watcher_msg = Timestamp::now();
+        if let WatcherMessage::Handshake(remote) = &msg {
+            self.process_handshake(remote, ctx);
+            return Box::pin(fut::ready(Ok(())));
+        }
+
+        // Watchers which speak the current protocol send the handshake
+        // before any other message.
+        if self.protocol.is_none() {
+            info!("Watcher did not send a handshake, assuming protocol version 0");
+            self.process_handshake(&Handshake::legacy(), ctx);
+        }
+
+        if let Some(capability) = msg.capability() {
+            let supported = self
+                .protocol
+                .as_ref()
+                .map(|protocol| protocol.supports(capability))
+                .unwrap_or(false);
+
+            if !supported {
+                warn!(
+                    "Received message from Watcher which requires '{}', which was not negotiated: {:?}",
+                    capability, msg
+                );
+                return Box::pin(fut::ready(Ok(())));
+            }
+        }
 
-        let network = self.network;
+        let network = self.network.clone();
+        let prefix = self.ss58_prefix;
         let db = self.db.clone();
         let dn_verifier = self.dn_verifier.clone();
+        let judgement_config = self.judgement_config.clone();
+        let challenge_config = self.challenge_config.clone();
         let inserted_states = Arc::clone(&self.inserted_states);
+        let stale_requests = Arc::clone(&self.stale_requests);
+        let stale_display_names = Arc::clone(&self.stale_display_names);
 
         Box::pin(
             async move {
                 match msg {
+                    // Processed above.
+                    WatcherMessage::Handshake(_) => {}
                     WatcherMessage::Ack(data) => {
                         if data.result.to_lowercase().contains("judgement given") {
                             // Create identity context.
@@ -541,41 +1529,129 @@ impl Handler<WatcherMessage> for Connector {
                                 )
                             })?;
 
-                            let context = IdentityContext::new(address, network);
+                            let context = watcher_context(&address, &network, prefix)?;
 
                             info!("Marking {:?} as judged", context);
                             db.set_judged(&context).await?;
                         }
                     }
+                    WatcherMessage::Error(data) => {
+                        // Record the error on the pending submission, if any.
+                        if let Some(address) = data.address {
+                            let context = watcher_context(&address, &network, prefix)?;
+                            db.record_judgement_error(&context, &data.result).await?;
+                        }
+                    }
                     WatcherMessage::NewJudgementRequest(data) => {
-                        let id = IdentityContext::new(data.address, network);
-                        process_request(&db, id, data.accounts, &dn_verifier, &inserted_states).await?;
+                        let id = watcher_context(&data.address, &network, prefix)?;
+                        process_request(&db, id, data.accounts, &dn_verifier, &judgement_config, &challenge_config, &inserted_states).await?;
                     }
                     WatcherMessage::PendingJudgementsRequests(data) => {
                         // Convert data.
                         let data: Vec<(IdentityContext, HashMap<AccountType, String>)> = data
                             .into_iter()
-                            .map(|req| (
-                                IdentityContext::new(req.address, network),
+                            .map(|req| Ok((
+                                watcher_context(&req.address, &network, prefix)?,
                                 req.accounts
-                            ))
+                            )))
+                            .collect::<Result<_>>()?;
+
+                        let pending: HashSet<IdentityContext> = data
+                            .iter()
+                            .map(|(context, _)| context.clone())
                             .collect();
 
+                        // A request which fails does not affect the others.
                         for (context, accounts) in data {
-                            process_request(&db, context, accounts, &dn_verifier, &inserted_states).await?;
+                            if let Err(err) = process_request(&db, context.clone(), accounts, &dn_verifier, &judgement_config, &challenge_config, &inserted_states).await {
+                                error!("Failed to process judgement request {:?}: {:?}", context, err);
+                            }
+                        }
+
+                        // The response contains all pending requests of the
+                        // network. Requests which are missing were cancelled
+                        // or the identity was cleared.
+                        let stored = db.fetch_pending_requests(&network).await?;
+                        let threshold = Timestamp::now().raw().saturating_sub(CANCELLATION_GRACE_PERIOD);
+                        let stale: HashSet<IdentityContext> = stored
+                            .iter()
+                            .filter(|state| {
+                                !pending.contains(&state.context)
+                                    && state.inserted_timestamp.raw() < threshold
+                            })
+                            .map(|state| state.context.clone())
+                            .collect();
+
+                        let stale_count = stale.len();
+                        if stale_requests.write().await.can_remove(pending.len(), stored.len(), &stale) {
+                            for context in stale {
+                                info!("Judgement request is no longer pending, cancelling: {:?}", context);
+                                db.cancel_judgement_request(&context, CancellationReason::NoLongerPending).await?;
+                            }
+                        } else {
+                            warn!(
+                                "Watcher reported {} pending judgements on {:?}, refusing to cancel {} of {} stored requests",
+                                pending.len(),
+                                network,
+                                stale_count,
+                                stored.len()
+                            );
                         }
                     }
                     WatcherMessage::ActiveDisplayNames(data) => {
+                        // The response contains all display names of the
+                        // network.
+                        let mut active = HashSet::new();
                         for mut name in data {
                             name.try_decode_hex();
 
-                            let context = IdentityContext::new(name.address, network);
+                            let context = match watcher_context(&name.address, &network, prefix) {
+                                Ok(context) => context,
+                                Err(err) => {
+                                    warn!("Skipping display name {:?}: {:?}", name.display_name, err);
+                                    continue;
+                                }
+                            };
                             let entry = DisplayNameEntry {
                                 context,
                                 display_name: name.display_name,
                             };
 
                             db.insert_display_name(&entry).await?;
+                            active.insert(entry);
+                        }
+
+                        // Remove the names which were cleared or changed.
+                        let stored = db.fetch_display_names(&network).await?;
+                        let stale: Vec<DisplayNameEntry> = stored
+                            .iter()
+                            .filter(|entry| !active.contains(*entry))
+                            .cloned()
+                            .collect();
+
+                        let stale_set: HashSet<DisplayNameEntry> = stale.iter().cloned().collect();
+                        if stale_display_names.write().await.can_remove(active.len(), stored.len(), &stale_set) {
+                            if !stale.is_empty() {
+                                debug!("Removing {} stale display names on {:?}", stale.len(), network);
+                                db.remove_display_names(&stale).await?;
+                            }
+                        } else {
+                            warn!(
+                                "Watcher reported {} display names on {:?}, refusing to remove {} of {} stored names",
+                                active.len(),
+                                network,
+                                stale.len(),
+                                stored.len()
+                            );
+                        }
+                    }
+                    WatcherMessage::JudgementRequestCancelled(data) => {
+                        let context = watcher_context(&data.address, &network, prefix)?;
+
+                        if db.cancel_judgement_request(&context, data.reason).await? {
+                            info!("Judgement request was cancelled: {:?}, {:?}", context, data.reason);
+                        } else {
+                            debug!("Cancelled judgement request is unknown: {:?}", context);
                         }
                     }
                 }
@@ -586,6 +1662,86 @@ impl Handler<WatcherMessage> for Connector {
     }
 }
 
+/// Whether the stored entries (display names or judgement requests) which were
+/// not reported by the Watcher can be removed. An empty response or one which
+/// would remove a large part of the stored entries is considered to be
+/// incomplete.
+fn can_remove_stale_entries(active: usize, stored: usize, stale: usize) -> bool {
+    stale == 0
+        || (active > 0
+            && (stale <= MAX_STALE_ENTRIES
+                || stale as f64 <= stored as f64 * MAX_STALE_ENTRIES_RATIO))
+}
+
+/// The identity context of an address received from the Watcher. The address
+/// is normalized to the prefix of the network, the Watcher might use another
+/// format (e.g. the generic Substrate format).
+fn watcher_context(
+    address: &ChainAddress,
+    network: &ChainName,
+    prefix: u16,
+) -> Result<IdentityContext> {
+    let address = address
+        .normalize(prefix)
+        .map_err(|err| anyhow!("invalid address from Watcher: {:?}", err))?;
+
+    Ok(IdentityContext::new(address, network.clone()))
+}
+
+/// The stale entries (display names or judgement requests) whose removal was
+/// refused by the last syncs. A partial response of the Watcher is unlikely to
+/// miss the same entries repeatedly, so entries which are reported as stale
+/// consistently are most likely gone (e.g. many identities were cleared at
+/// once). Empty responses are never accepted.
+#[derive(Debug)]
+struct StaleEntries<T> {
+    refused: HashSet<T>,
+    // The number of consecutive syncs which reported the refused entries.
+    syncs: usize,
+}
+
+impl<T> Default for StaleEntries<T> {
+    fn default() -> Self {
+        StaleEntries {
+            refused: HashSet::new(),
+            syncs: 0,
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> StaleEntries<T> {
+    /// Whether the stale entries can be removed, see
+    /// `can_remove_stale_entries`. Otherwise, the removal is accepted once the
+    /// same entries were reported as stale on `STALE_ENTRIES_CONFIRMATIONS`
+    /// consecutive syncs.
+    fn can_remove(&mut self, active: usize, stored: usize, stale: &HashSet<T>) -> bool {
+        if can_remove_stale_entries(active, stored, stale.len()) {
+            *self = Self::default();
+            return true;
+        }
+
+        // Empty responses interrupt the consecutive syncs.
+        if active == 0 {
+            *self = Self::default();
+            return false;
+        }
+
+        if &self.refused == stale {
+            self.syncs += 1;
+        } else {
+            self.refused = stale.clone();
+            self.syncs = 1;
+        }
+
+        if self.syncs >= STALE_ENTRIES_CONFIRMATIONS {
+            *self = Self::default();
+            true
+        } else {
+            false
+        }
+    }
+}
+
 /// Handle websocket messages received from the Watcher. Those messages will be
 /// forwarded to the `Handler<WatcherMessage>` implementation.
 impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
@@ -598,16 +1754,81 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
             conn: Addr<Connector>,
             msg: std::result::Result<Frame, WsProtocolError>,
         ) -> Result<()> {
+            /// Parses a judgement request. Requests which cannot be parsed
+            /// or have an invalid address and fields which are skipped are
+            /// reported to the Watcher.
+            async fn parse_request(
+                conn: &Addr<Connector>,
+                data: serde_json::Value,
+            ) -> Result<Option<JudgementRequest>> {
+                let address = data
+                    .get("address")
+                    .and_then(|address| address.as_str())
+                    .map(|address| ChainAddress::from(address.to_string()));
+
+                let (request, skipped) = match serde_json::from_value::<JudgementRequestRaw>(data) {
+                    Ok(raw) => raw.into_request(),
+                    Err(err) => {
+                        error!("Skipping invalid judgement request from Watcher: {:?}", err);
+
+                        conn.send(ClientCommand::RequestError(ErrorResponse {
+                            result: format!("invalid judgement request: {}", err),
+                            address,
+                        }))
+                        .await??;
+
+                        return Ok(None);
+                    }
+                };
+
+                if let Err(err) = request.address.decode() {
+                    error!(
+                        "Skipping judgement request with invalid address from Watcher: {:?}",
+                        err
+                    );
+
+                    conn.send(ClientCommand::RequestError(ErrorResponse {
+                        result: format!("invalid address: {}", err),
+                        address: Some(request.address),
+                    }))
+                    .await??;
+
+                    return Ok(None);
+                }
+
+                if !skipped.is_empty() {
+                    warn!(
+                        "Skipping fields of judgement request from Watcher: {:?}, {:?}",
+                        request.address, skipped
+                    );
+
+                    conn.send(ClientCommand::RequestError(ErrorResponse {
+                        result: format!("skipped fields without a value: {}", skipped.join(", ")),
+                        address: Some(request.address.clone()),
+                    }))
+                    .await??;
+                }
+
+                Ok(Some(request))
+            }
+
             let parsed: ResponseMessage<serde_json::Value> = match msg {
                 Ok(Frame::Text(txt)) => serde_json::from_slice(&txt)?,
                 Ok(other) => {
                     debug!("Received unexpected message: {:?}", other);
                     return Ok(());
                 }
-                Err(err) => return Err(anyhow!("error message: {:?}", err)),
+                // Handled above.
+                Err(_) => return Ok(()),
             };
 
             match parsed.event {
+                EventType::Handshake => {
+                    debug!("Received handshake from Watcher: {:?}", parsed.data);
+
+                    let data: Handshake = serde_json::from_value(parsed.data)?;
+                    conn.send(WatcherMessage::Handshake(data)).await??;
+                }
                 EventType::Ack => {
                     debug!("Received acknowledgement from Watcher: {:?}", parsed.data);
 
@@ -616,6 +1837,13 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
                 }
                 EventType::Error => {
                     error!("Received error from Watcher: {:?}", parsed.data);
+
+                    // Errors which do not refer to an identity are only logged.
+                    if let Ok(data) = serde_json::from_value::<ErrorResponse>(parsed.data) {
+                        if data.address.is_some() {
+                            conn.send(WatcherMessage::Error(data)).await??;
+                        }
+                    }
                 }
                 EventType::NewJudgementRequest => {
                     debug!(
@@ -623,16 +1851,50 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
                         parsed.data
                     );
 
-                    let data: JudgementRequest = serde_json::from_value(parsed.data)?;
-                    conn.send(WatcherMessage::NewJudgementRequest(data))
-                        .await??;
+                    if let Some(data) = parse_request(&conn, parsed.data).await? {
+                        conn.send(WatcherMessage::NewJudgementRequest(data))
+                            .await??;
+                    }
                 }
                 EventType::PendingJudgementsResponse => {
                     debug!("Received pending judgments from Watcher: {:?}", parsed.data);
 
-                    let data: Vec<JudgementRequest> = serde_json::from_value(parsed.data)?;
-                    conn.send(WatcherMessage::PendingJudgementsRequests(data))
-                        .await??;
+                    let data: Vec<serde_json::Value> = serde_json::from_value(parsed.data)?;
+                    let total = data.len();
+
+                    let mut requests = vec![];
+                    for data in data {
+                        if let Some(request) = parse_request(&conn, data).await? {
+                            requests.push(request);
+                        }
+                    }
+
+                    if requests.len() == total {
+                        conn.send(WatcherMessage::PendingJudgementsRequests(requests))
+                            .await??;
+                    } else {
+                        // Stored requests which are missing from the response
+                        // get cancelled, which must not happen to the invalid
+                        // ones. Process the others individually instead.
+                        warn!(
+                            "Skipped {} of {} pending judgements from Watcher, not cancelling stale requests",
+                            total - requests.len(),
+                            total
+                        );
+
+                        for request in requests {
+                            let address = request.address.clone();
+                            if let Err(err) = conn
+                                .send(WatcherMessage::NewJudgementRequest(request))
+                                .await?
+                            {
+                                error!(
+                                    "Failed to process judgement request {:?}: {:?}",
+                                    address, err
+                                );
+                            }
+                        }
+                    }
                 }
                 EventType::DisplayNamesResponse => {
                     debug!("Received display names from the Watcher");
@@ -641,6 +1903,16 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
                     conn.send(WatcherMessage::ActiveDisplayNames(data))
                         .await??;
                 }
+                EventType::JudgementRequestCancelled => {
+                    debug!(
+                        "Received judgement request cancellation from Watcher: {:?}",
+                        parsed.data
+                    );
+
+                    let data: JudgementCancellation = serde_json::from_value(parsed.data)?;
+                    conn.send(WatcherMessage::JudgementRequestCancelled(data))
+                        .await??;
+                }
                 _ => {
                     warn!("Received unrecognized message from Watcher: {:?}", parsed);
                 }
@@ -656,6 +1928,66 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
                 endpoint = self.endpoint.as_str()
             );
 
+            // Any message proves that the Watcher is still reachable.
+            if msg.is_ok() {
+                self.last_watcher_msg = Timestamp::now();
+            }
+
+            // Messages without a valid signature are dropped, if signing is
+            // configured. Those are not answered, the Watcher would reject
+            // the response just the same.
+            if let Ok(Frame::Text(txt)) = &msg {
+                if let Err(err) = self.link.verify(txt) {
+                    warn!("Rejecting message from Watcher: {:?}", err);
+                    return;
+                }
+            }
+
+            // Handle websocket control frames directly.
+            match &msg {
+                Ok(Frame::Ping(bytes)) => {
+                    if let Some(sink) = self.sink.as_mut() {
+                        if let Err(err) = sink.write(Message::Pong(bytes.clone())) {
+                            error!("Failed to respond to ping from Watcher: {:?}", err);
+                        }
+                    }
+
+                    return;
+                }
+                Ok(Frame::Pong(_)) => {
+                    debug!("Received pong from Watcher");
+                    return;
+                }
+                // The stream does not recover from protocol errors, such as
+                // frames which exceed the maximum size. Reset the connection.
+                Err(err) => {
+                    if ctx.state().alive() {
+                        error!(
+                            "Websocket protocol error, resetting connection to Watcher: {:?}",
+                            err
+                        );
+                        ctx.stop();
+                    }
+
+                    return;
+                }
+                _ => {}
+            }
+
+            // Watchers which speak the current protocol send the handshake
+            // before any other message, there is no need to wait for the
+            // handshake timeout otherwise.
+            if self.protocol.is_none() {
+                if let Ok(Frame::Text(txt)) = &msg {
+                    if let Ok(parsed) = serde_json::from_slice::<ResponseMessage<IgnoredAny>>(txt) {
+                        if parsed.event != EventType::Handshake {
+                            info!("Watcher did not send a handshake, assuming protocol version 0");
+                            self.process_handshake(&Handshake::legacy(), ctx);
+                        }
+                    }
+                }
+            }
+
             let addr = ctx.address();
             actix::spawn(
                 async move {
@@ -669,26 +2001,72 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for Connector {
     }
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+/// The identity fields as named by the Watcher. Fields which are not known
+/// (yet), such as those of newer identity pallets, are kept as `Other`.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
 pub enum AccountType {
-    #[serde(rename = "legal_name")]
     LegalName,
-    #[serde(rename = "display_name")]
     DisplayName,
-    #[serde(rename = "email")]
     Email,
-    #[serde(rename = "web")]
     Web,
-    #[serde(rename = "twitter")]
     Twitter,
-    #[serde(rename = "matrix")]
     Matrix,
-    #[serde(rename = "pgpFingerprint")]
     PGPFingerprint,
-    #[serde(rename = "image")]
     Image,
-    #[serde(rename = "additional")]
     Additional,
+    Other(String),
+}
+
+impl AccountType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            AccountType::LegalName => "legal_name",
+            AccountType::DisplayName => "display_name",
+            AccountType::Email => "email",
+            AccountType::Web => "web",
+            AccountType::Twitter => "twitter",
+            AccountType::Matrix => "matrix",
+            AccountType::PGPFingerprint => "pgpFingerprint",
+            AccountType::Image => "image",
+            AccountType::Additional => "additional",
+            AccountType::Other(name) => name.as_str(),
+        }
+    }
+}
+
+impl From<String> for AccountType {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "legal_name" => AccountType::LegalName,
+            "display_name" => AccountType::DisplayName,
+            "email" => AccountType::Email,
+            "web" => AccountType::Web,
+            "twitter" => AccountType::Twitter,
+            "matrix" => AccountType::Matrix,
+            "pgpFingerprint" => AccountType::PGPFingerprint,
+            "image" => AccountType::Image,
+            "additional" => AccountType::Additional,
+            _ => AccountType::Other(name),
+        }
+    }
+}
+
+impl serde::Serialize for AccountType {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for AccountType {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(<String as serde::Deserialize>::deserialize(deserializer)?.into())
+    }
 }
 
 impl From<(AccountType, String)> for IdentityFieldValue {
@@ -702,9 +2080,12 @@ impl From<(AccountType, String)> for IdentityFieldValue {
             AccountType::Web => IdentityFieldValue::Web(value),
             AccountType::Twitter => IdentityFieldValue::Twitter(value.to_lowercase()),
             AccountType::Matrix => IdentityFieldValue::Matrix(value),
-            AccountType::PGPFingerprint => IdentityFieldValue::PGPFingerprint(()),
+            AccountType::PGPFingerprint => {
+                IdentityFieldValue::PGPFingerprint(normalize_fingerprint(&value))
+            }
             AccountType::Image => IdentityFieldValue::Image(()),
             AccountType::Additional => IdentityFieldValue::Additional(()),
+            AccountType::Other(name) => IdentityFieldValue::Other { name, value },
         }
     }
 }
@@ -712,7 +2093,7 @@ impl From<(AccountType, String)> for IdentityFieldValue {
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::{Database, DisplayNameConfig};
+    use crate::{ChallengeConfig, Database, DisplayNameConfig, JudgementConfig, LockoutConfig};
     use tokio::sync::mpsc::UnboundedReceiver;
 
     impl JudgementRequest {
@@ -748,6 +2129,12 @@ pub mod tests {
         pub fn new_judgement_request(req: JudgementRequest) -> Self {
             WatcherMessage::NewJudgementRequest(req)
         }
+        pub fn judgement_given(address: ChainAddress) -> Self {
+            WatcherMessage::Ack(AckResponse {
+                result: "judgement given".to_string(),
+                address: Some(address),
+            })
+        }
     }
 
     pub struct ConnectorMocker {
@@ -759,14 +2146,33 @@ pub mod tests {
 
     impl ConnectorMocker {
         pub fn new(db: Database) -> Self {
+            Self::with_config(
+                db,
+                JudgementConfig::default(),
+                ChallengeConfig::default(),
+                LockoutConfig::default(),
+            )
+        }
+        pub fn with_config(
+            db: Database,
+            judgement_config: JudgementConfig,
+            challenge_config: ChallengeConfig,
+            lockout_config: LockoutConfig,
+        ) -> Self {
             let dn_config = DisplayNameConfig {
                 enabled: false,
                 limit: 0.85,
             };
 
             let dn_verifier = DisplayNameVerifier::new(db.clone(), dn_config);
-            let (addr, queue, inserted_states) =
-                Connector::start_testing(ChainName::Polkadot, db, dn_verifier);
+            let (addr, queue, inserted_states) = Connector::start_testing(
+                ChainName::polkadot(),
+                db,
+                dn_verifier,
+                judgement_config,
+                challenge_config,
+                lockout_config,
+            );
 
             ConnectorMocker {
                 queue,
@@ -789,12 +2195,15 @@ pub mod tests {
 
             while let Ok(msg) = self.queue.try_recv() {
                 match msg {
-                    ClientCommand::ProvideJudgement(_) => counter.provide_judgement += 1,
+                    ClientCommand::ProvideJudgement(_, _) => counter.provide_judgement += 1,
                     ClientCommand::RequestPendingJudgements => {
                         counter.request_pending_judgements += 1
                     }
                     ClientCommand::RequestDisplayNames => counter.request_display_names += 1,
                     ClientCommand::Ping => counter.ping += 1,
+                    ClientCommand::Handshake(_)
+                    | ClientCommand::Error(_)
+                    | ClientCommand::RequestError(_) => {}
                 }
 
                 outgoing.push(msg);
@@ -817,6 +2226,9 @@ pub mod tests {
             network: ChainName,
             db: Database,
             dn_verifier: DisplayNameVerifier,
+            judgement_config: JudgementConfig,
+            challenge_config: ChallengeConfig,
+            lockout_config: LockoutConfig,
         ) -> (
             Addr<Connector>,
             UnboundedReceiver<ClientCommand>,
@@ -831,11 +2243,23 @@ pub mod tests {
                 sink: None,
                 db,
                 dn_verifier,
+                judgement_config,
+                challenge_config,
+                lockout_config,
                 endpoint: "".to_string(),
                 network,
+                ss58_prefix: 0,
                 outgoing,
                 inserted_states: Arc::clone(&inserted_states),
+                stale_requests: Default::default(),
+                stale_display_names: Default::default(),
                 last_watcher_msg: Timestamp::now(),
+                status: Default::default(),
+                protocol: Handshake::local().negotiate(&Handshake::local()).ok(),
+                queued: vec![],
+                refused: None,
+                active: true,
+                link: Default::default(),
             }
             .start();
 
@@ -0,0 +1,356 @@
+use super::migrations::{self, latest_version};
+use super::{Database, DISPLAY_NAMES, EVENT_COLLECTION, IDENTITY_COLLECTION};
+use crate::connector::DisplayNameEntry;
+use crate::primitives::{
+    ChainAddress, ChainName, Event, IdentityContext, JudgementState, Timestamp,
+};
+use crate::{Networks, Result};
+use bson::{from_document, Document};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// The version of the export format itself. The records are versioned by the
+/// schema version of their collection, see `ExportHeader`.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// The exported collections and the record types they are written as.
+const EXPORTED_COLLECTIONS: &[(&str, &str)] = &[
+    (IDENTITY_COLLECTION, "identity"),
+    (DISPLAY_NAMES, "display_name"),
+    (EVENT_COLLECTION, "event"),
+];
+
+/// The first line of every export.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ExportHeader {
+    pub format_version: u32,
+    pub created: Timestamp,
+    // The schema version of the records, per collection. Records of older
+    // versions are upgraded on import.
+    pub schema_versions: HashMap<String, u32>,
+}
+
+impl ExportHeader {
+    fn new() -> Self {
+        ExportHeader {
+            format_version: EXPORT_FORMAT_VERSION,
+            created: Timestamp::now(),
+            schema_versions: EXPORTED_COLLECTIONS
+                .iter()
+                .map(|(collection, _)| (collection.to_string(), latest_version(collection)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum ExportRecord {
+    Header(ExportHeader),
+    Identity(JudgementState),
+    DisplayName(DisplayNameEntry),
+    Event(Event),
+}
+
+// A record which was not upgraded and validated yet.
+#[derive(Debug, Deserialize)]
+struct RawRecord {
+    #[serde(rename = "type")]
+    record_type: String,
+    value: serde_json::Value,
+}
+
+/// Restricts which data gets exported. Unset values match everything.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ExportFilter {
+    pub chain: Option<ChainName>,
+    pub address: Option<ChainAddress>,
+    // Unix time in seconds, inclusive. Applies to the insertion time of
+    // identities and the creation time of events. Display names are not
+    // filtered by time.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+impl ExportFilter {
+    /// Parses the filter from `--chain <CHAIN>`, `--address <ADDR>`,
+    /// `--from <UNIX_TIME>` and `--to <UNIX_TIME>` arguments.
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut filter = ExportFilter::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("Missing value of argument '{}'", arg))?;
+
+            let parse_time = |value: &str| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid Unix time '{}' of argument '{}'", value, arg))
+            };
+
+            match arg.as_str() {
+                "--chain" => filter.chain = Some(ChainName::from(value.clone())),
+                "--address" => filter.address = Some(ChainAddress::from(value.clone())),
+                "--from" => filter.from = Some(parse_time(value)?),
+                "--to" => filter.to = Some(parse_time(value)?),
+                _ => return Err(anyhow!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        Ok(filter)
+    }
+    pub fn matches_context(&self, context: &IdentityContext) -> bool {
+        self.chain
+            .as_ref()
+            .map(|chain| &context.chain == chain)
+            .unwrap_or(true)
+            && self
+                .address
+                .as_ref()
+                .map(|address| &context.address == address)
+                .unwrap_or(true)
+    }
+    pub fn matches_timestamp(&self, timestamp: &Timestamp) -> bool {
+        self.from
+            .map(|from| timestamp.raw() >= from)
+            .unwrap_or(true)
+            && self.to.map(|to| timestamp.raw() <= to).unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TransferSummary {
+    pub identities: usize,
+    pub display_names: usize,
+    pub events: usize,
+}
+
+/// Writes all identities, display names and events which match the filter
+/// to the file, as JSON Lines. The first line is the `ExportHeader`.
+pub async fn export(db: &Database, path: &str, filter: &ExportFilter) -> Result<TransferSummary> {
+    let identities = db.export_identities(filter).await?;
+    let display_names = db.export_display_names(filter).await?;
+    let events = db.export_events(filter).await?;
+
+    let summary = TransferSummary {
+        identities: identities.len(),
+        display_names: display_names.len(),
+        events: events.len(),
+    };
+
+    let file = File::create(path)
+        .map_err(|err| anyhow!("Failed to create export file '{}': {:?}", path, err))?;
+    let mut writer = BufWriter::new(file);
+
+    let records = std::iter::once(ExportRecord::Header(ExportHeader::new()))
+        .chain(identities.into_iter().map(ExportRecord::Identity))
+        .chain(display_names.into_iter().map(ExportRecord::DisplayName))
+        .chain(events.into_iter().map(ExportRecord::Event));
+
+    for record in records {
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+
+    Ok(summary)
+}
+
+/// Reads an export created by `export` and inserts its content. All records
+/// are validated before anything is written. Identities replace the stored
+/// identity with the same context, display names and events which already
+/// exist are skipped. Importing the same file twice is therefore harmless.
+pub async fn import(db: &Database, path: &str, networks: &Networks) -> Result<TransferSummary> {
+    let file = File::open(path)
+        .map_err(|err| anyhow!("Failed to open import file '{}': {:?}", path, err))?;
+
+    let records = read_records(BufReader::new(file), networks)?;
+
+    let mut summary = TransferSummary::default();
+    for record in records {
+        match record {
+            ExportRecord::Header(_) => {}
+            ExportRecord::Identity(state) => {
+                db.import_identity(&state).await?;
+                summary.identities += 1;
+            }
+            ExportRecord::DisplayName(name) => {
+                db.insert_display_name(&name).await?;
+                summary.display_names += 1;
+            }
+            ExportRecord::Event(event) => {
+                db.import_event(&event).await?;
+                summary.events += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Parses, upgrades and validates all records. Fails on the first invalid
+/// record, pointing to its line.
+fn read_records<R: BufRead>(reader: R, networks: &Networks) -> Result<Vec<ExportRecord>> {
+    let mut lines = reader.lines().enumerate();
+
+    let header = match lines.next() {
+        Some((_, line)) => match serde_json::from_str::<ExportRecord>(&line?) {
+            Ok(ExportRecord::Header(header)) => header,
+            _ => {
+                return Err(anyhow!(
+                    "The first line of the import is not a valid header"
+                ))
+            }
+        },
+        None => return Err(anyhow!("The import is empty")),
+    };
+
+    if header.format_version != EXPORT_FORMAT_VERSION {
+        return Err(anyhow!(
+            "Unsupported export format version {}, expected {}",
+            header.format_version,
+            EXPORT_FORMAT_VERSION
+        ));
+    }
+
+    let mut records = vec![ExportRecord::Header(header.clone())];
+    for (index, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = parse_record(&line, &header)
+            .and_then(|record| validate_record(&record, networks).map(|_| record))
+            .map_err(|err| anyhow!("Invalid record on line {}: {:?}", index + 1, err))?;
+
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn parse_record(line: &str, header: &ExportHeader) -> Result<ExportRecord> {
+    let raw: RawRecord = serde_json::from_str(line)?;
+
+    let collection = EXPORTED_COLLECTIONS
+        .iter()
+        .find(|(_, record_type)| *record_type == raw.record_type)
+        .map(|(collection, _)| *collection)
+        .ok_or_else(|| anyhow!("Unknown record type '{}'", raw.record_type))?;
+
+    let version = header
+        .schema_versions
+        .get(collection)
+        .copied()
+        .ok_or_else(|| anyhow!("The header has no schema version of '{}'", collection))?;
+
+    if version > latest_version(collection) {
+        return Err(anyhow!(
+            "Schema version {} of '{}' is newer than the supported version {}",
+            version,
+            collection,
+            latest_version(collection)
+        ));
+    }
+
+    // Upgrade the record like a stored document.
+    let mut doc: Document = bson::to_document(&raw.value)?;
+    for migration in migrations::pending(collection, version) {
+        migration.apply(&mut doc)?;
+    }
+
+    let record = match collection {
+        IDENTITY_COLLECTION => ExportRecord::Identity(from_document(doc)?),
+        DISPLAY_NAMES => ExportRecord::DisplayName(from_document(doc)?),
+        _ => ExportRecord::Event(from_document(doc)?),
+    };
+
+    Ok(record)
+}
+
+fn validate_record(record: &ExportRecord, networks: &Networks) -> Result<()> {
+    let context = match record {
+        ExportRecord::Header(_) => return Err(anyhow!("Unexpected header")),
+        ExportRecord::Identity(state) => &state.context,
+        ExportRecord::DisplayName(name) => &name.context,
+        ExportRecord::Event(event) => event.message.context(),
+    };
+
+    let network = networks
+        .get(&context.chain)
+        .ok_or_else(|| anyhow!("Unknown network '{}'", context.chain.as_str()))?;
+
+    let (prefix, _) = context.address.decode()?;
+    if prefix != network.ss58_prefix {
+        return Err(anyhow!(
+            "Address {} is not encoded for network '{}'",
+            context.address.as_str(),
+            context.chain.as_str()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn filter_from_args() {
+        let filter = ExportFilter::from_args(&args(&[
+            "--chain", "polkadot", "--from", "100", "--to", "200",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            filter,
+            ExportFilter {
+                chain: Some(ChainName::from("polkadot".to_string())),
+                address: None,
+                from: Some(100),
+                to: Some(200),
+            }
+        );
+
+        assert_eq!(
+            ExportFilter::from_args(&[]).unwrap(),
+            ExportFilter::default()
+        );
+        assert!(ExportFilter::from_args(&args(&["--chain"])).is_err());
+        assert!(ExportFilter::from_args(&args(&["--from", "yesterday"])).is_err());
+        assert!(ExportFilter::from_args(&args(&["--network", "polkadot"])).is_err());
+    }
+
+    #[test]
+    fn filter_matches() {
+        let alice = IdentityContext::alice();
+        let bob = IdentityContext::bob();
+
+        let filter = ExportFilter {
+            address: Some(alice.address.clone()),
+            ..Default::default()
+        };
+        assert!(filter.matches_context(&alice));
+        assert!(!filter.matches_context(&bob));
+
+        let filter = ExportFilter {
+            from: Some(Timestamp::now().raw() - 10),
+            to: Some(Timestamp::now().raw() + 10),
+            ..Default::default()
+        };
+        assert!(filter.matches_timestamp(&Timestamp::now()));
+        assert!(!filter.matches_timestamp(&Timestamp::with_offset(60)));
+        assert!(!filter.matches_timestamp(&Timestamp::default()));
+    }
+}
@@ -0,0 +1,1210 @@
+use super::{
+    apply_lockout, create_tombstone, matches_field_name, record_failed_attempt,
+    record_submission_attempt, second_challenge_change, set_default_judgement, set_flag,
+    EventArchive, EventSubscription, ExportFilter, Storage,
+};
+use crate::adapters::admin::RawFieldName;
+use crate::api::VerifyChallenge;
+use crate::connector::{CancellationReason, DisplayNameEntry, Judgement, WatcherStatusReport};
+use crate::primitives::{
+    ChainName, ChallengeType, ErasureReport, Event, ExpectedMessage, ExternalMessage,
+    IdentityContext, IdentityField, IdentityFieldValue, JudgementHistoryEntry, JudgementState,
+    NotificationMessage, StateTransition, Timestamp, Tombstone,
+};
+use crate::{LockoutConfig, LockoutPolicy, Result};
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+
+/// Keeps all state in memory. Mostly intended for testing and local
+/// development, where no MongoDB instance is available. Provides the same
+/// semantics as `MongoStorage`.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    identities: Vec<JudgementState>,
+    // Sorted by id, ascending.
+    events: Vec<(u64, Event)>,
+    // Notifies subscribers about new events.
+    events_added: Arc<Notify>,
+    // The id of the next event to return, per subscriber.
+    subscriber_positions: HashMap<String, u64>,
+    display_names: Vec<DisplayNameEntry>,
+    // Ordered by insertion.
+    history: Vec<JudgementHistoryEntry>,
+    tombstones: Vec<Tombstone>,
+    // One entry per network.
+    watcher_status: Vec<WatcherStatusReport>,
+    next_event_id: u64,
+}
+
+impl MemoryState {
+    fn identity(&self, context: &IdentityContext) -> Option<&JudgementState> {
+        self.identities
+            .iter()
+            .find(|state| &state.context == context)
+    }
+    fn identity_mut(&mut self, context: &IdentityContext) -> Option<&mut JudgementState> {
+        self.identities
+            .iter_mut()
+            .find(|state| &state.context == context)
+    }
+    fn field_mut(
+        &mut self,
+        context: &IdentityContext,
+        value: &IdentityFieldValue,
+    ) -> Option<&mut IdentityField> {
+        self.identity_mut(context)?
+            .fields
+            .iter_mut()
+            .find(|field| &field.value == value)
+    }
+    /// Returns a copy of every identity which matches the filter.
+    fn select<F>(&self, filter: F) -> Vec<JudgementState>
+    where
+        F: Fn(&JudgementState) -> bool,
+    {
+        self.identities
+            .iter()
+            .filter(|state| filter(state))
+            .cloned()
+            .collect()
+    }
+    fn insert_event<T: Into<Event>>(&mut self, event: T) {
+        self.events.push((self.next_event_id, event.into()));
+        self.next_event_id += 1;
+        self.events_added.notify_waiters();
+    }
+    /// Adds a snapshot of the current state of the identity to its history.
+    fn record_history(&mut self, context: &IdentityContext, transition: StateTransition) {
+        if let Some(state) = self.identity(context).cloned() {
+            self.history
+                .push(JudgementHistoryEntry::new(transition, state));
+        }
+    }
+    fn record_failed_attempt(
+        &mut self,
+        context: &IdentityContext,
+        value: &IdentityFieldValue,
+        lockout: &LockoutPolicy,
+    ) {
+        let events = match self.field_mut(context, value) {
+            Some(field) => record_failed_attempt(context, field, lockout.get(&context.chain)),
+            None => return,
+        };
+
+        for event in events {
+            self.insert_event(event);
+        }
+    }
+    /// Check if all fields have been verified.
+    fn process_fully_verified(&mut self, state: &JudgementState) {
+        let current = match self.identity_mut(&state.context) {
+            Some(current) => current,
+            None => return,
+        };
+
+        if state.check_full_verification() {
+            if current.is_fully_verified {
+                return;
+            }
+
+            // Create a timed delay for issuing judgments. Between 30 seconds to
+            // 5 minutes. This is used to prevent timing attacks where a user
+            // updates the identity right before the judgement is issued.
+            let offset = thread_rng().gen_range(30..300);
+
+            current.is_fully_verified = true;
+            current.judgement_submitted = false;
+            current.submission = None;
+            current.completion_timestamp = Some(Timestamp::now());
+            current.issue_judgement_at = Some(Timestamp::with_offset(offset));
+            set_default_judgement(current);
+
+            self.insert_event(NotificationMessage::IdentityFullyVerified {
+                context: state.context.clone(),
+            });
+            self.record_history(&state.context, StateTransition::FullyVerified);
+        } else if current.is_fully_verified {
+            // Reset verification state if identity was changed.
+            current.is_fully_verified = false;
+            current.judgement_submitted = false;
+            current.submission = None;
+        }
+    }
+    /// If the first challenge was already verified and the second challenge
+    /// got replaced, the new second challenge must be sent to the user.
+    fn notify_second_challenge_change(
+        &mut self,
+        context: &IdentityContext,
+        field: &IdentityField,
+        new_challenge: &ChallengeType,
+    ) {
+        if let Some(event) =
+            second_challenge_change(context, &field.value, &field.challenge, new_challenge)
+        {
+            self.insert_event(event);
+        }
+    }
+    fn verify_manually(
+        &mut self,
+        context: &IdentityContext,
+        field: &RawFieldName,
+        full_check: bool,
+    ) -> Result<Option<()>> {
+        if let RawFieldName::All = field {
+            return Err(anyhow!(
+                "field name 'all' is abstract and cannot be verified individually"
+            ));
+        }
+
+        let field_state = match self.identity_mut(context).and_then(|state| {
+            state
+                .fields
+                .iter_mut()
+                .find(|f| matches_field_name(&f.value, field))
+        }) {
+            Some(field_state) => field_state,
+            None => return Ok(None),
+        };
+
+        let modified = match &mut field_state.challenge {
+            // Emails additionally require the secondary verification.
+            ChallengeType::ExpectedMessage { expected, second } => {
+                let mut modified = set_flag(&mut expected.is_verified);
+                if let (RawFieldName::Email, Some(second)) = (field, second) {
+                    modified |= set_flag(&mut second.is_verified);
+                }
+                modified
+            }
+            ChallengeType::DisplayNameCheck {
+                passed,
+                violations: _,
+            } => set_flag(passed),
+            ChallengeType::SignedMessage { expected } => set_flag(&mut expected.is_verified),
+            ChallengeType::Unsupported { is_verified } => {
+                let modified = *is_verified != Some(true);
+                *is_verified = Some(true);
+                modified
+            }
+        };
+
+        if !modified {
+            return Ok(None);
+        }
+
+        // Create event.
+        if full_check {
+            self.insert_event(NotificationMessage::ManuallyVerified {
+                context: context.clone(),
+                field: field.clone(),
+            });
+
+            // Check the new state.
+            if let Some(state) = self.identity(context).cloned() {
+                self.process_fully_verified(&state);
+            } else {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(()))
+    }
+    fn set_pending_judgement(&mut self, context: &IdentityContext, judgement: Judgement) -> bool {
+        let state = match self.identity_mut(context) {
+            Some(state) => state,
+            None => return false,
+        };
+
+        // Create a timed delay for issuing judgments, same as with fully
+        // verified identities.
+        let offset = thread_rng().gen_range(30..300);
+
+        state.judgement = Some(judgement);
+        state.judgement_submitted = false;
+        state.submission = None;
+        state.issue_judgement_at = Some(Timestamp::with_offset(offset));
+
+        self.insert_event(NotificationMessage::JudgementPending {
+            context: context.clone(),
+            judgement,
+        });
+
+        true
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn connectivity_check(&self) -> Result<()> {
+        Ok(())
+    }
+    async fn migrate(&self) -> Result<()> {
+        // Nothing is persisted, so all entries are in the latest format.
+        Ok(())
+    }
+    async fn ensure_indexes(&self) -> Result<()> {
+        Ok(())
+    }
+    async fn add_judgement_request(&self, request: &JudgementState) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        // Check if a request of the same address exists yet (occurs when a
+        // field gets updated during pending judgement process).
+        let mut current = match db.identity(&request.context).cloned() {
+            Some(current) => current,
+            None => {
+                db.identities.push(request.clone());
+                db.record_history(&request.context, StateTransition::Inserted);
+                return Ok(true);
+            }
+        };
+
+        // Determine which fields should be updated.
+        let mut has_changed = false;
+        let mut to_add = vec![];
+        for new_field in &request.fields {
+            if let Some(current_field) = current
+                .fields
+                .iter()
+                .find(|current| current.value == new_field.value)
+            {
+                to_add.push(current_field.clone());
+            } else {
+                to_add.push(new_field.clone());
+                has_changed = true;
+            }
+        }
+
+        // If nothing was modified, return (detect removed entries).
+        if !has_changed && request.fields.len() == current.fields.len() {
+            return Ok(false);
+        }
+
+        current.fields = to_add;
+
+        // Any pending judgement was decided on the old values and is
+        // therefore reset.
+        if let Some(state) = db.identity_mut(&request.context) {
+            state.fields = current.fields.clone();
+            state.judgement = None;
+        }
+
+        db.insert_event(NotificationMessage::IdentityUpdated {
+            context: request.context.clone(),
+        });
+        db.record_history(&request.context, StateTransition::FieldsChanged);
+
+        // Check full verification status.
+        db.process_fully_verified(&current);
+
+        Ok(true)
+    }
+    #[cfg(test)]
+    async fn delete_judgement(&self, context: &IdentityContext) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let count = db.identities.len();
+        db.identities.retain(|state| &state.context != context);
+
+        if count - db.identities.len() != 1 {
+            panic!()
+        }
+
+        Ok(())
+    }
+    async fn verify_manually(
+        &self,
+        context: &IdentityContext,
+        field: &RawFieldName,
+        full_check: bool,
+    ) -> Result<Option<()>> {
+        self.state
+            .lock()
+            .await
+            .verify_manually(context, field, full_check)
+    }
+    async fn verify_message(
+        &self,
+        message: &ExternalMessage,
+        lockout: &LockoutPolicy,
+    ) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let states = db.select(|state| {
+            state
+                .fields
+                .iter()
+                .any(|field| field.value.matches_origin(message))
+        });
+
+        for mut id_state in states {
+            let field_state = id_state
+                .fields
+                .iter_mut()
+                .find(|field| field.value.matches_origin(message))
+                .unwrap();
+
+            // Locked fields ignore any verification attempts.
+            if field_state.is_locked() {
+                continue;
+            }
+
+            let context = id_state.context.clone();
+            let field_value = field_state.value.clone();
+
+            let challenge = &mut field_state.challenge;
+            if !challenge.is_verified() {
+                match challenge {
+                    ChallengeType::ExpectedMessage {
+                        ref mut expected,
+                        second,
+                    } => {
+                        // Expired challenges are ignored until they get
+                        // regenerated.
+                        if !expected.is_verified && !expected.is_expired() {
+                            if expected.verify_message(message) {
+                                if let Some(field) = db.field_mut(&context, &field_value) {
+                                    if let ChallengeType::ExpectedMessage { expected, .. } =
+                                        &mut field.challenge
+                                    {
+                                        expected.set_verified();
+                                    }
+                                }
+
+                                db.insert_event(NotificationMessage::FieldVerified {
+                                    context: context.clone(),
+                                    field: field_value.clone(),
+                                });
+
+                                if second.is_some() {
+                                    db.insert_event(NotificationMessage::AwaitingSecondChallenge {
+                                        context: context.clone(),
+                                        field: field_value,
+                                    });
+                                }
+                            } else {
+                                db.insert_event(NotificationMessage::FieldVerificationFailed {
+                                    context: context.clone(),
+                                    field: field_value.clone(),
+                                });
+
+                                db.record_failed_attempt(&context, &field_value, lockout);
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "Invalid challenge type when verifying message. This is a bug"
+                        ))
+                    }
+                }
+            }
+
+            // Check if the identity is fully verified.
+            db.process_fully_verified(&id_state);
+        }
+
+        Ok(())
+    }
+    async fn verify_second_challenge(
+        &self,
+        mut request: VerifyChallenge,
+        lockout: &LockoutPolicy,
+    ) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        let mut verified = false;
+
+        // Trim received challenge, just in case.
+        request.challenge = request.challenge.trim().to_string();
+
+        let states = db.select(|state| {
+            state
+                .fields
+                .iter()
+                .any(|field| field.value == request.entry)
+        });
+
+        for mut state in states {
+            let field_state = state
+                .fields
+                .iter_mut()
+                .find(|field| field.value == request.entry)
+                .unwrap();
+
+            if field_state.is_locked() {
+                continue;
+            }
+
+            let context = state.context.clone();
+            let field_value = field_state.value.clone();
+
+            match &mut field_state.challenge {
+                ChallengeType::ExpectedMessage {
+                    expected: _,
+                    second: Some(second),
+                } => {
+                    if !second.is_expired() && request.challenge.contains(&second.value) {
+                        second.set_verified();
+                        verified = true;
+
+                        if let Some(field) = db.field_mut(&context, &field_value) {
+                            if let ChallengeType::ExpectedMessage {
+                                second: Some(second),
+                                ..
+                            } = &mut field.challenge
+                            {
+                                second.set_verified();
+                            }
+                        }
+
+                        db.insert_event(NotificationMessage::SecondFieldVerified {
+                            context: context.clone(),
+                            field: field_value.clone(),
+                        });
+                    } else {
+                        db.insert_event(NotificationMessage::SecondFieldVerificationFailed {
+                            context: context.clone(),
+                            field: field_value.clone(),
+                        });
+
+                        db.record_failed_attempt(&context, &field_value, lockout);
+                    }
+                }
+                // This should never happens, but the provided field value
+                // depends on user input, so...
+                ChallengeType::ExpectedMessage { second: None, .. } => continue,
+                _ => {
+                    panic!("Invalid challenge type when verifying message");
+                }
+            }
+
+            // Check if the identity is fully verified.
+            db.process_fully_verified(&state);
+        }
+
+        Ok(verified)
+    }
+    async fn verify_signed_message(
+        &self,
+        fingerprint: &str,
+        text: &str,
+        lockout: &LockoutPolicy,
+    ) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        let entry = IdentityFieldValue::PGPFingerprint(fingerprint.to_string());
+        let mut verified = false;
+
+        let states = db.select(|state| state.fields.iter().any(|field| field.value == entry));
+
+        for mut state in states {
+            let field_state = state
+                .fields
+                .iter_mut()
+                .find(|field| field.value == entry)
+                .unwrap();
+
+            if field_state.is_locked() {
+                continue;
+            }
+
+            let context = state.context.clone();
+            let field_value = field_state.value.clone();
+
+            match &mut field_state.challenge {
+                ChallengeType::SignedMessage { expected } => {
+                    if expected.is_verified {
+                        continue;
+                    }
+
+                    if !expected.is_expired() && text.contains(&expected.value) {
+                        expected.set_verified();
+                        verified = true;
+
+                        if let Some(field) = db.field_mut(&context, &entry) {
+                            if let ChallengeType::SignedMessage { expected } = &mut field.challenge
+                            {
+                                expected.set_verified();
+                            }
+                        }
+
+                        db.insert_event(NotificationMessage::FieldVerified {
+                            context: context.clone(),
+                            field: field_value,
+                        });
+                    } else {
+                        db.insert_event(NotificationMessage::FieldVerificationFailed {
+                            context: context.clone(),
+                            field: field_value,
+                        });
+
+                        db.record_failed_attempt(&context, &entry, lockout);
+                    }
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid challenge type when verifying signed message. This is a bug"
+                    ))
+                }
+            }
+
+            // Check if the identity is fully verified.
+            db.process_fully_verified(&state);
+        }
+
+        Ok(verified)
+    }
+    async fn fetch_second_challenge(
+        &self,
+        context: &IdentityContext,
+        field: &IdentityFieldValue,
+    ) -> Result<ExpectedMessage> {
+        let db = self.state.lock().await;
+
+        let field_state = db
+            .identity(context)
+            .and_then(|state| state.fields.iter().find(|f| &f.value == field))
+            .ok_or_else(|| anyhow!("No entry found for {:?}", field))?;
+
+        match &field_state.challenge {
+            ChallengeType::ExpectedMessage {
+                expected: _,
+                second: Some(second),
+            } => Ok(second.clone()),
+            _ => Err(anyhow!("No second challenge found for {:?}", field)),
+        }
+    }
+    async fn regenerate_expired_challenges(&self, network: &ChainName, ttl: u64) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let states = db.select(|state| &state.context.chain == network && !state.is_fully_verified);
+
+        for state in states {
+            for field in state.fields {
+                let current = match db.field_mut(&state.context, &field.value) {
+                    Some(current) => current,
+                    None => continue,
+                };
+
+                if !current.challenge.regenerate_expired(ttl) {
+                    continue;
+                }
+
+                let challenge = current.challenge.clone();
+
+                db.insert_event(NotificationMessage::ChallengeRegenerated {
+                    context: state.context.clone(),
+                    field: field.value.clone(),
+                });
+
+                db.notify_second_challenge_change(&state.context, &field, &challenge);
+            }
+        }
+
+        Ok(())
+    }
+    async fn apply_lockout_policy(
+        &self,
+        network: &ChainName,
+        config: &LockoutConfig,
+    ) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let mut events = vec![];
+        for state in db
+            .identities
+            .iter_mut()
+            .filter(|state| &state.context.chain == network && !state.is_fully_verified)
+        {
+            for field in &mut state.fields {
+                events.extend(apply_lockout(&state.context, field, config));
+            }
+        }
+
+        for event in events {
+            db.insert_event(event);
+        }
+
+        Ok(())
+    }
+    async fn subscribe_events(&self, subscriber: &str) -> Result<Box<dyn EventSubscription>> {
+        let db = self.state.lock().await;
+
+        Ok(Box::new(MemorySubscription {
+            state: Arc::clone(&self.state),
+            events_added: Arc::clone(&db.events_added),
+            subscriber: subscriber.to_string(),
+            position: db
+                .subscriber_positions
+                .get(subscriber)
+                .copied()
+                .unwrap_or(db.next_event_id),
+            pending: false,
+        }))
+    }
+    async fn fetch_judgement_state(
+        &self,
+        context: &IdentityContext,
+    ) -> Result<Option<JudgementState>> {
+        Ok(self.state.lock().await.identity(context).cloned())
+    }
+    async fn fetch_judgement_history(
+        &self,
+        context: &IdentityContext,
+    ) -> Result<Vec<JudgementHistoryEntry>> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .history
+            .iter()
+            .filter(|entry| &entry.state.context == context)
+            .cloned()
+            .collect())
+    }
+    async fn fetch_unverified_web_domains(&self) -> Result<Vec<String>> {
+        let db = self.state.lock().await;
+
+        let mut domains = vec![];
+        for state in &db.identities {
+            for field in &state.fields {
+                if let IdentityFieldValue::Web(domain) = &field.value {
+                    if !field.challenge.is_verified() {
+                        domains.push(domain.clone());
+                    }
+                }
+            }
+        }
+
+        domains.sort();
+        domains.dedup();
+
+        Ok(domains)
+    }
+    async fn fetch_judgement_candidates(&self, network: &ChainName) -> Result<Vec<JudgementState>> {
+        let now = Timestamp::now().raw();
+
+        Ok(self.state.lock().await.select(|state| {
+            &state.context.chain == network
+                // Identities can be judged without being fully verified,
+                // e.g. when being flagged as erroneous.
+                && (state.is_fully_verified || state.judgement.is_some())
+                && !state.judgement_submitted
+                && state
+                    .issue_judgement_at
+                    .map(|at| at.raw() < now)
+                    .unwrap_or(false)
+                && state
+                    .submission
+                    .as_ref()
+                    .map(|s| !s.failed && s.next_attempt.raw() <= now)
+                    .unwrap_or(true)
+        }))
+    }
+    async fn record_judgement_submission(
+        &self,
+        context: &IdentityContext,
+        judgement: Judgement,
+        max_attempts: Option<usize>,
+    ) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        let state = match db.identity_mut(context) {
+            Some(state) if !state.judgement_submitted => state,
+            _ => return Ok(false),
+        };
+
+        if record_submission_attempt(state, judgement, max_attempts) {
+            return Ok(true);
+        }
+
+        db.insert_event(NotificationMessage::JudgementSubmissionFailed {
+            context: context.clone(),
+            judgement,
+        });
+
+        Ok(false)
+    }
+    async fn record_judgement_error(&self, context: &IdentityContext, error: &str) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        if let Some(state) = db.identity_mut(context) {
+            if let Some(submission) = state.submission.as_mut() {
+                if !state.judgement_submitted {
+                    submission.last_error = Some(error.to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+    async fn fetch_failed_submissions(&self) -> Result<Vec<JudgementState>> {
+        Ok(self.state.lock().await.select(|state| {
+            !state.judgement_submitted
+                && state.submission.as_ref().map(|s| s.failed).unwrap_or(false)
+        }))
+    }
+    async fn retry_judgement_submission(&self, context: &IdentityContext) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        let state = match db.identity_mut(context) {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        let failed = !state.judgement_submitted
+            && state.submission.as_ref().map(|s| s.failed).unwrap_or(false);
+
+        if failed {
+            state.submission = None;
+        }
+
+        Ok(failed)
+    }
+    async fn fetch_pending_requests(&self, network: &ChainName) -> Result<Vec<JudgementState>> {
+        Ok(self.state.lock().await.select(|state| {
+            &state.context.chain == network
+                && !state.judgement_submitted
+                && state.submission.is_none()
+        }))
+    }
+    async fn cancel_judgement_request(
+        &self,
+        context: &IdentityContext,
+        reason: CancellationReason,
+    ) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        if db.identity(context).is_none() {
+            return Ok(false);
+        }
+
+        db.record_history(context, StateTransition::Cancelled);
+        db.identities.retain(|state| &state.context != context);
+        db.insert_event(NotificationMessage::JudgementRequestCancelled {
+            context: context.clone(),
+            reason,
+        });
+
+        Ok(true)
+    }
+    async fn full_manual_verification(&self, context: &IdentityContext) -> Result<bool> {
+        let mut db = self.state.lock().await;
+
+        let state = match db.identity_mut(context) {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        // Create a timed delay for issuing judgments. Between 30 seconds to
+        // 5 minutes. This is used to prevent timing attacks where a user
+        // updates the identity right before the judgement is issued.
+        let offset = thread_rng().gen_range(30..300);
+
+        state.is_fully_verified = true;
+        state.judgement_submitted = false;
+        state.submission = None;
+        state.completion_timestamp = Some(Timestamp::now());
+        state.issue_judgement_at = Some(Timestamp::with_offset(offset));
+        set_default_judgement(state);
+
+        // Verify all possible fields. Unused fields are silently ignored.
+        for field in [
+            RawFieldName::LegalName,
+            RawFieldName::DisplayName,
+            RawFieldName::Email,
+            RawFieldName::Web,
+            RawFieldName::Twitter,
+            RawFieldName::Matrix,
+        ] {
+            let _ = db.verify_manually(context, &field, false)?;
+        }
+
+        db.insert_event(NotificationMessage::FullManualVerification {
+            context: context.clone(),
+        });
+        db.record_history(context, StateTransition::FullyVerified);
+
+        Ok(true)
+    }
+    async fn set_pending_judgement(
+        &self,
+        context: &IdentityContext,
+        judgement: Judgement,
+    ) -> Result<bool> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .set_pending_judgement(context, judgement))
+    }
+    async fn apply_failed_attempts_policy(
+        &self,
+        network: &ChainName,
+        max_failed_attempts: usize,
+    ) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let flagged = db.select(|state| {
+            &state.context.chain == network
+                && !state.is_fully_verified
+                && !state.judgement_submitted
+                && state.judgement.is_none()
+                && state
+                    .fields
+                    .iter()
+                    .any(|field| field.failed_attempts >= max_failed_attempts)
+        });
+
+        for state in flagged {
+            info!(
+                "Too many failed verification attempts, issuing erroneous judgement: {:?}",
+                state.context
+            );
+
+            db.set_pending_judgement(&state.context, Judgement::Erroneous);
+        }
+
+        Ok(())
+    }
+    async fn set_judged(&self, context: &IdentityContext) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        if let Some(state) = db.identity_mut(context) {
+            if !state.judgement_submitted {
+                state.judgement_submitted = true;
+
+                db.insert_event(NotificationMessage::JudgementProvided {
+                    context: context.clone(),
+                });
+                db.record_history(context, StateTransition::JudgementProvided);
+            }
+        }
+
+        Ok(())
+    }
+    async fn insert_display_name(&self, name: &DisplayNameEntry) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        if !db.display_names.contains(name) {
+            db.display_names.push(name.clone());
+        }
+
+        Ok(())
+    }
+    async fn fetch_display_names(&self, chain: &ChainName) -> Result<Vec<DisplayNameEntry>> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .display_names
+            .iter()
+            .filter(|name| &name.context.chain == chain)
+            .cloned()
+            .collect())
+    }
+    async fn remove_display_names(&self, names: &[DisplayNameEntry]) -> Result<()> {
+        self.state
+            .lock()
+            .await
+            .display_names
+            .retain(|name| !names.contains(name));
+
+        Ok(())
+    }
+    async fn set_display_name_valid(&self, state: &JudgementState) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let field = state
+            .fields
+            .iter()
+            .find(|field| matches!(field.value, IdentityFieldValue::DisplayName(_)))
+            .map(|field| field.value.clone())
+            .expect("Failed to retrieve display name. This is a bug");
+
+        if let Some(current) = db.field_mut(&state.context, &field) {
+            if let ChallengeType::DisplayNameCheck { passed, .. } = &mut current.challenge {
+                *passed = true;
+            }
+        }
+
+        // Create event
+        db.insert_event(NotificationMessage::FieldVerified {
+            context: state.context.clone(),
+            field,
+        });
+
+        db.process_fully_verified(state);
+
+        Ok(())
+    }
+    async fn insert_display_name_violations(
+        &self,
+        context: &IdentityContext,
+        violations: &[DisplayNameEntry],
+    ) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        let field = db.identity_mut(context).and_then(|state| {
+            state
+                .fields
+                .iter_mut()
+                .find(|field| matches!(field.value, IdentityFieldValue::DisplayName(_)))
+        });
+
+        if let Some(field) = field {
+            if let ChallengeType::DisplayNameCheck {
+                passed,
+                violations: current,
+            } = &mut field.challenge
+            {
+                *passed = false;
+                *current = violations.to_vec();
+            }
+        }
+
+        Ok(())
+    }
+    async fn process_interrupted_updates(&self) -> Result<()> {
+        // Updates are applied at once, nothing can be interrupted.
+        Ok(())
+    }
+    async fn prune_events(
+        &self,
+        message_type: &str,
+        max_age: u64,
+        archive: Option<&EventArchive>,
+    ) -> Result<usize> {
+        let mut db = self.state.lock().await;
+
+        let threshold = Timestamp::now().raw().saturating_sub(max_age);
+        let is_expired = |event: &Event| -> Result<bool> {
+            let message = serde_json::to_value(&event.message)?;
+            Ok(event.timestamp.raw() < threshold && message["type"] == message_type)
+        };
+
+        let mut expired = vec![];
+        for (_, event) in &db.events {
+            if is_expired(event)? {
+                expired.push(event.clone());
+            }
+        }
+
+        if let Some(archive) = archive {
+            archive.append(&expired)?;
+        }
+
+        let mut remaining = vec![];
+        for (id, event) in db.events.drain(..) {
+            if !is_expired(&event)? {
+                remaining.push((id, event));
+            }
+        }
+        db.events = remaining;
+
+        Ok(expired.len())
+    }
+    async fn export_identities(&self, filter: &ExportFilter) -> Result<Vec<JudgementState>> {
+        Ok(self.state.lock().await.select(|state| {
+            filter.matches_context(&state.context)
+                && filter.matches_timestamp(&state.inserted_timestamp)
+        }))
+    }
+    async fn export_display_names(&self, filter: &ExportFilter) -> Result<Vec<DisplayNameEntry>> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .display_names
+            .iter()
+            .filter(|name| filter.matches_context(&name.context))
+            .cloned()
+            .collect())
+    }
+    async fn export_events(&self, filter: &ExportFilter) -> Result<Vec<Event>> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .events
+            .iter()
+            .map(|(_, event)| event)
+            .filter(|event| {
+                filter.matches_context(event.message.context())
+                    && filter.matches_timestamp(&event.timestamp)
+            })
+            .cloned()
+            .collect())
+    }
+    async fn import_identity(&self, state: &JudgementState) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        match db.identity_mut(&state.context) {
+            Some(current) => *current = state.clone(),
+            None => db.identities.push(state.clone()),
+        }
+
+        Ok(())
+    }
+    async fn import_event(&self, event: &Event) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        if !db.events.iter().any(|(_, existing)| existing == event) {
+            db.insert_event(event.clone());
+        }
+
+        Ok(())
+    }
+    async fn erase_identity(&self, context: &IdentityContext) -> Result<ErasureReport> {
+        let mut db = self.state.lock().await;
+
+        // Cancelled identities are only kept in the history.
+        let state = db.identity(context).or_else(|| {
+            db.history
+                .iter()
+                .rev()
+                .map(|entry| &entry.state)
+                .find(|state| &state.context == context)
+        });
+
+        let tombstone = state.and_then(|state| {
+            let judged_at = db
+                .events
+                .iter()
+                .rev()
+                .map(|(_, event)| event)
+                .find(|event| {
+                    matches!(
+                        &event.message,
+                        NotificationMessage::JudgementProvided { context: c } if c == context
+                    )
+                })
+                .map(|event| event.timestamp);
+
+            create_tombstone(state, judged_at)
+        });
+
+        if let Some(tombstone) = &tombstone {
+            // Keep the first tombstone if the erasure is repeated.
+            if !db.tombstones.iter().any(|t| t.context == tombstone.context) {
+                db.tombstones.push(tombstone.clone());
+            }
+        }
+
+        let identities = db.identities.len();
+        db.identities.retain(|state| &state.context != context);
+
+        let events = db.events.len();
+        db.events
+            .retain(|(_, event)| event.message.context() != context);
+
+        let display_names = db.display_names.len();
+        db.display_names.retain(|name| &name.context != context);
+
+        let history = db.history.len();
+        db.history.retain(|entry| &entry.state.context != context);
+
+        let mut violations = 0;
+        for state in db.identities.iter_mut() {
+            if state.anonymise_violations(context) {
+                violations += 1;
+            }
+        }
+        for entry in db.history.iter_mut() {
+            if entry.state.anonymise_violations(context) {
+                violations += 1;
+            }
+        }
+
+        Ok(ErasureReport {
+            context: context.clone(),
+            identity_removed: db.identities.len() < identities,
+            events_removed: events - db.events.len(),
+            display_names_removed: display_names - db.display_names.len(),
+            history_entries_removed: history - db.history.len(),
+            violations_anonymised: violations,
+            archived_events_removed: None,
+            tombstone,
+        })
+    }
+    async fn store_watcher_status(&self, report: &WatcherStatusReport) -> Result<()> {
+        let mut db = self.state.lock().await;
+
+        db.watcher_status
+            .retain(|entry| entry.network != report.network);
+        db.watcher_status.push(report.clone());
+
+        Ok(())
+    }
+    async fn fetch_watcher_status(&self) -> Result<Vec<WatcherStatusReport>> {
+        Ok(self.state.lock().await.watcher_status.clone())
+    }
+    #[cfg(test)]
+    async fn fetch_tombstones(&self, context: &IdentityContext) -> Result<Vec<Tombstone>> {
+        Ok(self
+            .state
+            .lock()
+            .await
+            .tombstones
+            .iter()
+            .filter(|tombstone| &tombstone.context == context)
+            .cloned()
+            .collect())
+    }
+}
+
+struct MemorySubscription {
+    state: Arc<Mutex<MemoryState>>,
+    events_added: Arc<Notify>,
+    subscriber: String,
+    // The id of the next event to return.
+    position: u64,
+    // Whether the position was not persisted yet.
+    pending: bool,
+}
+
+#[async_trait]
+impl EventSubscription for MemorySubscription {
+    async fn next(&mut self) -> Result<NotificationMessage> {
+        let events_added = Arc::clone(&self.events_added);
+
+        loop {
+            // Must be created before checking for new events, otherwise a
+            // notification could get lost.
+            let notified = events_added.notified();
+
+            {
+                let mut db = self.state.lock().await;
+
+                if self.pending {
+                    db.subscriber_positions
+                        .insert(self.subscriber.clone(), self.position);
+                    self.pending = false;
+                }
+
+                let index = db.events.partition_point(|(id, _)| *id < self.position);
+                if let Some((id, event)) = db.events.get(index) {
+                    self.position = id + 1;
+                    self.pending = true;
+
+                    return Ok(event.message.clone());
+                }
+            }
+
+            notified.await;
+        }
+    }
+}
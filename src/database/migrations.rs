@@ -0,0 +1,224 @@
+use super::{DISPLAY_NAMES, EVENT_COLLECTION, IDENTITY_COLLECTION, JUDGEMENT_HISTORY, TOMBSTONES};
+use crate::primitives::ExpectedMessage;
+use crate::Result;
+use bson::{doc, to_bson, Bson, Document};
+
+/// Every collection which has a schema version. Collections which are not
+/// listed here (e.g. the positions of event subscribers) are not versioned.
+pub const VERSIONED_COLLECTIONS: &[&str] = &[
+    IDENTITY_COLLECTION,
+    EVENT_COLLECTION,
+    DISPLAY_NAMES,
+    JUDGEMENT_HISTORY,
+    TOMBSTONES,
+];
+
+/// Upgrades the documents of a collection from `version - 1` to `version`.
+pub struct Migration {
+    pub collection: &'static str,
+    pub version: u32,
+    pub description: &'static str,
+    // Upgrades a single document in place. Must leave documents which are
+    // already upgraded untouched, since interrupted migrations get applied
+    // again from the start.
+    pub upgrade: fn(&mut Document) -> Result<()>,
+}
+
+impl Migration {
+    pub fn apply(&self, doc: &mut Document) -> Result<()> {
+        (self.upgrade)(doc).map_err(|err| {
+            anyhow!(
+                "Failed to apply migration {} of '{}' ({}): {:?}",
+                self.version,
+                self.collection,
+                self.description,
+                err
+            )
+        })
+    }
+}
+
+/// All migrations, ordered by version per collection. Existing entries must
+/// never be changed, only appended.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        collection: IDENTITY_COLLECTION,
+        version: 1,
+        description: "store PGP fingerprints as strings",
+        upgrade: pgp_fingerprint_as_string,
+    },
+    Migration {
+        collection: IDENTITY_COLLECTION,
+        version: 2,
+        description: "add creation time and TTL of challenges",
+        upgrade: add_challenge_ttl,
+    },
+    Migration {
+        collection: IDENTITY_COLLECTION,
+        version: 3,
+        description: "add lockout state of fields",
+        upgrade: add_lockout_state,
+    },
+    Migration {
+        collection: IDENTITY_COLLECTION,
+        version: 4,
+        description: "add scheduled judgement",
+        upgrade: add_scheduled_judgement,
+    },
+    Migration {
+        collection: IDENTITY_COLLECTION,
+        version: 5,
+        description: "add judgement submission",
+        upgrade: add_judgement_submission,
+    },
+    Migration {
+        collection: IDENTITY_COLLECTION,
+        version: 6,
+        description: "add challenges of web fields",
+        upgrade: add_web_challenge,
+    },
+];
+
+/// The schema version the current code reads and writes.
+pub fn latest_version(collection: &str) -> u32 {
+    MIGRATIONS
+        .iter()
+        .filter(|migration| migration.collection == collection)
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The migrations required to upgrade the collection from the given version
+/// to the latest one, in the order they must be applied.
+pub fn pending(collection: &str, version: u32) -> impl Iterator<Item = &'static Migration> + '_ {
+    MIGRATIONS
+        .iter()
+        .filter(move |migration| migration.collection == collection && migration.version > version)
+}
+
+/// Upgrades a single document of the collection from the given version to
+/// the latest one.
+#[cfg(test)]
+pub fn upgrade_document(collection: &str, version: u32, doc: &mut Document) -> Result<()> {
+    for migration in pending(collection, version) {
+        migration.apply(doc)?;
+    }
+
+    Ok(())
+}
+
+/// Calls `f` for each entry of the `fields` array of an identity.
+fn for_each_field<F>(doc: &mut Document, mut f: F) -> Result<()>
+where
+    F: FnMut(&mut Document) -> Result<()>,
+{
+    let fields = doc
+        .get_array_mut("fields")
+        .map_err(|err| anyhow!("Invalid identity document: {:?}", err))?;
+
+    for field in fields {
+        match field {
+            Bson::Document(field) => f(field)?,
+            _ => return Err(anyhow!("Invalid field entry in identity document")),
+        }
+    }
+
+    Ok(())
+}
+
+fn set_if_missing(doc: &mut Document, key: &str, value: Bson) {
+    if !doc.contains_key(key) {
+        doc.insert(key, value);
+    }
+}
+
+// Older entries stored the PGP fingerprint as `null`.
+fn pgp_fingerprint_as_string(doc: &mut Document) -> Result<()> {
+    for_each_field(doc, |field| {
+        if let Ok(value) = field.get_document_mut("value") {
+            if value.get_str("type") == Ok("p_g_p_fingerprint")
+                && matches!(value.get("value"), None | Some(Bson::Null))
+            {
+                value.insert("value", "");
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn add_challenge_ttl(doc: &mut Document) -> Result<()> {
+    for_each_field(doc, |field| {
+        let content = match field
+            .get_document_mut("challenge")
+            .and_then(|challenge| challenge.get_document_mut("content"))
+        {
+            Ok(content) => content,
+            Err(_) => return Ok(()),
+        };
+
+        for key in &["expected", "second"] {
+            if let Ok(message) = content.get_document_mut(key) {
+                set_if_missing(message, "created_at", Bson::Int64(0));
+                set_if_missing(message, "ttl", Bson::Null);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn add_lockout_state(doc: &mut Document) -> Result<()> {
+    for_each_field(doc, |field| {
+        set_if_missing(field, "recent_failures", Bson::Array(vec![]));
+        set_if_missing(field, "locked_until", Bson::Null);
+        set_if_missing(field, "flagged", Bson::Boolean(false));
+        Ok(())
+    })
+}
+
+fn add_scheduled_judgement(doc: &mut Document) -> Result<()> {
+    set_if_missing(doc, "judgement", Bson::Null);
+    Ok(())
+}
+
+fn add_judgement_submission(doc: &mut Document) -> Result<()> {
+    set_if_missing(doc, "submission", Bson::Null);
+    Ok(())
+}
+
+// Web fields were not verified before, those got an unsupported challenge.
+// Manual verifications via the admin interface are kept.
+fn add_web_challenge(doc: &mut Document) -> Result<()> {
+    for_each_field(doc, |field| {
+        let is_web = field
+            .get_document("value")
+            .map(|value| value.get_str("type") == Ok("web"))
+            .unwrap_or(false);
+
+        let is_verified = match field.get_document("challenge") {
+            Ok(challenge) if is_web && challenge.get_str("type") == Ok("unsupported") => challenge
+                .get_document("content")
+                .and_then(|content| content.get_bool("is_verified"))
+                .unwrap_or(false),
+            _ => return Ok(()),
+        };
+
+        let mut expected = ExpectedMessage::random();
+        expected.is_verified = is_verified;
+
+        field.insert(
+            "challenge",
+            doc! {
+                "type": "expected_message",
+                "content": {
+                    "expected": to_bson(&expected)?,
+                    "second": Bson::Null,
+                },
+            },
+        );
+
+        Ok(())
+    })
+}
@@ -0,0 +1,471 @@
+use crate::adapters::admin::RawFieldName;
+use crate::api::VerifyChallenge;
+use crate::connector::{CancellationReason, DisplayNameEntry, Judgement, WatcherStatusReport};
+use crate::primitives::{
+    ChainName, ChallengeType, ErasureReport, Event, ExpectedMessage, ExternalMessage,
+    IdentityContext, IdentityField, IdentityFieldValue, JudgementHistoryEntry, JudgementState,
+    JudgementSubmission, NotificationMessage, Timestamp, Tombstone,
+};
+use crate::{DatabaseBackend, DatabaseConfig, LockoutAction, LockoutConfig, LockoutPolicy, Result};
+use std::ops::Deref;
+use std::sync::Arc;
+
+pub use export::ExportFilter;
+pub use memory::MemoryStorage;
+pub use mongo::MongoStorage;
+pub use retention::EventArchive;
+
+pub mod export;
+mod memory;
+pub mod migrations;
+mod mongo;
+pub mod retention;
+
+// In seconds. The delay between attempts to submit a judgement doubles with
+// every attempt, starting at the base delay.
+#[cfg(not(test))]
+const SUBMISSION_RETRY_BASE_DELAY: u64 = 60;
+#[cfg(test)]
+const SUBMISSION_RETRY_BASE_DELAY: u64 = 1;
+const SUBMISSION_RETRY_MAX_DELAY: u64 = 3600;
+
+pub const IDENTITY_COLLECTION: &str = "identities";
+pub const EVENT_COLLECTION: &str = "event_log";
+pub const DISPLAY_NAMES: &str = "display_names";
+pub const JUDGEMENT_HISTORY: &str = "judgement_history";
+pub const TOMBSTONES: &str = "tombstones";
+
+/// Sets the flag, returns whether the value was modified.
+fn set_flag(flag: &mut bool) -> bool {
+    let modified = !*flag;
+    *flag = true;
+    modified
+}
+
+fn matches_field_name(value: &IdentityFieldValue, field: &RawFieldName) -> bool {
+    matches!(
+        (value, field),
+        (IdentityFieldValue::LegalName(_), RawFieldName::LegalName)
+            | (
+                IdentityFieldValue::DisplayName(_),
+                RawFieldName::DisplayName
+            )
+            | (IdentityFieldValue::Email(_), RawFieldName::Email)
+            | (IdentityFieldValue::Web(_), RawFieldName::Web)
+            | (IdentityFieldValue::Twitter(_), RawFieldName::Twitter)
+            | (IdentityFieldValue::Matrix(_), RawFieldName::Matrix)
+    )
+}
+
+/// Creates the tombstone of the identity, if a judgement was given. The
+/// judgement is provided at the given time, if known.
+fn create_tombstone(state: &JudgementState, judged_at: Option<Timestamp>) -> Option<Tombstone> {
+    if !state.judgement_submitted {
+        return None;
+    }
+
+    Some(Tombstone {
+        context: state.context.clone(),
+        // States judged before judgements were recorded do not have one set.
+        judgement: state.judgement.unwrap_or(Judgement::Reasonable),
+        judged_at,
+        erased_at: Timestamp::now(),
+    })
+}
+
+/// If the first challenge was already verified and the second challenge got
+/// replaced, the new second challenge must be sent to the user.
+fn second_challenge_change(
+    context: &IdentityContext,
+    field: &IdentityFieldValue,
+    old: &ChallengeType,
+    new: &ChallengeType,
+) -> Option<NotificationMessage> {
+    if let (
+        ChallengeType::ExpectedMessage {
+            expected,
+            second: Some(new),
+        },
+        ChallengeType::ExpectedMessage {
+            expected: _,
+            second: Some(old),
+        },
+    ) = (new, old)
+    {
+        if expected.is_verified && new.value != old.value {
+            return Some(NotificationMessage::AwaitingSecondChallenge {
+                context: context.clone(),
+                field: field.clone(),
+            });
+        }
+    }
+
+    None
+}
+
+/// Applies the `LockoutAction` to the field if it failed verification at
+/// least `max_failed_attempts` times within the window. Older failed attempts
+/// are discarded. Returns the resulting events.
+fn apply_lockout(
+    context: &IdentityContext,
+    field: &mut IdentityField,
+    config: &LockoutConfig,
+) -> Vec<NotificationMessage> {
+    let max_failed_attempts = match config.max_failed_attempts {
+        Some(max) => max,
+        None => return vec![],
+    };
+
+    let cutoff = Timestamp::now().raw().saturating_sub(config.window);
+    field.recent_failures.retain(|t| t.raw() >= cutoff);
+
+    if field.recent_failures.len() < max_failed_attempts || field.is_locked() {
+        return vec![];
+    }
+
+    info!(
+        "Too many failed verification attempts, applying {:?} to {:?} of {:?}",
+        config.action, field.value, context
+    );
+
+    field.recent_failures.clear();
+
+    let mut events = vec![];
+    match config.action {
+        LockoutAction::Lock => {
+            let locked_until = Timestamp::with_offset(config.cooldown);
+            field.locked_until = Some(locked_until);
+
+            events.push(NotificationMessage::FieldLocked {
+                context: context.clone(),
+                field: field.value.clone(),
+                locked_until,
+            });
+        }
+        LockoutAction::Regenerate => {
+            let old = field.challenge.clone();
+            field.challenge.regenerate_unverified();
+
+            events.push(NotificationMessage::FieldChallengeReset {
+                context: context.clone(),
+                field: field.value.clone(),
+            });
+            events.extend(second_challenge_change(
+                context,
+                &field.value,
+                &old,
+                &field.challenge,
+            ));
+        }
+        LockoutAction::Flag => {
+            // Only notify once.
+            if set_flag(&mut field.flagged) {
+                events.push(NotificationMessage::FieldFlagged {
+                    context: context.clone(),
+                    field: field.value.clone(),
+                });
+            }
+        }
+    }
+
+    events
+}
+
+/// Counts a failed verification attempt of the field and applies the lockout
+/// policy right away. Returns the resulting events.
+fn record_failed_attempt(
+    context: &IdentityContext,
+    field: &mut IdentityField,
+    config: &LockoutConfig,
+) -> Vec<NotificationMessage> {
+    field.failed_attempts += 1;
+    field.recent_failures.push(Timestamp::now());
+
+    apply_lockout(context, field, config)
+}
+
+/// Sets the `Reasonable` judgement, unless a different judgement was already
+/// decided on (e.g. by an admin).
+fn set_default_judgement(state: &mut JudgementState) {
+    if state.judgement.is_none() {
+        state.judgement = Some(Judgement::Reasonable);
+    }
+}
+
+/// The delay before the next attempt to submit a judgement, after the given
+/// number of attempts.
+pub fn submission_retry_delay(attempts: usize) -> u64 {
+    let exponent = attempts.saturating_sub(1).min(u32::MAX as usize) as u32;
+
+    SUBMISSION_RETRY_BASE_DELAY
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(SUBMISSION_RETRY_MAX_DELAY)
+}
+
+/// Records an attempt to submit the judgement of the identity. Returns
+/// `false` if all attempts were used up, the submission is marked as failed
+/// instead.
+fn record_submission_attempt(
+    state: &mut JudgementState,
+    judgement: Judgement,
+    max_attempts: Option<usize>,
+) -> bool {
+    let submission = state
+        .submission
+        .get_or_insert_with(|| JudgementSubmission::new(judgement));
+
+    if max_attempts
+        .map(|max| submission.attempts >= max)
+        .unwrap_or(false)
+    {
+        submission.failed = true;
+        return false;
+    }
+
+    submission.attempts += 1;
+    submission.last_attempt = Timestamp::now();
+    submission.next_attempt = Timestamp::with_offset(submission_retry_delay(submission.attempts));
+
+    true
+}
+
+/// The persistence operations of the registrar. Every backend must provide
+/// the same semantics, including the creation of events in the event log.
+#[async_trait]
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    /// Checks if a connection could be established to the database and if
+    /// all indexes exist. See `ensure_indexes`.
+    async fn connectivity_check(&self) -> Result<()>;
+    /// Upgrades the stored documents to the latest schema version of each
+    /// collection. See `migrations::MIGRATIONS`. Safe to run concurrently and
+    /// repeatedly.
+    async fn migrate(&self) -> Result<()>;
+    /// Creates the indexes required by the queries of the backend, if they do
+    /// not exist yet.
+    async fn ensure_indexes(&self) -> Result<()>;
+    async fn add_judgement_request(&self, request: &JudgementState) -> Result<bool>;
+    #[cfg(test)]
+    async fn delete_judgement(&self, context: &IdentityContext) -> Result<()>;
+    async fn verify_manually(
+        &self,
+        context: &IdentityContext,
+        field: &RawFieldName,
+        // Whether it should check if the idenity has been fully verified.
+        full_check: bool,
+    ) -> Result<Option<()>>;
+    /// Verifies the challenge of every field matching the origin of the
+    /// message. The lockout policy is applied to failed attempts right away.
+    async fn verify_message(
+        &self,
+        message: &ExternalMessage,
+        lockout: &LockoutPolicy,
+    ) -> Result<()>;
+    async fn verify_second_challenge(
+        &self,
+        request: VerifyChallenge,
+        lockout: &LockoutPolicy,
+    ) -> Result<bool>;
+    /// Verifies the signed message challenge of every field with the given
+    /// (PGP) fingerprint. The signature itself must have been verified by the
+    /// caller.
+    async fn verify_signed_message(
+        &self,
+        fingerprint: &str,
+        text: &str,
+        lockout: &LockoutPolicy,
+    ) -> Result<bool>;
+    async fn fetch_second_challenge(
+        &self,
+        context: &IdentityContext,
+        field: &IdentityFieldValue,
+    ) -> Result<ExpectedMessage>;
+    /// Replaces the expired challenges of identities which are not fully
+    /// verified yet and sets the TTL of challenges which were created without
+    /// one. See `ChallengeType::regenerate_expired`.
+    async fn regenerate_expired_challenges(&self, network: &ChainName, ttl: u64) -> Result<()>;
+    /// Applies the configured `LockoutAction` to fields which failed
+    /// verification at least `max_failed_attempts` times within the
+    /// configured window. Older failed attempts are discarded. Failed
+    /// attempts are checked when they happen already, this catches up on
+    /// changes of the configuration.
+    async fn apply_lockout_policy(&self, network: &ChainName, config: &LockoutConfig)
+        -> Result<()>;
+    /// Subscribes to the event log. The subscription continues where the
+    /// subscriber with the same name left off. New subscribers only receive
+    /// events which get inserted after subscribing.
+    async fn subscribe_events(&self, subscriber: &str) -> Result<Box<dyn EventSubscription>>;
+    async fn fetch_judgement_state(
+        &self,
+        context: &IdentityContext,
+    ) -> Result<Option<JudgementState>>;
+    /// Fetches the snapshots of the identity, oldest first. See
+    /// `StateTransition` for when snapshots are taken.
+    async fn fetch_judgement_history(
+        &self,
+        context: &IdentityContext,
+    ) -> Result<Vec<JudgementHistoryEntry>>;
+    /// Fetches the (raw) values of all web fields which have not been
+    /// verified yet.
+    async fn fetch_unverified_web_domains(&self) -> Result<Vec<String>>;
+    /// Fetches the identities of the network whose judgement is due, including
+    /// submissions which are due for a retry. Failed submissions are excluded.
+    async fn fetch_judgement_candidates(&self, network: &ChainName) -> Result<Vec<JudgementState>>;
+    /// Records an attempt to submit the judgement, see `JudgementSubmission`.
+    /// Returns `false` if the identity was already judged or no attempts are
+    /// left. In the latter case the submission is marked as failed. The
+    /// judgement must only be sent if `true` is returned.
+    async fn record_judgement_submission(
+        &self,
+        context: &IdentityContext,
+        judgement: Judgement,
+        max_attempts: Option<usize>,
+    ) -> Result<bool>;
+    /// Stores an error reported by the Watcher for the pending submission of
+    /// the identity.
+    async fn record_judgement_error(&self, context: &IdentityContext, error: &str) -> Result<()>;
+    /// Fetches all identities whose judgement submission failed.
+    async fn fetch_failed_submissions(&self) -> Result<Vec<JudgementState>>;
+    /// Resets a failed submission, so the judgement gets submitted again.
+    /// Returns whether a failed submission was found.
+    async fn retry_judgement_submission(&self, context: &IdentityContext) -> Result<bool>;
+    /// Fetches the requests of the network whose judgement was neither
+    /// provided nor submitted yet. Those must be pending on chain.
+    async fn fetch_pending_requests(&self, network: &ChainName) -> Result<Vec<JudgementState>>;
+    /// Removes the judgement request, including any pending submission. A
+    /// snapshot of the state is added to its history beforehand. Returns
+    /// `false` if the request does not exist.
+    async fn cancel_judgement_request(
+        &self,
+        context: &IdentityContext,
+        reason: CancellationReason,
+    ) -> Result<bool>;
+    // (Warning) This fully verifies the identity without having to verify
+    // individual fields.
+    async fn full_manual_verification(&self, context: &IdentityContext) -> Result<bool>;
+    /// Schedules the given judgement, independent of the verification status
+    /// of the identity.
+    async fn set_pending_judgement(
+        &self,
+        context: &IdentityContext,
+        judgement: Judgement,
+    ) -> Result<bool>;
+    /// Schedules an `Erroneous` judgement for identities where any of the
+    /// fields failed verification at least `max_failed_attempts` times.
+    async fn apply_failed_attempts_policy(
+        &self,
+        network: &ChainName,
+        max_failed_attempts: usize,
+    ) -> Result<()>;
+    async fn set_judged(&self, context: &IdentityContext) -> Result<()>;
+    async fn insert_display_name(&self, name: &DisplayNameEntry) -> Result<()>;
+    async fn fetch_display_names(&self, chain: &ChainName) -> Result<Vec<DisplayNameEntry>>;
+    async fn remove_display_names(&self, names: &[DisplayNameEntry]) -> Result<()>;
+    async fn set_display_name_valid(&self, state: &JudgementState) -> Result<()>;
+    async fn insert_display_name_violations(
+        &self,
+        context: &IdentityContext,
+        violations: &[DisplayNameEntry],
+    ) -> Result<()>;
+    /// Inserts the events and history entries of identity updates which were
+    /// interrupted before those were moved to their collections, and completes
+    /// interrupted cancellations.
+    async fn process_interrupted_updates(&self) -> Result<()>;
+    /// Removes all events of the given type (e.g. `field_verification_failed`)
+    /// which are older than `max_age` seconds. The events are appended to the
+    /// archive, if any, before they are removed. Returns the number of
+    /// removed events.
+    async fn prune_events(
+        &self,
+        message_type: &str,
+        max_age: u64,
+        archive: Option<&EventArchive>,
+    ) -> Result<usize>;
+    async fn export_identities(&self, filter: &ExportFilter) -> Result<Vec<JudgementState>>;
+    async fn export_display_names(&self, filter: &ExportFilter) -> Result<Vec<DisplayNameEntry>>;
+    /// Fetches the matching events, in insertion order.
+    async fn export_events(&self, filter: &ExportFilter) -> Result<Vec<Event>>;
+    /// Inserts the identity as is, replacing the identity with the same
+    /// context. Does not create any events.
+    async fn import_identity(&self, state: &JudgementState) -> Result<()>;
+    /// Inserts the event, unless the same event with the same timestamp
+    /// exists already.
+    async fn import_event(&self, event: &Event) -> Result<()>;
+    /// Removes all personal data of the identity: the identity itself, its
+    /// events, display names and history, and anonymises the display name
+    /// violations of other identities which refer to it. Identities which
+    /// were cancelled are erased as well. If a judgement was given, a
+    /// `Tombstone` is stored beforehand, at most one per identity. Use
+    /// `Database::erase` in order to clean the event archive as well.
+    async fn erase_identity(&self, context: &IdentityContext) -> Result<ErasureReport>;
+    /// Stores the connection states of the Watchers of the network, replacing
+    /// the previous report of the network.
+    async fn store_watcher_status(&self, report: &WatcherStatusReport) -> Result<()>;
+    async fn fetch_watcher_status(&self) -> Result<Vec<WatcherStatusReport>>;
+    #[cfg(test)]
+    async fn fetch_tombstones(&self, context: &IdentityContext) -> Result<Vec<Tombstone>>;
+}
+
+#[async_trait]
+pub trait EventSubscription: Send {
+    /// Waits for the next event, in insertion order. The position of the
+    /// previously returned event is persisted beforehand, so events are not
+    /// missed across restarts. The last event returned before a restart
+    /// might get delivered again.
+    async fn next(&mut self) -> Result<NotificationMessage>;
+}
+
+/// Handle to the configured storage backend. All operations of `Storage` can
+/// be called on it directly.
+#[derive(Debug, Clone)]
+pub struct Database {
+    storage: Arc<dyn Storage>,
+    // Events are removed from the archive as well when erasing an identity.
+    archive: Option<EventArchive>,
+}
+
+impl Database {
+    /// Connects to the MongoDB database.
+    pub async fn new(uri: &str, db: &str) -> Result<Self> {
+        Ok(Database {
+            storage: Arc::new(MongoStorage::new(uri, db).await?),
+            archive: None,
+        })
+    }
+    /// Creates an empty database which only lives in memory. Its content is
+    /// lost on shutdown.
+    pub fn in_memory() -> Self {
+        Database {
+            storage: Arc::new(MemoryStorage::new()),
+            archive: None,
+        }
+    }
+    pub async fn from_config(config: &DatabaseConfig) -> Result<Self> {
+        match config.backend {
+            DatabaseBackend::Mongodb => Self::new(&config.uri, &config.name).await,
+            DatabaseBackend::Memory => Ok(Self::in_memory()),
+        }
+    }
+    pub fn with_event_archive(mut self, archive: Option<EventArchive>) -> Self {
+        self.archive = archive;
+        self
+    }
+    /// Removes all personal data of the identity, see
+    /// `Storage::erase_identity`, including its events in the event archive.
+    pub async fn erase(&self, context: &IdentityContext) -> Result<ErasureReport> {
+        let mut report = self.storage.erase_identity(context).await?;
+
+        if let Some(archive) = &self.archive {
+            report.archived_events_removed = Some(archive.remove_identity(context)?);
+        }
+
+        Ok(report)
+    }
+}
+
+impl Deref for Database {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &Self::Target {
+        self.storage.as_ref()
+    }
+}
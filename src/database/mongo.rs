@@ -0,0 +1,2704 @@
+use super::migrations::{self, Migration, VERSIONED_COLLECTIONS};
+use super::{
+    apply_lockout, create_tombstone, matches_field_name, record_failed_attempt,
+    record_submission_attempt, second_challenge_change, set_default_judgement, set_flag,
+    EventArchive, EventSubscription, ExportFilter, Storage, DISPLAY_NAMES, EVENT_COLLECTION,
+    IDENTITY_COLLECTION, JUDGEMENT_HISTORY, TOMBSTONES,
+};
+use crate::adapters::admin::RawFieldName;
+use crate::api::VerifyChallenge;
+use crate::connector::{CancellationReason, DisplayNameEntry, Judgement, WatcherStatusReport};
+use crate::primitives::{
+    ChainName, ChallengeType, ErasureReport, Event, ExpectedMessage, ExternalMessage,
+    IdentityContext, IdentityFieldValue, JudgementHistoryEntry, JudgementState,
+    NotificationMessage, StateTransition, Timestamp, Tombstone, ERASED_PLACEHOLDER,
+};
+use crate::{LockoutConfig, LockoutPolicy, Result};
+use bson::oid::ObjectId;
+use bson::{doc, from_document, to_bson, to_document, Bson, Document};
+use futures::StreamExt;
+use mongodb::error::{Error as MongoError, ErrorKind as MongoErrorKind, WriteFailure};
+use mongodb::options::{
+    FindOneAndUpdateOptions, FindOneOptions, FindOptions, ReplaceOptions, UpdateOptions,
+};
+use mongodb::{Client, Collection, Cursor, Database as MongoDb};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use tokio::time::{sleep, Duration};
+
+const EVENT_SUBSCRIBERS: &str = "event_subscribers";
+const SCHEMA_VERSIONS: &str = "schema_versions";
+const WATCHER_STATUS: &str = "watcher_status";
+
+// Server error codes.
+const CHANGE_STREAM_UNSUPPORTED: i32 = 40573;
+const CHANGE_STREAM_HISTORY_LOST: i32 = 286;
+const NAMESPACE_NOT_FOUND: i32 = 26;
+const DUPLICATE_KEY: i32 = 11000;
+
+// Gives up on updating an identity if it gets modified concurrently this
+// often in a row.
+const MAX_UPDATE_ATTEMPTS: usize = 10;
+
+// The number of events which are archived and removed at once.
+const PRUNE_BATCH_SIZE: i64 = 1000;
+
+// In seconds. A migration lock which was not released within this time (e.g.
+// because the instance crashed) is taken over by other instances.
+const MIGRATION_LOCK_TTL: u64 = 600;
+
+/// Convenience trait. Converts a value to BSON.
+trait ToBson {
+    fn to_bson(&self) -> Result<Bson>;
+    fn to_document(&self) -> Result<Document>;
+}
+
+impl<T: Serialize> ToBson for T {
+    fn to_bson(&self) -> Result<Bson> {
+        Ok(to_bson(self)?)
+    }
+    fn to_document(&self) -> Result<Document> {
+        Ok(to_document(self)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MongoStorage {
+    db: MongoDb,
+}
+
+impl MongoStorage {
+    pub async fn new(uri: &str, db: &str) -> Result<Self> {
+        Ok(MongoStorage {
+            db: Client::with_uri_str(uri).await?.database(db),
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    async fn connectivity_check(&self) -> Result<()> {
+        self.db
+            .list_collection_names(None)
+            .await
+            .map_err(|err| anyhow!("Failed to connect to database: {:?}", err))?;
+
+        let mut missing = vec![];
+        for index in indexes() {
+            let existing = self.fetch_indexes(index.collection).await?;
+            if !existing.iter().any(|found| index.matches(found)) {
+                missing.push(format!("{}.{}", index.collection, index.name));
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Missing or outdated database indexes: {}",
+                missing.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+    async fn ensure_indexes(&self) -> Result<()> {
+        for index in indexes() {
+            self.db
+                .run_command(
+                    doc! {
+                        "createIndexes": index.collection,
+                        "indexes": [
+                            {
+                                "key": index.keys.clone(),
+                                "name": index.name,
+                                "unique": index.unique,
+                            }
+                        ],
+                    },
+                    None,
+                )
+                .await
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to create index '{}' on '{}': {:?}",
+                        index.name,
+                        index.collection,
+                        err
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+    async fn migrate(&self) -> Result<()> {
+        for collection in VERSIONED_COLLECTIONS {
+            self.migrate_collection(collection).await?;
+        }
+
+        Ok(())
+    }
+    async fn add_judgement_request(&self, request: &JudgementState) -> Result<bool> {
+        let coll = self.db.collection::<Document>(IDENTITY_COLLECTION);
+
+        loop {
+            // Check if a request of the same address exists yet (occurs when a
+            // field gets updated during pending judgement process).
+            let updated = self
+                .update_identity(&request.context, |update| {
+                    let current = &mut update.state;
+
+                    // Determine which fields should be updated.
+                    let mut has_changed = false;
+                    let mut to_add = vec![];
+                    for new_field in &request.fields {
+                        // If the current field value is the same as the new one,
+                        // insert the current field state back into storage. If the
+                        // value is new, insert/update the current field state.
+                        if let Some(current_field) = current
+                            .fields
+                            .iter()
+                            .find(|current| current.value == new_field.value)
+                        {
+                            to_add.push(current_field.clone());
+                        } else {
+                            to_add.push(new_field.clone());
+                            has_changed = true;
+                        }
+                    }
+
+                    // If nothing was modified, return (detect removed entries).
+                    if !has_changed && request.fields.len() == current.fields.len() {
+                        return Ok(false);
+                    }
+
+                    // All deprecated fields are overwritten. Any pending judgement
+                    // was decided on the old values and is therefore reset.
+                    current.fields = to_add;
+                    current.judgement = None;
+
+                    update.events.push(NotificationMessage::IdentityUpdated {
+                        context: request.context.clone(),
+                    });
+                    update.transitions.push(StateTransition::FieldsChanged);
+
+                    // Check full verification status.
+                    update.process_fully_verified();
+
+                    Ok(true)
+                })
+                .await?;
+
+            if let Some(updated) = updated {
+                return Ok(updated);
+            }
+
+            // The history entry is inserted along with the state, like in
+            // `update_identity`.
+            let mut doc = request.to_document()?;
+            doc.insert(
+                "pending_history",
+                vec![HistoryWrapper {
+                    id: ObjectId::new(),
+                    entry: JudgementHistoryEntry::new(StateTransition::Inserted, request.clone()),
+                }
+                .to_bson()?],
+            );
+
+            match coll.insert_one(doc, None).await {
+                Ok(_) => {
+                    self.flush_pending(&request.context).await?;
+                    return Ok(true);
+                }
+                // Inserted concurrently, update that entry instead. An
+                // interrupted cancellation of the previous request is
+                // completed first.
+                Err(err) if is_duplicate_key_error(&err) => {
+                    self.remove_cancelled_identity(&request.context).await?;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    #[cfg(test)]
+    async fn delete_judgement(&self, context: &IdentityContext) -> Result<()> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let res = coll
+            .delete_one(
+                doc! {
+                    "context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        if res.deleted_count != 1 {
+            panic!()
+        }
+
+        Ok(())
+    }
+    async fn verify_manually(
+        &self,
+        context: &IdentityContext,
+        field: &RawFieldName,
+        // Whether it should check if the idenity has been fully verified.
+        full_check: bool,
+    ) -> Result<Option<()>> {
+        if let RawFieldName::All = field {
+            return Err(anyhow!(
+                "field name 'all' is abstract and cannot be verified individually"
+            ));
+        }
+
+        Ok(self
+            .update_identity(context, |update| {
+                if !update.verify_manually(field) {
+                    return Ok(None);
+                }
+
+                // Create event.
+                if full_check {
+                    update.events.push(NotificationMessage::ManuallyVerified {
+                        context: context.clone(),
+                        field: field.clone(),
+                    });
+
+                    // Check the new state.
+                    update.process_fully_verified();
+                }
+
+                Ok(Some(()))
+            })
+            .await?
+            .flatten())
+    }
+    async fn verify_message(
+        &self,
+        message: &ExternalMessage,
+        lockout: &LockoutPolicy,
+    ) -> Result<()> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        // Fetch the identities with a field matching the message origin (in
+        // theory, there could be multiple pending requests with the same
+        // external account specified).
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "fields.value": message.origin.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let mut contexts = vec![];
+        while let Some(state) = cursor.next().await {
+            contexts.push(state?.context);
+        }
+
+        // If a field was found, update it.
+        for context in contexts {
+            self.update_identity(&context, |update| {
+                let field_state = match update
+                    .state
+                    .fields
+                    .iter_mut()
+                    .find(|field| field.value.matches_origin(message))
+                {
+                    Some(field_state) => field_state,
+                    // The field was removed in the meantime.
+                    None => return Ok(()),
+                };
+
+                // Locked fields ignore any verification attempts.
+                if field_state.is_locked() {
+                    return Ok(());
+                }
+
+                // If the message contains the challenge, set it as valid (or
+                // invalid if otherwise).
+
+                let field_value = field_state.value.clone();
+                let mut failed = false;
+
+                if !field_state.challenge.is_verified() {
+                    match &mut field_state.challenge {
+                        ChallengeType::ExpectedMessage { expected, second } => {
+                            // Only proceed if the expected challenge has not been
+                            // verified yet. Expired challenges are ignored until
+                            // they get regenerated.
+                            if !expected.is_verified && !expected.is_expired() {
+                                if expected.verify_message(message) {
+                                    update.events.push(NotificationMessage::FieldVerified {
+                                        context: context.clone(),
+                                        field: field_value.clone(),
+                                    });
+
+                                    if second.is_some() {
+                                        update.events.push(
+                                            NotificationMessage::AwaitingSecondChallenge {
+                                                context: context.clone(),
+                                                field: field_value,
+                                            },
+                                        );
+                                    }
+                                } else {
+                                    update.events.push(
+                                        NotificationMessage::FieldVerificationFailed {
+                                            context: context.clone(),
+                                            field: field_value,
+                                        },
+                                    );
+
+                                    failed = true;
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(anyhow!(
+                                "Invalid challenge type when verifying message. This is a bug"
+                            ))
+                        }
+                    }
+                }
+
+                if failed {
+                    let events =
+                        record_failed_attempt(&context, field_state, lockout.get(&context.chain));
+                    update.events.extend(events);
+                }
+
+                // Check if the identity is fully verified.
+                update.process_fully_verified();
+
+                Ok(())
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+    async fn verify_second_challenge(
+        &self,
+        mut request: VerifyChallenge,
+        lockout: &LockoutPolicy,
+    ) -> Result<bool> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut verified = false;
+
+        // Trim received challenge, just in case.
+        request.challenge = request.challenge.trim().to_string();
+
+        // Query database.
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "fields.value": request.entry.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let mut contexts = vec![];
+        while let Some(state) = cursor.next().await {
+            contexts.push(state?.context);
+        }
+
+        for context in contexts {
+            let res = self
+                .update_identity(&context, |update| {
+                    let field_state = match update
+                        .state
+                        .fields
+                        .iter_mut()
+                        .find(|field| field.value == request.entry)
+                    {
+                        Some(field_state) => field_state,
+                        // The field was removed in the meantime.
+                        None => return Ok(false),
+                    };
+
+                    if field_state.is_locked() {
+                        return Ok(false);
+                    }
+
+                    let field_value = field_state.value.clone();
+
+                    let verified = match &mut field_state.challenge {
+                        ChallengeType::ExpectedMessage {
+                            expected: _,
+                            second: Some(second),
+                        } => {
+                            if !second.is_expired() && request.challenge.contains(&second.value) {
+                                second.set_verified();
+
+                                update
+                                    .events
+                                    .push(NotificationMessage::SecondFieldVerified {
+                                        context: context.clone(),
+                                        field: field_value,
+                                    });
+
+                                true
+                            } else {
+                                update.events.push(
+                                    NotificationMessage::SecondFieldVerificationFailed {
+                                        context: context.clone(),
+                                        field: field_value,
+                                    },
+                                );
+
+                                false
+                            }
+                        }
+                        // This should never happens, but the provided field value
+                        // depends on user input, so...
+                        ChallengeType::ExpectedMessage { second: None, .. } => return Ok(false),
+                        _ => {
+                            panic!("Invalid challenge type when verifying message");
+                        }
+                    };
+
+                    if !verified {
+                        let events = record_failed_attempt(
+                            &context,
+                            field_state,
+                            lockout.get(&context.chain),
+                        );
+                        update.events.extend(events);
+                    }
+
+                    // Check if the identity is fully verified.
+                    update.process_fully_verified();
+
+                    Ok(verified)
+                })
+                .await?;
+
+            verified |= res.unwrap_or(false);
+        }
+
+        Ok(verified)
+    }
+    async fn verify_signed_message(
+        &self,
+        fingerprint: &str,
+        text: &str,
+        lockout: &LockoutPolicy,
+    ) -> Result<bool> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let entry = IdentityFieldValue::PGPFingerprint(fingerprint.to_string());
+        let mut verified = false;
+
+        // Query database.
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "fields.value": entry.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let mut contexts = vec![];
+        while let Some(state) = cursor.next().await {
+            contexts.push(state?.context);
+        }
+
+        for context in contexts {
+            let res = self
+                .update_identity(&context, |update| {
+                    let field_state = match update
+                        .state
+                        .fields
+                        .iter_mut()
+                        .find(|field| field.value == entry)
+                    {
+                        Some(field_state) => field_state,
+                        // The field was removed in the meantime.
+                        None => return Ok(false),
+                    };
+
+                    if field_state.is_locked() {
+                        return Ok(false);
+                    }
+
+                    let field_value = field_state.value.clone();
+
+                    let verified = match &mut field_state.challenge {
+                        ChallengeType::SignedMessage { expected } => {
+                            if expected.is_verified {
+                                return Ok(false);
+                            }
+
+                            if !expected.is_expired() && text.contains(&expected.value) {
+                                expected.set_verified();
+
+                                update.events.push(NotificationMessage::FieldVerified {
+                                    context: context.clone(),
+                                    field: field_value,
+                                });
+
+                                true
+                            } else {
+                                update
+                                    .events
+                                    .push(NotificationMessage::FieldVerificationFailed {
+                                        context: context.clone(),
+                                        field: field_value,
+                                    });
+
+                                false
+                            }
+                        }
+                        _ => {
+                            return Err(anyhow!(
+                            "Invalid challenge type when verifying signed message. This is a bug"
+                        ))
+                        }
+                    };
+
+                    if !verified {
+                        let events = record_failed_attempt(
+                            &context,
+                            field_state,
+                            lockout.get(&context.chain),
+                        );
+                        update.events.extend(events);
+                    }
+
+                    // Check if the identity is fully verified.
+                    update.process_fully_verified();
+
+                    Ok(verified)
+                })
+                .await?;
+
+            verified |= res.unwrap_or(false);
+        }
+
+        Ok(verified)
+    }
+    async fn fetch_second_challenge(
+        &self,
+        context: &IdentityContext,
+        field: &IdentityFieldValue,
+    ) -> Result<ExpectedMessage> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        // Query database.
+        let try_state = coll
+            .find_one(
+                doc! {
+                    "context": context.to_bson()?,
+                    "fields.value": field.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        if let Some(state) = try_state {
+            // Optimize this. Should be handled by the query itself.
+            let field_state = state
+                .fields
+                .iter()
+                .find(|f| &f.value == field)
+                // Technically, this should never return an error...
+                .ok_or_else(|| anyhow!("Failed to select field when verifying message"))?;
+
+            match &field_state.challenge {
+                ChallengeType::ExpectedMessage {
+                    expected: _,
+                    second,
+                } => {
+                    if let Some(second) = second {
+                        Ok(second.clone())
+                    } else {
+                        Err(anyhow!("No second challenge found for {:?}", field))
+                    }
+                }
+                _ => Err(anyhow!("No second challenge found for {:?}", field)),
+            }
+        } else {
+            Err(anyhow!("No entry found for {:?}", field))
+        }
+    }
+    async fn regenerate_expired_challenges(&self, network: &ChainName, ttl: u64) -> Result<()> {
+        for context in self.fetch_unverified_contexts(network).await? {
+            self.update_identity(&context, |update| {
+                if update.state.is_fully_verified {
+                    return Ok(());
+                }
+
+                for index in 0..update.state.fields.len() {
+                    let field = &mut update.state.fields[index];
+                    let old = field.challenge.clone();
+                    if !field.challenge.regenerate_expired(ttl) {
+                        continue;
+                    }
+
+                    let value = field.value.clone();
+                    let new = field.challenge.clone();
+
+                    update
+                        .events
+                        .push(NotificationMessage::ChallengeRegenerated {
+                            context: context.clone(),
+                            field: value.clone(),
+                        });
+
+                    update.notify_second_challenge_change(&value, &old, &new);
+                }
+
+                Ok(())
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+    async fn apply_lockout_policy(
+        &self,
+        network: &ChainName,
+        config: &LockoutConfig,
+    ) -> Result<()> {
+        if config.max_failed_attempts.is_none() {
+            return Ok(());
+        }
+
+        for context in self.fetch_unverified_contexts(network).await? {
+            self.update_identity(&context, |update| {
+                if !update.state.is_fully_verified {
+                    update.apply_lockout_policy(config);
+                }
+
+                Ok(())
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+    async fn subscribe_events(&self, subscriber: &str) -> Result<Box<dyn EventSubscription>> {
+        let position = self.fetch_subscriber_position(subscriber).await?;
+
+        let mut subscription = ChangeStreamSubscription {
+            storage: self.clone(),
+            subscriber: subscriber.to_string(),
+            stream: None,
+            resume_token: position.resume_token,
+            pending: false,
+        };
+
+        match subscription.open().await {
+            Ok(()) => Ok(Box::new(subscription)),
+            Err(err) if is_command_error(&err, CHANGE_STREAM_UNSUPPORTED) => {
+                warn!(
+                    "Change streams are not supported by the database, falling back to polling the event log"
+                );
+
+                Ok(Box::new(PollingSubscription {
+                    storage: self.clone(),
+                    subscriber: subscriber.to_string(),
+                    cursor: position
+                        .last_event
+                        .map(EventCursor::resume_after)
+                        .unwrap_or_else(EventCursor::new),
+                    queue: VecDeque::new(),
+                    pending: None,
+                }))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+    async fn fetch_judgement_state(
+        &self,
+        context: &IdentityContext,
+    ) -> Result<Option<JudgementState>> {
+        let coll = self.db.collection(IDENTITY_COLLECTION);
+
+        // Find the context.
+        let doc = coll
+            .find_one(
+                doc! {
+                    "context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        if let Some(doc) = doc {
+            Ok(Some(from_document(doc)?))
+        } else {
+            // Not active request exists.
+            Ok(None)
+        }
+    }
+    async fn fetch_judgement_history(
+        &self,
+        context: &IdentityContext,
+    ) -> Result<Vec<JudgementHistoryEntry>> {
+        let coll = self
+            .db
+            .collection::<JudgementHistoryEntry>(JUDGEMENT_HISTORY);
+
+        // Ids are increasing, which keeps entries with the same timestamp in
+        // order.
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "state.context": context.to_bson()?,
+                },
+                FindOptions::builder().sort(doc! { "_id": 1 }).build(),
+            )
+            .await?;
+
+        let mut history = vec![];
+        while let Some(entry) = cursor.next().await {
+            history.push(entry?);
+        }
+
+        Ok(history)
+    }
+    async fn fetch_unverified_web_domains(&self) -> Result<Vec<String>> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "fields": {
+                        "$elemMatch": {
+                            "value.type": "web",
+                            "challenge.content.expected.is_verified": false,
+                        }
+                    }
+                },
+                None,
+            )
+            .await?;
+
+        let mut domains = vec![];
+        while let Some(state) = cursor.next().await {
+            for field in state?.fields {
+                if let IdentityFieldValue::Web(domain) = field.value {
+                    if !field.challenge.is_verified() {
+                        domains.push(domain);
+                    }
+                }
+            }
+        }
+
+        domains.sort();
+        domains.dedup();
+
+        Ok(domains)
+    }
+    async fn fetch_judgement_candidates(&self, network: &ChainName) -> Result<Vec<JudgementState>> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "context.chain": network.as_str().to_bson()?,
+                    // Identities can be judged without being fully verified,
+                    // e.g. when being flagged as erroneous.
+                    "$or": [
+                        { "is_fully_verified": true },
+                        { "judgement": { "$ne": Bson::Null } },
+                    ],
+                    "judgement_submitted": false,
+                    "issue_judgement_at": {
+                        "$lt": Timestamp::now().to_bson()?,
+                    },
+                    "cancelled": { "$exists": false },
+                    "$and": [{
+                        "$or": [
+                            { "submission": Bson::Null },
+                            {
+                                "submission.failed": false,
+                                "submission.next_attempt": {
+                                    "$lte": Timestamp::now().to_bson()?,
+                                },
+                            },
+                        ],
+                    }],
+                },
+                None,
+            )
+            .await?;
+
+        let mut completed = vec![];
+        while let Some(state) = cursor.next().await {
+            completed.push(state?);
+        }
+
+        Ok(completed)
+    }
+    async fn record_judgement_submission(
+        &self,
+        context: &IdentityContext,
+        judgement: Judgement,
+        max_attempts: Option<usize>,
+    ) -> Result<bool> {
+        Ok(self
+            .update_identity(context, |update| {
+                if update.state.judgement_submitted {
+                    return Ok(false);
+                }
+
+                if record_submission_attempt(&mut update.state, judgement, max_attempts) {
+                    return Ok(true);
+                }
+
+                update
+                    .events
+                    .push(NotificationMessage::JudgementSubmissionFailed {
+                        context: context.clone(),
+                        judgement,
+                    });
+
+                Ok(false)
+            })
+            .await?
+            .unwrap_or(false))
+    }
+    async fn record_judgement_error(&self, context: &IdentityContext, error: &str) -> Result<()> {
+        let coll = self.db.collection::<()>(IDENTITY_COLLECTION);
+
+        coll.update_one(
+            doc! {
+                "context": context.to_bson()?,
+                "judgement_submitted": false,
+                "submission": {
+                    "$ne": Bson::Null,
+                },
+            },
+            doc! {
+                "$set": {
+                    "submission.last_error": error,
+                }
+            },
+            None,
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn fetch_failed_submissions(&self) -> Result<Vec<JudgementState>> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "judgement_submitted": false,
+                    "submission.failed": true,
+                },
+                None,
+            )
+            .await?;
+
+        let mut failed = vec![];
+        while let Some(state) = cursor.next().await {
+            failed.push(state?);
+        }
+
+        Ok(failed)
+    }
+    async fn retry_judgement_submission(&self, context: &IdentityContext) -> Result<bool> {
+        let coll = self.db.collection::<()>(IDENTITY_COLLECTION);
+
+        let res = coll
+            .update_one(
+                doc! {
+                    "context": context.to_bson()?,
+                    "judgement_submitted": false,
+                    "submission.failed": true,
+                },
+                doc! {
+                    "$set": {
+                        "submission": Bson::Null,
+                    }
+                },
+                None,
+            )
+            .await?;
+
+        Ok(res.modified_count == 1)
+    }
+    async fn fetch_pending_requests(&self, network: &ChainName) -> Result<Vec<JudgementState>> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "context.chain": network.as_str().to_bson()?,
+                    "judgement_submitted": false,
+                    "submission": Bson::Null,
+                },
+                None,
+            )
+            .await?;
+
+        let mut pending = vec![];
+        while let Some(state) = cursor.next().await {
+            pending.push(state?);
+        }
+
+        Ok(pending)
+    }
+    async fn cancel_judgement_request(
+        &self,
+        context: &IdentityContext,
+        reason: CancellationReason,
+    ) -> Result<bool> {
+        // The state is marked as cancelled along with its events and history
+        // first and only removed afterwards, so interrupted cancellations can
+        // be completed (see `process_interrupted_updates`).
+        let marked = self
+            .update_identity(context, |update| {
+                update.cancelled = Some(reason);
+                update
+                    .events
+                    .push(NotificationMessage::JudgementRequestCancelled {
+                        context: context.clone(),
+                        reason,
+                    });
+                update.transitions.push(StateTransition::Cancelled);
+
+                Ok(())
+            })
+            .await?
+            .is_some();
+
+        let removed = self.remove_cancelled_identity(context).await?;
+
+        Ok(marked || removed)
+    }
+    async fn full_manual_verification(&self, context: &IdentityContext) -> Result<bool> {
+        Ok(self
+            .update_identity(context, |update| {
+                // Create a timed delay for issuing judgments. Between 30 seconds to
+                // 5 minutes. This is used to prevent timing attacks where a user
+                // updates the identity right before the judgement is issued.
+                let offset = thread_rng().gen_range(30..300);
+
+                let state = &mut update.state;
+                state.is_fully_verified = true;
+                state.judgement_submitted = false;
+                state.submission = None;
+                state.completion_timestamp = Some(Timestamp::now());
+                state.issue_judgement_at = Some(Timestamp::with_offset(offset));
+                set_default_judgement(state);
+
+                // Verify all possible fields. Unused fields are silently ignored.
+                for field in [
+                    RawFieldName::LegalName,
+                    RawFieldName::DisplayName,
+                    RawFieldName::Email,
+                    RawFieldName::Web,
+                    RawFieldName::Twitter,
+                    RawFieldName::Matrix,
+                ] {
+                    update.verify_manually(&field);
+                }
+
+                update
+                    .events
+                    .push(NotificationMessage::FullManualVerification {
+                        context: context.clone(),
+                    });
+                update.transitions.push(StateTransition::FullyVerified);
+
+                Ok(true)
+            })
+            .await?
+            .unwrap_or(false))
+    }
+    async fn set_pending_judgement(
+        &self,
+        context: &IdentityContext,
+        judgement: Judgement,
+    ) -> Result<bool> {
+        Ok(self
+            .update_identity(context, |update| {
+                update.set_pending_judgement(judgement);
+                Ok(())
+            })
+            .await?
+            .is_some())
+    }
+    async fn apply_failed_attempts_policy(
+        &self,
+        network: &ChainName,
+        max_failed_attempts: usize,
+    ) -> Result<()> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "context.chain": network.as_str().to_bson()?,
+                    "is_fully_verified": false,
+                    "judgement_submitted": false,
+                    "judgement": Bson::Null,
+                    "fields.failed_attempts": {
+                        "$gte": max_failed_attempts.to_bson()?,
+                    }
+                },
+                None,
+            )
+            .await?;
+
+        let mut flagged = vec![];
+        while let Some(state) = cursor.next().await {
+            flagged.push(state?.context);
+        }
+
+        for context in flagged {
+            info!(
+                "Too many failed verification attempts, issuing erroneous judgement: {:?}",
+                context
+            );
+
+            self.set_pending_judgement(&context, Judgement::Erroneous)
+                .await?;
+        }
+
+        Ok(())
+    }
+    async fn set_judged(&self, context: &IdentityContext) -> Result<()> {
+        self.update_identity(context, |update| {
+            if update.state.judgement_submitted {
+                return Ok(());
+            }
+
+            update.state.judgement_submitted = true;
+
+            update.events.push(NotificationMessage::JudgementProvided {
+                context: context.clone(),
+            });
+            update.transitions.push(StateTransition::JudgementProvided);
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+    async fn insert_display_name(&self, name: &DisplayNameEntry) -> Result<()> {
+        let coll = self.db.collection::<DisplayNameEntry>(DISPLAY_NAMES);
+
+        coll.update_one(
+            doc! {
+                "display_name": name.display_name.to_bson()?,
+                "context": name.context.to_bson()?,
+            },
+            doc! {
+                "$setOnInsert": name.to_bson()?,
+            },
+            {
+                let mut opt = UpdateOptions::default();
+                opt.upsert = Some(true);
+                Some(opt)
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn fetch_display_names(&self, chain: &ChainName) -> Result<Vec<DisplayNameEntry>> {
+        let coll = self.db.collection::<DisplayNameEntry>(DISPLAY_NAMES);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "context.chain": chain.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let mut names = vec![];
+        while let Some(doc) = cursor.next().await {
+            names.push(doc?);
+        }
+
+        Ok(names)
+    }
+    async fn remove_display_names(&self, names: &[DisplayNameEntry]) -> Result<()> {
+        let coll = self.db.collection::<DisplayNameEntry>(DISPLAY_NAMES);
+
+        for name in names {
+            coll.delete_many(
+                doc! {
+                    "display_name": name.display_name.to_bson()?,
+                    "context": name.context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+    async fn set_display_name_valid(&self, state: &JudgementState) -> Result<()> {
+        self.update_identity(&state.context, |update| {
+            let field = update
+                .state
+                .fields
+                .iter_mut()
+                .find(|field| matches!(field.value, IdentityFieldValue::DisplayName(_)))
+                .expect("Failed to retrieve display name. This is a bug");
+
+            if let ChallengeType::DisplayNameCheck { passed, .. } = &mut field.challenge {
+                *passed = true;
+            }
+
+            // Create event
+            update.events.push(NotificationMessage::FieldVerified {
+                context: state.context.clone(),
+                field: field.value.clone(),
+            });
+
+            update.process_fully_verified();
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+    async fn insert_display_name_violations(
+        &self,
+        context: &IdentityContext,
+        violations: &[DisplayNameEntry],
+    ) -> Result<()> {
+        let coll = self.db.collection::<()>(IDENTITY_COLLECTION);
+
+        coll.update_one(
+            doc! {
+                "context": context.to_bson()?,
+                "fields.value.type": "display_name",
+            },
+            doc! {
+                "$set": {
+                    "fields.$.challenge.content.passed": false,
+                    "fields.$.challenge.content.violations": violations.to_bson()?
+                }
+            },
+            None,
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn process_interrupted_updates(&self) -> Result<()> {
+        // Insert the events and history entries of updates which were
+        // interrupted before those were moved to their collections, and
+        // remove the identities whose cancellation was interrupted.
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "$or": [
+                        { "pending_events.0": { "$exists": true } },
+                        { "pending_history.0": { "$exists": true } },
+                        { "cancelled": { "$exists": true } },
+                    ]
+                },
+                None,
+            )
+            .await?;
+
+        let mut contexts = vec![];
+        while let Some(state) = cursor.next().await {
+            contexts.push(state?.context);
+        }
+
+        for context in contexts {
+            self.flush_pending(&context).await?;
+            self.remove_cancelled_identity(&context).await?;
+        }
+
+        Ok(())
+    }
+    async fn prune_events(
+        &self,
+        message_type: &str,
+        max_age: u64,
+        archive: Option<&EventArchive>,
+    ) -> Result<usize> {
+        let coll = self.db.collection::<EventWrapper>(EVENT_COLLECTION);
+
+        let threshold = Timestamp::now().raw().saturating_sub(max_age).to_bson()?;
+        let filter = doc! {
+            "message.type": message_type,
+            "timestamp": {
+                "$lt": threshold,
+            }
+        };
+
+        // Removed in batches, so the events of a batch can be archived
+        // beforehand without loading all of them at once.
+        let mut count = 0;
+        loop {
+            let mut cursor = coll
+                .find(
+                    filter.clone(),
+                    FindOptions::builder()
+                        .sort(doc! { "_id": 1 })
+                        .limit(PRUNE_BATCH_SIZE)
+                        .build(),
+                )
+                .await?;
+
+            let mut ids = vec![];
+            let mut events = vec![];
+            while let Some(wrapper) = cursor.next().await {
+                let wrapper = wrapper?;
+                ids.push(wrapper.id);
+                events.push(wrapper.event);
+            }
+
+            if ids.is_empty() {
+                break;
+            }
+
+            if let Some(archive) = archive {
+                archive.append(&events)?;
+            }
+
+            let res = coll
+                .delete_many(
+                    doc! {
+                        "_id": {
+                            "$in": &ids,
+                        }
+                    },
+                    None,
+                )
+                .await?;
+
+            count += res.deleted_count as usize;
+
+            if (ids.len() as i64) < PRUNE_BATCH_SIZE {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+    async fn export_identities(&self, filter: &ExportFilter) -> Result<Vec<JudgementState>> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                export_query(filter, "context", Some("inserted_timestamp"))?,
+                None,
+            )
+            .await?;
+
+        let mut identities = vec![];
+        while let Some(state) = cursor.next().await {
+            identities.push(state?);
+        }
+
+        Ok(identities)
+    }
+    async fn export_display_names(&self, filter: &ExportFilter) -> Result<Vec<DisplayNameEntry>> {
+        let coll = self.db.collection::<DisplayNameEntry>(DISPLAY_NAMES);
+
+        let mut cursor = coll
+            .find(export_query(filter, "context", None)?, None)
+            .await?;
+
+        let mut names = vec![];
+        while let Some(name) = cursor.next().await {
+            names.push(name?);
+        }
+
+        Ok(names)
+    }
+    async fn export_events(&self, filter: &ExportFilter) -> Result<Vec<Event>> {
+        let coll = self.db.collection::<EventWrapper>(EVENT_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                export_query(filter, "message.value.context", Some("timestamp"))?,
+                FindOptions::builder().sort(doc! { "_id": 1 }).build(),
+            )
+            .await?;
+
+        let mut events = vec![];
+        while let Some(wrapper) = cursor.next().await {
+            events.push(wrapper?.event);
+        }
+
+        Ok(events)
+    }
+    async fn import_identity(&self, state: &JudgementState) -> Result<()> {
+        let coll = self.db.collection::<Document>(IDENTITY_COLLECTION);
+
+        coll.replace_one(
+            doc! {
+                "context": state.context.to_bson()?,
+            },
+            state.to_document()?,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn import_event(&self, event: &Event) -> Result<()> {
+        let coll = self.db.collection::<Document>(EVENT_COLLECTION);
+
+        let exists = coll
+            .find_one(
+                doc! {
+                    "timestamp": event.timestamp.to_bson()?,
+                    "message": event.message.to_bson()?,
+                },
+                None,
+            )
+            .await?
+            .is_some();
+
+        if !exists {
+            coll.insert_one(event.to_document()?, None).await?;
+        }
+
+        Ok(())
+    }
+    async fn erase_identity(&self, context: &IdentityContext) -> Result<ErasureReport> {
+        // Cancelled identities are only kept in the history.
+        let state = match self.fetch_judgement_state(context).await? {
+            Some(state) => Some(state),
+            None => self
+                .fetch_judgement_history(context)
+                .await?
+                .pop()
+                .map(|entry| entry.state),
+        };
+
+        let tombstone = match state {
+            Some(state) => {
+                let judged_at = self
+                    .db
+                    .collection::<Event>(EVENT_COLLECTION)
+                    .find_one(
+                        doc! {
+                            "message.type": "judgement_provided",
+                            "message.value.context": context.to_bson()?,
+                        },
+                        FindOneOptions::builder()
+                            .sort(doc! { "timestamp": -1 })
+                            .build(),
+                    )
+                    .await?
+                    .map(|event| event.timestamp);
+
+                create_tombstone(&state, judged_at)
+            }
+            None => None,
+        };
+
+        // Stored first, so the proof is not lost if the erasure gets
+        // interrupted. Keeps the first tombstone if the erasure is repeated.
+        if let Some(tombstone) = &tombstone {
+            self.db
+                .collection::<Tombstone>(TOMBSTONES)
+                .update_one(
+                    doc! {
+                        "context": context.to_bson()?,
+                    },
+                    doc! {
+                        "$setOnInsert": tombstone.to_bson()?,
+                    },
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+
+        let identity = self
+            .db
+            .collection::<Document>(IDENTITY_COLLECTION)
+            .delete_one(
+                doc! {
+                    "context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let events = self
+            .db
+            .collection::<Document>(EVENT_COLLECTION)
+            .delete_many(
+                doc! {
+                    "message.value.context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let display_names = self
+            .db
+            .collection::<Document>(DISPLAY_NAMES)
+            .delete_many(
+                doc! {
+                    "context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let history = self
+            .db
+            .collection::<Document>(JUDGEMENT_HISTORY)
+            .delete_many(
+                doc! {
+                    "state.context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let violations = self.anonymise_violations(context).await?;
+
+        Ok(ErasureReport {
+            context: context.clone(),
+            identity_removed: identity.deleted_count > 0,
+            events_removed: events.deleted_count as usize,
+            display_names_removed: display_names.deleted_count as usize,
+            history_entries_removed: history.deleted_count as usize,
+            violations_anonymised: violations,
+            archived_events_removed: None,
+            tombstone,
+        })
+    }
+    async fn store_watcher_status(&self, report: &WatcherStatusReport) -> Result<()> {
+        let coll = self.db.collection::<WatcherStatusReport>(WATCHER_STATUS);
+
+        coll.replace_one(
+            doc! {
+                "network": report.network.to_bson()?,
+            },
+            report,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn fetch_watcher_status(&self) -> Result<Vec<WatcherStatusReport>> {
+        let coll = self.db.collection::<WatcherStatusReport>(WATCHER_STATUS);
+
+        let mut cursor = coll.find(None, None).await?;
+
+        let mut reports = vec![];
+        while let Some(report) = cursor.next().await {
+            reports.push(report?);
+        }
+
+        Ok(reports)
+    }
+    #[cfg(test)]
+    async fn fetch_tombstones(&self, context: &IdentityContext) -> Result<Vec<Tombstone>> {
+        let coll = self.db.collection::<Tombstone>(TOMBSTONES);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let mut tombstones = vec![];
+        while let Some(tombstone) = cursor.next().await {
+            tombstones.push(tombstone?);
+        }
+
+        Ok(tombstones)
+    }
+}
+
+/// Converts the export filter to a query. `context` is the key of the
+/// identity context and `timestamp` the key of the time to filter by, if
+/// any.
+fn export_query(filter: &ExportFilter, context: &str, timestamp: Option<&str>) -> Result<Document> {
+    let mut query = doc! {};
+
+    if let Some(chain) = &filter.chain {
+        query.insert(format!("{}.chain", context), chain.to_bson()?);
+    }
+    if let Some(address) = &filter.address {
+        query.insert(format!("{}.address", context), address.to_bson()?);
+    }
+
+    if let Some(timestamp) = timestamp {
+        let mut range = doc! {};
+        if let Some(from) = filter.from {
+            range.insert("$gte", from.to_bson()?);
+        }
+        if let Some(to) = filter.to {
+            range.insert("$lte", to.to_bson()?);
+        }
+
+        if !range.is_empty() {
+            query.insert(timestamp, range);
+        }
+    }
+
+    Ok(query)
+}
+
+impl MongoStorage {
+    /// Applies the changes of `f` to the identity and creates the events it
+    /// returns. The new state, its events and history entries are written with
+    /// a single conditional update: if the identity was modified since it was
+    /// read, `f` is called again with the current state. Returns `None` if the
+    /// identity does not exist or is being cancelled.
+    async fn update_identity<F, R>(&self, context: &IdentityContext, mut f: F) -> Result<Option<R>>
+    where
+        F: FnMut(&mut IdentityUpdate) -> Result<R> + Send,
+        R: Send,
+    {
+        let coll = self.db.collection::<Document>(IDENTITY_COLLECTION);
+
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let current = match coll
+                .find_one(
+                    doc! {
+                        "context": context.to_bson()?,
+                    },
+                    None,
+                )
+                .await?
+            {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+
+            if current.contains_key("cancelled") {
+                return Ok(None);
+            }
+
+            let state: JudgementState = from_document(current.clone())?;
+            let mut update = IdentityUpdate {
+                state: state.clone(),
+                events: vec![],
+                transitions: vec![],
+                cancelled: None,
+            };
+
+            let res = f(&mut update)?;
+            if update.state == state && update.events.is_empty() && update.cancelled.is_none() {
+                return Ok(Some(res));
+            }
+
+            // Keep the id and any events of previous updates which were not
+            // moved to the event log yet.
+            let mut replacement = current.clone();
+            for (key, value) in update.state.to_document()? {
+                replacement.insert(key, value);
+            }
+
+            let mut pending = current
+                .get_array("pending_events")
+                .cloned()
+                .unwrap_or_default();
+
+            for message in update.events {
+                pending.push(
+                    EventWrapper {
+                        id: ObjectId::new(),
+                        event: message.into(),
+                    }
+                    .to_bson()?,
+                );
+            }
+
+            replacement.insert("pending_events", pending);
+
+            let mut history = current
+                .get_array("pending_history")
+                .cloned()
+                .unwrap_or_default();
+
+            for transition in update.transitions {
+                history.push(
+                    HistoryWrapper {
+                        id: ObjectId::new(),
+                        entry: JudgementHistoryEntry::new(transition, update.state.clone()),
+                    }
+                    .to_bson()?,
+                );
+            }
+
+            replacement.insert("pending_history", history);
+
+            if let Some(reason) = update.cancelled {
+                replacement.insert("cancelled", reason.to_bson()?);
+            }
+
+            let id = current.get_object_id("_id")?;
+            let res_update = coll
+                .replace_one(
+                    doc! {
+                        "_id": id,
+                        "$expr": {
+                            "$eq": ["$$ROOT", { "$literal": current }],
+                        },
+                    },
+                    replacement,
+                    None,
+                )
+                .await?;
+
+            if res_update.matched_count == 1 {
+                self.flush_pending(context).await?;
+                return Ok(Some(res));
+            }
+
+            debug!("Identity {:?} was modified concurrently, retrying", context);
+        }
+
+        Err(anyhow!(
+            "Failed to update identity {:?}, it was modified concurrently too often",
+            context
+        ))
+    }
+    /// Moves the pending events and history entries of the identity to the
+    /// event log and history. Entries which were already inserted (e.g. by a
+    /// concurrent call) are skipped.
+    async fn flush_pending(&self, context: &IdentityContext) -> Result<()> {
+        let coll = self.db.collection::<PendingEntries>(IDENTITY_COLLECTION);
+        let event_log = self.db.collection::<EventWrapper>(EVENT_COLLECTION);
+        let history = self.db.collection::<HistoryWrapper>(JUDGEMENT_HISTORY);
+
+        let pending = match coll
+            .find_one(
+                doc! {
+                    "context": context.to_bson()?,
+                },
+                None,
+            )
+            .await?
+        {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        if pending.pending_events.is_empty() && pending.pending_history.is_empty() {
+            return Ok(());
+        }
+
+        let mut event_ids = vec![];
+        for event in &pending.pending_events {
+            match event_log.insert_one(event, None).await {
+                Ok(_) => {}
+                Err(err) if is_duplicate_key_error(&err) => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            event_ids.push(event.id);
+        }
+
+        let mut history_ids = vec![];
+        for entry in &pending.pending_history {
+            match history.insert_one(entry, None).await {
+                Ok(_) => {}
+                Err(err) if is_duplicate_key_error(&err) => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            history_ids.push(entry.id);
+        }
+
+        coll.update_one(
+            doc! {
+                "context": context.to_bson()?,
+            },
+            doc! {
+                "$pull": {
+                    "pending_events": {
+                        "_id": {
+                            "$in": event_ids,
+                        }
+                    },
+                    "pending_history": {
+                        "_id": {
+                            "$in": history_ids,
+                        }
+                    },
+                }
+            },
+            None,
+        )
+        .await?;
+
+        Ok(())
+    }
+    /// Removes the identity if it was marked as cancelled, once its pending
+    /// events and history entries are flushed. Returns whether it was removed.
+    async fn remove_cancelled_identity(&self, context: &IdentityContext) -> Result<bool> {
+        let coll = self.db.collection::<Document>(IDENTITY_COLLECTION);
+
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            self.flush_pending(context).await?;
+
+            let res = coll
+                .delete_one(
+                    doc! {
+                        "context": context.to_bson()?,
+                        "cancelled": { "$exists": true },
+                        "pending_events.0": { "$exists": false },
+                        "pending_history.0": { "$exists": false },
+                    },
+                    None,
+                )
+                .await?;
+
+            if res.deleted_count == 1 {
+                return Ok(true);
+            }
+
+            let is_cancelled = coll
+                .find_one(
+                    doc! {
+                        "context": context.to_bson()?,
+                        "cancelled": { "$exists": true },
+                    },
+                    None,
+                )
+                .await?
+                .is_some();
+
+            if !is_cancelled {
+                return Ok(false);
+            }
+        }
+
+        Err(anyhow!(
+            "Failed to remove cancelled identity {:?}, it was modified concurrently too often",
+            context
+        ))
+    }
+    /// Anonymises the display name violations of the other identities and of
+    /// their history entries which refer to the erased identity, see
+    /// `JudgementState::anonymise_violations`. Returns the number of changed
+    /// identities and history entries.
+    async fn anonymise_violations(&self, erased: &IdentityContext) -> Result<usize> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "fields.challenge.content.violations.context": erased.to_bson()?,
+                },
+                None,
+            )
+            .await?;
+
+        let mut contexts = vec![];
+        while let Some(state) = cursor.next().await {
+            contexts.push(state?.context);
+        }
+
+        let mut anonymised = 0;
+        for context in contexts {
+            let changed = self
+                .update_identity(&context, |update| {
+                    Ok(update.state.anonymise_violations(erased))
+                })
+                .await?
+                .unwrap_or(false);
+
+            if changed {
+                anonymised += 1;
+            }
+        }
+
+        // History entries are snapshots, so they are updated in place.
+        let history = self
+            .db
+            .collection::<Document>(JUDGEMENT_HISTORY)
+            .update_many(
+                doc! {
+                    "state.fields.challenge.content.violations.context": erased.to_bson()?,
+                },
+                doc! {
+                    "$set": {
+                        "state.fields.$[field].challenge.content.violations.$[entry].context.address": ERASED_PLACEHOLDER,
+                        "state.fields.$[field].challenge.content.violations.$[entry].display_name": ERASED_PLACEHOLDER,
+                    }
+                },
+                UpdateOptions::builder()
+                    .array_filters(vec![
+                        doc! {
+                            "field.challenge.content.violations.context": erased.to_bson()?,
+                        },
+                        doc! {
+                            "entry.context": erased.to_bson()?,
+                        },
+                    ])
+                    .build(),
+            )
+            .await?;
+
+        Ok(anonymised + history.modified_count as usize)
+    }
+}
+
+/// The state of an identity as changed by `MongoStorage::update_identity`.
+struct IdentityUpdate {
+    state: JudgementState,
+    // Inserted into the event log along with the new state.
+    events: Vec<NotificationMessage>,
+    // A snapshot of the new state is added to the history for each entry.
+    transitions: Vec<StateTransition>,
+    // Marks the identity as cancelled, it gets removed once the events and
+    // history entries are inserted.
+    cancelled: Option<CancellationReason>,
+}
+
+impl IdentityUpdate {
+    /// Check if all fields have been verified.
+    fn process_fully_verified(&mut self) {
+        let state = &mut self.state;
+
+        if state.check_full_verification() {
+            if state.is_fully_verified {
+                return;
+            }
+
+            // Create a timed delay for issuing judgments. Between 30 seconds to
+            // 5 minutes. This is used to prevent timing attacks where a user
+            // updates the identity right before the judgement is issued.
+            let offset = thread_rng().gen_range(30..300);
+
+            state.is_fully_verified = true;
+            state.judgement_submitted = false;
+            state.submission = None;
+            state.completion_timestamp = Some(Timestamp::now());
+            state.issue_judgement_at = Some(Timestamp::with_offset(offset));
+            set_default_judgement(state);
+
+            self.events
+                .push(NotificationMessage::IdentityFullyVerified {
+                    context: state.context.clone(),
+                });
+            self.transitions.push(StateTransition::FullyVerified);
+        } else if state.is_fully_verified {
+            // Reset verification state if identity was changed.
+            state.is_fully_verified = false;
+            state.judgement_submitted = false;
+            state.submission = None;
+        }
+    }
+    /// Sets the judgement to be issued after a short delay.
+    fn set_pending_judgement(&mut self, judgement: Judgement) {
+        let state = &mut self.state;
+
+        // Create a timed delay for issuing judgments, same as with fully
+        // verified identities.
+        let offset = thread_rng().gen_range(30..300);
+
+        state.judgement = Some(judgement);
+        state.judgement_submitted = false;
+        state.submission = None;
+        state.issue_judgement_at = Some(Timestamp::with_offset(offset));
+
+        self.events.push(NotificationMessage::JudgementPending {
+            context: state.context.clone(),
+            judgement,
+        });
+    }
+    /// If the first challenge was already verified and the second challenge
+    /// got replaced, the new second challenge must be sent to the user.
+    fn notify_second_challenge_change(
+        &mut self,
+        field: &IdentityFieldValue,
+        old: &ChallengeType,
+        new: &ChallengeType,
+    ) {
+        self.events.extend(second_challenge_change(
+            &self.state.context,
+            field,
+            old,
+            new,
+        ));
+    }
+    /// Applies the lockout policy to the fields with too many recent failed
+    /// attempts.
+    fn apply_lockout_policy(&mut self, config: &LockoutConfig) {
+        let context = self.state.context.clone();
+
+        for field in &mut self.state.fields {
+            let events = apply_lockout(&context, field, config);
+            self.events.extend(events);
+        }
+    }
+    /// Verifies the challenge of the given field, returns whether it was
+    /// modified.
+    fn verify_manually(&mut self, field: &RawFieldName) -> bool {
+        let field_state = match self
+            .state
+            .fields
+            .iter_mut()
+            .find(|f| matches_field_name(&f.value, field))
+        {
+            Some(field_state) => field_state,
+            None => return false,
+        };
+
+        match &mut field_state.challenge {
+            // Emails additionally require the secondary verification.
+            ChallengeType::ExpectedMessage { expected, second } => {
+                let mut modified = set_flag(&mut expected.is_verified);
+                if let (RawFieldName::Email, Some(second)) = (field, second) {
+                    modified |= set_flag(&mut second.is_verified);
+                }
+                modified
+            }
+            ChallengeType::DisplayNameCheck {
+                passed,
+                violations: _,
+            } => set_flag(passed),
+            ChallengeType::SignedMessage { expected } => set_flag(&mut expected.is_verified),
+            ChallengeType::Unsupported { is_verified } => {
+                let modified = *is_verified != Some(true);
+                *is_verified = Some(true);
+                modified
+            }
+        }
+    }
+}
+
+impl MongoStorage {
+    /// Fetches the identities of the network which are not fully verified.
+    async fn fetch_unverified_contexts(&self, network: &ChainName) -> Result<Vec<IdentityContext>> {
+        let coll = self.db.collection::<JudgementState>(IDENTITY_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "context.chain": network.as_str().to_bson()?,
+                    "is_fully_verified": false,
+                },
+                None,
+            )
+            .await?;
+
+        let mut contexts = vec![];
+        while let Some(state) = cursor.next().await {
+            contexts.push(state?.context);
+        }
+
+        Ok(contexts)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EventWrapper {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HistoryWrapper {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(flatten)]
+    entry: JudgementHistoryEntry,
+}
+
+// The last event which was returned to the subscriber, when polling.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LastEvent {
+    id: ObjectId,
+    timestamp: Timestamp,
+}
+
+/// An index required by the queries of `MongoStorage`.
+struct Index {
+    collection: &'static str,
+    name: &'static str,
+    keys: Document,
+    unique: bool,
+}
+
+impl Index {
+    /// Checks the index against an entry returned by `listIndexes`.
+    fn matches(&self, found: &Document) -> bool {
+        found.get_str("name") == Ok(self.name)
+            && found.get_document("key") == Ok(&self.keys)
+            && found.get_bool("unique").unwrap_or(false) == self.unique
+    }
+}
+
+fn indexes() -> Vec<Index> {
+    vec![
+        Index {
+            collection: IDENTITY_COLLECTION,
+            name: "context",
+            keys: doc! {
+                "context": 1,
+            },
+            unique: true,
+        },
+        Index {
+            collection: IDENTITY_COLLECTION,
+            name: "fields_value",
+            keys: doc! {
+                "fields.value": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: IDENTITY_COLLECTION,
+            name: "judgement_candidates",
+            keys: doc! {
+                "context.chain": 1,
+                "is_fully_verified": 1,
+                "judgement_submitted": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: EVENT_COLLECTION,
+            name: "timestamp",
+            keys: doc! {
+                "timestamp": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: EVENT_COLLECTION,
+            name: "message_type_timestamp",
+            keys: doc! {
+                "message.type": 1,
+                "timestamp": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: DISPLAY_NAMES,
+            name: "context_chain",
+            keys: doc! {
+                "context.chain": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: JUDGEMENT_HISTORY,
+            name: "state_context",
+            keys: doc! {
+                "state.context": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: TOMBSTONES,
+            name: "context",
+            keys: doc! {
+                "context": 1,
+            },
+            unique: false,
+        },
+        Index {
+            collection: EVENT_SUBSCRIBERS,
+            name: "subscriber",
+            keys: doc! {
+                "subscriber": 1,
+            },
+            unique: true,
+        },
+        Index {
+            collection: WATCHER_STATUS,
+            name: "network",
+            keys: doc! {
+                "network": 1,
+            },
+            unique: true,
+        },
+    ]
+}
+
+impl MongoStorage {
+    async fn fetch_indexes(&self, collection: &str) -> Result<Vec<Document>> {
+        let resp = match self
+            .db
+            .run_command(
+                doc! {
+                    "listIndexes": collection,
+                },
+                None,
+            )
+            .await
+        {
+            Ok(resp) => resp,
+            // The collection does not exist yet.
+            Err(err) if is_command_error(&err, NAMESPACE_NOT_FOUND) => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(resp
+            .get_document("cursor")
+            .and_then(|cursor| cursor.get_array("firstBatch"))?
+            .iter()
+            .filter_map(|index| index.as_document().cloned())
+            .collect())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SchemaVersion {
+    #[serde(rename = "_id")]
+    collection: String,
+    version: u32,
+}
+
+impl MongoStorage {
+    /// Migrates the collection to the latest schema version. Only one instance
+    /// migrates a collection at a time, the others wait for the lock and find
+    /// the collection up to date.
+    async fn migrate_collection(&self, collection: &str) -> Result<()> {
+        let token = self.acquire_migration_lock(collection).await?;
+        let res = self.run_migrations(collection).await;
+
+        if let Err(err) = self.release_migration_lock(collection, &token).await {
+            error!(
+                "Failed to release migration lock of '{}': {:?}",
+                collection, err
+            );
+        }
+
+        res
+    }
+    async fn run_migrations(&self, collection: &str) -> Result<()> {
+        let versions = self.db.collection::<SchemaVersion>(SCHEMA_VERSIONS);
+        let coll = self.db.collection::<Document>(collection);
+        let latest = migrations::latest_version(collection);
+
+        let current = match versions
+            .find_one(
+                doc! {
+                    "_id": collection,
+                },
+                None,
+            )
+            .await?
+        {
+            Some(entry) => entry.version,
+            // Collections which were created before schema versions were
+            // introduced are in the original format. Empty ones are created
+            // in the latest format.
+            None if coll.estimated_document_count(None).await? > 0 => 0,
+            None => latest,
+        };
+
+        if current > latest {
+            return Err(anyhow!(
+                "Schema version {} of '{}' is newer than the supported version {}",
+                current,
+                collection,
+                latest
+            ));
+        }
+
+        for migration in migrations::pending(collection, current) {
+            info!(
+                "Migrating '{}' to schema version {}: {}",
+                collection, migration.version, migration.description
+            );
+
+            let mut cursor = coll.find(None, None).await?;
+            while let Some(doc) = cursor.next().await {
+                Self::migrate_document(&coll, migration, doc?).await?;
+            }
+
+            self.store_schema_version(collection, migration.version)
+                .await?;
+        }
+
+        if current == latest {
+            // Make sure the version gets recorded for new collections.
+            self.store_schema_version(collection, latest).await?;
+        }
+
+        Ok(())
+    }
+    /// Applies the migration to a single document. The document is only
+    /// replaced if it was not modified in the meantime (e.g. by an instance
+    /// which is not upgraded yet), otherwise the migration is applied to the
+    /// modified document again.
+    async fn migrate_document(
+        coll: &Collection<Document>,
+        migration: &Migration,
+        mut doc: Document,
+    ) -> Result<()> {
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let mut upgraded = doc.clone();
+            migration.apply(&mut upgraded)?;
+
+            if upgraded == doc {
+                return Ok(());
+            }
+
+            let id = doc.get("_id").cloned().unwrap_or(Bson::Null);
+            let res = coll
+                .replace_one(
+                    doc! {
+                        "_id": id.clone(),
+                        "$expr": {
+                            "$eq": ["$$ROOT", { "$literal": doc }],
+                        },
+                    },
+                    upgraded,
+                    None,
+                )
+                .await?;
+
+            if res.matched_count == 1 {
+                return Ok(());
+            }
+
+            doc = match coll.find_one(doc! { "_id": id }, None).await? {
+                Some(doc) => doc,
+                // Removed in the meantime.
+                None => return Ok(()),
+            };
+        }
+
+        Err(anyhow!(
+            "Failed to migrate a document of '{}', it was modified concurrently {} times in a row",
+            coll.name(),
+            MAX_UPDATE_ATTEMPTS
+        ))
+    }
+    /// Takes the migration lock of the collection, waiting for other instances
+    /// to release it. Returns the token which identifies the holder.
+    async fn acquire_migration_lock(&self, collection: &str) -> Result<String> {
+        let coll = self.db.collection::<Document>(SCHEMA_VERSIONS);
+        let id = migration_lock_id(collection);
+        let random: [u8; 16] = thread_rng().gen();
+        let token = hex::encode(random);
+
+        loop {
+            let now = Timestamp::now().raw();
+            let res = coll
+                .find_one_and_update(
+                    doc! {
+                        "_id": id.as_str(),
+                        "locked_until": {
+                            "$lt": now.to_bson()?,
+                        },
+                    },
+                    doc! {
+                        "$set": {
+                            "owner": token.as_str(),
+                            "locked_until": (now + MIGRATION_LOCK_TTL).to_bson()?,
+                        }
+                    },
+                    {
+                        let mut opt = FindOneAndUpdateOptions::default();
+                        opt.upsert = Some(true);
+                        Some(opt)
+                    },
+                )
+                .await;
+
+            match res {
+                Ok(_) => return Ok(token),
+                // The lock is held by another instance, the upsert conflicts
+                // with the existing lock document.
+                Err(err)
+                    if is_duplicate_key_error(&err) || is_command_error(&err, DUPLICATE_KEY) =>
+                {
+                    debug!("Waiting for another instance to migrate '{}'", collection);
+                    sleep(Duration::from_secs(1)).await;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    async fn release_migration_lock(&self, collection: &str, token: &str) -> Result<()> {
+        let coll = self.db.collection::<Document>(SCHEMA_VERSIONS);
+
+        coll.delete_one(
+            doc! {
+                "_id": migration_lock_id(collection),
+                "owner": token,
+            },
+            None,
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn store_schema_version(&self, collection: &str, version: u32) -> Result<()> {
+        let coll = self.db.collection::<()>(SCHEMA_VERSIONS);
+
+        coll.update_one(
+            doc! {
+                "_id": collection,
+            },
+            doc! {
+                "$set": {
+                    "version": version,
+                }
+            },
+            {
+                let mut opt = UpdateOptions::default();
+                opt.upsert = Some(true);
+                Some(opt)
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SubscriberPosition {
+    resume_token: Option<Document>,
+    last_event: Option<LastEvent>,
+}
+
+// Stored in `schema_versions`, next to the version entries of the collections.
+fn migration_lock_id(collection: &str) -> String {
+    format!("{}.migration_lock", collection)
+}
+
+fn is_command_error(err: &MongoError, code: i32) -> bool {
+    matches!(&*err.kind, MongoErrorKind::Command(err) if err.code == code)
+}
+
+fn is_duplicate_key_error(err: &MongoError) -> bool {
+    matches!(
+        &*err.kind,
+        MongoErrorKind::Write(WriteFailure::WriteError(err)) if err.code == DUPLICATE_KEY
+    )
+}
+
+// Events and history entries of an identity which were not moved to their
+// collections yet. See `MongoStorage::update_identity`.
+#[derive(Debug, Deserialize)]
+struct PendingEntries {
+    #[serde(default)]
+    pending_events: Vec<EventWrapper>,
+    #[serde(default)]
+    pending_history: Vec<HistoryWrapper>,
+}
+
+impl MongoStorage {
+    async fn fetch_subscriber_position(&self, subscriber: &str) -> Result<SubscriberPosition> {
+        let coll = self.db.collection::<SubscriberPosition>(EVENT_SUBSCRIBERS);
+
+        Ok(coll
+            .find_one(
+                doc! {
+                    "subscriber": subscriber,
+                },
+                None,
+            )
+            .await?
+            .unwrap_or_default())
+    }
+    async fn store_subscriber_position<T: Serialize>(
+        &self,
+        subscriber: &str,
+        field: &str,
+        value: &T,
+    ) -> Result<()> {
+        let coll = self.db.collection::<()>(EVENT_SUBSCRIBERS);
+
+        coll.update_one(
+            doc! {
+                "subscriber": subscriber,
+            },
+            doc! {
+                "$set": {
+                    field: value.to_bson()?,
+                }
+            },
+            {
+                let mut opt = UpdateOptions::default();
+                opt.upsert = Some(true);
+                Some(opt)
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+    async fn fetch_events(&self, event_tracker: &mut EventCursor) -> Result<Vec<EventWrapper>> {
+        let coll = self.db.collection(EVENT_COLLECTION);
+
+        let mut cursor = coll
+            .find(
+                doc! {
+                    "timestamp": {
+                        "$gte": event_tracker.timestamp.raw().to_bson()?,
+                    }
+                },
+                None,
+            )
+            .await?;
+
+        let mut events = vec![];
+        while let Some(doc) = cursor.next().await {
+            events.push(from_document::<EventWrapper>(doc?)?);
+        }
+
+        // Sort by id, ascending.
+        events.sort_by_key(|event| event.id);
+
+        Ok(event_tracker.track(events))
+    }
+}
+
+/// Receives events via [change
+/// streams](https://docs.mongodb.com/manual/changeStreams/), which requires a
+/// replica set.
+struct ChangeStreamSubscription {
+    storage: MongoStorage,
+    subscriber: String,
+    stream: Option<Cursor<Document>>,
+    resume_token: Option<Document>,
+    // Whether the resume token was not persisted yet.
+    pending: bool,
+}
+
+impl ChangeStreamSubscription {
+    async fn open(&mut self) -> std::result::Result<(), MongoError> {
+        fn pipeline(resume_token: Option<&Document>) -> Vec<Document> {
+            let mut stage = doc! {};
+            if let Some(token) = resume_token {
+                stage.insert("resumeAfter", token.clone());
+            }
+
+            vec![
+                doc! {
+                    "$changeStream": stage,
+                },
+                doc! {
+                    "$match": {
+                        "operationType": "insert",
+                    }
+                },
+            ]
+        }
+
+        let coll = self.storage.db.collection::<Document>(EVENT_COLLECTION);
+
+        let stream = match coll
+            .aggregate(pipeline(self.resume_token.as_ref()), None)
+            .await
+        {
+            Ok(stream) => stream,
+            Err(err) if is_command_error(&err, CHANGE_STREAM_HISTORY_LOST) => {
+                warn!(
+                    "Resume token of event subscriber '{}' expired, events might have been missed",
+                    self.subscriber
+                );
+
+                self.resume_token = None;
+                coll.aggregate(pipeline(None), None).await?
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventSubscription for ChangeStreamSubscription {
+    async fn next(&mut self) -> Result<NotificationMessage> {
+        if self.pending {
+            if let Some(token) = &self.resume_token {
+                self.storage
+                    .store_subscriber_position(&self.subscriber, "resume_token", token)
+                    .await?;
+            }
+
+            self.pending = false;
+        }
+
+        loop {
+            if self.stream.is_none() {
+                self.open().await?;
+            }
+
+            let stream = self.stream.as_mut().unwrap();
+            match stream.next().await {
+                Some(Ok(change)) => {
+                    // Skip malformed events instead of getting stuck on them.
+                    self.resume_token = Some(change.get_document("_id")?.clone());
+                    self.pending = true;
+
+                    let event: Event = from_document(change.get_document("fullDocument")?.clone())?;
+                    return Ok(event.message);
+                }
+                Some(Err(err)) => {
+                    // Resumed on the next call.
+                    self.stream = None;
+                    return Err(err.into());
+                }
+                // The change stream was closed by the server, resume.
+                None => self.stream = None,
+            }
+        }
+    }
+}
+
+// Keeps track of the latest, fetched events to avoid sending old messages or
+// duplicates.
+struct EventCursor {
+    timestamp: Timestamp,
+    fetched_ids: HashMap<ObjectId, Timestamp>,
+}
+
+impl EventCursor {
+    fn new() -> Self {
+        EventCursor {
+            timestamp: Timestamp::now(),
+            fetched_ids: HashMap::new(),
+        }
+    }
+    // Events which were inserted within the same second as the last event
+    // might get delivered again.
+    fn resume_after(last: LastEvent) -> Self {
+        EventCursor {
+            timestamp: last.timestamp,
+            fetched_ids: HashMap::from([(last.id, last.timestamp)]),
+        }
+    }
+    fn track(&mut self, events: Vec<EventWrapper>) -> Vec<EventWrapper> {
+        let mut new = vec![];
+
+        for wrapper in events {
+            if self.fetched_ids.contains_key(&wrapper.id) {
+                continue;
+            }
+
+            // Track event in EventCursor
+            let timestamp = wrapper.event.timestamp;
+            self.fetched_ids.insert(wrapper.id, timestamp);
+            self.timestamp = self.timestamp.max(timestamp);
+
+            // Save event
+            new.push(wrapper);
+        }
+
+        // Clean cache, only keep ids of the last 10 seconds.
+        let current = self.timestamp.raw();
+        self.fetched_ids
+            .retain(|_, timestamp| timestamp.raw() > current - 10);
+
+        new
+    }
+}
+
+/// Polls the event log. Fallback for deployments which do not support change
+/// streams (e.g. standalone servers).
+struct PollingSubscription {
+    storage: MongoStorage,
+    subscriber: String,
+    cursor: EventCursor,
+    queue: VecDeque<EventWrapper>,
+    // The last returned event, if not persisted yet.
+    pending: Option<LastEvent>,
+}
+
+#[async_trait]
+impl EventSubscription for PollingSubscription {
+    async fn next(&mut self) -> Result<NotificationMessage> {
+        if let Some(last) = &self.pending {
+            self.storage
+                .store_subscriber_position(&self.subscriber, "last_event", last)
+                .await?;
+
+            self.pending = None;
+        }
+
+        loop {
+            if let Some(wrapper) = self.queue.pop_front() {
+                self.pending = Some(LastEvent {
+                    id: wrapper.id,
+                    timestamp: wrapper.event.timestamp,
+                });
+
+                return Ok(wrapper.event.message);
+            }
+
+            self.queue
+                .extend(self.storage.fetch_events(&mut self.cursor).await?);
+
+            if self.queue.is_empty() {
+                sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_matches_list_indexes_entry() {
+        let index = indexes().remove(0);
+
+        // As returned by `listIndexes`.
+        let mut found = doc! {
+            "v": 2,
+            "key": {
+                "context": 1,
+            },
+            "name": "context",
+            "unique": true,
+        };
+        assert!(index.matches(&found));
+
+        found.insert("unique", false);
+        assert!(!index.matches(&found));
+
+        found.insert("unique", true);
+        found.insert(
+            "key",
+            doc! {
+                "context": -1,
+            },
+        );
+        assert!(!index.matches(&found));
+    }
+
+    #[test]
+    fn index_names_are_unique() {
+        let indexes = indexes();
+        for index in &indexes {
+            assert_eq!(
+                indexes
+                    .iter()
+                    .filter(|other| other.collection == index.collection
+                        && other.name == index.name)
+                    .count(),
+                1
+            );
+        }
+    }
+}
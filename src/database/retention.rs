@@ -0,0 +1,127 @@
+use super::Database;
+use crate::primitives::{Event, IdentityContext};
+use crate::{Result, RetentionConfig};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use tokio::time::{interval, Duration};
+
+/// Appends events to a file, one JSON object per line. Used to keep a copy of
+/// events before they are removed from the event log.
+#[derive(Debug, Clone)]
+pub struct EventArchive {
+    path: String,
+}
+
+impl EventArchive {
+    pub fn new(path: String) -> Self {
+        EventArchive { path }
+    }
+    pub fn append(&self, events: &[Event]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut content = String::new();
+        for event in events {
+            content.push_str(&serde_json::to_string(event)?);
+            content.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| anyhow!("Failed to open event archive '{}': {:?}", self.path, err))?;
+
+        // The events get deleted right after, so make sure they were written.
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+    /// Removes the archived events of the identity, returns the number of
+    /// removed events. Lines which can not be parsed are kept.
+    pub fn remove_identity(&self, context: &IdentityContext) -> Result<usize> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => {
+                return Err(anyhow!(
+                    "Failed to read event archive '{}': {:?}",
+                    self.path,
+                    err
+                ))
+            }
+        };
+
+        let mut kept = String::new();
+        let mut removed = 0;
+        for line in content.lines() {
+            match serde_json::from_str::<Event>(line) {
+                Ok(event) if event.message.context() == context => removed += 1,
+                _ => {
+                    kept.push_str(line);
+                    kept.push('\n');
+                }
+            }
+        }
+
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        // Replace the archive at once, so it does not get truncated if
+        // interrupted.
+        let tmp = format!("{}.tmp", self.path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp)
+            .map_err(|err| anyhow!("Failed to open '{}': {:?}", tmp, err))?;
+
+        file.write_all(kept.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(removed)
+    }
+}
+
+/// Removes expired events from the event log according to the retention
+/// policy of each event type.
+pub async fn prune_event_log(db: &Database, config: &RetentionConfig) -> Result<()> {
+    let archive = config.archive.clone().map(EventArchive::new);
+
+    for (message_type, max_age) in &config.max_age {
+        let count = db
+            .prune_events(message_type, *max_age, archive.as_ref())
+            .await?;
+
+        if count > 0 {
+            info!("Removed {} expired '{}' events", count, message_type);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn start_event_retention_task(db: Database, config: RetentionConfig) {
+    if config.max_age.is_empty() {
+        return;
+    }
+
+    info!("Starting event log retention background task");
+
+    actix::spawn(async move {
+        let mut interval = interval(Duration::from_secs(config.interval));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = prune_event_log(&db, &config).await {
+                error!("Failed to prune event log: {:?}", err);
+            }
+        }
+    });
+}
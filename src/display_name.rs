@@ -19,10 +19,20 @@ impl DisplayNameVerifier {
     pub async fn check_similarities(
         &self,
         name: &str,
-        chain: ChainName,
+        chain: &ChainName,
         // Skip comparison for this account, usually for the issuer itself
         // (required when re-requesting judgement).
         skip: Option<&IdentityContext>,
+    ) -> Result<Vec<DisplayNameEntry>> {
+        self.check_similarities_with_limit(name, chain, skip, self.config.limit)
+            .await
+    }
+    async fn check_similarities_with_limit(
+        &self,
+        name: &str,
+        chain: &ChainName,
+        skip: Option<&IdentityContext>,
+        limit: f64,
     ) -> Result<Vec<DisplayNameEntry>> {
         let current = self.db.fetch_display_names(chain).await?;
 
@@ -35,7 +45,7 @@ impl DisplayNameVerifier {
                 }
             }
 
-            if is_too_similar(name, &existing.display_name, self.config.limit) {
+            if is_too_similar(name, &existing.display_name, limit) {
                 // Only show up to `VIOLATIONS_CAP` violations.
                 if violations.len() == VIOLATIONS_CAP {
                     break;
@@ -47,6 +57,20 @@ impl DisplayNameVerifier {
 
         Ok(violations)
     }
+    /// Whether the display name of the identity is (nearly) identical to the
+    /// display name of another identity, as determined by `limit`.
+    pub async fn is_impersonation(&self, state: &JudgementState, limit: f64) -> Result<bool> {
+        let name = if let Some(name) = state.display_name() {
+            name
+        } else {
+            return Ok(false);
+        };
+
+        Ok(!self
+            .check_similarities_with_limit(name, &state.context.chain, Some(&state.context), limit)
+            .await?
+            .is_empty())
+    }
     pub async fn verify_display_name(&self, state: &JudgementState) -> Result<()> {
         if !self.config.enabled {
             return Ok(());
@@ -59,7 +83,7 @@ impl DisplayNameVerifier {
         };
 
         let violations = self
-            .check_similarities(name, state.context.chain, Some(&state.context))
+            .check_similarities(name, &state.context.chain, Some(&state.context))
             .await?;
 
         if !violations.is_empty() {
@@ -122,5 +146,5 @@ fn jaro_words(left: &str, right: &str, delimiter: &[&str]) -> f64 {
         total += temp;
     }
 
-    total as f64 / left_words.len().max(right_words.len()) as f64
+    total / left_words.len().max(right_words.len()) as f64
 }
@@ -8,8 +8,10 @@ extern crate serde;
 extern crate async_trait;
 
 use actix::clock::sleep;
+use adapters::admin::erase_address;
 use adapters::matrix::MatrixHandle;
-use primitives::ChainName;
+use primitives::{ChainAddress, ChainName};
+use std::collections::HashMap;
 use std::fs;
 use std::time::Duration;
 
@@ -17,8 +19,10 @@ pub type Result<T> = std::result::Result<T, anyhow::Error>;
 
 use adapters::run_adapters;
 use api::run_rest_api_server;
-use connector::run_connector;
-use database::Database;
+use connector::{run_connector, WatcherStatus};
+use database::retention::start_event_retention_task;
+use database::{export, Database, EventArchive, ExportFilter};
+use mock_watcher::{MockWatcher, WatcherFixture};
 use notifier::run_session_notifier;
 
 mod adapters;
@@ -26,17 +30,90 @@ mod api;
 mod connector;
 mod database;
 mod display_name;
+// Public, so the mock can be used to test other Watcher clients as well.
+pub mod mock_watcher;
 mod notifier;
+mod pgp;
 mod primitives;
 #[cfg(test)]
 mod tests;
+mod watcher_auth;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub db: DatabaseConfig,
+    // Configs which predate `networks` use the networks which were supported
+    // back then, see `Config::apply_legacy_watchers`.
+    #[serde(default = "legacy_networks")]
+    pub networks: Networks,
     pub instance: InstanceType,
 }
 
+impl Config {
+    /// Moves the endpoints of the deprecated `watcher` section of the adapter
+    /// listener to the corresponding networks. Networks without a Watcher are
+    /// removed.
+    fn apply_legacy_watchers(&mut self) -> Result<()> {
+        let watchers = match &mut self.instance {
+            InstanceType::AdapterListener(config) => std::mem::take(&mut config.watcher),
+            InstanceType::SingleInstance(config) => std::mem::take(&mut config.adapter.watcher),
+            InstanceType::SessionNotifier(_) => return Ok(()),
+        };
+
+        if watchers.is_empty() {
+            return Ok(());
+        }
+
+        if self
+            .networks
+            .iter()
+            .any(|network| !network.endpoints.is_empty())
+        {
+            return Err(anyhow!(
+                "The `watcher` section was replaced by `networks`, specify the endpoints there"
+            ));
+        }
+
+        warn!("The `watcher` section is deprecated, specify the endpoints in `networks` instead");
+
+        for WatcherConfig { network, endpoint } in watchers {
+            self.networks
+                .0
+                .iter_mut()
+                .find(|config| config.name == network)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unknown network '{}' in the `watcher` section, specify it in `networks`",
+                        network.as_str()
+                    )
+                })?
+                .endpoints
+                .push(endpoint);
+        }
+
+        self.networks
+            .0
+            .retain(|network| !network.endpoints.is_empty());
+
+        Ok(())
+    }
+}
+
+fn legacy_networks() -> Networks {
+    let network = |name: &str, ss58_prefix| NetworkConfig {
+        name: ChainName::from(name.to_string()),
+        ss58_prefix,
+        endpoints: vec![],
+        judgement: None,
+        challenge: None,
+        lockout: None,
+        watcher_auth: None,
+        watcher_tls: None,
+    };
+
+    Networks(vec![network("polkadot", 0), network("kusama", 2)])
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "role", content = "config")]
 pub enum InstanceType {
@@ -55,28 +132,152 @@ pub struct SingleInstanceConfig {
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct DatabaseConfig {
+    #[serde(default)]
+    pub backend: DatabaseBackend,
     pub uri: String,
     pub name: String,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseBackend {
+    Mongodb,
+    // Not shared between instances and lost on shutdown. The `uri` and
+    // `name` are ignored.
+    Memory,
+}
+
+// Enum variants can not be marked as `#[default]` with Rust 1.56.
+#[allow(clippy::derivable_impls)]
+impl Default for DatabaseBackend {
+    fn default() -> Self {
+        DatabaseBackend::Mongodb
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct NotifierConfig {
     pub api_address: String,
     pub display_name: DisplayNameConfig,
+    #[serde(default)]
+    pub pgp: PgpConfig,
+    // Applied to failed second challenges and PGP signatures. Should match
+    // the lockout policy of the adapter listener.
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    // Name under which the position in the event log is persisted. Must be
+    // stable across restarts and distinct for instances with their own
+    // config.
+    #[serde(default = "default_subscriber")]
+    pub subscriber: String,
+}
+
+fn default_subscriber() -> String {
+    "session_notifier".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Networks(Vec<NetworkConfig>);
+
+impl Networks {
+    pub fn get(&self, name: &ChainName) -> Option<&NetworkConfig> {
+        self.0.iter().find(|network| &network.name == name)
+    }
+    pub fn with_ss58_prefix(&self, prefix: u16) -> impl Iterator<Item = &NetworkConfig> {
+        self.0
+            .iter()
+            .filter(move |network| network.ss58_prefix == prefix)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &NetworkConfig> {
+        self.0.iter()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct NetworkConfig {
+    pub name: ChainName,
+    pub ss58_prefix: u16,
+    // The Watchers of this network, in order of preference. A single endpoint
+    // can be specified as `endpoint`. Only used by the adapter listener.
+    #[serde(alias = "endpoint", deserialize_with = "one_or_many")]
+    pub endpoints: Vec<String>,
+    // Overwrites the corresponding policies of the adapter listener for this
+    // network, if set.
+    pub judgement: Option<JudgementConfig>,
+    pub challenge: Option<ChallengeConfig>,
+    pub lockout: Option<LockoutConfig>,
+    // Authentication of the connections to the Watchers and the TLS settings
+    // of `wss://` endpoints. Only used by the adapter listener.
+    pub watcher_auth: Option<WatcherAuthConfig>,
+    pub watcher_tls: Option<WatcherTlsConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherAuthConfig {
+    // Sent as `Authorization: Bearer <token>` when connecting.
+    BearerToken(String),
+    // Messages are signed with HMAC-SHA256, messages of the Watcher without a
+    // valid signature are rejected.
+    HmacSecret(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WatcherTlsConfig {
+    // PEM file with the CA certificates to trust instead of the public root
+    // certificates, e.g. of a private CA.
+    pub ca_cert: Option<String>,
+    // PEM files of the client certificate (chain) and its private key, if the
+    // Watcher requires client authentication.
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    match <OneOrMany as serde::Deserialize>::deserialize(deserializer)? {
+        OneOrMany::One(value) => Ok(vec![value]),
+        OneOrMany::Many(values) => Ok(values),
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct AdapterConfig {
+    // Deprecated, replaced by `networks`.
+    #[serde(default)]
     pub watcher: Vec<WatcherConfig>,
     pub matrix: MatrixConfig,
     pub twitter: TwitterConfig,
     pub email: EmailConfig,
+    #[serde(default)]
+    pub web: WebConfig,
     pub display_name: DisplayNameConfig,
+    #[serde(default)]
+    pub judgement: JudgementConfig,
+    #[serde(default)]
+    pub challenge: ChallengeConfig,
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct WatcherConfig {
     pub network: ChainName,
     pub endpoint: String,
@@ -88,6 +289,137 @@ pub struct DisplayNameConfig {
     pub limit: f64,
 }
 
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct JudgementConfig {
+    // Issue an erroneous judgement if any field failed verification this many
+    // times. Disabled if not set.
+    pub erroneous_failed_attempts: Option<usize>,
+    // Issue an erroneous judgement if the display name is at least this
+    // similar to the display name of another identity. Disabled if not set.
+    pub erroneous_display_name_limit: Option<f64>,
+    // Give up submitting a judgement which was not confirmed by the Watcher
+    // after this many attempts. Retried indefinitely if not set.
+    pub max_submission_attempts: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ChallengeConfig {
+    // In seconds. Challenges do not expire if not set.
+    pub ttl: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LockoutConfig {
+    // Disabled if not set.
+    pub max_failed_attempts: Option<usize>,
+    // In seconds. Only failed attempts within this window are counted.
+    pub window: u64,
+    pub action: LockoutAction,
+    // In seconds. Only used by `LockoutAction::Lock`.
+    pub cooldown: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            max_failed_attempts: None,
+            window: 3600,
+            action: LockoutAction::Lock,
+            cooldown: 600,
+        }
+    }
+}
+
+/// The lockout policy of each network, taking the overwrites of
+/// `NetworkConfig` into account.
+#[derive(Debug, Clone, Default)]
+pub struct LockoutPolicy {
+    default: LockoutConfig,
+    networks: HashMap<ChainName, LockoutConfig>,
+}
+
+impl LockoutPolicy {
+    pub fn new(default: LockoutConfig, networks: &Networks) -> Self {
+        LockoutPolicy {
+            default,
+            networks: networks
+                .iter()
+                .filter_map(|network| {
+                    network
+                        .lockout
+                        .clone()
+                        .map(|config| (network.name.clone(), config))
+                })
+                .collect(),
+        }
+    }
+    pub fn get(&self, network: &ChainName) -> &LockoutConfig {
+        self.networks.get(network).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockoutAction {
+    // Ignore verification attempts until the cooldown has passed.
+    Lock,
+    // Replace the challenge with a new one.
+    Regenerate,
+    // Flag the field for admin review.
+    Flag,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RetentionConfig {
+    // In seconds. How often expired events are removed from the event log.
+    pub interval: u64,
+    // In seconds, per event type (e.g. `field_verification_failed`). Events
+    // of types which are not listed are kept forever.
+    pub max_age: HashMap<String, u64>,
+    // Expired events are appended to this file (JSON lines) before being
+    // removed. Not archived if not set.
+    pub archive: Option<String>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            interval: 3600,
+            max_age: HashMap::new(),
+            archive: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PgpConfig {
+    pub enabled: bool,
+    pub keyserver: String,
+    // Signatures submitted while this many `gpg` processes are running are
+    // rejected.
+    #[serde(default = "default_max_concurrent_verifications")]
+    pub max_concurrent_verifications: usize,
+}
+
+fn default_max_concurrent_verifications() -> usize {
+    4
+}
+
+impl Default for PgpConfig {
+    fn default() -> Self {
+        PgpConfig {
+            enabled: false,
+            keyserver: "hkps://keys.openpgp.org".to_string(),
+            max_concurrent_verifications: default_max_concurrent_verifications(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct MatrixConfig {
@@ -122,6 +454,22 @@ pub struct EmailConfig {
     pub request_interval: u64,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WebConfig {
+    pub enabled: bool,
+    pub request_interval: u64,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            enabled: false,
+            request_interval: 60,
+        }
+    }
+}
+
 fn open_config() -> Result<Config> {
     // Open config file.
     let content = fs::read_to_string("config.yaml")
@@ -131,50 +479,198 @@ fn open_config() -> Result<Config> {
         })?;
 
     // Parse config file as JSON.
-    let config = serde_yaml::from_str::<Config>(&content)
+    let mut config = serde_yaml::from_str::<Config>(&content)
         .map_err(|err| anyhow!("Failed to parse config: {:?}", err))?;
 
+    config.apply_legacy_watchers()?;
+
     Ok(config)
 }
 
-async fn config_adapter_listener(db: Database, config: AdapterConfig) -> Result<()> {
-    let watchers = config.watcher.clone();
+async fn config_adapter_listener(
+    db: Database,
+    networks: Networks,
+    config: AdapterConfig,
+) -> Result<()> {
     let dn_config = config.display_name.clone();
-    run_adapters(config.clone(), db.clone()).await?;
-    run_connector(db, watchers, dn_config).await
+    let judgement_config = config.judgement.clone();
+    let challenge_config = config.challenge.clone();
+    let lockout_config = config.lockout.clone();
+    let db = db.with_event_archive(config.retention.archive.clone().map(EventArchive::new));
+    run_adapters(config.clone(), db.clone(), networks.clone()).await?;
+    start_event_retention_task(db.clone(), config.retention.clone());
+    run_connector(
+        db,
+        networks,
+        dn_config,
+        judgement_config,
+        challenge_config,
+        lockout_config,
+        WatcherStatus::default(),
+    )
+    .await
 }
 
-async fn config_session_notifier(db: Database, not_config: NotifierConfig) -> Result<()> {
-    let lookup = run_rest_api_server(not_config, db.clone()).await?;
+async fn config_session_notifier(
+    db: Database,
+    networks: Networks,
+    not_config: NotifierConfig,
+) -> Result<()> {
+    let subscriber = not_config.subscriber.clone();
+    let lookup = run_rest_api_server(not_config, db.clone(), networks).await?;
 
-    actix::spawn(async move { run_session_notifier(db, lookup).await });
+    actix::spawn(async move { run_session_notifier(db, lookup, subscriber).await });
 
     Ok(())
 }
 
+/// Connects to the database, upgrades it to the latest schema version and
+/// creates all required indexes.
+async fn setup_database(config: &DatabaseConfig) -> Result<Database> {
+    info!("Initializing connection to database");
+    let db = Database::from_config(config).await?;
+
+    info!("Upgrading database schema");
+    db.migrate().await?;
+
+    info!("Creating database indexes");
+    db.ensure_indexes().await?;
+
+    db.connectivity_check().await?;
+
+    Ok(db)
+}
+
+/// Upgrades the database to the latest schema version and returns. The
+/// service itself does the same on startup.
+pub async fn migrate() -> Result<()> {
+    let root = open_config()?;
+    setup_database(&root.db).await?;
+
+    info!("Database schema is up to date");
+
+    Ok(())
+}
+
+/// Writes the data of the database to a file. Expects the file path,
+/// optionally followed by filter arguments (see `ExportFilter::from_args`).
+pub async fn export(args: &[String]) -> Result<()> {
+    let path = args
+        .first()
+        .ok_or_else(|| anyhow!("Missing path of the export file"))?;
+    let filter = ExportFilter::from_args(&args[1..])?;
+
+    let root = open_config()?;
+    let db = setup_database(&root.db).await?;
+
+    let summary = export::export(&db, path, &filter).await?;
+    info!(
+        "Exported {} identities, {} display names and {} events to {}",
+        summary.identities, summary.display_names, summary.events, path
+    );
+
+    Ok(())
+}
+
+/// Inserts the data of a file created by `export` into the database.
+/// Expects the file path.
+pub async fn import(args: &[String]) -> Result<()> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(anyhow!("Expected the path of the import file")),
+    };
+
+    let root = open_config()?;
+    let db = setup_database(&root.db).await?;
+
+    let summary = export::import(&db, path, &root.networks).await?;
+    info!(
+        "Imported {} identities, {} display names and {} events from {}",
+        summary.identities, summary.display_names, summary.events, path
+    );
+
+    Ok(())
+}
+
+/// Removes all personal data of the given address, including identities which
+/// were cancelled. Expects the address, the network is determined like for
+/// admin commands.
+pub async fn erase(args: &[String]) -> Result<()> {
+    let address = match args {
+        [address] => ChainAddress::from(address.clone()),
+        _ => return Err(anyhow!("Expected the address of the identity")),
+    };
+
+    let root = open_config()?;
+    let db = setup_database(&root.db).await?;
+
+    let archive = match &root.instance {
+        InstanceType::AdapterListener(config) => config.retention.archive.clone(),
+        InstanceType::SingleInstance(config) => config.adapter.retention.archive.clone(),
+        InstanceType::SessionNotifier(_) => None,
+    };
+
+    let db = db.with_event_archive(archive.map(EventArchive::new));
+    let reports = erase_address(&db, &root.networks, &address).await?;
+    if reports.is_empty() {
+        return Err(anyhow!(
+            "No personal data was found for {}",
+            address.as_str()
+        ));
+    }
+
+    info!(
+        "Erased personal data: {}",
+        serde_json::to_string_pretty(&reports)?
+    );
+
+    Ok(())
+}
+
+/// Runs a mock of the Watcher which serves the data of a fixture, for local
+/// development. Expects the path of the fixture, optionally followed by
+/// `--listen <ADDRESS>`. Does not require a config.
+pub async fn mock_watcher(args: &[String]) -> Result<()> {
+    let (path, address) = match args {
+        [path] => (path.as_str(), "127.0.0.1:8000"),
+        [path, flag, address] if flag == "--listen" => (path.as_str(), address.as_str()),
+        _ => {
+            return Err(anyhow!(
+                "Expected the path of the fixture, optionally followed by '--listen <ADDRESS>'"
+            ))
+        }
+    };
+
+    let fixture = WatcherFixture::from_file(path)?;
+    let watcher = MockWatcher::start(address, fixture)?;
+    info!("Mock Watcher listening on {}", watcher.endpoint());
+
+    loop {
+        sleep(Duration::from_secs(u64::MAX)).await;
+    }
+}
+
 pub async fn run() -> Result<()> {
     let root = open_config()?;
-    let (db_config, instance) = (root.db, root.instance);
+    let (db_config, networks, instance) = (root.db, root.networks, root.instance);
 
-    info!("Initializing connection to database");
-    let db = Database::new(&db_config.uri, &db_config.name).await?;
-    db.connectivity_check().await?;
+    let db = setup_database(&db_config).await?;
 
     match instance {
         InstanceType::AdapterListener(config) => {
             info!("Starting adapter listener instance");
-            config_adapter_listener(db, config).await?;
+            config_adapter_listener(db, networks, config).await?;
         }
         InstanceType::SessionNotifier(config) => {
             info!("Starting session notifier instance");
-            config_session_notifier(db, config).await?;
+            config_session_notifier(db, networks, config).await?;
         }
         InstanceType::SingleInstance(config) => {
             info!("Starting adapter listener and session notifier instances");
             let (adapter_config, notifier_config) = (config.adapter, config.notifier);
 
-            config_adapter_listener(db.clone(), adapter_config).await?;
-            config_session_notifier(db, notifier_config).await?;
+            config_adapter_listener(db.clone(), networks.clone(), adapter_config).await?;
+            config_session_notifier(db, networks, notifier_config).await?;
         }
     }
 
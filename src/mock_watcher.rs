@@ -0,0 +1,403 @@
+use crate::connector::{
+    AckResponse, DisplayNameEntryRaw, ErrorResponse, EventType, Handshake, JudgementCancellation,
+    JudgementResponse, ResponseMessage,
+};
+use crate::watcher_auth::{read_certs, read_private_key, WatcherLink};
+use crate::{Result, WatcherAuthConfig};
+use actix::prelude::*;
+use actix_web::dev::ServerHandle;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, App, Error as ActixError, HttpRequest, HttpResponse, HttpServer};
+use actix_web_actors::ws;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{RootCertStore, ServerConfig};
+use serde::Serialize;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// The scripted data served by the mock Watcher.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WatcherFixture {
+    // The handshake sent in response to the handshake of the registrar. The
+    // mock behaves like a Watcher from before the handshake was introduced
+    // (protocol version 0) if set to `null`.
+    #[serde(default = "default_handshake")]
+    pub handshake: Option<Handshake>,
+    // Sent in response to every `pendingJudgementsRequest`. The requests are
+    // kept as they are, so fixtures can contain invalid ones.
+    #[serde(default)]
+    pub pending_judgements: Vec<serde_json::Value>,
+    // Pushed once to every new connection, after the handshake.
+    #[serde(default)]
+    pub new_judgement_requests: Vec<serde_json::Value>,
+    // Sent in response to every `displayNamesRequest`.
+    #[serde(default)]
+    pub display_names: Vec<DisplayNameEntryRaw>,
+    // Pushed once to every new connection, after the new judgement requests.
+    #[serde(default)]
+    pub cancelled_judgement_requests: Vec<JudgementCancellation>,
+}
+
+fn default_handshake() -> Option<Handshake> {
+    Some(Handshake::local())
+}
+
+impl Default for WatcherFixture {
+    fn default() -> Self {
+        WatcherFixture {
+            handshake: default_handshake(),
+            pending_judgements: vec![],
+            new_judgement_requests: vec![],
+            display_names: vec![],
+            cancelled_judgement_requests: vec![],
+        }
+    }
+}
+
+impl WatcherFixture {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to open fixture '{}': {:?}", path, err))?;
+
+        serde_json::from_str(&content)
+            .map_err(|err| anyhow!("Failed to parse fixture '{}': {:?}", path, err))
+    }
+}
+
+/// The authentication and TLS settings of the mock Watcher.
+#[derive(Debug, Clone, Default)]
+pub struct MockWatcherConfig {
+    // Connections (bearer token) or messages (HMAC) of the registrar must be
+    // authenticated accordingly.
+    pub auth: Option<WatcherAuthConfig>,
+    // Serves `wss://` instead of `ws://`, if set.
+    pub tls: Option<MockTlsConfig>,
+}
+
+/// PEM files of the TLS settings of the mock Watcher. The certificate must be
+/// issued for `localhost`.
+#[derive(Debug, Clone)]
+pub struct MockTlsConfig {
+    pub cert: String,
+    pub key: String,
+    // Clients must present a certificate issued by this CA, if set.
+    pub client_ca: Option<String>,
+}
+
+fn server_tls_config(config: &MockTlsConfig) -> Result<ServerConfig> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots
+                    .add(&cert)
+                    .map_err(|err| anyhow!("Invalid CA certificate in '{}': {:?}", path, err))?;
+            }
+
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    builder
+        .with_single_cert(read_certs(&config.cert)?, read_private_key(&config.key)?)
+        .map_err(|err| anyhow!("Invalid certificate '{}': {:?}", config.cert, err))
+}
+
+#[derive(Clone)]
+struct SharedState {
+    fixture: Arc<WatcherFixture>,
+    link: WatcherLink,
+    judgements: Arc<RwLock<Vec<JudgementResponse>>>,
+    errors: Arc<RwLock<Vec<ErrorResponse>>>,
+    // All sessions ever opened, including closed ones.
+    sessions: Arc<RwLock<Vec<Addr<MockWatcherSession>>>>,
+}
+
+/// A websocket server which speaks the Watcher protocol. Serves the scripted
+/// data of a `WatcherFixture` and records (and acknowledges) the judgements
+/// provided by the registrar. Used for local development and to test the
+/// connector against a real websocket connection.
+pub struct MockWatcher {
+    address: SocketAddr,
+    state: SharedState,
+    server: ServerHandle,
+    tls: bool,
+}
+
+impl MockWatcher {
+    /// Starts the server on the given address. Use port `0` to pick any free
+    /// port, see `endpoint`.
+    pub fn start(address: &str, fixture: WatcherFixture) -> Result<Self> {
+        Self::start_with_config(address, fixture, MockWatcherConfig::default())
+    }
+    pub fn start_with_config(
+        address: &str,
+        fixture: WatcherFixture,
+        config: MockWatcherConfig,
+    ) -> Result<Self> {
+        let state = SharedState {
+            fixture: Arc::new(fixture),
+            link: WatcherLink::new(config.auth.as_ref(), None)?.signed_by_watcher(),
+            judgements: Default::default(),
+            errors: Default::default(),
+            sessions: Default::default(),
+        };
+
+        let t_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(t_state.clone()))
+                .service(web::resource("/").to(mock_watcher_route))
+        })
+        .workers(1);
+
+        let server = match &config.tls {
+            Some(tls) => server.bind_rustls(address, server_tls_config(tls)?)?,
+            None => server.bind(address)?,
+        };
+
+        let address = server
+            .addrs()
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Mock Watcher is not bound to any address"))?;
+
+        let server = server.run();
+        let handle = server.handle();
+        actix::spawn(async move {
+            let _ = server.await;
+        });
+
+        Ok(MockWatcher {
+            address,
+            state,
+            server: handle,
+            tls: config.tls.is_some(),
+        })
+    }
+    /// The websocket endpoint to connect to.
+    pub fn endpoint(&self) -> String {
+        if self.tls {
+            format!("wss://localhost:{}", self.address.port())
+        } else {
+            format!("ws://{}", self.address)
+        }
+    }
+    /// The judgements provided by the registrar, in the order received.
+    pub async fn judgements(&self) -> Vec<JudgementResponse> {
+        self.state.judgements.read().await.clone()
+    }
+    /// The errors reported by the registrar, in the order received.
+    pub async fn errors(&self) -> Vec<ErrorResponse> {
+        self.state.errors.read().await.clone()
+    }
+    /// The number of connections opened by the registrar.
+    pub async fn connections(&self) -> usize {
+        self.state.sessions.read().await.len()
+    }
+    /// Closes all open connections, as if the Watcher restarted.
+    pub async fn disconnect(&self) {
+        for session in self.state.sessions.read().await.iter() {
+            session.do_send(Disconnect);
+        }
+    }
+    pub async fn stop(&self) {
+        self.server.stop(false).await;
+    }
+}
+
+async fn mock_watcher_route(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<SharedState>,
+) -> std::result::Result<HttpResponse, ActixError> {
+    let header = req
+        .headers()
+        .get(AUTHORIZATION)
+        .map(|value| value.as_bytes());
+    if !state.link.authorize(header) {
+        warn!("Mock Watcher refused connection with invalid credentials");
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    ws::start(
+        MockWatcherSession {
+            state: state.get_ref().clone(),
+        },
+        &req,
+        stream,
+    )
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+struct Disconnect;
+
+struct MockWatcherSession {
+    state: SharedState,
+}
+
+impl MockWatcherSession {
+    fn send<T: Serialize>(&self, event: EventType, data: T, ctx: &mut ws::WebsocketContext<Self>) {
+        match self.state.link.encode(event, data) {
+            Ok(msg) => ctx.text(msg),
+            Err(err) => error!("Failed to serialize message of mock Watcher: {:?}", err),
+        }
+    }
+    fn push_judgement_requests(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        for request in &self.state.fixture.new_judgement_requests {
+            self.send(EventType::NewJudgementRequest, request, ctx);
+        }
+        for cancellation in &self.state.fixture.cancelled_judgement_requests {
+            self.send(EventType::JudgementRequestCancelled, cancellation, ctx);
+        }
+    }
+    fn process(&mut self, txt: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        // Not answered, the registrar would reject the response just the same.
+        if let Err(err) = self.state.link.verify(txt.as_bytes()) {
+            warn!("Mock Watcher rejected message: {:?}", err);
+            return;
+        }
+
+        let parsed: ResponseMessage<serde_json::Value> = match serde_json::from_str(txt) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                warn!("Mock Watcher received invalid message: {:?}", err);
+                self.send(EventType::Error, "invalid message", ctx);
+                return;
+            }
+        };
+
+        match parsed.event {
+            EventType::Handshake => {
+                // Watchers from before the handshake do not respond.
+                if let Some(handshake) = &self.state.fixture.handshake {
+                    self.send(EventType::Handshake, handshake, ctx);
+                    self.push_judgement_requests(ctx);
+                }
+            }
+            EventType::PendingJudgementsRequest => {
+                let pending = &self.state.fixture.pending_judgements;
+                self.send(EventType::PendingJudgementsResponse, pending, ctx);
+            }
+            EventType::DisplayNamesRequest => {
+                let display_names = &self.state.fixture.display_names;
+                self.send(EventType::DisplayNamesResponse, display_names, ctx);
+            }
+            EventType::JudgementResult => {
+                let judgement: JudgementResponse = match serde_json::from_value(parsed.data) {
+                    Ok(judgement) => judgement,
+                    Err(err) => {
+                        warn!("Mock Watcher received invalid judgement: {:?}", err);
+                        self.send(EventType::Error, "invalid judgement", ctx);
+                        return;
+                    }
+                };
+
+                info!(
+                    "Mock Watcher received judgement: {:?}, {:?}",
+                    judgement.address, judgement.judgement
+                );
+
+                let judgements = Arc::clone(&self.state.judgements);
+                ctx.spawn(
+                    async move {
+                        judgements.write().await.push(judgement.clone());
+                        judgement
+                    }
+                    .into_actor(self)
+                    .map(|judgement, act, ctx| {
+                        act.send(
+                            EventType::Ack,
+                            AckResponse {
+                                result: "judgement given".to_string(),
+                                address: Some(judgement.address),
+                            },
+                            ctx,
+                        );
+                    }),
+                );
+            }
+            EventType::Error => {
+                warn!("Mock Watcher received error: {:?}", parsed.data);
+
+                // Errors which are not about a judgement request are plain
+                // strings.
+                if let Ok(error) = serde_json::from_value::<ErrorResponse>(parsed.data) {
+                    let errors = Arc::clone(&self.state.errors);
+                    ctx.spawn(
+                        async move {
+                            errors.write().await.push(error);
+                        }
+                        .into_actor(self),
+                    );
+                }
+            }
+            _ => {
+                warn!("Mock Watcher received unexpected message: {:?}", parsed);
+                self.send(EventType::Error, "unexpected message", ctx);
+            }
+        }
+    }
+}
+
+impl Actor for MockWatcherSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let sessions = Arc::clone(&self.state.sessions);
+        let addr = ctx.address();
+        ctx.spawn(
+            async move {
+                sessions.write().await.push(addr);
+            }
+            .into_actor(self),
+        );
+
+        // Watchers from before the handshake push requests right away.
+        if self.state.fixture.handshake.is_none() {
+            self.push_judgement_requests(ctx);
+        }
+    }
+}
+
+impl Handler<Disconnect> for MockWatcherSession {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Disconnect, ctx: &mut Self::Context) {
+        ctx.close(Some(ws::CloseCode::Away.into()));
+        ctx.stop();
+    }
+}
+
+impl StreamHandler<std::result::Result<ws::Message, ws::ProtocolError>> for MockWatcherSession {
+    fn handle(
+        &mut self,
+        msg: std::result::Result<ws::Message, ws::ProtocolError>,
+        ctx: &mut Self::Context,
+    ) {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(err) => {
+                warn!("Mock Watcher connection failed: {:?}", err);
+                ctx.stop();
+                return;
+            }
+        };
+
+        match msg {
+            ws::Message::Text(txt) => self.process(&txt, ctx),
+            ws::Message::Ping(bytes) => ctx.pong(&bytes),
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => {}
+        }
+    }
+}
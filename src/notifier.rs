@@ -1,55 +1,69 @@
 use crate::api::{LookupServer, NotifyAccountState};
-use crate::database::{Database, EventCursor};
-use crate::primitives::{IdentityContext, JudgementState};
+use crate::database::Database;
+use crate::primitives::NotificationMessage;
 use crate::Result;
 use actix::prelude::*;
-use std::collections::HashMap;
 use tokio::time::{sleep, Duration};
 
-pub async fn run_session_notifier(mut db: Database, server: Addr<LookupServer>) {
+pub async fn run_session_notifier(db: Database, server: Addr<LookupServer>, subscriber: String) {
     async fn local(
-        db: &mut Database,
+        db: &Database,
         server: &Addr<LookupServer>,
-        cursor: &mut EventCursor,
+        event: NotificationMessage,
     ) -> Result<()> {
-        let events = db.fetch_events(cursor).await?;
-        let mut cache: HashMap<IdentityContext, JudgementState> = HashMap::new();
+        let state = match db.fetch_judgement_state(event.context()).await? {
+            Some(state) => state,
+            // Cancelled requests are removed, their last state is kept in the
+            // history.
+            None if matches!(event, NotificationMessage::JudgementRequestCancelled { .. }) => db
+                .fetch_judgement_history(event.context())
+                .await?
+                .pop()
+                .map(|entry| entry.state)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No history found for cancelled context: {:?}",
+                        event.context()
+                    )
+                })?,
+            None => {
+                return Err(anyhow!(
+                    "No identity state found for context: {:?}",
+                    event.context()
+                ))
+            }
+        };
 
-        for event in events {
-            let state = match cache.get(event.context()) {
-                Some(state) => state.clone(),
-                None => {
-                    let state = db
-                        .fetch_judgement_state(event.context())
-                        .await?
-                        .ok_or_else(|| {
-                            anyhow!("No identity state found for context: {:?}", event.context())
-                        })?;
-
-                    cache.insert(event.context().clone(), state.clone());
-
-                    state
-                }
-            };
-
-            server.do_send(NotifyAccountState {
-                state: state.into(),
-                notifications: vec![event],
-            });
-        }
+        server.do_send(NotifyAccountState {
+            state: state.into(),
+            notifications: vec![event],
+        });
 
         Ok(())
     }
 
-    let mut cursor = EventCursor::new();
+    let mut events = loop {
+        match db.subscribe_events(&subscriber).await {
+            Ok(events) => break events,
+            Err(err) => {
+                error!("Failed to subscribe to event log: {:?}", err);
+                sleep(Duration::from_secs(1)).await;
+            }
+        }
+    };
+
     loop {
-        if let Err(err) = local(&mut db, &server, &mut cursor).await {
+        let res = match events.next().await {
+            Ok(event) => local(&db, &server, event).await,
+            Err(err) => {
+                // Prevent a busy loop if the database is unavailable.
+                sleep(Duration::from_secs(1)).await;
+                Err(err)
+            }
+        };
+
+        if let Err(err) = res {
             error!("Error in session notifier event loop: {:?}", err);
         }
-
-        // Fetch events based on intervals until ["Change
-        // Streams"](https://docs.mongodb.com/manual/changeStreams/) are
-        // implemented in the Rust MongoDb driver.
-        sleep(Duration::from_secs(1)).await;
     }
 }
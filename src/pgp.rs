@@ -0,0 +1,294 @@
+use crate::Result;
+use rand::{thread_rng, Rng};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+const SIGNED_MESSAGE_HEADER: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+const SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+// In seconds. Includes fetching the public key from the keyserver.
+const GPG_TIMEOUT: u64 = 30;
+
+/// The content of a clearsigned message whose signature was successfully
+/// verified.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SignedMessage {
+    /// The fingerprint of the primary key, normalized with
+    /// `normalize_fingerprint`.
+    pub fingerprint: String,
+    pub text: String,
+}
+
+#[async_trait]
+pub trait SignatureVerifier {
+    async fn verify(&self, message: &str) -> Result<SignedMessage>;
+}
+
+/// Verifies clearsigned messages with the `gpg` binary. The public key of the
+/// signer is fetched from the configured keyserver, using a throw-away keyring
+/// for each verification.
+pub struct GpgVerifier {
+    keyserver: String,
+}
+
+impl GpgVerifier {
+    pub fn new(keyserver: String) -> Self {
+        GpgVerifier { keyserver }
+    }
+    async fn run_gpg(&self, home: &Path, message: &str) -> Result<String> {
+        let mut child = Command::new("gpg")
+            .arg("--homedir")
+            .arg(home)
+            .args(["--batch", "--no-tty", "--status-fd", "1"])
+            .args(["--keyserver", self.keyserver.as_str()])
+            .args(["--auto-key-retrieve", "--verify"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| anyhow!("failed to execute gpg: {:?}", err))?;
+
+        // Write the message to stdin and close it.
+        {
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| anyhow!("failed to open stdin of gpg"))?;
+
+            stdin.write_all(message.as_bytes()).await?;
+        }
+
+        let output = timeout(Duration::from_secs(GPG_TIMEOUT), child.wait_with_output())
+            .await
+            .map_err(|_| anyhow!("gpg did not finish within {} seconds", GPG_TIMEOUT))??;
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+#[async_trait]
+impl SignatureVerifier for GpgVerifier {
+    async fn verify(&self, message: &str) -> Result<SignedMessage> {
+        let text = extract_signed_text(message)?;
+
+        // Create an isolated keyring.
+        let random: [u8; 8] = thread_rng().gen();
+        let home = std::env::temp_dir().join(format!("registrar-gpg-{}", hex::encode(random)));
+        std::fs::create_dir(&home)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&home, std::fs::Permissions::from_mode(0o700))?;
+        }
+
+        let status = self.run_gpg(&home, message).await;
+        let _ = std::fs::remove_dir_all(&home)
+            .map_err(|err| warn!("Failed to remove temporary gpg keyring: {:?}", err));
+
+        let fingerprint = parse_valid_signature(&status?)
+            .ok_or_else(|| anyhow!("invalid signature or unknown public key"))?;
+
+        Ok(SignedMessage { fingerprint, text })
+    }
+}
+
+/// Brings a fingerprint into a comparable form, e.g. `0xab12 cd34` becomes
+/// `AB12CD34`.
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    let fingerprint = fingerprint.trim();
+    let fingerprint = fingerprint
+        .strip_prefix("0x")
+        .or_else(|| fingerprint.strip_prefix("0X"))
+        .unwrap_or(fingerprint);
+
+    fingerprint
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Extracts the (dash-unescaped) text of a clearsigned message. This does NOT
+/// verify the signature.
+pub fn extract_signed_text(message: &str) -> Result<String> {
+    // Multiple signed blocks would allow combining a valid signature of one
+    // block with the text of another.
+    if message.matches(SIGNED_MESSAGE_HEADER).count() != 1
+        || message.matches(SIGNATURE_HEADER).count() != 1
+    {
+        return Err(anyhow!("expected exactly one clearsigned message"));
+    }
+
+    let mut lines = message.lines().map(|line| line.trim_end_matches('\r'));
+
+    lines
+        .find(|line| line.trim() == SIGNED_MESSAGE_HEADER)
+        .ok_or_else(|| anyhow!("not a clearsigned message"))?;
+
+    // Skip the armor headers (e.g. "Hash: SHA256"), terminated by an empty
+    // line.
+    for line in &mut lines {
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut text = vec![];
+    let mut terminated = false;
+    for line in lines {
+        if line == SIGNATURE_HEADER {
+            terminated = true;
+            break;
+        }
+
+        text.push(line.strip_prefix("- ").unwrap_or(line));
+    }
+
+    if !terminated {
+        return Err(anyhow!("no signature found in clearsigned message"));
+    }
+
+    Ok(text.join("\n"))
+}
+
+/// Parses the machine-readable status output of `gpg --status-fd` and returns
+/// the (normalized) fingerprint of the primary key if the signature is valid.
+fn parse_valid_signature(status: &str) -> Option<String> {
+    let mut good = false;
+    let mut fingerprint = None;
+
+    for line in status.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.get(1) {
+            Some(&"GOODSIG") => good = true,
+            // Any of those invalidates the signature.
+            Some(&"BADSIG") | Some(&"ERRSIG") | Some(&"EXPKEYSIG") | Some(&"REVKEYSIG") => {
+                return None
+            }
+            Some(&"VALIDSIG") => {
+                // Only a single signature is accepted.
+                if fingerprint.is_some() {
+                    return None;
+                }
+
+                // The fingerprint of the primary key is the last field. If not
+                // present, the signing key is the primary key.
+                fingerprint = parts.get(11).or_else(|| parts.get(2)).cloned();
+            }
+            _ => {}
+        }
+    }
+
+    if good {
+        fingerprint.map(normalize_fingerprint)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Accepts any clearsigned message. The fingerprint of the "signer" is the
+    /// content of the signature block.
+    pub struct StubVerifier;
+
+    #[async_trait]
+    impl SignatureVerifier for StubVerifier {
+        async fn verify(&self, message: &str) -> Result<SignedMessage> {
+            let text = extract_signed_text(message)?;
+            let fingerprint = message
+                .split(SIGNATURE_HEADER)
+                .nth(1)
+                .and_then(|sig| sig.lines().map(|l| l.trim()).find(|l| !l.is_empty()))
+                .map(normalize_fingerprint)
+                .ok_or_else(|| anyhow!("no fingerprint in stub signature"))?;
+
+            Ok(SignedMessage { fingerprint, text })
+        }
+    }
+
+    /// Creates a clearsigned message which is accepted by `StubVerifier`.
+    pub fn stub_clearsign(text: &str, fingerprint: &str) -> String {
+        format!(
+            "{}\nHash: SHA256\n\n{}\n{}\n\n{}\n-----END PGP SIGNATURE-----\n",
+            SIGNED_MESSAGE_HEADER, text, SIGNATURE_HEADER, fingerprint
+        )
+    }
+
+    #[test]
+    fn normalize_fingerprints() {
+        assert_eq!(normalize_fingerprint("ab12cd34"), "AB12CD34");
+        assert_eq!(normalize_fingerprint("0xab12cd34"), "AB12CD34");
+        assert_eq!(normalize_fingerprint(" AB12 CD34 "), "AB12CD34");
+    }
+
+    #[test]
+    fn extract_clearsigned_text() {
+        let message = "\
+-----BEGIN PGP SIGNED MESSAGE-----\r
+Hash: SHA512\r
+\r
+My challenge: 1234\r
+- -- dash escaped\r
+-----BEGIN PGP SIGNATURE-----\r
+\r
+iQIzBAEBCgAdFiEE\r
+-----END PGP SIGNATURE-----\r
+";
+
+        let text = extract_signed_text(message).unwrap();
+        assert_eq!(text, "My challenge: 1234\n-- dash escaped");
+
+        // Not a clearsigned message.
+        assert!(extract_signed_text("My challenge: 1234").is_err());
+
+        // Missing signature.
+        let message = "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\nMy challenge\n";
+        assert!(extract_signed_text(message).is_err());
+
+        // Multiple signed messages.
+        let message = format!(
+            "{}\n{}",
+            stub_clearsign("A", "AB"),
+            stub_clearsign("B", "CD")
+        );
+        assert!(extract_signed_text(&message).is_err());
+    }
+
+    #[test]
+    fn parse_gpg_status() {
+        let status = "\
+[GNUPG:] NEWSIG
+[GNUPG:] KEY_CONSIDERED 5FB9E5D7AF1E8CDB4B7F6DB4F9A5C4B9A1A21D7E 0
+[GNUPG:] SIG_ID 2AjhwFhyAm/2jv4uHLdoZuxzfOs 2021-01-01 1609459200
+[GNUPG:] GOODSIG F9A5C4B9A1A21D7E Alice <alice@email.com>
+[GNUPG:] VALIDSIG 0BF6D7C1A2BC31B0F5A6C6B6E3C9C0F8A1A22B33 2021-01-01 1609459200 0 4 0 1 10 01 5FB9E5D7AF1E8CDB4B7F6DB4F9A5C4B9A1A21D7E
+[GNUPG:] TRUST_UNDEFINED 0 pgp
+";
+
+        // Returns the primary key fingerprint, not the subkey fingerprint.
+        assert_eq!(
+            parse_valid_signature(status),
+            Some("5FB9E5D7AF1E8CDB4B7F6DB4F9A5C4B9A1A21D7E".to_string())
+        );
+
+        let status = "\
+[GNUPG:] NEWSIG
+[GNUPG:] ERRSIG F9A5C4B9A1A21D7E 1 10 01 1609459200 9 -
+[GNUPG:] NO_PUBKEY F9A5C4B9A1A21D7E
+";
+        assert_eq!(parse_valid_signature(status), None);
+
+        let status = "\
+[GNUPG:] NEWSIG
+[GNUPG:] BADSIG F9A5C4B9A1A21D7E Alice <alice@email.com>
+";
+        assert_eq!(parse_valid_signature(status), None);
+    }
+}
@@ -1,7 +1,14 @@
 use actix::Message;
 
 use crate::adapters::admin::RawFieldName;
-use crate::connector::DisplayNameEntry;
+use crate::connector::{CancellationReason, DisplayNameEntry, Judgement};
+use crate::Result;
+use blake2::{Blake2b512, Digest};
+use std::str::FromStr;
+
+const SS58_CHECKSUM_PREFIX: &[u8] = b"SS58PRE";
+const SS58_CHECKSUM_LEN: usize = 2;
+const PUBLIC_KEY_LEN: usize = 32;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -27,6 +34,79 @@ impl ChainAddress {
     pub fn as_str(&self) -> &str {
         self.0.as_str()
     }
+    /// Encodes the public key as an SS58 address with the given network
+    /// prefix.
+    pub fn encode(prefix: u16, public: &[u8; PUBLIC_KEY_LEN]) -> Self {
+        let mut data = match prefix {
+            0..=63 => vec![prefix as u8],
+            // Two-byte prefix, see the SS58 specification.
+            _ => vec![
+                ((prefix & 0b0000_0000_1111_1100) as u8) >> 2 | 0b0100_0000,
+                ((prefix >> 8) as u8) | ((prefix & 0b0000_0000_0000_0011) as u8) << 6,
+            ],
+        };
+
+        data.extend_from_slice(public);
+        let checksum = ss58_checksum(&data);
+        data.extend_from_slice(&checksum[..SS58_CHECKSUM_LEN]);
+
+        ChainAddress(bs58::encode(data).into_string())
+    }
+    /// Decodes the SS58 address and validates its checksum. Returns the
+    /// network prefix and the public key.
+    pub fn decode(&self) -> Result<(u16, [u8; PUBLIC_KEY_LEN])> {
+        let data = bs58::decode(self.as_str())
+            .into_vec()
+            .map_err(|err| anyhow!("invalid base58 encoding of {}: {:?}", self.0, err))?;
+
+        let (prefix_len, prefix) = match data.first() {
+            Some(0..=63) => (1, data[0] as u16),
+            Some(64..=127) if data.len() > 1 => {
+                let lower = (data[0] << 2) | (data[1] >> 6);
+                let upper = data[1] & 0b0011_1111;
+                (2, lower as u16 | (upper as u16) << 8)
+            }
+            _ => return Err(anyhow!("invalid SS58 prefix of {}", self.0)),
+        };
+
+        if data.len() != prefix_len + PUBLIC_KEY_LEN + SS58_CHECKSUM_LEN {
+            return Err(anyhow!("invalid length of SS58 address {}", self.0));
+        }
+
+        let (payload, checksum) = data.split_at(prefix_len + PUBLIC_KEY_LEN);
+        if checksum != &ss58_checksum(payload)[..SS58_CHECKSUM_LEN] {
+            return Err(anyhow!("invalid checksum of SS58 address {}", self.0));
+        }
+
+        let mut public = [0; PUBLIC_KEY_LEN];
+        public.copy_from_slice(&payload[prefix_len..]);
+
+        Ok((prefix, public))
+    }
+    /// Re-encodes the address with the given network prefix, respectively in
+    /// the canonical format of that network.
+    pub fn normalize(&self, prefix: u16) -> Result<Self> {
+        let (_, public) = self.decode()?;
+        Ok(Self::encode(prefix, &public))
+    }
+}
+
+fn ss58_checksum(payload: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b512::new();
+    hasher.update(SS58_CHECKSUM_PREFIX);
+    hasher.update(payload);
+    hasher.finalize().to_vec()
+}
+
+/// Only accepts valid SS58 addresses.
+impl FromStr for ChainAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let address = ChainAddress(s.trim().to_string());
+        address.decode()?;
+        Ok(address)
+    }
 }
 
 impl From<String> for ChainAddress {
@@ -35,19 +115,21 @@ impl From<String> for ChainAddress {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+/// The name of a network as specified in the config, e.g. "polkadot". See
+/// `NetworkConfig`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub enum ChainName {
-    Polkadot,
-    Kusama,
-}
+pub struct ChainName(String);
 
 impl ChainName {
     pub fn as_str(&self) -> &str {
-        match self {
-            ChainName::Polkadot => "polkadot",
-            ChainName::Kusama => "kusama",
-        }
+        self.0.as_str()
+    }
+}
+
+impl From<String> for ChainName {
+    fn from(v: String) -> Self {
+        ChainName(v)
     }
 }
 
@@ -57,6 +139,15 @@ pub struct IdentityField {
     pub value: IdentityFieldValue,
     pub challenge: ChallengeType,
     pub failed_attempts: usize,
+    // Failed attempts which were not processed by the lockout policy yet.
+    #[serde(default)]
+    pub recent_failures: Vec<Timestamp>,
+    // Verification attempts are ignored until this time is reached.
+    #[serde(default)]
+    pub locked_until: Option<Timestamp>,
+    // Flagged for admin review due to repeated failed attempts.
+    #[serde(default)]
+    pub flagged: bool,
 }
 
 impl IdentityField {
@@ -66,10 +157,9 @@ impl IdentityField {
         let challenge = {
             match val {
                 LegalName(_) => ChallengeType::Unsupported { is_verified: None },
-                Web(_) => ChallengeType::Unsupported { is_verified: None },
-                PGPFingerprint(_) => ChallengeType::Unsupported { is_verified: None },
                 Image(_) => ChallengeType::Unsupported { is_verified: None },
                 Additional(_) => ChallengeType::Unsupported { is_verified: None },
+                Other { .. } => ChallengeType::Unsupported { is_verified: None },
                 DisplayName(_) => ChallengeType::DisplayNameCheck {
                     passed: false,
                     violations: vec![],
@@ -86,6 +176,13 @@ impl IdentityField {
                     expected: ExpectedMessage::random(),
                     second: None,
                 },
+                Web(_) => ChallengeType::ExpectedMessage {
+                    expected: ExpectedMessage::random(),
+                    second: None,
+                },
+                PGPFingerprint(_) => ChallengeType::SignedMessage {
+                    expected: ExpectedMessage::random(),
+                },
             }
         };
 
@@ -93,8 +190,16 @@ impl IdentityField {
             value: val,
             challenge,
             failed_attempts: 0,
+            recent_failures: vec![],
+            locked_until: None,
+            flagged: false,
         }
     }
+    pub fn is_locked(&self) -> bool {
+        self.locked_until
+            .map(|until| Timestamp::now().raw() < until.raw())
+            .unwrap_or(false)
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -108,6 +213,10 @@ pub enum ChallengeType {
         passed: bool,
         violations: Vec<DisplayNameEntry>,
     },
+    // The expected message must be signed with the specified key (e.g. PGP).
+    SignedMessage {
+        expected: ExpectedMessage,
+    },
     Unsupported {
         // For manual judgements via the admin interface.
         is_verified: Option<bool>,
@@ -128,9 +237,62 @@ impl ChallengeType {
                 passed,
                 violations: _,
             } => *passed,
+            ChallengeType::SignedMessage { expected } => expected.is_verified,
             ChallengeType::Unsupported { is_verified } => is_verified.unwrap_or(false),
         }
     }
+    fn expected_messages_mut(&mut self) -> Vec<&mut ExpectedMessage> {
+        match self {
+            ChallengeType::ExpectedMessage { expected, second } => {
+                let mut messages = vec![expected];
+                if let Some(second) = second {
+                    messages.push(second);
+                }
+                messages
+            }
+            ChallengeType::SignedMessage { expected } => vec![expected],
+            ChallengeType::DisplayNameCheck { .. } | ChallengeType::Unsupported { .. } => vec![],
+        }
+    }
+    pub fn set_ttl(&mut self, ttl: Option<u64>) {
+        for message in self.expected_messages_mut() {
+            message.ttl = ttl;
+        }
+    }
+    /// Replaces all unverified challenges with new ones, keeping the TTL.
+    /// Returns whether any challenge was replaced.
+    pub fn regenerate_unverified(&mut self) -> bool {
+        let mut regenerated = false;
+        for message in self.expected_messages_mut() {
+            if !message.is_verified {
+                *message = ExpectedMessage::random().with_ttl(message.ttl);
+                regenerated = true;
+            }
+        }
+
+        regenerated
+    }
+    /// Replaces all unverified challenges which are expired with new ones.
+    /// Challenges which were created without a TTL are kept, but expire
+    /// `ttl` seconds from now. Returns whether any challenge was replaced.
+    pub fn regenerate_expired(&mut self, ttl: u64) -> bool {
+        let mut regenerated = false;
+        for message in self.expected_messages_mut() {
+            if message.is_verified {
+                continue;
+            }
+
+            if message.ttl.is_none() {
+                message.created_at = Timestamp::now();
+                message.ttl = Some(ttl);
+            } else if message.is_expired() {
+                *message = ExpectedMessage::random().with_ttl(Some(ttl));
+                regenerated = true;
+            }
+        }
+
+        regenerated
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -138,6 +300,12 @@ impl ChallengeType {
 pub struct ExpectedMessage {
     pub value: String,
     pub is_verified: bool,
+    // Older entries do not have a creation time.
+    #[serde(default)]
+    pub created_at: Timestamp,
+    // In seconds. The challenge does not expire if not set.
+    #[serde(default)]
+    pub ttl: Option<u64>,
 }
 
 impl ExpectedMessage {
@@ -148,9 +316,25 @@ impl ExpectedMessage {
         ExpectedMessage {
             value: hex::encode(random),
             is_verified: false,
+            created_at: Timestamp::now(),
+            ttl: None,
+        }
+    }
+    pub fn with_ttl(mut self, ttl: Option<u64>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    pub fn is_expired(&self) -> bool {
+        match self.ttl {
+            Some(ttl) => Timestamp::now().raw() > self.created_at.raw() + ttl,
+            None => false,
         }
     }
     pub fn verify_message(&mut self, message: &ExternalMessage) -> bool {
+        if self.is_expired() {
+            return false;
+        }
+
         for value in &message.values {
             if value.0.contains(&self.value) {
                 self.set_verified();
@@ -178,9 +362,19 @@ pub enum IdentityFieldValue {
     Web(String),
     Twitter(String),
     Matrix(String),
-    PGPFingerprint(()),
+    // Older entries stored the fingerprint as `null`.
+    PGPFingerprint(#[serde(deserialize_with = "null_as_empty")] String),
     Image(()),
     Additional(()),
+    // Fields the registrar does not know (yet), as named by the Watcher.
+    Other { name: String, value: String },
+}
+
+fn null_as_empty<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(<Option<String> as serde::Deserialize>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 impl IdentityFieldValue {
@@ -198,11 +392,48 @@ impl IdentityFieldValue {
                 ExternalMessageType::Matrix(n2) => n1 == n2,
                 _ => false,
             },
+            IdentityFieldValue::Web(n1) => match &message.origin {
+                ExternalMessageType::Web(n2) => n1 == n2,
+                _ => false,
+            },
             _ => false,
         }
     }
 }
 
+/// The attempts to submit a judgement to the Watcher. Reset whenever a new
+/// judgement is scheduled.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct JudgementSubmission {
+    pub judgement: Judgement,
+    pub attempts: usize,
+    pub first_attempt: Timestamp,
+    pub last_attempt: Timestamp,
+    pub next_attempt: Timestamp,
+    // The last error reported by the Watcher for this identity.
+    pub last_error: Option<String>,
+    // Set once all attempts were used up. Failed submissions are only retried
+    // if an admin requests it.
+    pub failed: bool,
+}
+
+impl JudgementSubmission {
+    pub fn new(judgement: Judgement) -> Self {
+        let now = Timestamp::now();
+
+        JudgementSubmission {
+            judgement,
+            attempts: 0,
+            first_attempt: now,
+            last_attempt: now,
+            next_attempt: now,
+            last_error: None,
+            failed: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct JudgementStateBlanked {
@@ -211,6 +442,7 @@ pub struct JudgementStateBlanked {
     pub inserted_timestamp: Timestamp,
     pub completion_timestamp: Option<Timestamp>,
     pub judgement_submitted: bool,
+    pub submission: Option<JudgementSubmission>,
     pub fields: Vec<IdentityFieldBlanked>,
 }
 
@@ -220,6 +452,8 @@ pub struct IdentityFieldBlanked {
     pub value: IdentityFieldValue,
     pub challenge: ChallengeTypeBlanked,
     failed_attempts: usize,
+    locked_until: Option<Timestamp>,
+    flagged: bool,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -233,6 +467,9 @@ pub enum ChallengeTypeBlanked {
         passed: bool,
         violations: Vec<DisplayNameEntry>,
     },
+    SignedMessage {
+        expected: ExpectedMessage,
+    },
     Unsupported {
         // For manual judgements via the admin interface.
         is_verified: Option<bool>,
@@ -254,6 +491,7 @@ impl From<JudgementState> for JudgementStateBlanked {
             inserted_timestamp: s.inserted_timestamp,
             completion_timestamp: s.completion_timestamp,
             judgement_submitted: s.judgement_submitted,
+            submission: s.submission,
             fields: s
                 .fields
                 .into_iter()
@@ -272,18 +510,121 @@ impl From<JudgementState> for JudgementStateBlanked {
                             ChallengeType::DisplayNameCheck { passed, violations } => {
                                 ChallengeTypeBlanked::DisplayNameCheck { passed, violations }
                             }
+                            ChallengeType::SignedMessage { expected } => {
+                                ChallengeTypeBlanked::SignedMessage { expected }
+                            }
                             ChallengeType::Unsupported { is_verified } => {
                                 ChallengeTypeBlanked::Unsupported { is_verified }
                             }
                         }
                     },
                     failed_attempts: f.failed_attempts,
+                    locked_until: f.locked_until,
+                    flagged: f.flagged,
                 })
                 .collect(),
         }
     }
 }
 
+/// The transitions of an identity at which a snapshot of its state is added
+/// to its history.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateTransition {
+    Inserted,
+    FieldsChanged,
+    FullyVerified,
+    JudgementProvided,
+    // The request was cancelled and the identity removed. This is the last
+    // recorded state.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct JudgementHistoryEntry {
+    pub transition: StateTransition,
+    pub timestamp: Timestamp,
+    // The state right after the transition.
+    pub state: JudgementState,
+}
+
+impl JudgementHistoryEntry {
+    pub fn new(transition: StateTransition, state: JudgementState) -> Self {
+        JudgementHistoryEntry {
+            transition,
+            timestamp: Timestamp::now(),
+            state,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct JudgementHistoryEntryBlanked {
+    pub transition: StateTransition,
+    pub timestamp: Timestamp,
+    pub state: JudgementStateBlanked,
+}
+
+impl From<JudgementHistoryEntry> for JudgementHistoryEntryBlanked {
+    fn from(e: JudgementHistoryEntry) -> Self {
+        JudgementHistoryEntryBlanked {
+            transition: e.transition,
+            timestamp: e.timestamp,
+            state: e.state.into(),
+        }
+    }
+}
+
+/// Kept after the personal data of an identity was erased, as proof that a
+/// judgement was given.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Tombstone {
+    pub context: IdentityContext,
+    pub judgement: Judgement,
+    // When the judgement was provided, if known.
+    pub judged_at: Option<Timestamp>,
+    pub erased_at: Timestamp,
+}
+
+// Replaces the personal data of erased identities which is referenced by
+// other identities.
+pub const ERASED_PLACEHOLDER: &str = "[erased]";
+
+/// What was removed when erasing the personal data of an identity.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ErasureReport {
+    pub context: IdentityContext,
+    pub identity_removed: bool,
+    pub events_removed: usize,
+    pub display_names_removed: usize,
+    pub history_entries_removed: usize,
+    // Identities and history entries of other identities whose display name
+    // violations referred to the erased identity.
+    pub violations_anonymised: usize,
+    // Not set if no event archive is configured. Exports are never modified.
+    pub archived_events_removed: Option<usize>,
+    // Only created if a judgement was given.
+    pub tombstone: Option<Tombstone>,
+}
+
+impl ErasureReport {
+    /// Whether no personal data of the identity was found.
+    pub fn is_empty(&self) -> bool {
+        !self.identity_removed
+            && self.events_removed == 0
+            && self.display_names_removed == 0
+            && self.history_entries_removed == 0
+            && self.violations_anonymised == 0
+            && self.archived_events_removed.unwrap_or(0) == 0
+            && self.tombstone.is_none()
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct JudgementState {
@@ -293,6 +634,10 @@ pub struct JudgementState {
     pub completion_timestamp: Option<Timestamp>,
     pub judgement_submitted: bool,
     pub issue_judgement_at: Option<Timestamp>,
+    // The judgement which gets submitted once `issue_judgement_at` is reached.
+    pub judgement: Option<Judgement>,
+    // Tracks the submission of the judgement until the Watcher confirms it.
+    pub submission: Option<JudgementSubmission>,
     pub fields: Vec<IdentityField>,
 }
 
@@ -305,9 +650,18 @@ impl JudgementState {
             completion_timestamp: None,
             judgement_submitted: false,
             issue_judgement_at: None,
+            judgement: None,
+            submission: None,
             fields: fields.into_iter().map(IdentityField::new).collect(),
         }
     }
+    pub fn with_challenge_ttl(mut self, ttl: Option<u64>) -> Self {
+        for field in &mut self.fields {
+            field.challenge.set_ttl(ttl);
+        }
+
+        self
+    }
     pub fn check_full_verification(&self) -> bool {
         self.fields
             .iter()
@@ -322,6 +676,26 @@ impl JudgementState {
                 _ => panic!("Failed to get display name. This is a bug."),
             })
     }
+    /// Replaces the address and display name of the erased identity in the
+    /// display name violations, the violations themselves are kept. Returns
+    /// whether any violation was anonymised.
+    pub fn anonymise_violations(&mut self, erased: &IdentityContext) -> bool {
+        let mut anonymised = false;
+        for field in &mut self.fields {
+            if let ChallengeType::DisplayNameCheck { violations, .. } = &mut field.challenge {
+                for entry in violations
+                    .iter_mut()
+                    .filter(|entry| &entry.context == erased)
+                {
+                    entry.context.address = ChainAddress::from(ERASED_PLACEHOLDER.to_string());
+                    entry.display_name = ERASED_PLACEHOLDER.to_string();
+                    anonymised = true;
+                }
+            }
+        }
+
+        anonymised
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Message)]
@@ -340,6 +714,7 @@ pub enum ExternalMessageType {
     Email(String),
     Twitter(String),
     Matrix(String),
+    Web(String),
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -358,7 +733,7 @@ impl From<u32> for MessageId {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Timestamp(u64);
 
@@ -465,6 +840,35 @@ pub enum NotificationMessage {
     FullManualVerification {
         context: IdentityContext,
     },
+    JudgementPending {
+        context: IdentityContext,
+        judgement: Judgement,
+    },
+    ChallengeRegenerated {
+        context: IdentityContext,
+        field: IdentityFieldValue,
+    },
+    FieldLocked {
+        context: IdentityContext,
+        field: IdentityFieldValue,
+        locked_until: Timestamp,
+    },
+    FieldChallengeReset {
+        context: IdentityContext,
+        field: IdentityFieldValue,
+    },
+    FieldFlagged {
+        context: IdentityContext,
+        field: IdentityFieldValue,
+    },
+    JudgementSubmissionFailed {
+        context: IdentityContext,
+        judgement: Judgement,
+    },
+    JudgementRequestCancelled {
+        context: IdentityContext,
+        reason: CancellationReason,
+    },
 }
 
 impl NotificationMessage {
@@ -483,20 +887,31 @@ impl NotificationMessage {
             JudgementProvided { context } => context,
             ManuallyVerified { context, field: _ } => context,
             FullManualVerification { context } => context,
+            JudgementPending {
+                context,
+                judgement: _,
+            } => context,
+            ChallengeRegenerated { context, field: _ } => context,
+            FieldLocked {
+                context,
+                field: _,
+                locked_until: _,
+            } => context,
+            FieldChallengeReset { context, field: _ } => context,
+            FieldFlagged { context, field: _ } => context,
+            JudgementSubmissionFailed {
+                context,
+                judgement: _,
+            } => context,
+            JudgementRequestCancelled { context, reason: _ } => context,
         }
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub struct IdentityJudged {
-    context: IdentityContext,
-    timestamp: Timestamp,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{NetworkConfig, Networks};
 
     impl IdentityContext {
         pub fn alice() -> Self {
@@ -504,7 +919,7 @@ mod tests {
                 address: ChainAddress(
                     "1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZP".to_string(),
                 ),
-                chain: ChainName::Polkadot,
+                chain: ChainName::polkadot(),
             }
         }
         pub fn bob() -> Self {
@@ -512,7 +927,7 @@ mod tests {
                 address: ChainAddress(
                     "1b3NhsSEqWSQwS6nPGKgCrSjv9Kp13CnhraLV5Coyd8ooXB".to_string(),
                 ),
-                chain: ChainName::Polkadot,
+                chain: ChainName::polkadot(),
             }
         }
     }
@@ -526,6 +941,8 @@ mod tests {
                 completion_timestamp: None,
                 judgement_submitted: false,
                 issue_judgement_at: None,
+                judgement: None,
+                submission: None,
                 fields: vec![
                     IdentityField::new(IdentityFieldValue::ALICE_DISPLAY_NAME()),
                     IdentityField::new(IdentityFieldValue::ALICE_EMAIL()),
@@ -551,6 +968,7 @@ mod tests {
                 ExternalMessageType::Email(n) => IdentityFieldValue::Email(n),
                 ExternalMessageType::Twitter(n) => IdentityFieldValue::Twitter(n),
                 ExternalMessageType::Matrix(n) => IdentityFieldValue::Matrix(n),
+                ExternalMessageType::Web(n) => IdentityFieldValue::Web(n),
             }
         }
     }
@@ -580,6 +998,35 @@ mod tests {
         }
     }
 
+    impl ChainName {
+        pub fn polkadot() -> Self {
+            ChainName("polkadot".to_string())
+        }
+        pub fn kusama() -> Self {
+            ChainName("kusama".to_string())
+        }
+    }
+
+    impl Default for Networks {
+        fn default() -> Self {
+            let network = |name: ChainName, ss58_prefix| NetworkConfig {
+                name,
+                ss58_prefix,
+                endpoints: vec![],
+                judgement: None,
+                challenge: None,
+                lockout: None,
+                watcher_auth: None,
+                watcher_tls: None,
+            };
+
+            Networks(vec![
+                network(ChainName::polkadot(), 0),
+                network(ChainName::kusama(), 2),
+            ])
+        }
+    }
+
     impl IdentityField {
         pub fn expected_message(&self) -> &ExpectedMessage {
             match &self.challenge {
@@ -629,6 +1076,18 @@ mod tests {
                 _ => panic!(),
             }
         }
+        pub fn expected_signed_message(&self) -> &ExpectedMessage {
+            match &self.challenge {
+                ChallengeType::SignedMessage { expected } => expected,
+                _ => panic!(),
+            }
+        }
+        pub fn expected_signed_message_mut(&mut self) -> &mut ExpectedMessage {
+            match &mut self.challenge {
+                ChallengeType::SignedMessage { expected } => expected,
+                _ => panic!(),
+            }
+        }
         pub fn expected_unsupported_mut(&mut self) -> &mut Option<bool> {
             match &mut self.challenge {
                 ChallengeType::Unsupported { is_verified } => is_verified,
@@ -636,4 +1095,51 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn decode_ss58_addresses() {
+        let alice = IdentityContext::alice().address;
+        let (prefix, public) = alice.decode().unwrap();
+        assert_eq!(prefix, 0);
+
+        // Kusama encoding of the same key.
+        let kusama = ChainAddress::from("D9M4hMBfbDw1RheWttBqp8xYYB6NnAYbNTmgjTvELxnqWbv");
+        assert_eq!(kusama.decode().unwrap(), (2, public));
+
+        // Generic Substrate encoding of the same key.
+        let generic = ChainAddress::from("5CdjQP1K3ED1FmtCkC58wrmxPwtra7MN8zd2J5BxkYkJ6NNR");
+        assert_eq!(generic.decode().unwrap(), (42, public));
+
+        // Two-byte prefix.
+        let two_byte = ChainAddress::from("Vdr6Z27SH1jCaPMdCbn4VLSJAs88xowf7yYUx1FwuFS86ina9");
+        assert_eq!(two_byte.decode().unwrap(), (1284, public));
+        assert_eq!(ChainAddress::encode(1284, &public), two_byte);
+
+        // Invalid checksum.
+        let invalid = ChainAddress::from("1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZQ");
+        assert!(invalid.decode().is_err());
+
+        // Invalid characters or length.
+        assert!(ChainAddress::from("Alice").decode().is_err());
+        assert!(ChainAddress::from("0OIl").decode().is_err());
+        assert!(ChainAddress::from("").decode().is_err());
+    }
+
+    #[test]
+    fn normalize_ss58_addresses() {
+        let alice = IdentityContext::alice().address;
+        let kusama = ChainAddress::from("D9M4hMBfbDw1RheWttBqp8xYYB6NnAYbNTmgjTvELxnqWbv");
+        let generic = ChainAddress::from("5CdjQP1K3ED1FmtCkC58wrmxPwtra7MN8zd2J5BxkYkJ6NNR");
+
+        assert_eq!(alice.normalize(0).unwrap(), alice);
+        assert_eq!(alice.normalize(2).unwrap(), kusama);
+        assert_eq!(generic.normalize(0).unwrap(), alice);
+        assert_eq!(generic.normalize(2).unwrap(), kusama);
+
+        assert_eq!(
+            ChainAddress::from_str(" 1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZP ").unwrap(),
+            alice
+        );
+        assert!(ChainAddress::from_str("Alice").is_err());
+    }
 }
@@ -3,8 +3,8 @@ use crate::api::VerifyChallenge;
 use crate::api::{JsonResult, ResponseAccountState};
 use crate::connector::WatcherMessage;
 use crate::primitives::{
-    ExpectedMessage, ExternalMessage, ExternalMessageType, IdentityContext, MessageId,
-    NotificationMessage, Timestamp,
+    ChainAddress, ChainName, ExpectedMessage, ExternalMessage, ExternalMessageType,
+    IdentityContext, MessageId, NotificationMessage, Timestamp,
 };
 use actix_http::StatusCode;
 use futures::{FutureExt, StreamExt};
@@ -32,6 +32,37 @@ async fn current_judgement_state_single_identity() {
     assert!(stream.next().now_or_never().is_none());
 }
 
+#[actix::test]
+async fn current_judgement_state_invalid_address() {
+    let (_db, _connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    // Invalid checksum.
+    let context = IdentityContext::new(
+        ChainAddress::from("1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZQ"),
+        ChainName::polkadot(),
+    );
+
+    stream.send(context.to_ws()).await.unwrap();
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Err("Invalid address".to_string()));
+}
+
+#[actix::test]
+async fn current_judgement_state_unknown_network() {
+    let (_db, _connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let context = IdentityContext::new(
+        IdentityContext::alice().address,
+        ChainName::from("westend".to_string()),
+    );
+
+    stream.send(context.to_ws()).await.unwrap();
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Err("Unknown network".to_string()));
+}
+
 #[actix::test]
 async fn current_judgement_state_multiple_inserts() {
     let (_db, connector, mut api, _) = new_env().await;
@@ -651,6 +682,9 @@ async fn verify_invalid_message_awaiting_second_challenge() {
 
     assert_eq!(res.status(), StatusCode::OK);
 
+    // Failed second challenges are counted as well.
+    *alice.get_field_mut(&F::ALICE_EMAIL()).failed_attempts_mut() = 1;
+
     // Check for `SecondFieldVerified` notification.
     let expected = ResponseAccountState {
         state: alice.clone().into(),
@@ -735,7 +769,9 @@ async fn verify_full_identity() {
         .verify_message(&msg);
     assert!(changed);
 
-    db.verify_message(&msg).await.unwrap();
+    db.verify_message(&msg, &LockoutPolicy::default())
+        .await
+        .unwrap();
 
     // Check updated state with notification.
     let exp_resp = ResponseAccountState {
@@ -766,7 +802,9 @@ async fn verify_full_identity() {
         .verify_message(&msg);
     assert!(changed);
 
-    db.verify_message(&msg).await.unwrap();
+    db.verify_message(&msg, &LockoutPolicy::default())
+        .await
+        .unwrap();
 
     // Check updated state with notification.
     let exp_resp = ResponseAccountState {
@@ -797,14 +835,17 @@ async fn verify_full_identity() {
         .expected_second_mut()
         .set_verified();
 
-    db.verify_second_challenge(VerifyChallenge {
-        entry: F::ALICE_EMAIL(),
-        challenge: alice
-            .get_field(&F::ALICE_EMAIL())
-            .expected_second()
-            .value
-            .to_string(),
-    })
+    db.verify_second_challenge(
+        VerifyChallenge {
+            entry: F::ALICE_EMAIL(),
+            challenge: alice
+                .get_field(&F::ALICE_EMAIL())
+                .expected_second()
+                .value
+                .to_string(),
+        },
+        &LockoutPolicy::default(),
+    )
     .await
     .unwrap();
 
@@ -837,7 +878,9 @@ async fn verify_full_identity() {
         .verify_message(&msg);
     assert!(changed);
 
-    db.verify_message(&msg).await.unwrap();
+    db.verify_message(&msg, &LockoutPolicy::default())
+        .await
+        .unwrap();
 
     // Check updated state with notification.
     // Identity is fully verified now.
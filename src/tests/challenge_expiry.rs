@@ -0,0 +1,179 @@
+use super::*;
+use crate::api::{JsonResult, ResponseAccountState};
+use crate::database::ExportFilter;
+use crate::primitives::{
+    ChainName, ExternalMessage, ExternalMessageType, IdentityContext, JudgementState, MessageId,
+    NotificationMessage, Timestamp,
+};
+use futures::{FutureExt, StreamExt};
+
+#[actix::test]
+async fn expired_challenge_is_rejected() {
+    let (db, _, mut api, injector) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    // Insert judgement state with challenges that expire immediately.
+    let alice = JudgementState::alice().with_challenge_ttl(Some(0));
+    db.add_judgement_request(&alice).await.unwrap();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    sleep(Duration::from_secs(2)).await;
+
+    // Send valid message, but the challenge has expired.
+    injector
+        .send(ExternalMessage {
+            origin: ExternalMessageType::Email("alice@email.com".to_string()),
+            id: MessageId::from(0u32),
+            timestamp: Timestamp::now(),
+            values: alice
+                .get_field(&F::ALICE_EMAIL())
+                .expected_message()
+                .to_message_parts(),
+        })
+        .await;
+
+    // No state changes, the attempt is not counted either.
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(!field.expected_message().is_verified);
+    assert_eq!(field.failed_attempts, 0);
+}
+
+#[actix::test]
+async fn expired_challenges_are_regenerated() {
+    let (db, _, mut api, injector) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    // Insert judgement state with challenges that expire immediately.
+    let alice = JudgementState::alice().with_challenge_ttl(Some(0));
+    db.add_judgement_request(&alice).await.unwrap();
+
+    // Subscribe to endpoint.
+    let _ = subscribe_context(&mut stream, IdentityContext::alice()).await;
+
+    sleep(Duration::from_secs(2)).await;
+    db.regenerate_expired_challenges(&ChainName::polkadot(), 300)
+        .await
+        .unwrap();
+
+    // Each field with a challenge gets a notification (in any order).
+    let mut notifications = vec![];
+    for _ in 0..3 {
+        let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+        match resp {
+            JsonResult::Ok(resp) => notifications.extend(resp.notifications),
+            _ => panic!(),
+        }
+    }
+
+    for field in [F::ALICE_EMAIL(), F::ALICE_TWITTER(), F::ALICE_MATRIX()] {
+        assert!(
+            notifications.contains(&NotificationMessage::ChallengeRegenerated {
+                context: alice.context.clone(),
+                field,
+            })
+        );
+    }
+
+    let mut state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    for field in [F::ALICE_EMAIL(), F::ALICE_TWITTER(), F::ALICE_MATRIX()] {
+        let old = alice.get_field(&field).expected_message();
+        let new = state.get_field(&field).expected_message();
+
+        assert_ne!(old.value, new.value);
+        assert_eq!(new.ttl, Some(300));
+        assert!(!new.is_expired());
+    }
+
+    // The fresh challenge is valid.
+    injector
+        .send(ExternalMessage {
+            origin: ExternalMessageType::Email("alice@email.com".to_string()),
+            id: MessageId::from(0u32),
+            timestamp: Timestamp::now(),
+            values: state
+                .get_field(&F::ALICE_EMAIL())
+                .expected_message()
+                .to_message_parts(),
+        })
+        .await;
+
+    state
+        .get_field_mut(&F::ALICE_EMAIL())
+        .expected_message_mut()
+        .set_verified();
+
+    let expected = ResponseAccountState {
+        state: state.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerified {
+            context: state.context.clone(),
+            field: F::ALICE_EMAIL(),
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+}
+
+#[actix::test]
+async fn challenges_without_ttl_are_kept() {
+    let (db, _, _api, _) = new_env().await;
+
+    // Insert judgement state which was created before challenges could expire.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    db.regenerate_expired_challenges(&ChainName::polkadot(), 300)
+        .await
+        .unwrap();
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    for field in [F::ALICE_EMAIL(), F::ALICE_TWITTER(), F::ALICE_MATRIX()] {
+        let old = alice.get_field(&field).expected_message();
+        let new = state.get_field(&field).expected_message();
+
+        assert_eq!(old.value, new.value);
+        assert_eq!(new.ttl, Some(300));
+        assert!(!new.is_expired());
+    }
+
+    // No challenge was replaced.
+    let events = db.export_events(&ExportFilter::default()).await.unwrap();
+    assert!(!events.iter().any(|event| matches!(
+        event.message,
+        NotificationMessage::ChallengeRegenerated { .. }
+    )));
+
+    // Valid challenges are left untouched.
+    db.regenerate_expired_challenges(&ChainName::polkadot(), 300)
+        .await
+        .unwrap();
+
+    let current = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state, current);
+}
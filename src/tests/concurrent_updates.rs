@@ -0,0 +1,247 @@
+use super::*;
+use crate::adapters::admin::RawFieldName;
+use crate::api::VerifyChallenge;
+use crate::connector::Judgement;
+use crate::database::EventSubscription;
+use crate::primitives::{
+    ExternalMessage, ExternalMessageType, IdentityContext, IdentityField, JudgementState,
+    MessageId, NotificationMessage, Timestamp,
+};
+use futures::future::join_all;
+use tokio::time::timeout;
+
+/// Creates a message which contains the expected challenge of the field.
+fn valid_message(state: &JudgementState, field: &F) -> ExternalMessage {
+    let origin = match field.clone() {
+        F::Email(n) => ExternalMessageType::Email(n),
+        F::Twitter(n) => ExternalMessageType::Twitter(n),
+        F::Matrix(n) => ExternalMessageType::Matrix(n),
+        _ => panic!(),
+    };
+
+    ExternalMessage {
+        origin,
+        id: MessageId::from(0u32),
+        timestamp: Timestamp::now(),
+        values: state.get_field(field).expected_message().to_message_parts(),
+    }
+}
+
+/// Receives the given number of events and checks that no other events
+/// follow.
+async fn receive_events(
+    events: &mut Box<dyn EventSubscription>,
+    count: usize,
+) -> Vec<NotificationMessage> {
+    let mut received = vec![];
+    for _ in 0..count {
+        let event = timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+
+        received.push(event);
+    }
+
+    assert!(timeout(Duration::from_secs(2), events.next())
+        .await
+        .is_err());
+
+    received
+}
+
+fn occurrences(events: &[NotificationMessage], event: &NotificationMessage) -> usize {
+    events.iter().filter(|e| *e == event).count()
+}
+
+#[actix::test]
+async fn concurrent_messages_verify_identity_once() {
+    let (db, _, _api, _) = new_env().await;
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+    db.verify_manually(&alice.context, &RawFieldName::DisplayName, false)
+        .await
+        .unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+    let lockout = LockoutPolicy::default();
+
+    // Verify all remaining challenges at the same time.
+    let email = valid_message(&alice, &F::ALICE_EMAIL());
+    let twitter = valid_message(&alice, &F::ALICE_TWITTER());
+    let matrix = valid_message(&alice, &F::ALICE_MATRIX());
+    let second = VerifyChallenge {
+        entry: F::ALICE_EMAIL(),
+        challenge: alice
+            .get_field(&F::ALICE_EMAIL())
+            .expected_second()
+            .value
+            .clone(),
+    };
+
+    let (r1, r2, r3, r4) = futures::join!(
+        db.verify_message(&email, &lockout),
+        db.verify_message(&twitter, &lockout),
+        db.verify_message(&matrix, &lockout),
+        db.verify_second_challenge(second, &lockout),
+    );
+    r1.unwrap();
+    r2.unwrap();
+    r3.unwrap();
+    assert!(r4.unwrap());
+
+    // No verification got lost.
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(state.is_fully_verified);
+    assert!(state.check_full_verification());
+    assert_eq!(state.judgement, Some(Judgement::Reasonable));
+
+    // Each change created its event, the identity was verified exactly once.
+    let received = receive_events(&mut events, 6).await;
+    for field in [F::ALICE_EMAIL(), F::ALICE_TWITTER(), F::ALICE_MATRIX()] {
+        assert_eq!(
+            occurrences(
+                &received,
+                &NotificationMessage::FieldVerified {
+                    context: alice.context.clone(),
+                    field,
+                }
+            ),
+            1
+        );
+    }
+    assert_eq!(
+        occurrences(
+            &received,
+            &NotificationMessage::SecondFieldVerified {
+                context: alice.context.clone(),
+                field: F::ALICE_EMAIL(),
+            }
+        ),
+        1
+    );
+    assert_eq!(
+        occurrences(
+            &received,
+            &NotificationMessage::IdentityFullyVerified {
+                context: IdentityContext::alice(),
+            }
+        ),
+        1
+    );
+}
+
+#[actix::test]
+async fn concurrent_duplicate_messages_verify_field_once() {
+    let (db, _, _api, _) = new_env().await;
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+    let lockout = LockoutPolicy::default();
+
+    // The same message is received several times at once.
+    let email = valid_message(&alice, &F::ALICE_EMAIL());
+    for res in join_all((0..10).map(|_| db.verify_message(&email, &lockout))).await {
+        res.unwrap();
+    }
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(field.expected_message().is_verified);
+    assert_eq!(field.failed_attempts, 0);
+
+    let received = receive_events(&mut events, 2).await;
+    assert_eq!(
+        received,
+        vec![
+            NotificationMessage::FieldVerified {
+                context: alice.context.clone(),
+                field: F::ALICE_EMAIL(),
+            },
+            NotificationMessage::AwaitingSecondChallenge {
+                context: alice.context.clone(),
+                field: F::ALICE_EMAIL(),
+            },
+        ]
+    );
+}
+
+#[actix::test]
+async fn concurrent_update_and_verification() {
+    let (db, _, _api, _) = new_env().await;
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+    let lockout = LockoutPolicy::default();
+
+    // The Twitter account gets changed while other fields are verified.
+    let new_twitter = F::Twitter("@alice_new".to_string());
+    let mut updated = alice.clone();
+    *updated.get_field_mut(&F::ALICE_TWITTER()) = IdentityField::new(new_twitter.clone());
+
+    let email = valid_message(&alice, &F::ALICE_EMAIL());
+    let matrix = valid_message(&alice, &F::ALICE_MATRIX());
+
+    let (r1, r2, r3) = futures::join!(
+        db.add_judgement_request(&updated),
+        db.verify_message(&email, &lockout),
+        db.verify_message(&matrix, &lockout),
+    );
+    assert!(r1.unwrap());
+    r2.unwrap();
+    r3.unwrap();
+
+    // Neither the update nor the verifications got lost.
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(state.fields.len(), 4);
+    assert!(state.fields.iter().all(|f| f.value != F::ALICE_TWITTER()));
+    assert!(!state.get_field(&new_twitter).challenge.is_verified());
+    assert!(
+        state
+            .get_field(&F::ALICE_EMAIL())
+            .expected_message()
+            .is_verified
+    );
+    assert!(state.get_field(&F::ALICE_MATRIX()).challenge.is_verified());
+
+    let received = receive_events(&mut events, 4).await;
+    assert_eq!(
+        occurrences(
+            &received,
+            &NotificationMessage::IdentityUpdated {
+                context: alice.context.clone(),
+            }
+        ),
+        1
+    );
+    assert_eq!(
+        occurrences(
+            &received,
+            &NotificationMessage::FieldVerified {
+                context: alice.context.clone(),
+                field: F::ALICE_MATRIX(),
+            }
+        ),
+        1
+    );
+}
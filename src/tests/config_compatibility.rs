@@ -0,0 +1,125 @@
+use crate::primitives::ChainName;
+use crate::{Config, InstanceType};
+
+// The config format of releases before `networks` was introduced.
+const LEGACY_ADAPTER_LISTENER: &str = r#"
+db:
+  uri: mongodb://localhost:27017/
+  name: registrar_db
+instance:
+  role: adapter_listener
+  config:
+    watcher:
+      - network: kusama
+        endpoint: ws://localhost:8000
+    matrix:
+      enabled: false
+      homeserver: homeserver
+      username: username
+      password: password
+      db_path: db_path
+      admins: null
+    twitter:
+      enabled: false
+      api_key: key
+      api_secret: secret
+      token: token
+      token_secret: secret
+      request_interval: 300
+    email:
+      enabled: false
+      smtp_server: server
+      imap_server: server
+      inbox: inbox
+      user: user
+      password: password
+      request_interval: 5
+    display_name:
+      enabled: true
+      limit: 0.85
+"#;
+
+const LEGACY_SESSION_NOTIFIER: &str = r#"
+db:
+  uri: mongodb://localhost:27017/
+  name: registrar_db
+instance:
+  role: session_notifier
+  config:
+    api_address: 0.0.0.0:8000
+    display_name:
+      enabled: true
+      limit: 0.85
+"#;
+
+fn parse(content: &str) -> crate::Result<Config> {
+    let mut config: Config = serde_yaml::from_str(content)?;
+    config.apply_legacy_watchers()?;
+    Ok(config)
+}
+
+#[test]
+fn legacy_adapter_listener_config() {
+    let config = parse(LEGACY_ADAPTER_LISTENER).unwrap();
+
+    // Only the network with a Watcher is kept.
+    let networks: Vec<_> = config.networks.iter().collect();
+    assert_eq!(networks.len(), 1);
+    assert_eq!(networks[0].name, ChainName::kusama());
+    assert_eq!(networks[0].ss58_prefix, 2);
+    assert_eq!(networks[0].endpoints, vec!["ws://localhost:8000"]);
+
+    // The sections which were added since then are optional.
+    let adapter = match config.instance {
+        InstanceType::AdapterListener(adapter) => adapter,
+        _ => panic!("expected an adapter listener config"),
+    };
+    assert!(adapter.watcher.is_empty());
+    assert!(!adapter.web.enabled);
+    assert!(adapter.judgement.erroneous_failed_attempts.is_none());
+    assert!(adapter.challenge.ttl.is_none());
+    assert!(adapter.lockout.max_failed_attempts.is_none());
+    assert!(adapter.retention.max_age.is_empty());
+    assert!(adapter.retention.archive.is_none());
+}
+
+#[test]
+fn legacy_session_notifier_config() {
+    let config = parse(LEGACY_SESSION_NOTIFIER).unwrap();
+
+    let networks: Vec<_> = config
+        .networks
+        .iter()
+        .map(|network| (network.name.clone(), network.ss58_prefix))
+        .collect();
+    assert_eq!(
+        networks,
+        vec![(ChainName::polkadot(), 0), (ChainName::kusama(), 2)]
+    );
+
+    let notifier = match config.instance {
+        InstanceType::SessionNotifier(notifier) => notifier,
+        _ => panic!("expected a session notifier config"),
+    };
+    assert!(!notifier.pgp.enabled);
+    assert_eq!(notifier.subscriber, "session_notifier");
+}
+
+#[test]
+fn legacy_watcher_and_networks_are_exclusive() {
+    let config = format!(
+        "networks:\n  - name: kusama\n    ss58_prefix: 2\n    endpoint: ws://localhost:8001\n{}",
+        LEGACY_ADAPTER_LISTENER
+    );
+    assert!(parse(&config).is_err());
+
+    // Unknown networks must be specified in `networks`.
+    let config = LEGACY_ADAPTER_LISTENER.replace("network: kusama", "network: westend");
+    assert!(parse(&config).is_err());
+}
+
+#[test]
+fn sample_configs() {
+    parse(include_str!("../../config/sample.adapter_listener.yaml")).unwrap();
+    parse(include_str!("../../config/sample.session_notifier.yaml")).unwrap();
+}
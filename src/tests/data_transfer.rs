@@ -0,0 +1,230 @@
+use super::*;
+use crate::connector::DisplayNameEntry;
+use crate::database::export::{export, import, ExportHeader, ExportRecord, TransferSummary};
+use crate::database::ExportFilter;
+use crate::primitives::{ChainName, JudgementState, NotificationMessage, Timestamp};
+use crate::Networks;
+use std::collections::HashMap;
+
+fn export_path() -> String {
+    let random: u32 = thread_rng().gen_range(u32::MIN..u32::MAX);
+    std::env::temp_dir()
+        .join(format!("registrar_export_{}.jsonl", random))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Inserts Alice and Bob, a display name and some events.
+async fn populate(db: &Database) {
+    db.add_judgement_request(&JudgementState::alice())
+        .await
+        .unwrap();
+    db.add_judgement_request(&bob()).await.unwrap();
+
+    db.insert_display_name(&DisplayNameEntry {
+        context: IdentityContext::alice(),
+        display_name: "Alice".to_string(),
+    })
+    .await
+    .unwrap();
+
+    db.full_manual_verification(&IdentityContext::alice())
+        .await
+        .unwrap();
+    db.set_judged(&IdentityContext::bob()).await.unwrap();
+}
+
+fn read_export(path: &str) -> Vec<ExportRecord> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[actix::test]
+async fn export_and_import_all_data() {
+    let (db, _, _api, _) = new_env().await;
+    populate(&db).await;
+
+    let path = export_path();
+    let exported = export(&db, &path, &ExportFilter::default()).await.unwrap();
+
+    assert_eq!(
+        exported,
+        TransferSummary {
+            identities: 2,
+            display_names: 1,
+            events: 2,
+        }
+    );
+
+    // Import into an empty database.
+    let (other, _, _api, _) = new_env().await;
+    let imported = import(&other, &path, &Networks::default()).await.unwrap();
+    assert_eq!(imported, exported);
+
+    for context in [IdentityContext::alice(), IdentityContext::bob()] {
+        assert_eq!(
+            other.fetch_judgement_state(&context).await.unwrap(),
+            db.fetch_judgement_state(&context).await.unwrap(),
+        );
+    }
+
+    // Importing again does not duplicate anything.
+    import(&other, &path, &Networks::default()).await.unwrap();
+
+    let path_other = export_path();
+    export(&other, &path_other, &ExportFilter::default())
+        .await
+        .unwrap();
+
+    // Apart from the creation time of the header, both exports are equal.
+    let records = read_export(&path);
+    let records_other = read_export(&path_other);
+    assert_eq!(records[1..], records_other[1..]);
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&path_other).unwrap();
+}
+
+#[actix::test]
+async fn export_filtered_by_address() {
+    let (db, _, _api, _) = new_env().await;
+    populate(&db).await;
+
+    let path = export_path();
+    let filter = ExportFilter {
+        address: Some(IdentityContext::bob().address),
+        ..Default::default()
+    };
+
+    let exported = export(&db, &path, &filter).await.unwrap();
+    assert_eq!(
+        exported,
+        TransferSummary {
+            identities: 1,
+            display_names: 0,
+            events: 1,
+        }
+    );
+
+    let records = read_export(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(records[0], ExportRecord::Header(_)));
+    assert_eq!(
+        records[1],
+        ExportRecord::Identity(
+            db.fetch_judgement_state(&IdentityContext::bob())
+                .await
+                .unwrap()
+                .unwrap()
+        )
+    );
+    match &records[2] {
+        ExportRecord::Event(event) => assert_eq!(
+            event.message,
+            NotificationMessage::JudgementProvided {
+                context: IdentityContext::bob(),
+            }
+        ),
+        _ => panic!(),
+    }
+
+    // Nothing was inserted in the future.
+    let filter = ExportFilter {
+        from: Some(Timestamp::with_offset(3600).raw()),
+        ..Default::default()
+    };
+
+    let exported = export(&db, &path, &filter).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(exported.identities, 0);
+    assert_eq!(exported.events, 0);
+}
+
+#[actix::test]
+async fn import_upgrades_older_records() {
+    let (db, _, _api, _) = new_env().await;
+
+    let mut schema_versions = HashMap::new();
+    schema_versions.insert("identities".to_string(), 0);
+    schema_versions.insert("display_names".to_string(), 0);
+    schema_versions.insert("event_log".to_string(), 0);
+
+    let header = ExportRecord::Header(ExportHeader {
+        format_version: 1,
+        created: Timestamp::now(),
+        schema_versions,
+    });
+
+    let identity: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/identity_v0.json")).unwrap();
+
+    let path = export_path();
+    std::fs::write(
+        &path,
+        format!(
+            "{}\n{}\n",
+            serde_json::to_string(&header).unwrap(),
+            serde_json::json!({ "type": "identity", "value": identity }),
+        ),
+    )
+    .unwrap();
+
+    let imported = import(&db, &path, &Networks::default()).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(imported.identities, 1);
+
+    let state = db
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(state.inserted_timestamp.raw(), 1637000000);
+    assert_eq!(state.judgement, None);
+}
+
+#[actix::test]
+async fn import_rejects_invalid_records() {
+    let (db, _, _api, _) = new_env().await;
+    populate(&db).await;
+
+    let path = export_path();
+    export(&db, &path, &ExportFilter::default()).await.unwrap();
+
+    // Add an identity of a network which is not configured.
+    let mut unknown = bob();
+    unknown.context.chain = ChainName::from("westend".to_string());
+    let mut content = std::fs::read_to_string(&path).unwrap();
+    content.push_str(&serde_json::to_string(&ExportRecord::Identity(unknown)).unwrap());
+    content.push('\n');
+    std::fs::write(&path, content).unwrap();
+
+    let (other, _, _api, _) = new_env().await;
+    let err = import(&other, &path, &Networks::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("line 7"));
+
+    // Nothing was imported.
+    assert!(other
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .is_none());
+
+    // Missing header.
+    std::fs::write(
+        &path,
+        serde_json::to_string(&ExportRecord::Identity(bob())).unwrap(),
+    )
+    .unwrap();
+    assert!(import(&other, &path, &Networks::default()).await.is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,8 +1,8 @@
 use super::*;
 use crate::api::{JsonResult, ResponseAccountState};
-use crate::connector::DisplayNameEntry;
+use crate::connector::{DisplayNameEntry, DisplayNameEntryRaw};
 use crate::display_name::DisplayNameVerifier;
-use crate::primitives::{IdentityContext, IdentityFieldValue};
+use crate::primitives::{ChainAddress, ChainName, IdentityContext, IdentityFieldValue};
 use crate::DisplayNameConfig;
 use futures::StreamExt;
 
@@ -35,6 +35,10 @@ async fn valid_display_name() {
     let mut alice = states[0].clone();
     verifier.verify_display_name(&alice).await.unwrap();
 
+    // Let the session notifier process the resulting events first, those are
+    // not sent to sessions which subscribe afterwards.
+    sleep(Duration::from_secs(1)).await;
+
     // Subscribe to endpoint.
     let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
 
@@ -81,6 +85,10 @@ async fn invalid_display_name() {
     let mut alice = states[0].clone();
     verifier.verify_display_name(&alice).await.unwrap();
 
+    // Let the session notifier process the resulting events first, those are
+    // not sent to sessions which subscribe afterwards.
+    sleep(Duration::from_secs(1)).await;
+
     // Subscribe to endpoint.
     let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
 
@@ -103,3 +111,128 @@ async fn invalid_display_name() {
     // Empty stream.
     assert!(stream.next().now_or_never().is_none());
 }
+
+/// A valid Polkadot address, distinct for each seed.
+fn address(seed: u8) -> String {
+    ChainAddress::encode(0, &[seed; 32]).as_str().to_string()
+}
+
+/// The response of the Watcher with all active display names.
+fn active_display_names(names: &[(String, &str)]) -> WatcherMessage {
+    WatcherMessage::ActiveDisplayNames(
+        names
+            .iter()
+            .map(|(address, name)| DisplayNameEntryRaw {
+                address: ChainAddress::from(address.clone()),
+                display_name: name.to_string(),
+            })
+            .collect(),
+    )
+}
+
+async fn stored_display_names(db: &Database) -> Vec<String> {
+    let mut names: Vec<String> = db
+        .fetch_display_names(&ChainName::polkadot())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.display_name)
+        .collect();
+
+    names.sort();
+    names
+}
+
+#[actix::test]
+async fn stale_display_names_are_removed() {
+    let (db, connector, _api, _) = new_env().await;
+
+    let alice = IdentityContext::alice().address.as_str().to_string();
+    let bob = IdentityContext::bob().address.as_str().to_string();
+
+    connector
+        .inject(active_display_names(&[
+            (alice.clone(), "Alice"),
+            (bob.clone(), "Bob"),
+        ]))
+        .await;
+    assert_eq!(stored_display_names(&db).await, vec!["Alice", "Bob"]);
+
+    // Bob cleared the display name.
+    connector
+        .inject(active_display_names(&[(alice.clone(), "Alice")]))
+        .await;
+    assert_eq!(stored_display_names(&db).await, vec!["Alice"]);
+
+    // Alice changed the display name.
+    connector
+        .inject(active_display_names(&[(alice, "Alice Wonderland")]))
+        .await;
+    assert_eq!(stored_display_names(&db).await, vec!["Alice Wonderland"]);
+}
+
+#[actix::test]
+async fn incomplete_display_names_response_is_ignored() {
+    let (db, connector, _api, _) = new_env().await;
+
+    let names: Vec<(String, String)> = (0..100)
+        .map(|i| (address(i), format!("Name {:03}", i)))
+        .collect();
+    let active = |count: usize| {
+        let names: Vec<(String, &str)> = names[..count]
+            .iter()
+            .map(|(address, name)| (address.clone(), name.as_str()))
+            .collect();
+        active_display_names(&names)
+    };
+
+    connector.inject(active(100)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 100);
+
+    // Empty and partial responses do not remove anything.
+    connector.inject(active(0)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 100);
+
+    connector.inject(active(50)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 100);
+
+    // Some names were cleared.
+    connector.inject(active(90)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 90);
+}
+
+#[actix::test]
+async fn consistently_stale_display_names_are_removed() {
+    let (db, connector, _api, _) = new_env().await;
+
+    let names: Vec<(String, String)> = (0..100)
+        .map(|i| (address(i), format!("Name {:03}", i)))
+        .collect();
+    let active = |count: usize| {
+        let names: Vec<(String, &str)> = names[..count]
+            .iter()
+            .map(|(address, name)| (address.clone(), name.as_str()))
+            .collect();
+        active_display_names(&names)
+    };
+
+    connector.inject(active(100)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 100);
+
+    // The stale names changed in between, the removal is not confirmed.
+    connector.inject(active(50)).await;
+    connector.inject(active(50)).await;
+    connector.inject(active(40)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 100);
+
+    // Empty responses interrupt the consecutive syncs.
+    connector.inject(active(40)).await;
+    connector.inject(active(0)).await;
+    connector.inject(active(40)).await;
+    connector.inject(active(40)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 100);
+
+    // The same names were reported as stale three times in a row.
+    connector.inject(active(40)).await;
+    assert_eq!(stored_display_names(&db).await.len(), 40);
+}
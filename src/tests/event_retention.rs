@@ -0,0 +1,164 @@
+use super::*;
+use crate::database::retention::prune_event_log;
+use crate::database::EventArchive;
+use crate::primitives::{Event, IdentityContext, JudgementState, NotificationMessage};
+use crate::RetentionConfig;
+use std::collections::HashMap;
+use tokio::time::timeout;
+
+fn archive_path() -> String {
+    let random: u32 = thread_rng().gen_range(u32::MIN..u32::MAX);
+    std::env::temp_dir()
+        .join(format!("registrar_archive_{}.jsonl", random))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[actix::test]
+async fn expired_events_are_archived_and_removed() {
+    let (db, _, _api, _) = new_env().await;
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+
+    db.full_manual_verification(&alice.context).await.unwrap();
+    db.set_judged(&alice.context).await.unwrap();
+
+    // Let the events expire.
+    sleep(Duration::from_secs(2)).await;
+
+    let path = archive_path();
+    let mut max_age = HashMap::new();
+    max_age.insert("full_manual_verification".to_string(), 1);
+    // Not expired yet.
+    max_age.insert("judgement_provided".to_string(), 3600);
+
+    let config = RetentionConfig {
+        interval: 60,
+        max_age,
+        archive: Some(path.clone()),
+    };
+
+    prune_event_log(&db, &config).await.unwrap();
+
+    // Only the expired event was archived.
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let archived: Vec<Event> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(archived.len(), 1);
+    assert_eq!(
+        archived[0].message,
+        NotificationMessage::FullManualVerification {
+            context: alice.context.clone(),
+        }
+    );
+
+    // The remaining events are still delivered.
+    let event = timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        event,
+        NotificationMessage::JudgementProvided {
+            context: alice.context.clone(),
+        }
+    );
+
+    // Pruning again does not remove anything else.
+    prune_event_log(&db, &config).await.unwrap();
+    assert!(std::fs::read_to_string(&path).is_err());
+}
+
+#[actix::test]
+async fn events_without_policy_are_kept() {
+    let (db, _, _api, _) = new_env().await;
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+    db.set_judged(&alice.context).await.unwrap();
+
+    sleep(Duration::from_secs(2)).await;
+
+    let mut max_age = HashMap::new();
+    max_age.insert("field_verification_failed".to_string(), 1);
+
+    let config = RetentionConfig {
+        interval: 60,
+        max_age,
+        archive: None,
+    };
+
+    prune_event_log(&db, &config).await.unwrap();
+
+    let event = timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        event,
+        NotificationMessage::JudgementProvided {
+            context: alice.context.clone(),
+        }
+    );
+}
+
+#[actix::test]
+async fn erased_identities_are_removed_from_the_archive() {
+    let (db, _, _api, _) = new_env().await;
+
+    let path = archive_path();
+    let archive = EventArchive::new(path.clone());
+    let db = db.with_event_archive(Some(archive.clone()));
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    archive
+        .append(&[
+            Event::new(NotificationMessage::FullManualVerification {
+                context: alice.context.clone(),
+            }),
+            Event::new(NotificationMessage::FullManualVerification {
+                context: IdentityContext::bob(),
+            }),
+            Event::new(NotificationMessage::JudgementProvided {
+                context: alice.context.clone(),
+            }),
+        ])
+        .unwrap();
+
+    let report = db.erase(&alice.context).await.unwrap();
+    assert!(report.identity_removed);
+    assert_eq!(report.archived_events_removed, Some(2));
+
+    // Only the events of other identities are kept.
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let archived: Vec<Event> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(
+        archived
+            .into_iter()
+            .map(|event| event.message)
+            .collect::<Vec<_>>(),
+        vec![NotificationMessage::FullManualVerification {
+            context: IdentityContext::bob(),
+        }]
+    );
+}
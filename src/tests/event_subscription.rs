@@ -0,0 +1,97 @@
+use super::*;
+use crate::connector::Judgement;
+use crate::primitives::{IdentityContext, JudgementState, NotificationMessage};
+
+#[actix::test]
+async fn events_are_delivered_in_order() {
+    let (db, _, _api, _) = new_env().await;
+    let mut events = db.subscribe_events("test").await.unwrap();
+
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    db.set_pending_judgement(&alice.context, Judgement::KnownGood)
+        .await
+        .unwrap();
+    db.set_judged(&alice.context).await.unwrap();
+
+    assert_eq!(
+        events.next().await.unwrap(),
+        NotificationMessage::JudgementPending {
+            context: IdentityContext::alice(),
+            judgement: Judgement::KnownGood,
+        }
+    );
+    assert_eq!(
+        events.next().await.unwrap(),
+        NotificationMessage::JudgementProvided {
+            context: IdentityContext::alice(),
+        }
+    );
+}
+
+#[actix::test]
+async fn subscription_resumes_after_restart() {
+    let (db, _, _api, _) = new_env().await;
+    let mut events = db.subscribe_events("test").await.unwrap();
+
+    let alice = JudgementState::alice();
+    let mut bob = JudgementState::alice();
+    bob.context = IdentityContext::bob();
+    db.add_judgement_request(&alice).await.unwrap();
+    db.add_judgement_request(&bob).await.unwrap();
+
+    db.set_pending_judgement(&alice.context, Judgement::Reasonable)
+        .await
+        .unwrap();
+    db.set_pending_judgement(&bob.context, Judgement::Reasonable)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        events.next().await.unwrap(),
+        NotificationMessage::JudgementPending {
+            context: IdentityContext::alice(),
+            judgement: Judgement::Reasonable,
+        }
+    );
+    assert_eq!(
+        events.next().await.unwrap(),
+        NotificationMessage::JudgementPending {
+            context: IdentityContext::bob(),
+            judgement: Judgement::Reasonable,
+        }
+    );
+
+    // Restart while new events are inserted.
+    drop(events);
+    db.set_judged(&alice.context).await.unwrap();
+
+    // The last event returned before the restart is delivered again, but
+    // nothing is missed.
+    let mut events = db.subscribe_events("test").await.unwrap();
+    assert_eq!(
+        events.next().await.unwrap(),
+        NotificationMessage::JudgementPending {
+            context: IdentityContext::bob(),
+            judgement: Judgement::Reasonable,
+        }
+    );
+    assert_eq!(
+        events.next().await.unwrap(),
+        NotificationMessage::JudgementProvided {
+            context: IdentityContext::alice(),
+        }
+    );
+
+    // Other subscribers only receive new events.
+    let mut other = db.subscribe_events("other").await.unwrap();
+    db.set_judged(&bob.context).await.unwrap();
+
+    assert_eq!(
+        other.next().await.unwrap(),
+        NotificationMessage::JudgementProvided {
+            context: IdentityContext::bob(),
+        }
+    );
+}
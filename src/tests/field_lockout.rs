@@ -0,0 +1,343 @@
+use super::*;
+use crate::api::{JsonResult, ResponseAccountState, VerifyChallenge};
+use crate::primitives::{
+    ChainName, ExpectedMessage, ExternalMessage, ExternalMessageType, IdentityContext,
+    JudgementState, MessageId, NotificationMessage, Timestamp,
+};
+use crate::{LockoutAction, LockoutConfig, LockoutPolicy, Networks};
+use futures::{FutureExt, StreamExt};
+
+fn lockout_config(action: LockoutAction) -> LockoutConfig {
+    LockoutConfig {
+        max_failed_attempts: Some(2),
+        window: 3600,
+        action,
+        cooldown: 600,
+    }
+}
+
+async fn send_invalid_emails(injector: &MessageInjector, count: u32) {
+    for id in 0..count {
+        injector
+            .send(ExternalMessage {
+                origin: ExternalMessageType::Email("alice@email.com".to_string()),
+                id: MessageId::from(id),
+                timestamp: Timestamp::now(),
+                values: ExpectedMessage::random().to_message_parts(),
+            })
+            .await;
+    }
+
+    sleep(Duration::from_secs(3)).await;
+}
+
+#[actix::test]
+async fn field_locked_after_failed_attempts() {
+    let (db, _, mut api, injector) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    // Insert judgement state.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    send_invalid_emails(&injector, 2).await;
+    for _ in 0..2 {
+        let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+        match resp {
+            JsonResult::Ok(resp) => assert_eq!(
+                resp.notifications,
+                vec![NotificationMessage::FieldVerificationFailed {
+                    context: alice.context.clone(),
+                    field: F::ALICE_EMAIL(),
+                }]
+            ),
+            _ => panic!(),
+        }
+    }
+
+    db.apply_lockout_policy(&ChainName::polkadot(), &lockout_config(LockoutAction::Lock))
+        .await
+        .unwrap();
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(field.is_locked());
+    assert!(field.recent_failures.is_empty());
+    assert_eq!(field.failed_attempts, 2);
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    match resp {
+        JsonResult::Ok(resp) => assert_eq!(
+            resp.notifications,
+            vec![NotificationMessage::FieldLocked {
+                context: alice.context.clone(),
+                field: F::ALICE_EMAIL(),
+                locked_until: field.locked_until.unwrap(),
+            }]
+        ),
+        _ => panic!(),
+    }
+
+    // Valid message is ignored while the field is locked.
+    injector
+        .send(ExternalMessage {
+            origin: ExternalMessageType::Email("alice@email.com".to_string()),
+            id: MessageId::from(2u32),
+            timestamp: Timestamp::now(),
+            values: alice
+                .get_field(&F::ALICE_EMAIL())
+                .expected_message()
+                .to_message_parts(),
+        })
+        .await;
+
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        !state
+            .get_field(&F::ALICE_EMAIL())
+            .expected_message()
+            .is_verified
+    );
+}
+
+#[actix::test]
+async fn field_challenge_reset_after_failed_attempts() {
+    let (db, _, _api, injector) = new_env().await;
+
+    // Insert judgement state.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    send_invalid_emails(&injector, 2).await;
+
+    db.apply_lockout_policy(
+        &ChainName::polkadot(),
+        &lockout_config(LockoutAction::Regenerate),
+    )
+    .await
+    .unwrap();
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(!field.is_locked());
+    assert!(field.recent_failures.is_empty());
+    assert_ne!(
+        field.expected_message().value,
+        alice.get_field(&F::ALICE_EMAIL()).expected_message().value
+    );
+
+    // Other fields are left untouched.
+    assert_eq!(
+        state.get_field(&F::ALICE_TWITTER()),
+        alice.get_field(&F::ALICE_TWITTER())
+    );
+
+    // The new challenge is valid.
+    injector
+        .send(ExternalMessage {
+            origin: ExternalMessageType::Email("alice@email.com".to_string()),
+            id: MessageId::from(2u32),
+            timestamp: Timestamp::now(),
+            values: field.expected_message().to_message_parts(),
+        })
+        .await;
+
+    sleep(Duration::from_secs(3)).await;
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        state
+            .get_field(&F::ALICE_EMAIL())
+            .expected_message()
+            .is_verified
+    );
+}
+
+#[actix::test]
+async fn field_flagged_after_failed_attempts() {
+    let (db, _, _api, injector) = new_env().await;
+
+    // Insert judgement state.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    send_invalid_emails(&injector, 2).await;
+
+    db.apply_lockout_policy(&ChainName::polkadot(), &lockout_config(LockoutAction::Flag))
+        .await
+        .unwrap();
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(field.flagged);
+    assert!(!field.is_locked());
+    assert!(field.recent_failures.is_empty());
+}
+
+#[actix::test]
+async fn failed_attempts_outside_of_window_are_discarded() {
+    let (db, _, _api, injector) = new_env().await;
+
+    // Insert judgement state.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    send_invalid_emails(&injector, 2).await;
+
+    let config = LockoutConfig {
+        window: 1,
+        ..lockout_config(LockoutAction::Lock)
+    };
+
+    db.apply_lockout_policy(&ChainName::polkadot(), &config)
+        .await
+        .unwrap();
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(!field.is_locked());
+    assert!(field.recent_failures.is_empty());
+    assert_eq!(field.failed_attempts, 2);
+}
+
+#[actix::test]
+async fn field_locked_at_failed_attempt() {
+    let (db, _, _api, _) = new_env().await;
+    let lockout = LockoutPolicy::new(lockout_config(LockoutAction::Lock), &Networks::default());
+
+    // Insert judgement state.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+
+    for id in 0..2u32 {
+        let message = ExternalMessage {
+            origin: ExternalMessageType::Email("alice@email.com".to_string()),
+            id: MessageId::from(id),
+            timestamp: Timestamp::now(),
+            values: ExpectedMessage::random().to_message_parts(),
+        };
+
+        db.verify_message(&message, &lockout).await.unwrap();
+    }
+
+    // The lockout is applied without the background task.
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(field.is_locked());
+    assert!(field.recent_failures.is_empty());
+    assert_eq!(field.failed_attempts, 2);
+
+    let mut received = vec![];
+    for _ in 0..3 {
+        received.push(events.next().await.unwrap());
+    }
+
+    let failed = NotificationMessage::FieldVerificationFailed {
+        context: alice.context.clone(),
+        field: F::ALICE_EMAIL(),
+    };
+    assert_eq!(
+        received,
+        vec![
+            failed.clone(),
+            failed,
+            NotificationMessage::FieldLocked {
+                context: alice.context.clone(),
+                field: F::ALICE_EMAIL(),
+                locked_until: field.locked_until.unwrap(),
+            },
+        ]
+    );
+}
+
+#[actix::test]
+async fn failed_second_challenges_are_counted() {
+    let (db, _, _api, _) = new_env().await;
+    let lockout = LockoutPolicy::new(lockout_config(LockoutAction::Lock), &Networks::default());
+
+    // Insert judgement state.
+    let alice = JudgementState::alice();
+    db.add_judgement_request(&alice).await.unwrap();
+
+    for _ in 0..2 {
+        let verified = db
+            .verify_second_challenge(
+                VerifyChallenge {
+                    entry: F::ALICE_EMAIL(),
+                    challenge: ExpectedMessage::random().value,
+                },
+                &lockout,
+            )
+            .await
+            .unwrap();
+
+        assert!(!verified);
+    }
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let field = state.get_field(&F::ALICE_EMAIL());
+    assert!(field.is_locked());
+    assert_eq!(field.failed_attempts, 2);
+
+    // The valid second challenge is ignored while the field is locked.
+    let verified = db
+        .verify_second_challenge(
+            VerifyChallenge {
+                entry: F::ALICE_EMAIL(),
+                challenge: alice
+                    .get_field(&F::ALICE_EMAIL())
+                    .expected_second()
+                    .value
+                    .clone(),
+            },
+            &lockout,
+        )
+        .await
+        .unwrap();
+
+    assert!(!verified);
+}
@@ -0,0 +1,198 @@
+use super::*;
+use crate::api::{JsonResult, ResponseAccountState};
+use crate::connector::{CancellationReason, Judgement, JudgementCancellation, JudgementRequest};
+use crate::primitives::{
+    ChainAddress, JudgementState, JudgementSubmission, NotificationMessage, StateTransition,
+    Timestamp,
+};
+use tokio::time::timeout;
+
+fn cancellation(state: &JudgementState, reason: CancellationReason) -> WatcherMessage {
+    WatcherMessage::JudgementRequestCancelled(JudgementCancellation {
+        address: state.context.address.clone(),
+        reason,
+    })
+}
+
+/// A request which was received a while ago.
+fn old_request(mut state: JudgementState) -> JudgementState {
+    state.inserted_timestamp = Timestamp::default();
+    state
+}
+
+#[actix::test]
+async fn cancelled_request_is_archived_and_notified() {
+    let (db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    connector.inject(alice_judgement_request()).await;
+    let alice = connector.inserted_states().await[0].clone();
+
+    let resp = subscribe_context(&mut stream, alice.context.clone()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    connector
+        .inject(cancellation(&alice, CancellationReason::IdentityCleared))
+        .await;
+
+    // The state is removed, but kept in the history.
+    assert!(db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .is_none());
+
+    let history = db.fetch_judgement_history(&alice.context).await.unwrap();
+    let last = history.last().unwrap();
+    assert_eq!(last.transition, StateTransition::Cancelled);
+    assert_eq!(last.state.context, alice.context);
+
+    // Open sessions are told, including the last state.
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    match resp {
+        JsonResult::Ok(resp) => {
+            assert_eq!(resp.state.context, alice.context);
+            assert_eq!(
+                resp.notifications,
+                vec![NotificationMessage::JudgementRequestCancelled {
+                    context: alice.context.clone(),
+                    reason: CancellationReason::IdentityCleared,
+                }]
+            );
+        }
+        _ => panic!(),
+    }
+
+    // Unknown requests are ignored.
+    connector
+        .inject(cancellation(&alice, CancellationReason::RequestCancelled))
+        .await;
+    assert_eq!(
+        db.fetch_judgement_history(&alice.context)
+            .await
+            .unwrap()
+            .len(),
+        history.len()
+    );
+}
+
+#[actix::test]
+async fn cancelled_request_is_not_submitted() {
+    let (db, mut connector, _api, _) = new_env().await;
+
+    let mut alice = JudgementState::alice();
+    alice.is_fully_verified = true;
+    alice.judgement = Some(Judgement::Reasonable);
+    alice.completion_timestamp = Some(Timestamp::now());
+    alice.issue_judgement_at = Some(Timestamp::default());
+    db.import_identity(&alice).await.unwrap();
+
+    connector
+        .inject(cancellation(&alice, CancellationReason::RequestCancelled))
+        .await;
+    let _ = connector.outgoing();
+
+    sleep(Duration::from_secs(3)).await;
+    let (_, counter) = connector.outgoing();
+    assert_eq!(counter.provide_judgement, 0);
+}
+
+#[actix::test]
+async fn requests_no_longer_pending_are_cancelled() {
+    let (db, connector, _api, _) = new_env().await;
+    let mut events = db.subscribe_events("test").await.unwrap();
+
+    let alice = old_request(JudgementState::alice());
+    let bob = old_request(bob());
+    db.import_identity(&alice).await.unwrap();
+    db.import_identity(&bob).await.unwrap();
+
+    // Alice is no longer pending.
+    connector
+        .inject(WatcherMessage::PendingJudgementsRequests(vec![
+            JudgementRequest::bob(),
+        ]))
+        .await;
+
+    assert!(db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .fetch_judgement_state(&bob.context)
+        .await
+        .unwrap()
+        .is_some());
+
+    let event = timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        event,
+        NotificationMessage::JudgementRequestCancelled {
+            context: alice.context.clone(),
+            reason: CancellationReason::NoLongerPending,
+        }
+    );
+}
+
+#[actix::test]
+async fn pending_reconciliation_keeps_judged_and_recent_requests() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Submitted to the Watcher, the request disappears once the judgement
+    // is provided.
+    let mut alice = old_request(JudgementState::alice());
+    alice.is_fully_verified = true;
+    alice.judgement = Some(Judgement::Reasonable);
+    alice.submission = Some(JudgementSubmission::new(Judgement::Reasonable));
+    db.import_identity(&alice).await.unwrap();
+
+    // Received recently.
+    let bob = bob();
+    db.import_identity(&bob).await.unwrap();
+
+    let other = JudgementRequest {
+        address: ChainAddress::encode(0, &[1; 32]),
+        accounts: JudgementRequest::alice().accounts,
+    };
+
+    connector
+        .inject(WatcherMessage::PendingJudgementsRequests(vec![other]))
+        .await;
+
+    assert!(db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(db
+        .fetch_judgement_state(&bob.context)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[actix::test]
+async fn empty_pending_response_cancels_nothing() {
+    let (db, connector, _api, _) = new_env().await;
+
+    let alice = old_request(JudgementState::alice());
+    db.import_identity(&alice).await.unwrap();
+
+    // Considered to be incomplete.
+    connector
+        .inject(WatcherMessage::PendingJudgementsRequests(vec![]))
+        .await;
+
+    assert!(db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .is_some());
+}
@@ -0,0 +1,242 @@
+use super::*;
+use crate::adapters::admin::{process_admin, Command, RawFieldName, Response};
+use crate::api::{JsonResult, ResponseAccountState};
+use crate::connector::{DisplayNameEntry, Judgement};
+use crate::primitives::{
+    ExpectedMessage, ExternalMessage, ExternalMessageType, IdentityContext, MessageId,
+    NotificationMessage, Timestamp,
+};
+use crate::{ChallengeConfig, JudgementConfig, LockoutConfig, Networks};
+use futures::{FutureExt, StreamExt};
+
+#[actix::test]
+async fn erroneous_judgement_on_failed_attempts() {
+    let (db, _, mut api, injector) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let connector = ConnectorMocker::with_config(
+        db.clone(),
+        JudgementConfig {
+            erroneous_failed_attempts: Some(2),
+            erroneous_display_name_limit: None,
+            max_submission_attempts: None,
+        },
+        ChallengeConfig::default(),
+        LockoutConfig::default(),
+    );
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let mut alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Send invalid messages (bad challenge).
+    for attempt in 1..=2 {
+        injector
+            .send(ExternalMessage {
+                origin: ExternalMessageType::Email("alice@email.com".to_string()),
+                id: MessageId::from(attempt as u32),
+                timestamp: Timestamp::now(),
+                values: ExpectedMessage::random().to_message_parts(),
+            })
+            .await;
+
+        *alice.get_field_mut(&F::ALICE_EMAIL()).failed_attempts_mut() = attempt;
+
+        let expected = ResponseAccountState {
+            state: alice.clone().into(),
+            notifications: vec![NotificationMessage::FieldVerificationFailed {
+                context: alice.context.clone(),
+                field: F::ALICE_EMAIL(),
+            }],
+        };
+
+        let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+        assert_eq!(resp, JsonResult::Ok(expected));
+    }
+
+    // Threshold reached, erroneous judgement is scheduled.
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::JudgementPending {
+            context: alice.context.clone(),
+            judgement: Judgement::Erroneous,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.judgement, Some(Judgement::Erroneous));
+    assert!(state.issue_judgement_at.is_some());
+    assert!(!state.is_fully_verified);
+
+    // Empty stream.
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn erroneous_judgement_on_impersonation() {
+    let (db, _, _api, _) = new_env().await;
+
+    let connector = ConnectorMocker::with_config(
+        db.clone(),
+        JudgementConfig {
+            erroneous_failed_attempts: None,
+            erroneous_display_name_limit: Some(0.95),
+            max_submission_attempts: None,
+        },
+        ChallengeConfig::default(),
+        LockoutConfig::default(),
+    );
+
+    // Bob already uses the display name of Alice.
+    db.insert_display_name(&DisplayNameEntry {
+        context: IdentityContext::bob(),
+        display_name: "Alice".to_string(),
+    })
+    .await
+    .unwrap();
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+
+    let state = db
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(state.judgement, Some(Judgement::Erroneous));
+    assert!(state.issue_judgement_at.is_some());
+}
+
+#[actix::test]
+async fn no_erroneous_judgement_on_distinct_display_name() {
+    let (db, _, _api, _) = new_env().await;
+
+    let connector = ConnectorMocker::with_config(
+        db.clone(),
+        JudgementConfig {
+            erroneous_failed_attempts: None,
+            erroneous_display_name_limit: Some(0.95),
+            max_submission_attempts: None,
+        },
+        ChallengeConfig::default(),
+        LockoutConfig::default(),
+    );
+
+    db.insert_display_name(&DisplayNameEntry {
+        context: IdentityContext::bob(),
+        display_name: "Alice Smith".to_string(),
+    })
+    .await
+    .unwrap();
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+
+    let state = db
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(state.judgement, None);
+    assert!(state.issue_judgement_at.is_none());
+}
+
+#[actix::test]
+async fn full_verification_sets_default_judgement() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+    let alice = IdentityContext::alice();
+
+    let resp = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Verify(alice.address.clone(), vec![RawFieldName::All]),
+    )
+    .await;
+    assert_eq!(resp, Response::FullyVerified(alice.address.clone()));
+
+    let state = db.fetch_judgement_state(&alice).await.unwrap().unwrap();
+    assert_eq!(state.judgement, Some(Judgement::Reasonable));
+}
+
+#[actix::test]
+async fn full_verification_keeps_admin_judgement() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+    let alice = IdentityContext::alice();
+
+    let resp = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Judge(alice.address.clone(), Judgement::KnownGood),
+    )
+    .await;
+    assert_eq!(
+        resp,
+        Response::JudgementPending(alice.address.clone(), Judgement::KnownGood)
+    );
+
+    let resp = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Verify(alice.address.clone(), vec![RawFieldName::All]),
+    )
+    .await;
+    assert_eq!(resp, Response::FullyVerified(alice.address.clone()));
+
+    let state = db.fetch_judgement_state(&alice).await.unwrap().unwrap();
+    assert_eq!(state.judgement, Some(Judgement::KnownGood));
+}
+
+#[actix::test]
+async fn identity_update_resets_judgement() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+    let alice = IdentityContext::alice();
+
+    let resp = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Judge(alice.address.clone(), Judgement::KnownGood),
+    )
+    .await;
+    assert_eq!(
+        resp,
+        Response::JudgementPending(alice.address.clone(), Judgement::KnownGood)
+    );
+
+    // Update the identity.
+    let mut request = JudgementRequest::alice();
+    request
+        .accounts
+        .insert(AccountType::Email, "alice2@email.com".to_string());
+    connector
+        .inject(WatcherMessage::new_judgement_request(request))
+        .await;
+
+    let state = db.fetch_judgement_state(&alice).await.unwrap().unwrap();
+    assert_eq!(state.judgement, None);
+}
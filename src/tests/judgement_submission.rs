@@ -0,0 +1,188 @@
+use super::*;
+use crate::adapters::admin::{process_admin, Command, Response};
+use crate::connector::{ErrorResponse, Judgement};
+use crate::database::submission_retry_delay;
+use crate::primitives::{ChainName, JudgementState, NotificationMessage, Timestamp};
+use crate::Networks;
+use tokio::time::timeout;
+
+/// A verified identity whose judgement is due.
+fn due_state(mut state: JudgementState) -> JudgementState {
+    state.is_fully_verified = true;
+    state.judgement = Some(Judgement::Reasonable);
+    state.completion_timestamp = Some(Timestamp::now());
+    state.issue_judgement_at = Some(Timestamp::default());
+    state
+}
+
+#[test]
+fn retry_delay_grows_exponentially() {
+    assert_eq!(submission_retry_delay(1), 1);
+    assert_eq!(submission_retry_delay(2), 2);
+    assert_eq!(submission_retry_delay(4), 8);
+    assert_eq!(submission_retry_delay(20), 3600);
+    assert_eq!(submission_retry_delay(usize::MAX), 3600);
+}
+
+#[actix::test]
+async fn unconfirmed_judgement_is_retried_until_confirmed() {
+    let (db, mut connector, _api, _) = new_env().await;
+
+    let alice = due_state(JudgementState::alice());
+    db.import_identity(&alice).await.unwrap();
+
+    // The judgement is submitted.
+    sleep(Duration::from_secs(2)).await;
+    let (_, counter) = connector.outgoing();
+    assert_eq!(counter.provide_judgement, 1);
+
+    let submission = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap()
+        .submission
+        .unwrap();
+    assert_eq!(submission.attempts, 1);
+    assert_eq!(submission.judgement, Judgement::Reasonable);
+    assert!(!submission.failed);
+
+    // The Watcher reports an error for the identity.
+    connector
+        .inject(WatcherMessage::Error(ErrorResponse {
+            result: "insufficient balance".to_string(),
+            address: Some(alice.context.address.clone()),
+        }))
+        .await;
+
+    // Not confirmed, so it gets submitted again.
+    sleep(Duration::from_secs(3)).await;
+    let (_, counter) = connector.outgoing();
+    assert!(counter.provide_judgement >= 1);
+
+    let submission = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap()
+        .submission
+        .unwrap();
+    assert!(submission.attempts >= 2);
+    assert_eq!(
+        submission.last_error,
+        Some("insufficient balance".to_string())
+    );
+
+    // Confirmed by the Watcher, no more submissions.
+    connector
+        .inject(WatcherMessage::judgement_given(
+            alice.context.address.clone(),
+        ))
+        .await;
+    let _ = connector.outgoing();
+
+    sleep(Duration::from_secs(5)).await;
+    let (_, counter) = connector.outgoing();
+    assert_eq!(counter.provide_judgement, 0);
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(state.judgement_submitted);
+}
+
+#[actix::test]
+async fn judgement_submission_fails_after_max_attempts() {
+    let (db, _, _api, _) = new_env().await;
+
+    // Not on the network of the Connector, the attempts are recorded manually.
+    let mut alice = due_state(JudgementState::alice());
+    alice.context.chain = ChainName::kusama();
+    let context = alice.context.clone();
+    db.import_identity(&alice).await.unwrap();
+
+    let mut events = db.subscribe_events("test").await.unwrap();
+
+    for attempt in 1..=2 {
+        let candidates = db.fetch_judgement_candidates(&context.chain).await.unwrap();
+        assert_eq!(candidates.len(), 1);
+
+        assert!(db
+            .record_judgement_submission(&context, Judgement::Reasonable, Some(2))
+            .await
+            .unwrap());
+
+        // Not due before the delay passed.
+        assert!(db
+            .fetch_judgement_candidates(&context.chain)
+            .await
+            .unwrap()
+            .is_empty());
+
+        sleep(Duration::from_secs(submission_retry_delay(attempt) + 1)).await;
+    }
+
+    // No attempts left.
+    assert!(!db
+        .record_judgement_submission(&context, Judgement::Reasonable, Some(2))
+        .await
+        .unwrap());
+
+    let event = timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        event,
+        NotificationMessage::JudgementSubmissionFailed {
+            context: context.clone(),
+            judgement: Judgement::Reasonable,
+        }
+    );
+
+    // Failed submissions are not retried, but visible to admins.
+    assert!(db
+        .fetch_judgement_candidates(&context.chain)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let failed = match process_admin(&db, &Networks::default(), Command::Failed).await {
+        Response::FailedSubmissions(failed) => failed,
+        _ => panic!(),
+    };
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].context, context);
+    assert_eq!(failed[0].submission.attempts, 2);
+    assert!(failed[0].submission.failed);
+
+    // An admin requests another attempt.
+    assert!(db.retry_judgement_submission(&context).await.unwrap());
+    assert!(!db.retry_judgement_submission(&context).await.unwrap());
+
+    let candidates = db.fetch_judgement_candidates(&context.chain).await.unwrap();
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].submission, None);
+
+    let res = process_admin(&db, &Networks::default(), Command::Failed).await;
+    assert_eq!(res, Response::FailedSubmissions(vec![]));
+}
+
+#[actix::test]
+async fn command_retry_without_failed_submission() {
+    let (db, connector, _api, _) = new_env().await;
+
+    connector.inject(alice_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+
+    let res = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Retry(alice.context.address.clone()),
+    )
+    .await;
+    assert_eq!(res, Response::NoFailedSubmission);
+}
@@ -5,7 +5,10 @@ use crate::primitives::{
     ExpectedMessage, ExternalMessage, ExternalMessageType, JudgementState, MessageId, Timestamp,
 };
 use crate::tests::F;
-use crate::{config_session_notifier, DatabaseConfig, DisplayNameConfig, NotifierConfig, Result};
+use crate::{
+    config_session_notifier, default_subscriber, DatabaseBackend, DatabaseConfig,
+    DisplayNameConfig, LockoutConfig, LockoutPolicy, Networks, NotifierConfig, PgpConfig, Result,
+};
 use rand::{thread_rng, Rng};
 use tokio::time::{sleep, Duration};
 
@@ -21,6 +24,7 @@ async fn run_mocker() -> Result<()> {
     let mut rng = thread_rng();
 
     let db_config = DatabaseConfig {
+        backend: DatabaseBackend::Mongodb,
         uri: "mongodb://localhost:27017".to_string(),
         name: format!("registrar_test_{}", rng.gen_range(u32::MIN..u32::MAX)),
     };
@@ -31,19 +35,26 @@ async fn run_mocker() -> Result<()> {
             enabled: true,
             limit: 0.85,
         },
+        pgp: PgpConfig {
+            enabled: false,
+            keyserver: "hkps://keys.openpgp.org".to_string(),
+            max_concurrent_verifications: 4,
+        },
+        lockout: LockoutConfig::default(),
+        subscriber: default_subscriber(),
     };
 
     info!("Starting mock adapter and session notifier instances");
 
     // Setup database
-    let db = Database::new(&db_config.uri, &db_config.name).await?;
+    let db = Database::from_config(&db_config).await?;
 
-    config_session_notifier(db.clone(), notifier_config).await?;
+    config_session_notifier(db.clone(), Networks::default(), notifier_config).await?;
 
     // Setup message verifier and injector.
     let injector = MessageInjector::new();
-    let listener = AdapterListener::new(db.clone()).await;
-    listener.start_message_adapter(injector.clone(), 1).await;
+    let listener = AdapterListener::new(db.clone(), LockoutPolicy::default()).await;
+    listener.start_message_adapter(injector.clone(), 1).await?;
 
     info!("Mocker setup completed");
 
@@ -0,0 +1,414 @@
+use super::*;
+use crate::api::JsonResult;
+use crate::connector::{
+    CancellationReason, DisplayNameEntry, DisplayNameEntryRaw, Judgement, JudgementCancellation,
+    JudgementResponse, NetworkState, WatcherStatus, WatcherStatusReport,
+};
+use crate::mock_watcher::{MockWatcher, WatcherFixture};
+use crate::primitives::{ChainAddress, ChainName, IdentityContext, JudgementState, Timestamp};
+use actix_http::StatusCode;
+use serde_json::json;
+
+const FIXTURE: &str = "src/tests/fixtures/watcher.json";
+
+async fn is_connected(status: &WatcherStatus) -> bool {
+    status
+        .states()
+        .await
+        .get(&ChainName::polkadot())
+        .map(|state| state.is_connected())
+        .unwrap_or(false)
+}
+
+async fn active_endpoint(status: &WatcherStatus) -> Option<String> {
+    status
+        .states()
+        .await
+        .remove(&ChainName::polkadot())
+        .and_then(|state| state.active)
+}
+
+/// A verified identity whose judgement is due.
+fn judgement_due(mut state: JudgementState) -> JudgementState {
+    state.is_fully_verified = true;
+    state.judgement = Some(Judgement::Reasonable);
+    state.completion_timestamp = Some(Timestamp::now());
+    state.issue_judgement_at = Some(Timestamp::default());
+    state
+}
+
+#[test]
+fn fixture_from_file() {
+    let fixture = WatcherFixture::from_file(FIXTURE).unwrap();
+
+    assert!(fixture.handshake.is_some());
+    assert_eq!(fixture.pending_judgements.len(), 1);
+    assert_eq!(fixture.new_judgement_requests.len(), 1);
+    assert_eq!(fixture.display_names.len(), 2);
+
+    assert!(WatcherFixture::from_file("src/tests/fixtures/missing.json").is_err());
+}
+
+#[actix::test]
+async fn connector_processes_scripted_watcher_data() {
+    let db = new_db().await;
+    let fixture = WatcherFixture::from_file(FIXTURE).unwrap();
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // The pending judgement and the pushed request were both inserted.
+    let alice = IdentityContext::alice();
+    let bob = IdentityContext::bob();
+    assert!(db.fetch_judgement_state(&alice).await.unwrap().is_some());
+    assert!(db.fetch_judgement_state(&bob).await.unwrap().is_some());
+
+    // Display names are synced, HEX encoded names get decoded.
+    let mut display_names = db
+        .fetch_display_names(&ChainName::polkadot())
+        .await
+        .unwrap();
+    display_names.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+    assert_eq!(
+        display_names,
+        vec![
+            DisplayNameEntry {
+                context: alice,
+                display_name: "Alice".to_string(),
+            },
+            DisplayNameEntry {
+                context: bob,
+                display_name: "Bob".to_string(),
+            },
+        ]
+    );
+
+    assert!(is_connected(&status).await);
+    assert_eq!(watcher.connections().await, 1);
+}
+
+#[actix::test]
+async fn connector_provides_judgements_to_watcher() {
+    let db = new_db().await;
+    let watcher = MockWatcher::start("127.0.0.1:0", WatcherFixture::default()).unwrap();
+
+    let alice = judgement_due(JudgementState::alice());
+    db.import_identity(&alice).await.unwrap();
+
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // The Watcher received the judgement and confirmed it.
+    let expected = vec![JudgementResponse {
+        address: alice.context.address.clone(),
+        judgement: Judgement::Reasonable,
+    }];
+    assert_eq!(watcher.judgements().await, expected);
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(state.judgement_submitted);
+
+    // Confirmed judgements are not submitted again.
+    sleep(Duration::from_secs(2)).await;
+    assert_eq!(watcher.judgements().await, expected);
+}
+
+#[actix::test]
+async fn connector_processes_cancelled_requests() {
+    let db = new_db().await;
+
+    let alice = JudgementState::alice();
+    db.import_identity(&alice).await.unwrap();
+
+    let fixture = WatcherFixture {
+        cancelled_judgement_requests: vec![JudgementCancellation {
+            address: alice.context.address.clone(),
+            reason: CancellationReason::RequestCancelled,
+        }],
+        ..Default::default()
+    };
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(2)).await;
+
+    assert!(db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[actix::test]
+async fn connector_normalizes_watcher_addresses() {
+    let db = new_db().await;
+
+    let bob = bob();
+    db.import_identity(&bob).await.unwrap();
+
+    // The Watcher uses the generic Substrate format.
+    let generic = |context: &IdentityContext| {
+        let (_, public) = context.address.decode().unwrap();
+        ChainAddress::encode(42, &public)
+    };
+
+    let fixture = WatcherFixture {
+        new_judgement_requests: vec![
+            json!({
+                "address": generic(&IdentityContext::alice()),
+                "accounts": {
+                    "display_name": "Alice",
+                    "email": "alice@email.com",
+                }
+            }),
+            json!({
+                "address": "1invalidAddress",
+                "accounts": {
+                    "email": "eve@email.com",
+                }
+            }),
+        ],
+        display_names: vec![DisplayNameEntryRaw {
+            address: generic(&IdentityContext::alice()),
+            display_name: "Alice".to_string(),
+        }],
+        cancelled_judgement_requests: vec![JudgementCancellation {
+            address: generic(&bob.context),
+            reason: CancellationReason::RequestCancelled,
+        }],
+        ..Default::default()
+    };
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // All addresses are stored in the Polkadot format.
+    assert!(db
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .is_some());
+    assert!(db
+        .fetch_judgement_state(&bob.context)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        db.fetch_display_names(&ChainName::polkadot())
+            .await
+            .unwrap(),
+        vec![DisplayNameEntry {
+            context: IdentityContext::alice(),
+            display_name: "Alice".to_string(),
+        }]
+    );
+
+    // The request with the invalid address is reported to the Watcher.
+    let errors = watcher.errors().await;
+    assert!(errors.iter().any(|error| error.address
+        == Some(ChainAddress::from("1invalidAddress".to_string()))
+        && error.result.starts_with("invalid address")));
+}
+
+#[actix::test]
+async fn connector_reconnects_after_watcher_disconnect() {
+    let db = new_db().await;
+    let watcher = MockWatcher::start("127.0.0.1:0", WatcherFixture::default()).unwrap();
+
+    let status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(watcher.connections().await, 1);
+
+    // The Watcher restarts.
+    watcher.disconnect().await;
+    sleep(Duration::from_secs(3)).await;
+
+    assert_eq!(watcher.connections().await, 2);
+    assert!(is_connected(&status).await);
+}
+
+#[actix::test]
+async fn watcher_healthcheck_reports_stored_states() {
+    // Polkadot and Kusama are configured.
+    let (db, _, api, _) = new_env().await;
+
+    let kusama = |reported_at| WatcherStatusReport {
+        network: ChainName::kusama(),
+        state: NetworkState {
+            active: Some("ws://kusama".to_string()),
+            endpoints: vec![],
+        },
+        reported_at,
+    };
+
+    // Nothing was stored by the adapter listener yet.
+    let mut res = api.get("/healthcheck/watcher").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: JsonResult<Vec<WatcherStatusReport>> = res.json().await.unwrap();
+    assert_eq!(body, JsonResult::Ok(vec![]));
+
+    // Only the state of Polkadot is stored.
+    let watcher = MockWatcher::start("127.0.0.1:0", WatcherFixture::default()).unwrap();
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(2)).await;
+
+    let res = api.get("/healthcheck/watcher").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    db.store_watcher_status(&kusama(Timestamp::now()))
+        .await
+        .unwrap();
+
+    let mut res = api.get("/healthcheck/watcher").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: JsonResult<Vec<WatcherStatusReport>> = res.json().await.unwrap();
+    match body {
+        JsonResult::Ok(reports) => {
+            assert_eq!(reports.len(), 2);
+            assert_eq!(reports[0].network, ChainName::polkadot());
+            assert_eq!(reports[0].state.active, Some(watcher.endpoint()));
+            assert_eq!(reports[1], kusama(reports[1].reported_at));
+        }
+        JsonResult::Err(err) => panic!("{}", err),
+    }
+
+    // The state of Kusama was not refreshed for a while.
+    db.store_watcher_status(&kusama(Timestamp::default()))
+        .await
+        .unwrap();
+
+    let res = api.get("/healthcheck/watcher").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[actix::test]
+async fn connector_resets_connection_on_oversized_frame() {
+    let db = new_db().await;
+
+    // The display names response exceeds the maximum frame size.
+    let fixture = WatcherFixture {
+        display_names: (0..60_000)
+            .map(|_| DisplayNameEntryRaw {
+                address: IdentityContext::alice().address,
+                display_name: "A".repeat(100),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(4)).await;
+
+    // The frame was rejected and the connection was reset.
+    assert!(watcher.connections().await >= 2);
+    assert!(db
+        .fetch_display_names(&ChainName::polkadot())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix::test]
+async fn connector_supports_watcher_without_handshake() {
+    let db = new_db().await;
+    let fixture = WatcherFixture {
+        handshake: None,
+        ..WatcherFixture::from_file(FIXTURE).unwrap()
+    };
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // The pushed request is accepted with protocol version 0.
+    assert!(db
+        .fetch_judgement_state(&IdentityContext::bob())
+        .await
+        .unwrap()
+        .is_some());
+    assert!(db
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .is_some());
+    assert!(is_connected(&status).await);
+}
+
+#[actix::test]
+async fn connector_skips_handshake_timeout_of_legacy_watcher() {
+    let db = new_db().await;
+    let alice = judgement_due(JudgementState::alice());
+    db.import_identity(&alice).await.unwrap();
+
+    // The first message of the Watcher is an invalid request, which is not
+    // processed any further.
+    let fixture = WatcherFixture {
+        handshake: None,
+        new_judgement_requests: vec![serde_json::json!({
+            "address": "1invalidRequest",
+            "accounts": "github",
+        })],
+        ..Default::default()
+    };
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // The judgement is submitted without waiting for the handshake timeout.
+    assert_eq!(
+        watcher.judgements().await,
+        vec![JudgementResponse {
+            address: alice.context.address.clone(),
+            judgement: Judgement::Reasonable,
+        }]
+    );
+}
+
+#[actix::test]
+async fn connector_fails_over_to_standby_watcher() {
+    let db = new_db().await;
+    let primary = MockWatcher::start("127.0.0.1:0", WatcherFixture::default()).unwrap();
+    let standby = MockWatcher::start("127.0.0.1:0", WatcherFixture::default()).unwrap();
+
+    let alice = judgement_due(JudgementState::alice());
+    db.import_identity(&alice).await.unwrap();
+
+    let status = connect_all(&db, &[&primary, &standby]).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // Both Watchers are connected, but only the active one receives the
+    // judgement.
+    assert_eq!(primary.connections().await, 1);
+    assert_eq!(standby.connections().await, 1);
+    assert_eq!(active_endpoint(&status).await, Some(primary.endpoint()));
+    assert_eq!(primary.judgements().await.len(), 1);
+    assert!(standby.judgements().await.is_empty());
+
+    // The primary Watcher goes down, the standby takes over.
+    primary.disconnect().await;
+    primary.stop().await;
+    sleep(Duration::from_secs(3)).await;
+
+    assert_eq!(active_endpoint(&status).await, Some(standby.endpoint()));
+
+    let bob = judgement_due(bob());
+    db.import_identity(&bob).await.unwrap();
+    sleep(Duration::from_secs(3)).await;
+
+    assert_eq!(primary.judgements().await.len(), 1);
+    assert_eq!(
+        standby.judgements().await,
+        vec![JudgementResponse {
+            address: bob.context.address.clone(),
+            judgement: Judgement::Reasonable,
+        }]
+    );
+}
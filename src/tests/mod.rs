@@ -1,11 +1,18 @@
 use crate::adapters::tests::MessageInjector;
 use crate::adapters::AdapterListener;
 use crate::api::{JsonResult, ResponseAccountState};
-use crate::connector::{AccountType, JudgementRequest, WatcherMessage};
+use crate::connector::{
+    run_connector, AccountType, JudgementRequest, WatcherMessage, WatcherStatus,
+};
 use crate::database::Database;
+use crate::mock_watcher::MockWatcher;
 use crate::notifier::run_session_notifier;
-use crate::primitives::{IdentityContext, IdentityFieldValue};
+use crate::primitives::{ChainName, IdentityContext, IdentityFieldValue, JudgementState};
 use crate::{api::tests::run_test_server, connector::tests::ConnectorMocker};
+use crate::{
+    ChallengeConfig, DisplayNameConfig, JudgementConfig, LockoutConfig, LockoutPolicy,
+    NetworkConfig, Networks, WatcherAuthConfig, WatcherTlsConfig,
+};
 use actix_codec::{AsyncRead, AsyncWrite, Framed};
 use actix_http::ws::Codec;
 use actix_http::ws::{Frame, ProtocolError};
@@ -19,10 +26,27 @@ use tokio::time::{sleep, Duration};
 
 mod api_judgement_state;
 mod background_tasks;
+mod challenge_expiry;
+mod concurrent_updates;
+mod config_compatibility;
+mod data_transfer;
 mod display_name_verification;
+mod event_retention;
+mod event_subscription;
 mod explicit;
+mod field_lockout;
+mod judgement_cancellation;
+mod judgement_policy;
+mod judgement_submission;
 mod live_mocker;
+mod mock_watcher;
+mod pgp_verification;
 mod process_admin_cmds;
+mod schema_migrations;
+mod unknown_identity_fields;
+mod watcher_auth;
+mod watcher_connection;
+mod web_verification;
 
 // Convenience type
 pub type F = IdentityFieldValue;
@@ -71,28 +95,95 @@ pub fn bob_judgement_request() -> WatcherMessage {
     WatcherMessage::new_judgement_request(JudgementRequest::bob())
 }
 
+pub fn bob() -> JudgementState {
+    let accounts = JudgementRequest::bob().accounts;
+    JudgementState::new(
+        IdentityContext::bob(),
+        accounts.into_iter().map(|a| a.into()).collect(),
+    )
+}
+
+async fn new_db() -> Database {
+    let db = Database::in_memory();
+    db.migrate().await.unwrap();
+    db
+}
+
+/// Runs the connector for Polkadot against the mock Watcher.
+async fn connect(db: &Database, watcher: &MockWatcher) -> WatcherStatus {
+    connect_all(db, &[watcher]).await
+}
+
+/// Runs the connector for Polkadot against the mock Watchers, in order of
+/// preference.
+async fn connect_all(db: &Database, watchers: &[&MockWatcher]) -> WatcherStatus {
+    connect_with(db, watchers, None, None).await.unwrap()
+}
+
+/// Runs the connector for Polkadot against the mock Watchers, with the given
+/// authentication and TLS settings.
+async fn connect_with(
+    db: &Database,
+    watchers: &[&MockWatcher],
+    watcher_auth: Option<WatcherAuthConfig>,
+    watcher_tls: Option<WatcherTlsConfig>,
+) -> crate::Result<WatcherStatus> {
+    let networks = Networks(vec![NetworkConfig {
+        name: ChainName::polkadot(),
+        ss58_prefix: 0,
+        endpoints: watchers.iter().map(|watcher| watcher.endpoint()).collect(),
+        judgement: None,
+        challenge: None,
+        lockout: None,
+        watcher_auth,
+        watcher_tls,
+    }]);
+
+    let status = WatcherStatus::default();
+    run_connector(
+        db.clone(),
+        networks,
+        DisplayNameConfig::default(),
+        JudgementConfig::default(),
+        ChallengeConfig::default(),
+        LockoutConfig::default(),
+        status.clone(),
+    )
+    .await?;
+
+    Ok(status)
+}
+
 // async fn new_env() -> (TestServer, ConnectorMocker, MessageInjector) {
 async fn new_env() -> (Database, ConnectorMocker, TestServer, MessageInjector) {
-    // Setup MongoDb database.
-    let random: u32 = thread_rng().gen_range(u32::MIN..u32::MAX);
-    let db = Database::new(
-        "mongodb://localhost:27017/",
-        &format!("registrar_test_{}", random),
-    )
-    .await
-    .unwrap();
+    // Setup database. Runs against MongoDb if `TEST_MONGODB_URI` is set,
+    // otherwise the in-memory backend is used.
+    let db = match std::env::var("TEST_MONGODB_URI") {
+        Ok(uri) => {
+            let random: u32 = thread_rng().gen_range(u32::MIN..u32::MAX);
+            Database::new(&uri, &format!("registrar_test_{}", random))
+                .await
+                .unwrap()
+        }
+        Err(_) => Database::in_memory(),
+    };
+    db.migrate().await.unwrap();
+    db.ensure_indexes().await.unwrap();
 
     // Setup API
     let (server, actor) = run_test_server(db.clone()).await;
 
     // Setup message verifier and injector.
     let injector = MessageInjector::new();
-    let listener = AdapterListener::new(db.clone()).await;
-    listener.start_message_adapter(injector.clone(), 1).await;
+    let listener = AdapterListener::new(db.clone(), LockoutPolicy::default()).await;
+    listener
+        .start_message_adapter(injector.clone(), 1)
+        .await
+        .unwrap();
 
     let t_db = db.clone();
     actix::spawn(async move {
-        run_session_notifier(t_db, actor).await;
+        run_session_notifier(t_db, actor, "session_notifier".to_string()).await;
     });
 
     // Setup connector mocker
@@ -0,0 +1,206 @@
+use super::*;
+use crate::api::{JsonResult, PgpSignatureVerifier, ResponseAccountState, VerifyPgpSignature};
+use crate::pgp::tests::{stub_clearsign, StubVerifier};
+use crate::pgp::{SignatureVerifier, SignedMessage};
+use crate::primitives::{IdentityContext, NotificationMessage};
+use actix::Actor;
+use actix_http::StatusCode;
+use futures::{FutureExt, StreamExt};
+
+const ALICE_FINGERPRINT: &str = "5FB9E5D7AF1E8CDB4B7F6DB4F9A5C4B9A1A21D7E";
+const EVE_FINGERPRINT: &str = "0BF6D7C1A2BC31B0F5A6C6B6E3C9C0F8A1A22B33";
+
+fn alice_with_pgp() -> WatcherMessage {
+    let mut req = JudgementRequest::alice();
+    // The Watcher sends the fingerprint in (lowercase) hex form.
+    req.accounts.insert(
+        AccountType::PGPFingerprint,
+        format!("0x{}", ALICE_FINGERPRINT.to_lowercase()),
+    );
+    WatcherMessage::new_judgement_request(req)
+}
+
+#[actix::test]
+async fn verify_pgp_signature_valid() {
+    let (_db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let field = F::PGPFingerprint(ALICE_FINGERPRINT.to_string());
+
+    // Insert judgement request.
+    connector.inject(alice_with_pgp()).await;
+    let states = connector.inserted_states().await;
+    let mut alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Sign the challenge.
+    let challenge = alice
+        .get_field(&field)
+        .expected_signed_message()
+        .value
+        .clone();
+    let res = api
+        .post("/api/verify_pgp_signature")
+        .send_json(&VerifyPgpSignature {
+            signed_message: stub_clearsign(
+                &format!("My registrar challenge: {}", challenge),
+                ALICE_FINGERPRINT,
+            ),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // PGP fingerprint of Alice is now verified.
+    alice
+        .get_field_mut(&field)
+        .expected_signed_message_mut()
+        .set_verified();
+
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerified {
+            context: alice.context.clone(),
+            field,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+
+    // Empty stream.
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn verify_pgp_signature_invalid_challenge() {
+    let (_db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let field = F::PGPFingerprint(ALICE_FINGERPRINT.to_string());
+
+    // Insert judgement request.
+    connector.inject(alice_with_pgp()).await;
+    let states = connector.inserted_states().await;
+    let mut alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Sign an invalid challenge.
+    let res = api
+        .post("/api/verify_pgp_signature")
+        .send_json(&VerifyPgpSignature {
+            signed_message: stub_clearsign("invalid", ALICE_FINGERPRINT),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    *alice.get_field_mut(&field).failed_attempts_mut() = 1;
+
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerificationFailed {
+            context: alice.context.clone(),
+            field,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+
+    // Empty stream.
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn verify_pgp_signature_wrong_key() {
+    let (_db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let field = F::PGPFingerprint(ALICE_FINGERPRINT.to_string());
+
+    // Insert judgement request.
+    connector.inject(alice_with_pgp()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Sign the valid challenge with a different key.
+    let challenge = alice
+        .get_field(&field)
+        .expected_signed_message()
+        .value
+        .clone();
+    let mut res = api
+        .post("/api/verify_pgp_signature")
+        .send_json(&VerifyPgpSignature {
+            signed_message: stub_clearsign(&challenge, EVE_FINGERPRINT),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: JsonResult<bool> = res.json().await.unwrap();
+    assert_eq!(body, JsonResult::Ok(false));
+
+    // No state changes.
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+}
+
+/// Takes two seconds for each verification.
+struct SlowVerifier;
+
+#[async_trait]
+impl SignatureVerifier for SlowVerifier {
+    async fn verify(&self, message: &str) -> crate::Result<SignedMessage> {
+        sleep(Duration::from_secs(2)).await;
+        StubVerifier.verify(message).await
+    }
+}
+
+#[actix::test]
+async fn verify_pgp_signature_saturated() {
+    let db = Database::in_memory();
+    db.migrate().await.unwrap();
+
+    let verifier = PgpSignatureVerifier::with_verifier(db, SlowVerifier, 1).start();
+    let msg = VerifyPgpSignature {
+        signed_message: stub_clearsign("invalid", ALICE_FINGERPRINT),
+    };
+
+    // Only one signature is verified at a time, the other one is rejected.
+    let (first, second) = futures::join!(verifier.send(msg.clone()), verifier.send(msg.clone()));
+    let mut results = vec![first.unwrap(), second.unwrap()];
+    results.sort_by_key(|res| matches!(res, JsonResult::Err(_)));
+    assert_eq!(
+        results,
+        vec![
+            JsonResult::Ok(false),
+            JsonResult::Err("Too many signatures are being verified, try again later".to_string()),
+        ]
+    );
+
+    // The permit is released after the verification.
+    assert_eq!(verifier.send(msg).await.unwrap(), JsonResult::Ok(false));
+}
@@ -1,9 +1,12 @@
 use super::*;
 use crate::adapters::admin::{process_admin, Command, RawFieldName, Response};
 use crate::api::{JsonResult, ResponseAccountState};
+use crate::connector::{CancellationReason, DisplayNameEntry, Judgement};
 use crate::primitives::{
-    IdentityContext, IdentityFieldValue, JudgementStateBlanked, NotificationMessage,
+    ChainAddress, ChallengeType, IdentityContext, IdentityField, IdentityFieldValue,
+    JudgementStateBlanked, NotificationMessage, StateTransition, ERASED_PLACEHOLDER,
 };
+use crate::Networks;
 use futures::{FutureExt, StreamExt};
 
 #[actix::test]
@@ -16,8 +19,40 @@ async fn command_status() {
     let alice = states[0].clone();
 
     // Request status.
-    let res = process_admin(&db, Command::Status(alice.context.address.clone())).await;
-    assert_eq!(res, Response::Status(JudgementStateBlanked::from(alice)));
+    let res = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Status(alice.context.address.clone()),
+    )
+    .await;
+    assert_eq!(
+        res,
+        Response::Status(Box::new(JudgementStateBlanked::from(alice)))
+    );
+}
+
+#[actix::test]
+async fn command_status_other_address_formats() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+
+    // Generic Substrate encoding of Alice's key is looked up on all chains.
+    let generic = ChainAddress::from("5CdjQP1K3ED1FmtCkC58wrmxPwtra7MN8zd2J5BxkYkJ6NNR");
+    let res = process_admin(&db, &Networks::default(), Command::Status(generic)).await;
+    assert_eq!(
+        res,
+        Response::Status(Box::new(JudgementStateBlanked::from(alice)))
+    );
+
+    // Kusama encoding of Alice's key, but Alice only requested a judgement
+    // on Polkadot.
+    let kusama = ChainAddress::from("D9M4hMBfbDw1RheWttBqp8xYYB6NnAYbNTmgjTvELxnqWbv");
+    let res = process_admin(&db, &Networks::default(), Command::Status(kusama)).await;
+    assert_eq!(res, Response::IdentityNotFound);
 }
 
 #[actix::test]
@@ -42,6 +77,7 @@ async fn command_verify_multiple_challenge_types() {
     // Manually verify.
     let resp = process_admin(
         &db,
+        &Networks::default(),
         Command::Verify(
             alice.context.address.clone(),
             vec![RawFieldName::DisplayName, RawFieldName::Email],
@@ -100,6 +136,7 @@ async fn command_verify_multiple_challenge_types() {
     // Manually verify twitter field.
     let resp = process_admin(
         &db,
+        &Networks::default(),
         Command::Verify(alice.context.address.clone(), vec![RawFieldName::Twitter]),
     )
     .await;
@@ -141,7 +178,7 @@ async fn command_verify_unsupported_field() {
         .inject(WatcherMessage::new_judgement_request({
             let mut req = JudgementRequest::alice();
             req.accounts
-                .insert(AccountType::Web, "alice.com".to_string());
+                .insert(AccountType::LegalName, "Alice Doe".to_string());
             req
         }))
         .await;
@@ -160,18 +197,19 @@ async fn command_verify_unsupported_field() {
     // Manually verify.
     let resp = process_admin(
         &db,
-        Command::Verify(alice.context.address.clone(), vec![RawFieldName::Web]),
+        &Networks::default(),
+        Command::Verify(alice.context.address.clone(), vec![RawFieldName::LegalName]),
     )
     .await;
 
     assert_eq!(
         resp,
-        Response::Verified(alice.context.address.clone(), vec![RawFieldName::Web])
+        Response::Verified(alice.context.address.clone(), vec![RawFieldName::LegalName])
     );
 
-    // Web is now verified.
+    // Legal name is now verified.
     let is_verified = alice
-        .get_field_mut(&IdentityFieldValue::Web("alice.com".to_string()))
+        .get_field_mut(&IdentityFieldValue::LegalName("Alice Doe".to_string()))
         .expected_unsupported_mut();
     *is_verified = Some(true);
 
@@ -180,7 +218,7 @@ async fn command_verify_unsupported_field() {
         state: alice.clone().into(),
         notifications: vec![NotificationMessage::ManuallyVerified {
             context: alice.context.clone(),
-            field: RawFieldName::Web,
+            field: RawFieldName::LegalName,
         }],
     };
 
@@ -213,6 +251,7 @@ async fn command_verify_all() {
     // Manually verify.
     let resp = process_admin(
         &db,
+        &Networks::default(),
         Command::Verify(alice.context.address.clone(), vec![RawFieldName::All]),
     )
     .await;
@@ -305,6 +344,7 @@ async fn command_verify_missing_field() {
     // Manually verify a field that does not exist.
     let resp = process_admin(
         &db,
+        &Networks::default(),
         Command::Verify(alice.context.address.clone(), vec![RawFieldName::Email]),
     )
     .await;
@@ -314,3 +354,287 @@ async fn command_verify_missing_field() {
     // Empty stream.
     assert!(stream.next().now_or_never().is_none());
 }
+
+#[actix::test]
+async fn command_history() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Insert judgement request.
+    connector.inject(alice_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+
+    // Change a field, then verify and judge the identity.
+    let mut updated = alice.clone();
+    let new_twitter = F::Twitter("@alice_new".to_string());
+    *updated.get_field_mut(&F::ALICE_TWITTER()) = IdentityField::new(new_twitter.clone());
+    assert!(db.add_judgement_request(&updated).await.unwrap());
+
+    let resp = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Verify(alice.context.address.clone(), vec![RawFieldName::All]),
+    )
+    .await;
+    assert_eq!(resp, Response::FullyVerified(alice.context.address.clone()));
+
+    db.set_judged(&alice.context).await.unwrap();
+
+    let history = match process_admin(
+        &db,
+        &Networks::default(),
+        Command::History(alice.context.address.clone()),
+    )
+    .await
+    {
+        Response::History(history) => history,
+        _ => panic!(),
+    };
+
+    let transitions: Vec<StateTransition> = history.iter().map(|e| e.transition).collect();
+    assert_eq!(
+        transitions,
+        vec![
+            StateTransition::Inserted,
+            StateTransition::FieldsChanged,
+            StateTransition::FullyVerified,
+            StateTransition::JudgementProvided,
+        ]
+    );
+
+    // The judged values are kept, even if the identity changes afterwards.
+    assert_eq!(history[0].state, JudgementStateBlanked::from(alice));
+    assert!(history[1]
+        .state
+        .fields
+        .iter()
+        .any(|field| field.value == new_twitter));
+
+    let judged = &history[3].state;
+    assert!(judged.is_fully_verified);
+    assert!(judged.judgement_submitted);
+}
+
+#[actix::test]
+async fn command_history_unknown_identity() {
+    let (db, _connector, _api, _) = new_env().await;
+
+    let res = process_admin(
+        &db,
+        &Networks::default(),
+        Command::History(IdentityContext::alice().address),
+    )
+    .await;
+    assert_eq!(res, Response::IdentityNotFound);
+}
+
+#[actix::test]
+async fn command_erase() {
+    let (db, connector, _api, _) = new_env().await;
+
+    // Insert judgement requests.
+    connector.inject(alice_judgement_request()).await;
+    connector.inject(bob_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+
+    db.insert_display_name(&DisplayNameEntry {
+        context: alice.context.clone(),
+        display_name: "Alice".to_string(),
+    })
+    .await
+    .unwrap();
+
+    // Verify and judge Alice.
+    db.full_manual_verification(&alice.context).await.unwrap();
+    db.set_judged(&alice.context).await.unwrap();
+
+    let report = match process_admin(
+        &db,
+        &Networks::default(),
+        Command::Erase(alice.context.address.clone()),
+    )
+    .await
+    {
+        Response::Erased(mut reports) => {
+            assert_eq!(reports.len(), 1);
+            reports.remove(0)
+        }
+        _ => panic!(),
+    };
+
+    assert_eq!(report.context, alice.context);
+    assert!(report.identity_removed);
+    // Full manual verification and judgement provided.
+    assert_eq!(report.events_removed, 2);
+    assert_eq!(report.display_names_removed, 1);
+    // Inserted, fully verified and judgement provided.
+    assert_eq!(report.history_entries_removed, 3);
+    // No event archive is configured.
+    assert_eq!(report.archived_events_removed, None);
+
+    let tombstone = report.tombstone.unwrap();
+    assert_eq!(tombstone.context, alice.context);
+    assert_eq!(tombstone.judgement, Judgement::Reasonable);
+    assert!(tombstone.judged_at.is_some());
+
+    // No personal data is left, but the tombstone is kept.
+    assert!(db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .fetch_judgement_history(&alice.context)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(db
+        .fetch_display_names(&alice.context.chain)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.fetch_tombstones(&alice.context).await.unwrap(),
+        vec![tombstone]
+    );
+
+    // Other identities are not affected.
+    assert!(db
+        .fetch_judgement_state(&IdentityContext::bob())
+        .await
+        .unwrap()
+        .is_some());
+
+    let res = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Erase(alice.context.address.clone()),
+    )
+    .await;
+    assert_eq!(res, Response::IdentityNotFound);
+}
+
+#[actix::test]
+async fn command_erase_without_judgement() {
+    let (db, connector, _api, _) = new_env().await;
+
+    connector.inject(alice_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+
+    let report = match process_admin(
+        &db,
+        &Networks::default(),
+        Command::Erase(alice.context.address.clone()),
+    )
+    .await
+    {
+        Response::Erased(mut reports) => {
+            assert_eq!(reports.len(), 1);
+            reports.remove(0)
+        }
+        _ => panic!(),
+    };
+
+    assert!(report.identity_removed);
+    assert_eq!(report.history_entries_removed, 1);
+    assert_eq!(report.tombstone, None);
+    assert!(db
+        .fetch_tombstones(&alice.context)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix::test]
+async fn command_erase_after_cancellation() {
+    let (db, connector, _api, _) = new_env().await;
+
+    connector.inject(alice_judgement_request()).await;
+    connector.inject(bob_judgement_request()).await;
+    let states = connector.inserted_states().await;
+    let alice = states[0].clone();
+    let bob = states[1].clone();
+
+    // Bob's display name is too similar to Alice's.
+    db.insert_display_name_violations(
+        &bob.context,
+        &[DisplayNameEntry {
+            context: alice.context.clone(),
+            display_name: "Alice".to_string(),
+        }],
+    )
+    .await
+    .unwrap();
+
+    // Alice gets judged, then clears her identity.
+    db.full_manual_verification(&alice.context).await.unwrap();
+    db.set_judged(&alice.context).await.unwrap();
+    assert!(db
+        .cancel_judgement_request(&alice.context, CancellationReason::IdentityCleared)
+        .await
+        .unwrap());
+
+    let report = match process_admin(
+        &db,
+        &Networks::default(),
+        Command::Erase(alice.context.address.clone()),
+    )
+    .await
+    {
+        Response::Erased(mut reports) => {
+            assert_eq!(reports.len(), 1);
+            reports.remove(0)
+        }
+        _ => panic!(),
+    };
+
+    assert_eq!(report.context, alice.context);
+    assert!(!report.identity_removed);
+    // Full manual verification, judgement provided and cancellation.
+    assert_eq!(report.events_removed, 3);
+    // Inserted, fully verified, judgement provided and cancelled.
+    assert_eq!(report.history_entries_removed, 4);
+    assert_eq!(report.violations_anonymised, 1);
+
+    // The tombstone is created from the last snapshot in the history.
+    let tombstone = report.tombstone.unwrap();
+    assert_eq!(tombstone.context, alice.context);
+    assert_eq!(tombstone.judgement, Judgement::Reasonable);
+    assert!(tombstone.judged_at.is_some());
+
+    assert!(db
+        .fetch_judgement_history(&alice.context)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Bob's violation is kept, but no longer refers to Alice.
+    let bob = db
+        .fetch_judgement_state(&bob.context)
+        .await
+        .unwrap()
+        .unwrap();
+    let violations = bob
+        .fields
+        .iter()
+        .find_map(|field| match &field.challenge {
+            ChallengeType::DisplayNameCheck { violations, .. } => Some(violations.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].context.address.as_str(), ERASED_PLACEHOLDER);
+    assert_eq!(violations[0].context.chain, alice.context.chain);
+    assert_eq!(violations[0].display_name, ERASED_PLACEHOLDER);
+
+    // Nothing is left to erase.
+    let res = process_admin(
+        &db,
+        &Networks::default(),
+        Command::Erase(alice.context.address.clone()),
+    )
+    .await;
+    assert_eq!(res, Response::IdentityNotFound);
+}
@@ -0,0 +1,152 @@
+use super::F;
+use crate::database::migrations::{self, latest_version};
+use crate::database::{EVENT_COLLECTION, IDENTITY_COLLECTION};
+use crate::primitives::{
+    ChallengeType, Event, IdentityContext, JudgementState, NotificationMessage,
+};
+use bson::{from_document, to_document, Bson, Document};
+
+fn load_fixture(content: &str) -> Document {
+    serde_json::from_str(content).unwrap()
+}
+
+/// Asserts that both documents have the same (nested) keys. Ignores the
+/// values, since numbers from JSON fixtures are not stored as `Int64`.
+fn assert_same_keys(left: &Document, right: &Document) {
+    fn check(left: &Bson, right: &Bson) {
+        match (left, right) {
+            (Bson::Document(left), Bson::Document(right)) => {
+                let mut left_keys: Vec<&String> = left.keys().collect();
+                let mut right_keys: Vec<&String> = right.keys().collect();
+                left_keys.sort();
+                right_keys.sort();
+                assert_eq!(left_keys, right_keys);
+
+                for (key, value) in left {
+                    check(value, right.get(key).unwrap());
+                }
+            }
+            (Bson::Array(left), Bson::Array(right)) => {
+                assert_eq!(left.len(), right.len());
+                for (left, right) in left.iter().zip(right) {
+                    check(left, right);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    check(
+        &Bson::Document(left.clone()),
+        &Bson::Document(right.clone()),
+    );
+}
+
+#[test]
+fn identity_is_upgraded_to_latest_version() {
+    let mut doc = load_fixture(include_str!("fixtures/identity_v0.json"));
+    migrations::upgrade_document(IDENTITY_COLLECTION, 0, &mut doc).unwrap();
+
+    let state: JudgementState = from_document(doc.clone()).unwrap();
+    assert_eq!(state.context, IdentityContext::alice());
+    assert_eq!(state.inserted_timestamp.raw(), 1637000000);
+    assert_eq!(state.judgement, None);
+    assert_eq!(state.submission, None);
+
+    let display_name = &state.fields[0];
+    assert!(display_name.challenge.is_verified());
+
+    let email = &state.fields[1];
+    assert_eq!(email.failed_attempts, 2);
+    assert!(email.recent_failures.is_empty());
+    assert_eq!(email.locked_until, None);
+    assert!(!email.flagged);
+    match &email.challenge {
+        ChallengeType::ExpectedMessage { expected, second } => {
+            let second = second.as_ref().unwrap();
+            assert!(expected.is_verified);
+            assert!(!second.is_verified);
+            assert_eq!(expected.created_at.raw(), 0);
+            assert_eq!(second.ttl, None);
+            assert!(!expected.is_expired());
+        }
+        _ => panic!(),
+    }
+
+    let pgp = &state.fields[3];
+    assert_eq!(pgp.value, F::PGPFingerprint(String::new()));
+
+    let web = &state.fields[4];
+    match &web.challenge {
+        ChallengeType::ExpectedMessage { expected, second } => {
+            assert!(!expected.is_verified);
+            assert_eq!(expected.ttl, None);
+            assert!(second.is_none());
+        }
+        _ => panic!(),
+    }
+
+    // The upgraded document does not rely on any defaults.
+    assert_same_keys(&to_document(&state).unwrap(), &doc);
+}
+
+#[test]
+fn upgraded_identity_is_not_modified() {
+    let state = JudgementState::alice();
+    let original = to_document(&state).unwrap();
+
+    let mut doc = original.clone();
+    migrations::upgrade_document(IDENTITY_COLLECTION, 0, &mut doc).unwrap();
+    assert_eq!(doc, original);
+}
+
+#[test]
+fn manually_verified_web_field_stays_verified() {
+    let mut doc = load_fixture(include_str!("fixtures/identity_v0.json"));
+    doc.get_array_mut("fields").unwrap()[4]
+        .as_document_mut()
+        .unwrap()
+        .insert(
+            "challenge",
+            load_fixture(r#"{ "type": "unsupported", "content": { "is_verified": true } }"#),
+        );
+
+    migrations::upgrade_document(IDENTITY_COLLECTION, 0, &mut doc).unwrap();
+
+    let state: JudgementState = from_document(doc).unwrap();
+    assert!(state.fields[4].expected_message().is_verified);
+}
+
+#[test]
+fn only_pending_migrations_are_applied() {
+    let latest = latest_version(IDENTITY_COLLECTION);
+    let versions: Vec<u32> = migrations::pending(IDENTITY_COLLECTION, 2)
+        .map(|migration| migration.version)
+        .collect();
+
+    assert_eq!(versions, (3..=latest).collect::<Vec<u32>>());
+    assert_eq!(migrations::pending(IDENTITY_COLLECTION, latest).count(), 0);
+}
+
+#[test]
+fn invalid_identity_is_rejected() {
+    let mut doc = load_fixture(r#"{ "context": null, "fields": 5 }"#);
+    assert!(migrations::upgrade_document(IDENTITY_COLLECTION, 0, &mut doc).is_err());
+}
+
+#[test]
+fn event_is_upgraded_to_latest_version() {
+    let mut doc = load_fixture(include_str!("fixtures/event_v0.json"));
+    migrations::upgrade_document(EVENT_COLLECTION, 0, &mut doc).unwrap();
+
+    let event: Event = from_document(doc.clone()).unwrap();
+    assert_eq!(event.timestamp.raw(), 1637000000);
+    assert_eq!(
+        event.message,
+        NotificationMessage::FieldVerified {
+            context: IdentityContext::alice(),
+            field: F::Email("alice@email.com".to_string()),
+        }
+    );
+    assert_same_keys(&to_document(&event).unwrap(), &doc);
+}
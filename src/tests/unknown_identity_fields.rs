@@ -0,0 +1,169 @@
+use super::*;
+use crate::connector::{ErrorResponse, JudgementRequestRaw};
+use crate::mock_watcher::{MockWatcher, WatcherFixture};
+use crate::primitives::{ChainAddress, ChainName, ChallengeType, IdentityField, JudgementState};
+use serde_json::json;
+
+const ALICE: &str = "1a2YiGNu1UUhJtihq8961c7FZtWGQuWDVMWTNBKJdmpGhZP";
+const BOB: &str = "1b3NhsSEqWSQwS6nPGKgCrSjv9Kp13CnhraLV5Coyd8ooXB";
+const INVALID: &str = "1invalidRequest";
+
+fn context(address: &str) -> IdentityContext {
+    IdentityContext::new(
+        ChainAddress::from(address.to_string()),
+        ChainName::polkadot(),
+    )
+}
+
+fn error(address: &str, result: &str) -> ErrorResponse {
+    ErrorResponse {
+        result: result.to_string(),
+        address: Some(ChainAddress::from(address.to_string())),
+    }
+}
+
+#[test]
+fn unknown_account_types() {
+    let ty: AccountType = serde_json::from_str("\"github\"").unwrap();
+    assert_eq!(ty, AccountType::Other("github".to_string()));
+
+    let ty: AccountType = serde_json::from_str("\"pgpFingerprint\"").unwrap();
+    assert_eq!(ty, AccountType::PGPFingerprint);
+    assert_eq!(
+        serde_json::to_string(&AccountType::Other("riot".to_string())).unwrap(),
+        "\"riot\""
+    );
+
+    // Unknown fields must be verified manually.
+    let field = IdentityField::new(IdentityFieldValue::from((
+        AccountType::Other("github".to_string()),
+        "alice".to_string(),
+    )));
+    assert_eq!(
+        field.value,
+        IdentityFieldValue::Other {
+            name: "github".to_string(),
+            value: "alice".to_string(),
+        }
+    );
+    assert_eq!(
+        field.challenge,
+        ChallengeType::Unsupported { is_verified: None }
+    );
+
+    // Fields without a string value are skipped.
+    let raw: JudgementRequestRaw = serde_json::from_value(json!({
+        "address": ALICE,
+        "accounts": {
+            "display_name": "Alice",
+            "discord": "alice#1234",
+            "riot": null,
+            "image": {"Raw": "0x00"},
+        }
+    }))
+    .unwrap();
+
+    let (request, skipped) = raw.into_request();
+    assert_eq!(skipped, vec!["image".to_string(), "riot".to_string()]);
+    assert_eq!(request.accounts.len(), 2);
+    assert_eq!(
+        request
+            .accounts
+            .get(&AccountType::Other("discord".to_string())),
+        Some(&"alice#1234".to_string())
+    );
+}
+
+#[actix::test]
+async fn connector_accepts_unknown_identity_fields() {
+    let db = new_db().await;
+
+    // A request which was received a while ago and is not reported by the
+    // Watcher anymore.
+    let mut stale = JudgementState::new(
+        context("1staleRequest"),
+        vec![IdentityFieldValue::Email("stale@email.com".to_string())],
+    );
+    stale.inserted_timestamp = Default::default();
+    db.add_judgement_request(&stale).await.unwrap();
+
+    let fixture = WatcherFixture {
+        pending_judgements: vec![
+            json!({
+                "address": ALICE,
+                "accounts": {
+                    "display_name": "Alice",
+                    "email": "alice@email.com",
+                    "github": "alice",
+                }
+            }),
+            json!({
+                "address": INVALID,
+                "accounts": "github",
+            }),
+        ],
+        new_judgement_requests: vec![json!({
+            "address": BOB,
+            "accounts": {
+                "display_name": "Bob",
+                "discord": "bob#1234",
+                "riot": null,
+            }
+        })],
+        ..Default::default()
+    };
+    let watcher = MockWatcher::start("127.0.0.1:0", fixture).unwrap();
+
+    let _status = connect(&db, &watcher).await;
+    sleep(Duration::from_secs(3)).await;
+
+    // The unknown fields are kept and must be verified manually.
+    let alice = db
+        .fetch_judgement_state(&context(ALICE))
+        .await
+        .unwrap()
+        .unwrap();
+    let github = alice
+        .fields
+        .iter()
+        .find(|field| matches!(field.value, IdentityFieldValue::Other { .. }))
+        .unwrap();
+    assert_eq!(
+        github.value,
+        IdentityFieldValue::Other {
+            name: "github".to_string(),
+            value: "alice".to_string(),
+        }
+    );
+    assert_eq!(
+        github.challenge,
+        ChallengeType::Unsupported { is_verified: None }
+    );
+
+    let bob = db
+        .fetch_judgement_state(&context(BOB))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bob.fields.len(), 2);
+
+    // The invalid request does not block the others, but the stored requests
+    // are not reconciled with the incomplete response.
+    assert!(db
+        .fetch_judgement_state(&context(INVALID))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .fetch_judgement_state(&stale.context)
+        .await
+        .unwrap()
+        .is_some());
+
+    // The skipped fields and requests are reported to the Watcher.
+    let errors = watcher.errors().await;
+    assert!(errors.contains(&error(BOB, "skipped fields without a value: riot")));
+    assert!(errors.iter().any(|error| error.address
+        == Some(ChainAddress::from(INVALID.to_string()))
+        && error.result.starts_with("invalid judgement request")));
+}
@@ -0,0 +1,266 @@
+use super::*;
+use crate::connector::{EventType, Judgement, JudgementResponse};
+use crate::mock_watcher::{MockTlsConfig, MockWatcher, MockWatcherConfig, WatcherFixture};
+use crate::primitives::{IdentityContext, JudgementState, Timestamp};
+use crate::watcher_auth::WatcherLink;
+use crate::{WatcherAuthConfig, WatcherTlsConfig};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+const FIXTURE: &str = "src/tests/fixtures/watcher.json";
+const CA_CERT: &str = "src/tests/fixtures/tls/ca.pem";
+const SERVER_CERT: &str = "src/tests/fixtures/tls/server.pem";
+const SERVER_KEY: &str = "src/tests/fixtures/tls/server.key";
+const CLIENT_CERT: &str = "src/tests/fixtures/tls/client.pem";
+const CLIENT_KEY: &str = "src/tests/fixtures/tls/client.key";
+
+fn server_tls(client_ca: Option<&str>) -> Option<MockTlsConfig> {
+    Some(MockTlsConfig {
+        cert: SERVER_CERT.to_string(),
+        key: SERVER_KEY.to_string(),
+        client_ca: client_ca.map(|path| path.to_string()),
+    })
+}
+
+fn client_tls(client_cert: bool) -> Option<WatcherTlsConfig> {
+    Some(WatcherTlsConfig {
+        ca_cert: Some(CA_CERT.to_string()),
+        client_cert: client_cert.then(|| CLIENT_CERT.to_string()),
+        client_key: client_cert.then(|| CLIENT_KEY.to_string()),
+    })
+}
+
+fn hmac_secret(secret: &str) -> Option<WatcherAuthConfig> {
+    Some(WatcherAuthConfig::HmacSecret(secret.to_string()))
+}
+
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+async fn is_synced(db: &Database) -> bool {
+    db.fetch_judgement_state(&IdentityContext::bob())
+        .await
+        .unwrap()
+        .is_some()
+}
+
+#[test]
+fn signed_messages() {
+    let link = WatcherLink::new(hmac_secret("secret").as_ref(), None).unwrap();
+    let watcher = WatcherLink::new(hmac_secret("secret").as_ref(), None)
+        .unwrap()
+        .signed_by_watcher();
+    let other = WatcherLink::new(hmac_secret("other").as_ref(), None)
+        .unwrap()
+        .signed_by_watcher();
+
+    let msg = watcher.encode(EventType::Ack, "judgement given").unwrap();
+    link.verify(msg.as_bytes()).unwrap();
+    assert!(other.verify(msg.as_bytes()).is_err());
+
+    // Messages are only accepted once.
+    assert!(link.verify(msg.as_bytes()).is_err());
+
+    // Messages cannot be reflected back to their sender.
+    let msg = link.encode(EventType::Ack, "judgement given").unwrap();
+    assert!(link.verify(msg.as_bytes()).is_err());
+    watcher.verify(msg.as_bytes()).unwrap();
+
+    // Tampered and unsigned messages are rejected.
+    let msg = watcher.encode(EventType::Ack, "judgement given").unwrap();
+    let tampered = msg.replace("judgement given", "judgement given!");
+    assert!(link.verify(tampered.as_bytes()).is_err());
+
+    let unsigned = WatcherLink::default()
+        .encode(EventType::Ack, "judgement given")
+        .unwrap();
+    assert!(link.verify(unsigned.as_bytes()).is_err());
+    WatcherLink::default().verify(msg.as_bytes()).unwrap();
+
+    // The signature covers `<sender>.<event>.<timestamp>.<nonce>.<data>`.
+    let now = Timestamp::now().raw();
+    let signed = |timestamp: u64, nonce: &str| {
+        serde_json::json!({
+            "event": "ack",
+            "data": {"result": "judgement given"},
+            "timestamp": timestamp,
+            "nonce": nonce,
+            "signature": sign(
+                "secret",
+                &format!(
+                    "watcher.ack.{}.{}.{{\"result\":\"judgement given\"}}",
+                    timestamp, nonce
+                )
+            ),
+        })
+        .to_string()
+    };
+    link.verify(signed(now, "01").as_bytes()).unwrap();
+    assert!(link.verify(signed(now, "01").as_bytes()).is_err());
+    assert!(link.verify(signed(now, "").as_bytes()).is_err());
+
+    // Old messages cannot be replayed.
+    assert!(link.verify(signed(now - 3600, "02").as_bytes()).is_err());
+    assert!(link.verify(signed(now + 3600, "03").as_bytes()).is_err());
+
+    assert!(WatcherLink::new(hmac_secret("").as_ref(), None).is_err());
+}
+
+#[actix::test]
+async fn connector_authenticates_with_bearer_token() {
+    let db = new_db().await;
+    let config = MockWatcherConfig {
+        auth: Some(WatcherAuthConfig::BearerToken("token".to_string())),
+        tls: None,
+    };
+    let fixture = WatcherFixture::from_file(FIXTURE).unwrap();
+    let watcher = MockWatcher::start_with_config("127.0.0.1:0", fixture, config).unwrap();
+
+    // Connections without (valid) credentials are refused.
+    assert!(connect_with(&db, &[&watcher], None, None).await.is_err());
+    let invalid = Some(WatcherAuthConfig::BearerToken("invalid".to_string()));
+    assert!(connect_with(&db, &[&watcher], invalid, None).await.is_err());
+    assert_eq!(watcher.connections().await, 0);
+
+    let valid = Some(WatcherAuthConfig::BearerToken("token".to_string()));
+    let _status = connect_with(&db, &[&watcher], valid, None).await.unwrap();
+    sleep(Duration::from_secs(2)).await;
+
+    assert!(is_synced(&db).await);
+}
+
+#[actix::test]
+async fn connector_signs_and_verifies_messages() {
+    let db = new_db().await;
+    let config = MockWatcherConfig {
+        auth: hmac_secret("secret"),
+        tls: None,
+    };
+    let fixture = WatcherFixture::from_file(FIXTURE).unwrap();
+    let watcher = MockWatcher::start_with_config("127.0.0.1:0", fixture, config).unwrap();
+
+    let mut alice = JudgementState::alice();
+    alice.is_fully_verified = true;
+    alice.judgement = Some(Judgement::Reasonable);
+    alice.completion_timestamp = Some(Timestamp::now());
+    alice.issue_judgement_at = Some(Timestamp::default());
+    db.import_identity(&alice).await.unwrap();
+
+    let _status = connect_with(&db, &[&watcher], hmac_secret("secret"), None)
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(3)).await;
+
+    // Both sides accept the messages of the other.
+    assert!(is_synced(&db).await);
+    assert_eq!(
+        watcher.judgements().await,
+        vec![JudgementResponse {
+            address: alice.context.address.clone(),
+            judgement: Judgement::Reasonable,
+        }]
+    );
+
+    let state = db
+        .fetch_judgement_state(&alice.context)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(state.judgement_submitted);
+}
+
+#[actix::test]
+async fn connector_rejects_messages_with_invalid_signature() {
+    let db = new_db().await;
+    let config = MockWatcherConfig {
+        auth: hmac_secret("secret"),
+        tls: None,
+    };
+    // Pushes the requests right away, without a handshake.
+    let fixture = WatcherFixture {
+        handshake: None,
+        ..WatcherFixture::from_file(FIXTURE).unwrap()
+    };
+    let watcher = MockWatcher::start_with_config("127.0.0.1:0", fixture, config).unwrap();
+
+    let _status = connect_with(&db, &[&watcher], hmac_secret("other"), None)
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(3)).await;
+
+    // The pushed and pending requests are dropped.
+    assert!(!is_synced(&db).await);
+    assert!(db
+        .fetch_judgement_state(&IdentityContext::alice())
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[actix::test]
+async fn connector_verifies_watcher_certificate() {
+    let db = new_db().await;
+    let config = MockWatcherConfig {
+        auth: None,
+        tls: server_tls(None),
+    };
+    let fixture = WatcherFixture::from_file(FIXTURE).unwrap();
+    let watcher = MockWatcher::start_with_config("127.0.0.1:0", fixture, config).unwrap();
+    assert!(watcher.endpoint().starts_with("wss://"));
+
+    // The certificate is not issued by a public CA.
+    assert!(connect_with(&db, &[&watcher], None, None).await.is_err());
+    let public_roots = Some(WatcherTlsConfig {
+        ca_cert: None,
+        client_cert: None,
+        client_key: None,
+    });
+    assert!(connect_with(&db, &[&watcher], None, public_roots)
+        .await
+        .is_err());
+
+    let _status = connect_with(&db, &[&watcher], None, client_tls(false))
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(2)).await;
+
+    assert!(is_synced(&db).await);
+}
+
+#[actix::test]
+async fn connector_presents_client_certificate() {
+    let db = new_db().await;
+    let config = MockWatcherConfig {
+        auth: hmac_secret("secret"),
+        tls: server_tls(Some(CA_CERT)),
+    };
+    let fixture = WatcherFixture::from_file(FIXTURE).unwrap();
+    let watcher = MockWatcher::start_with_config("127.0.0.1:0", fixture, config).unwrap();
+
+    assert!(
+        connect_with(&db, &[&watcher], hmac_secret("secret"), client_tls(false))
+            .await
+            .is_err()
+    );
+
+    // The key of the client certificate is missing.
+    let incomplete = Some(WatcherTlsConfig {
+        client_key: None,
+        ..client_tls(true).unwrap()
+    });
+    assert!(
+        connect_with(&db, &[&watcher], hmac_secret("secret"), incomplete)
+            .await
+            .is_err()
+    );
+
+    let _status = connect_with(&db, &[&watcher], hmac_secret("secret"), client_tls(true))
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(2)).await;
+
+    assert!(is_synced(&db).await);
+}
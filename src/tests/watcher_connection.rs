@@ -0,0 +1,270 @@
+use super::*;
+use crate::connector::{
+    reconnection_delay, ClientCommand, ConnectionState, DisplayNameEntryRaw, EndpointState,
+    EventType, Handshake, NetworkState, ResponseMessage, WatcherStatus, CAP_DISPLAY_NAMES,
+    CAP_JUDGEMENT_CANCELLATIONS, CAP_JUDGEMENT_REQUESTS, CAP_JUDGEMENT_RESULTS, PROTOCOL_VERSION,
+};
+use crate::primitives::{ChainName, IdentityContext, Timestamp};
+use crate::NetworkConfig;
+
+#[test]
+fn reconnection_delay_grows_exponentially() {
+    // Without jitter, half of the delay is used.
+    assert_eq!(reconnection_delay(0, 0.0), Duration::from_millis(500));
+    assert_eq!(reconnection_delay(1, 0.0), Duration::from_secs(1));
+    assert_eq!(reconnection_delay(4, 0.0), Duration::from_secs(8));
+
+    assert_eq!(reconnection_delay(0, 1.0), Duration::from_secs(1));
+    assert_eq!(reconnection_delay(4, 1.0), Duration::from_secs(16));
+
+    // The delay is capped.
+    assert_eq!(reconnection_delay(9, 1.0), Duration::from_secs(300));
+    assert_eq!(reconnection_delay(u32::MAX, 1.0), Duration::from_secs(300));
+    assert_eq!(reconnection_delay(u32::MAX, 0.0), Duration::from_secs(150));
+
+    // Invalid jitter values are clamped.
+    assert_eq!(reconnection_delay(1, 5.0), Duration::from_secs(2));
+    assert_eq!(reconnection_delay(1, -5.0), Duration::from_secs(1));
+}
+
+#[actix::test]
+async fn watcher_status_tracks_connection_state() {
+    let status = WatcherStatus::default();
+    let polkadot = ChainName::polkadot();
+    let kusama = ChainName::kusama();
+
+    assert!(status.states().await.is_empty());
+
+    status.set_connected(&polkadot, "ws://polkadot").await;
+    status.set_connected(&kusama, "ws://kusama").await;
+    assert!(status.states().await.values().all(|s| s.is_connected()));
+
+    // The Watcher of Kusama goes down.
+    let down_since = Timestamp::now();
+    status
+        .set_reconnecting(&kusama, "ws://kusama", down_since, 0)
+        .await;
+    status
+        .set_reconnecting(&kusama, "ws://kusama", down_since, 3)
+        .await;
+
+    let states = status.states().await;
+    assert!(states.get(&polkadot).unwrap().is_connected());
+    assert_eq!(
+        states.get(&kusama).unwrap(),
+        &NetworkState {
+            active: None,
+            endpoints: vec![EndpointState {
+                endpoint: "ws://kusama".to_string(),
+                state: ConnectionState::Reconnecting {
+                    down_since,
+                    failed_attempts: 3,
+                },
+            }],
+        }
+    );
+
+    // The status is shared between clones.
+    status.clone().set_connected(&kusama, "ws://kusama").await;
+    assert!(status.states().await.values().all(|s| s.is_connected()));
+}
+
+#[actix::test]
+async fn watcher_status_elects_first_connected_endpoint() {
+    let status = WatcherStatus::default();
+    let polkadot = ChainName::polkadot();
+    let endpoints = vec!["ws://primary".to_string(), "ws://standby".to_string()];
+
+    // Registered endpoints are not connected yet.
+    status.register(&polkadot, &endpoints).await;
+    let state = status.states().await.remove(&polkadot).unwrap();
+    assert_eq!(state.active, None);
+    assert_eq!(state.endpoints.len(), 2);
+    assert!(!status.is_active(&polkadot, "ws://primary").await);
+
+    // The order of preference is kept, no matter which connects first.
+    status.set_connected(&polkadot, "ws://standby").await;
+    assert!(status.is_active(&polkadot, "ws://standby").await);
+
+    status.set_connected(&polkadot, "ws://primary").await;
+    assert!(status.is_active(&polkadot, "ws://primary").await);
+    assert!(!status.is_active(&polkadot, "ws://standby").await);
+
+    // The standby takes over.
+    status
+        .set_reconnecting(&polkadot, "ws://primary", Timestamp::now(), 0)
+        .await;
+    assert!(status.is_active(&polkadot, "ws://standby").await);
+
+    status
+        .set_refused(&polkadot, "ws://standby", "incompatible".to_string())
+        .await;
+    let state = status.states().await.remove(&polkadot).unwrap();
+    assert!(!state.is_connected());
+    assert_eq!(
+        state
+            .endpoints
+            .iter()
+            .map(|entry| entry.endpoint.as_str())
+            .collect::<Vec<_>>(),
+        vec!["ws://primary", "ws://standby"]
+    );
+}
+
+#[test]
+fn network_config_accepts_one_or_many_endpoints() {
+    let config: NetworkConfig =
+        serde_yaml::from_str("name: polkadot\nss58_prefix: 0\nendpoint: ws://localhost:8000\n")
+            .unwrap();
+    assert_eq!(config.endpoints, vec!["ws://localhost:8000"]);
+
+    let config: NetworkConfig = serde_yaml::from_str(
+        "name: polkadot\nss58_prefix: 0\nendpoints:\n  - ws://localhost:8000\n  - ws://localhost:8001\n",
+    )
+    .unwrap();
+    assert_eq!(
+        config.endpoints,
+        vec!["ws://localhost:8000", "ws://localhost:8001"]
+    );
+}
+
+#[test]
+fn connection_state_serialization() {
+    let state = ConnectionState::Reconnecting {
+        down_since: Timestamp::default(),
+        failed_attempts: 2,
+    };
+
+    assert_eq!(
+        serde_json::to_value(state).unwrap(),
+        serde_json::json!({
+            "state": "reconnecting",
+            "down_since": 0,
+            "failed_attempts": 2,
+        })
+    );
+}
+
+fn handshake(version: u32, min_version: u32, capabilities: &[&str]) -> Handshake {
+    Handshake {
+        version,
+        min_version,
+        capabilities: capabilities.iter().map(|cap| cap.to_string()).collect(),
+    }
+}
+
+#[test]
+fn handshake_negotiation() {
+    let local = Handshake::local();
+
+    // Same version.
+    let protocol = local.negotiate(&Handshake::local()).unwrap();
+    assert_eq!(protocol.version, PROTOCOL_VERSION);
+    assert!(protocol.supports(CAP_JUDGEMENT_REQUESTS));
+    assert!(protocol.supports(CAP_JUDGEMENT_RESULTS));
+    assert!(protocol.supports(CAP_DISPLAY_NAMES));
+
+    // Newer Watcher which still supports the current version. Only common
+    // capabilities are used.
+    let protocol = local
+        .negotiate(&handshake(
+            PROTOCOL_VERSION + 1,
+            0,
+            &[CAP_JUDGEMENT_REQUESTS, CAP_JUDGEMENT_RESULTS, "unknown"],
+        ))
+        .unwrap();
+    assert_eq!(protocol.version, PROTOCOL_VERSION);
+    assert!(protocol.supports(CAP_JUDGEMENT_REQUESTS));
+    assert!(!protocol.supports(CAP_DISPLAY_NAMES));
+    assert!(!protocol.supports("unknown"));
+
+    // Watchers which predate the handshake.
+    let protocol = local.negotiate(&Handshake::legacy()).unwrap();
+    assert_eq!(protocol.version, 0);
+    assert!(protocol.supports(CAP_DISPLAY_NAMES));
+    assert!(!protocol.supports(CAP_JUDGEMENT_CANCELLATIONS));
+
+    // Watcher which requires a newer version.
+    let err = local
+        .negotiate(&handshake(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 1, &[]))
+        .unwrap_err();
+    assert!(err.to_string().contains("requires protocol version"));
+
+    // Watcher which is too old.
+    let strict = handshake(2, 2, &[]);
+    let err = strict.negotiate(&Handshake::legacy()).unwrap_err();
+    assert!(err.to_string().contains("is not supported"));
+}
+
+#[test]
+fn handshake_serialization() {
+    let parsed: ResponseMessage<Handshake> = serde_json::from_str(
+        r#"{"event":"handshake","data":{"version":1,"minVersion":0,"capabilities":["displayNames"]}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(parsed.event, EventType::Handshake);
+    assert_eq!(parsed.data, handshake(1, 0, &[CAP_DISPLAY_NAMES]));
+}
+
+#[actix::test]
+async fn messages_are_gated_on_negotiated_capabilities() {
+    let (db, mut connector, _api, _) = new_env().await;
+
+    connector
+        .inject(WatcherMessage::Handshake(handshake(
+            PROTOCOL_VERSION,
+            0,
+            &[CAP_JUDGEMENT_REQUESTS, CAP_JUDGEMENT_RESULTS],
+        )))
+        .await;
+
+    // Discard commands sent before the handshake.
+    let _ = connector.outgoing();
+    sleep(Duration::from_secs(3)).await;
+
+    let (_, counter) = connector.outgoing();
+    assert!(counter.request_pending_judgements > 0);
+    assert_eq!(counter.request_display_names, 0);
+
+    // Display names sent by the Watcher anyway are ignored.
+    connector
+        .inject(WatcherMessage::ActiveDisplayNames(vec![
+            DisplayNameEntryRaw {
+                address: IdentityContext::alice().address,
+                display_name: "Alice".to_string(),
+            },
+        ]))
+        .await;
+
+    assert!(db
+        .fetch_display_names(&ChainName::polkadot())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix::test]
+async fn incompatible_watcher_is_refused() {
+    let (_db, mut connector, _api, _) = new_env().await;
+
+    connector
+        .inject(WatcherMessage::Handshake(handshake(
+            PROTOCOL_VERSION + 1,
+            PROTOCOL_VERSION + 1,
+            &[CAP_JUDGEMENT_REQUESTS],
+        )))
+        .await;
+
+    let (outgoing, _) = connector.outgoing();
+    let errors: Vec<String> = outgoing
+        .into_iter()
+        .filter_map(|msg| match msg {
+            ClientCommand::Error(message) => Some(message),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("requires protocol version"));
+}
@@ -0,0 +1,367 @@
+use super::*;
+use crate::adapters::web::tests::StubResolver;
+use crate::adapters::web::{
+    WebVerifierBuilder, MAX_WELL_KNOWN_SIZE, TXT_RECORD_PREFIX, WELL_KNOWN_PATH,
+};
+use crate::api::{JsonResult, ResponseAccountState};
+use crate::primitives::{IdentityContext, NotificationMessage};
+use actix_web::{web, App, HttpResponse};
+use futures::{FutureExt, StreamExt};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// A port that nothing listens on, so `.well-known` lookups fail immediately.
+const UNREACHABLE_DOMAIN: &str = "127.0.0.1:1";
+
+fn alice_with_web(domain: &str) -> WatcherMessage {
+    let mut req = JudgementRequest::alice();
+    req.accounts.insert(AccountType::Web, domain.to_string());
+    WatcherMessage::new_judgement_request(req)
+}
+
+/// Serves the `.well-known` file with the content of the returned handle.
+fn run_well_known_server() -> (TestServer, Arc<RwLock<String>>) {
+    let content = Arc::new(RwLock::new(String::new()));
+
+    let t_content = Arc::clone(&content);
+    let server = actix_test::start(move || {
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&t_content)))
+            .route(
+                WELL_KNOWN_PATH,
+                web::get().to(|content: web::Data<Arc<RwLock<String>>>| async move {
+                    HttpResponse::Ok().body(content.read().await.clone())
+                }),
+            )
+    });
+
+    (server, content)
+}
+
+/// Redirects the `.well-known` file to the given URL.
+fn run_redirect_server(location: String) -> TestServer {
+    actix_test::start(move || {
+        let location = location.clone();
+        App::new().route(
+            WELL_KNOWN_PATH,
+            web::get().to(move || {
+                let location = location.clone();
+                async move {
+                    HttpResponse::Found()
+                        .append_header(("Location", location))
+                        .finish()
+                }
+            }),
+        )
+    })
+}
+
+/// Inserts the judgement request of Alice for the given domain, publishes
+/// her challenge with `publish` and asserts that the verifier ignores it.
+async fn assert_challenge_ignored(
+    domain: &str,
+    content: &Arc<RwLock<String>>,
+    publish: fn(String) -> String,
+    allow_private_addresses: bool,
+) {
+    let (db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    connector.inject(alice_with_web(domain)).await;
+    let alice = connector.inserted_states().await[0].clone();
+
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    let field = F::Web(domain.to_string());
+    let challenge = alice.get_field(&field).expected_message().value.clone();
+    *content.write().await = publish(challenge);
+
+    let builder = WebVerifierBuilder::new()
+        .database(db.clone())
+        .resolver(StubResolver::new(vec![]))
+        .scheme("http".to_string());
+    let builder = if allow_private_addresses {
+        builder.allow_private_addresses()
+    } else {
+        builder
+    };
+
+    AdapterListener::new(db, LockoutPolicy::default())
+        .await
+        .start_message_adapter(builder.build().unwrap(), 1)
+        .await
+        .unwrap();
+
+    // Neither verified nor counted as a failed attempt.
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn verify_web_well_known_file() {
+    let (db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let (server, content) = run_well_known_server();
+    let domain = server.addr().to_string();
+    let field = F::Web(domain.clone());
+
+    // Insert judgement request.
+    connector.inject(alice_with_web(&domain)).await;
+    let states = connector.inserted_states().await;
+    let mut alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Publish the challenge.
+    *content.write().await = alice.get_field(&field).expected_message().value.clone();
+
+    let verifier = WebVerifierBuilder::new()
+        .database(db.clone())
+        .resolver(StubResolver::new(vec![]))
+        .scheme("http".to_string())
+        .allow_private_addresses()
+        .build()
+        .unwrap();
+
+    AdapterListener::new(db, LockoutPolicy::default())
+        .await
+        .start_message_adapter(verifier, 1)
+        .await
+        .unwrap();
+
+    // Web domain of Alice is now verified.
+    alice
+        .get_field_mut(&field)
+        .expected_message_mut()
+        .set_verified();
+
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerified {
+            context: alice.context.clone(),
+            field,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+
+    // Empty stream.
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn verify_web_dns_txt_record() {
+    let (db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let field = F::Web(UNREACHABLE_DOMAIN.to_string());
+
+    // Insert judgement request.
+    connector.inject(alice_with_web(UNREACHABLE_DOMAIN)).await;
+    let states = connector.inserted_states().await;
+    let mut alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Publish the challenge, alongside unrelated records.
+    let challenge = alice.get_field(&field).expected_message().value.clone();
+    let verifier = WebVerifierBuilder::new()
+        .database(db.clone())
+        .resolver(StubResolver::new(vec![
+            "v=spf1 -all".to_string(),
+            format!("{}{}", TXT_RECORD_PREFIX, challenge),
+        ]))
+        .scheme("http".to_string())
+        .allow_private_addresses()
+        .build()
+        .unwrap();
+
+    AdapterListener::new(db, LockoutPolicy::default())
+        .await
+        .start_message_adapter(verifier, 1)
+        .await
+        .unwrap();
+
+    // Web domain of Alice is now verified.
+    alice
+        .get_field_mut(&field)
+        .expected_message_mut()
+        .set_verified();
+
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerified {
+            context: alice.context.clone(),
+            field,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+
+    // Empty stream.
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn verify_web_invalid_challenge() {
+    let (db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let (server, content) = run_well_known_server();
+    let domain = server.addr().to_string();
+    let field = F::Web(domain.clone());
+
+    // Insert judgement request.
+    connector.inject(alice_with_web(&domain)).await;
+    let states = connector.inserted_states().await;
+    let mut alice = states[0].clone();
+
+    // Subscribe to endpoint.
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    // Publish an invalid challenge.
+    *content.write().await = "invalid".to_string();
+
+    let verifier = WebVerifierBuilder::new()
+        .database(db.clone())
+        .resolver(StubResolver::new(vec![]))
+        .scheme("http".to_string())
+        .allow_private_addresses()
+        .build()
+        .unwrap();
+
+    AdapterListener::new(db, LockoutPolicy::default())
+        .await
+        .start_message_adapter(verifier, 1)
+        .await
+        .unwrap();
+
+    // The failed attempt is only counted once, even though the file is polled
+    // repeatedly.
+    *alice.get_field_mut(&field).failed_attempts_mut() = 1;
+
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerificationFailed {
+            context: alice.context.clone(),
+            field,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+
+    // Empty stream.
+    sleep(Duration::from_secs(3)).await;
+    assert!(stream.next().now_or_never().is_none());
+}
+
+#[actix::test]
+async fn verify_web_refuses_private_addresses() {
+    let (server, content) = run_well_known_server();
+    let domain = server.addr().to_string();
+
+    assert_challenge_ignored(&domain, &content, |challenge| challenge, false).await;
+}
+
+#[actix::test]
+async fn verify_web_ignores_large_files() {
+    let (server, content) = run_well_known_server();
+    let domain = server.addr().to_string();
+
+    assert_challenge_ignored(
+        &domain,
+        &content,
+        |challenge| format!("{}\n{}", challenge, "x".repeat(MAX_WELL_KNOWN_SIZE)),
+        true,
+    )
+    .await;
+}
+
+#[actix::test]
+async fn verify_web_ignores_redirects_to_other_hosts() {
+    let (target, content) = run_well_known_server();
+    let server = run_redirect_server(format!(
+        "http://localhost:{}{}",
+        target.addr().port(),
+        WELL_KNOWN_PATH
+    ));
+    let domain = server.addr().to_string();
+
+    assert_challenge_ignored(&domain, &content, |challenge| challenge, true).await;
+}
+
+#[actix::test]
+async fn verify_web_follows_redirects_to_same_host() {
+    let (db, connector, mut api, _) = new_env().await;
+    let mut stream = api.ws_at("/api/account_status").await.unwrap();
+
+    let (target, content) = run_well_known_server();
+    let server = run_redirect_server(format!("http://{}{}", target.addr(), WELL_KNOWN_PATH));
+    let domain = server.addr().to_string();
+    let field = F::Web(domain.clone());
+
+    connector.inject(alice_with_web(&domain)).await;
+    let mut alice = connector.inserted_states().await[0].clone();
+
+    let resp = subscribe_context(&mut stream, IdentityContext::alice()).await;
+    assert_eq!(
+        resp,
+        JsonResult::Ok(ResponseAccountState::with_no_notifications(alice.clone()))
+    );
+
+    *content.write().await = alice.get_field(&field).expected_message().value.clone();
+
+    let verifier = WebVerifierBuilder::new()
+        .database(db.clone())
+        .resolver(StubResolver::new(vec![]))
+        .scheme("http".to_string())
+        .allow_private_addresses()
+        .build()
+        .unwrap();
+
+    AdapterListener::new(db, LockoutPolicy::default())
+        .await
+        .start_message_adapter(verifier, 1)
+        .await
+        .unwrap();
+
+    alice
+        .get_field_mut(&field)
+        .expected_message_mut()
+        .set_verified();
+
+    let expected = ResponseAccountState {
+        state: alice.clone().into(),
+        notifications: vec![NotificationMessage::FieldVerified {
+            context: alice.context.clone(),
+            field,
+        }],
+    };
+
+    let resp: JsonResult<ResponseAccountState> = stream.next().await.into();
+    assert_eq!(resp, JsonResult::Ok(expected));
+}
@@ -0,0 +1,324 @@
+use crate::connector::{EventType, ResponseMessage};
+use crate::primitives::Timestamp;
+use crate::{Result, WatcherAuthConfig, WatcherTlsConfig};
+use awc::ws::WebsocketsRequest;
+use awc::{Client, Connector};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, Rng};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore};
+use serde::Serialize;
+use serde_json::value::RawValue;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+use subtle::ConstantTimeEq;
+
+// In seconds. Signed messages which are older (or newer, clocks drift) are
+// rejected. Within this window, the nonce of each message is remembered so
+// the message cannot be replayed.
+const MAX_SIGNATURE_AGE: u64 = 300;
+const MAX_NONCE_LENGTH: usize = 64;
+
+/// The sender of a signed message. Part of the signature, so messages of
+/// one side cannot be reflected back to it.
+#[derive(Debug, Clone, Copy)]
+enum Sender {
+    Registrar,
+    Watcher,
+}
+
+impl Sender {
+    fn label(&self) -> &'static str {
+        match self {
+            Sender::Registrar => "registrar",
+            Sender::Watcher => "watcher",
+        }
+    }
+    fn other(&self) -> Sender {
+        match self {
+            Sender::Registrar => Sender::Watcher,
+            Sender::Watcher => Sender::Registrar,
+        }
+    }
+}
+
+/// A message of the Watcher protocol, signed with HMAC-SHA256. The signature
+/// covers `<sender>.<event>.<timestamp>.<nonce>.<data>`, with `data` exactly
+/// as sent.
+#[derive(Serialize, Deserialize)]
+struct SignedMessage {
+    event: String,
+    data: Box<RawValue>,
+    timestamp: u64,
+    nonce: String,
+    signature: String,
+}
+
+#[derive(Clone)]
+struct MessageSigner {
+    secret: Vec<u8>,
+    // The sender of outgoing messages, received messages must be signed by
+    // the other side.
+    sender: Sender,
+    // The nonces of the received messages which are within the time window,
+    // with their timestamps.
+    received: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl MessageSigner {
+    fn mac(
+        &self,
+        sender: Sender,
+        event: &str,
+        timestamp: u64,
+        nonce: &str,
+        data: &str,
+    ) -> Hmac<Sha256> {
+        let mut mac: Hmac<Sha256> = Hmac::new_from_slice(&self.secret).unwrap();
+        mac.update(
+            format!(
+                "{}.{}.{}.{}.{}",
+                sender.label(),
+                event,
+                timestamp,
+                nonce,
+                data
+            )
+            .as_bytes(),
+        );
+        mac
+    }
+    fn sign<T: Serialize>(&self, event: EventType, data: T) -> Result<String> {
+        let event = serde_json::to_value(event)?
+            .as_str()
+            .map(|event| event.to_string())
+            .ok_or_else(|| anyhow!("Event type is not serialized as a string"))?;
+        let data = RawValue::from_string(serde_json::to_string(&data)?)?;
+        let timestamp = Timestamp::now().raw();
+        let nonce = hex::encode(thread_rng().gen::<[u8; 16]>());
+        let signature = hex::encode(
+            self.mac(self.sender, &event, timestamp, &nonce, data.get())
+                .finalize()
+                .into_bytes(),
+        );
+
+        Ok(serde_json::to_string(&SignedMessage {
+            event,
+            data,
+            timestamp,
+            nonce,
+            signature,
+        })?)
+    }
+    fn verify(&self, txt: &[u8]) -> Result<()> {
+        let msg: SignedMessage = serde_json::from_slice(txt)
+            .map_err(|err| anyhow!("Message is not signed: {:?}", err))?;
+
+        let now = Timestamp::now().raw();
+        if msg.timestamp + MAX_SIGNATURE_AGE < now || msg.timestamp > now + MAX_SIGNATURE_AGE {
+            return Err(anyhow!(
+                "Signature timestamp {} is out of range",
+                msg.timestamp
+            ));
+        }
+
+        if msg.nonce.is_empty() || msg.nonce.len() > MAX_NONCE_LENGTH {
+            return Err(anyhow!("Invalid nonce"));
+        }
+
+        let signature = hex::decode(&msg.signature)
+            .map_err(|err| anyhow!("Signature is not HEX encoded: {:?}", err))?;
+
+        self.mac(
+            self.sender.other(),
+            &msg.event,
+            msg.timestamp,
+            &msg.nonce,
+            msg.data.get(),
+        )
+        .verify_slice(&signature)
+        .map_err(|_| anyhow!("Invalid signature"))?;
+
+        // Only authentic messages are remembered.
+        let mut received = self
+            .received
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        received.retain(|_, timestamp| *timestamp + MAX_SIGNATURE_AGE >= now);
+        if received.insert(msg.nonce, msg.timestamp).is_some() {
+            return Err(anyhow!("Message was received before"));
+        }
+
+        Ok(())
+    }
+}
+
+/// How connections to the Watchers of a network are established and how
+/// messages are authenticated, see `WatcherAuthConfig` and `WatcherTlsConfig`.
+/// The defaults connect without authentication, `wss://` endpoints are
+/// verified with the public root certificates.
+#[derive(Clone, Default)]
+pub struct WatcherLink {
+    bearer_token: Option<String>,
+    signer: Option<MessageSigner>,
+    tls: Option<Arc<ClientConfig>>,
+}
+
+impl WatcherLink {
+    pub fn new(auth: Option<&WatcherAuthConfig>, tls: Option<&WatcherTlsConfig>) -> Result<Self> {
+        let mut link = WatcherLink::default();
+
+        match auth {
+            Some(WatcherAuthConfig::BearerToken(token)) => {
+                link.bearer_token = Some(token.clone());
+            }
+            Some(WatcherAuthConfig::HmacSecret(secret)) => {
+                if secret.is_empty() {
+                    return Err(anyhow!("The HMAC secret of the Watcher must not be empty"));
+                }
+
+                link.signer = Some(MessageSigner {
+                    secret: secret.as_bytes().to_vec(),
+                    sender: Sender::Registrar,
+                    received: Default::default(),
+                });
+            }
+            None => {}
+        }
+
+        if let Some(config) = tls {
+            link.tls = Some(Arc::new(client_tls_config(config)?));
+        }
+
+        Ok(link)
+    }
+    /// Signs messages as the Watcher and expects messages signed by the
+    /// registrar. Used by the mock Watcher.
+    pub fn signed_by_watcher(mut self) -> Self {
+        if let Some(signer) = &mut self.signer {
+            signer.sender = Sender::Watcher;
+        }
+
+        self
+    }
+    /// Whether credentials are sent to the Watcher when connecting.
+    pub fn sends_credentials(&self) -> bool {
+        self.bearer_token.is_some()
+    }
+    pub fn ws(&self, endpoint: &str) -> WebsocketsRequest {
+        let client = match &self.tls {
+            Some(config) => Client::builder()
+                .connector(Connector::new().rustls(Arc::clone(config)))
+                .finish(),
+            None => Client::new(),
+        };
+
+        let request = client.ws(endpoint);
+        match &self.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+    /// Checks the `Authorization` header of a connection request.
+    pub fn authorize(&self, header: Option<&[u8]>) -> bool {
+        match (&self.bearer_token, header) {
+            (Some(token), Some(header)) => {
+                format!("Bearer {}", token).as_bytes().ct_eq(header).into()
+            }
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+    /// Serializes a message of the Watcher protocol, signed if configured.
+    pub fn encode<T: Serialize>(&self, event: EventType, data: T) -> Result<String> {
+        match &self.signer {
+            Some(signer) => signer.sign(event, data),
+            None => Ok(serde_json::to_string(&ResponseMessage { event, data })?),
+        }
+    }
+    /// Checks the signature of a received message, if configured.
+    pub fn verify(&self, txt: &[u8]) -> Result<()> {
+        match &self.signer {
+            Some(signer) => signer.verify(txt),
+            None => Ok(()),
+        }
+    }
+}
+
+fn client_tls_config(config: &WatcherTlsConfig) -> Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match &config.ca_cert {
+        Some(path) => {
+            for cert in read_certs(path)? {
+                roots
+                    .add(&cert)
+                    .map_err(|err| anyhow!("Invalid CA certificate in '{}': {:?}", path, err))?;
+            }
+        }
+        None => {
+            roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+    }
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    let mut client_config = match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => builder
+            .with_single_cert(read_certs(cert)?, read_private_key(key)?)
+            .map_err(|err| anyhow!("Invalid client certificate '{}': {:?}", cert, err))?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => {
+            return Err(anyhow!(
+                "Both the client certificate and its key must be specified"
+            ))
+        }
+    };
+
+    // Websockets require HTTP/1.1.
+    client_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(client_config)
+}
+
+/// Reads all certificates of a PEM file.
+pub fn read_certs(path: &str) -> Result<Vec<Certificate>> {
+    let file = File::open(path).map_err(|err| anyhow!("Failed to open '{}': {:?}", path, err))?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|err| anyhow!("Failed to read certificates from '{}': {:?}", path, err))?;
+
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate found in '{}'", path));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first PKCS#8 or RSA private key of a PEM file.
+pub fn read_private_key(path: &str) -> Result<PrivateKey> {
+    let read = |f: fn(&mut dyn std::io::BufRead) -> std::io::Result<Vec<Vec<u8>>>| {
+        let file =
+            File::open(path).map_err(|err| anyhow!("Failed to open '{}': {:?}", path, err))?;
+
+        f(&mut BufReader::new(file))
+            .map_err(|err| anyhow!("Failed to read private key from '{}': {:?}", path, err))
+    };
+
+    read(rustls_pemfile::pkcs8_private_keys)?
+        .into_iter()
+        .chain(read(rustls_pemfile::rsa_private_keys)?)
+        .next()
+        .map(PrivateKey)
+        .ok_or_else(|| anyhow!("No private key found in '{}'", path))
+}
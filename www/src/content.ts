@@ -1,4 +1,4 @@
-import { CheckDisplayNameResult, DisplayNameChallenge, GenericMessage, State, Violation } from './json';
+import { CheckDisplayNameResult, DisplayNameChallenge, GenericMessage, OtherFieldValue, State, Violation } from './json';
 import { NotificationHandler } from './notifications.js';
 
 const BadgeVerified = `
@@ -13,6 +13,10 @@ const BadgeUnverified = `
     <span class="badge bg-warning text-dark">unverified</span>
 `;
 
+const BadgeLocked = `
+    <span class="badge bg-danger">locked</span>
+`;
+
 export const BadgeValid = `
     <span class="badge bg-success">valid</span>
 `;
@@ -92,7 +96,7 @@ export class ContentManager {
 
         let counter = 1;
         for (let field of state.fields) {
-            if (field.challenge.type == "expected_message") {
+            if (field.challenge.type == "expected_message" || field.challenge.type == "signed_message") {
                 let validity;
                 if (field.challenge.content.expected.is_verified) {
                     if (field.challenge.content.second && !field.challenge.content.second!.is_verified) {
@@ -106,6 +110,8 @@ export class ContentManager {
                             this.wipeEmailSecondChallengeContent();
                         }
                     }
+                } else if (field.locked_until && field.locked_until * 1000 > Date.now()) {
+                    validity = BadgeLocked;
                 } else {
                     validity = BadgeUnverified;
                 }
@@ -118,6 +124,10 @@ export class ContentManager {
                     to = "@w3f_registrar";
                 } else if (field.value.type == "matrix") {
                     to = "@registrar-v2:web3.foundation";
+                } else if (field.value.type == "web") {
+                    to = "DNS TXT record (polkadot-registrar=...) or /.well-known/polkadot-registrar.txt";
+                } else if (field.value.type == "p_g_p_fingerprint") {
+                    to = "Clearsigned message (gpg --clearsign) via /api/verify_pgp_signature";
                 }
 
                 table += `
@@ -152,7 +162,12 @@ export class ContentManager {
         let unsupported = "";
         for (let field of state.fields) {
             if (field.challenge.type == "unsupported") {
-                unsupported += `<li>${capitalizeFirstLetter(field.value.type)} ("${field.value.value}")</li>`;
+                if (field.value.type == "other") {
+                    const other = field.value.value as unknown as OtherFieldValue;
+                    unsupported += `<li>${capitalizeFirstLetter(other.name)} ("${other.value}")</li>`;
+                } else {
+                    unsupported += `<li>${capitalizeFirstLetter(field.value.type)} ("${field.value.value}")</li>`;
+                }
             }
         }
 
@@ -24,6 +24,8 @@ export interface Field {
     value: FieldValue;
     challenge: Challenge;
     failed_attempts: number;
+    locked_until: number | null;
+    flagged: boolean;
 }
 
 export interface FieldValue {
@@ -31,6 +33,12 @@ export interface FieldValue {
     value: string;
 }
 
+// The value of fields of type `other`.
+export interface OtherFieldValue {
+    name: string;
+    value: string;
+}
+
 export interface Challenge {
     type: string;
     content: any;
@@ -61,11 +69,22 @@ export interface NotificationFieldContext {
     field: FieldValue;
 }
 
+export interface FieldLocked {
+    context: Context;
+    field: FieldValue;
+    locked_until: number;
+}
+
 export interface ManuallyVerified {
     context: Context;
     field: string;
 }
 
+export interface JudgementPending {
+    context: Context;
+    judgement: string;
+}
+
 export interface CheckDisplayNameResult {
     type: string;
     value: any;
@@ -1,5 +1,5 @@
 import { capitalizeFirstLetter } from "./content.js";
-import { Notification, NotificationFieldContext, ManuallyVerified } from "./json";
+import { Notification, NotificationFieldContext, ManuallyVerified, JudgementPending, FieldLocked } from "./json";
 
 export class NotificationHandler {
     notify_idx: number
@@ -152,6 +152,42 @@ function notificationTypeResolver(notification: Notification): [string, string]
                 "bg-info text-light"
             ]
         }
+        case "challenge_regenerated": {
+            let data = notification.value as NotificationFieldContext;
+            return [
+                `The challenge for ${capitalizeFirstLetter(data.field.type)} account "${data.field.value}" has expired. A new challenge was generated.`,
+                "bg-info text-dark"
+            ]
+        }
+        case "field_locked": {
+            let data = notification.value as FieldLocked;
+            let until = new Date(data.locked_until * 1000).toLocaleTimeString();
+            return [
+                `Too many failed attempts for ${capitalizeFirstLetter(data.field.type)} account "${data.field.value}". Verification is locked until ${until}.`,
+                "bg-danger text-light"
+            ]
+        }
+        case "field_challenge_reset": {
+            let data = notification.value as NotificationFieldContext;
+            return [
+                `Too many failed attempts for ${capitalizeFirstLetter(data.field.type)} account "${data.field.value}". A new challenge was generated.`,
+                "bg-warning text-dark"
+            ]
+        }
+        case "field_flagged": {
+            let data = notification.value as NotificationFieldContext;
+            return [
+                `Too many failed attempts for ${capitalizeFirstLetter(data.field.type)} account "${data.field.value}". The account was flagged for manual review.`,
+                "bg-warning text-dark"
+            ]
+        }
+        case "judgement_pending": {
+            let data = notification.value as JudgementPending;
+            return [
+                `Judgement "${data.judgement}" will be issued in a couple of minutes.`,
+                data.judgement == "erroneous" ? "bg-danger text-light" : "bg-info text-light"
+            ]
+        }
         default: {
             throw new Error("unrecognized notification");
         }